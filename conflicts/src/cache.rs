@@ -0,0 +1,88 @@
+use std::collections::{HashMap, HashSet};
+
+/// The results of previous merges of two pulls on top of a base commit, so that only pulls whose
+/// head or base changed since the last run have to be merged again.
+pub struct ConflictCache {
+    path: std::path::PathBuf,
+    /// By cache key, the conflicting files, or None if the pulls merge cleanly.
+    entries: HashMap<String, Option<Vec<String>>>,
+}
+
+/// The key of two pulls on top of a base commit. The pulls are sorted, because whether they
+/// conflict does not depend on the order they are merged in.
+fn key(base: &str, head_a: &str, head_b: &str) -> String {
+    let (a, b) = if head_a <= head_b {
+        (head_a, head_b)
+    } else {
+        (head_b, head_a)
+    };
+    format!("{base}:{a}:{b}")
+}
+
+impl ConflictCache {
+    /// Load the cache from the file, or start with an empty one.
+    pub fn load(path: std::path::PathBuf) -> Self {
+        let entries = std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|s| serde_json::from_str(&s).ok())
+            .unwrap_or_default();
+        Self { path, entries }
+    }
+
+    /// Return None if the pulls were not merged yet.
+    pub fn get(&self, base: &str, head_a: &str, head_b: &str) -> Option<&Option<Vec<String>>> {
+        self.entries.get(&key(base, head_a, head_b))
+    }
+
+    pub fn insert(
+        &mut self,
+        base: &str,
+        head_a: &str,
+        head_b: &str,
+        conflict: Option<Vec<String>>,
+    ) {
+        self.entries.insert(key(base, head_a, head_b), conflict);
+    }
+
+    /// Drop the entries of an outdated base commit or of heads that are no longer open.
+    fn prune(&mut self, base: &str, heads: &HashSet<&str>) {
+        self.entries.retain(|k, _| {
+            let mut parts = k.split(':');
+            parts.next() == Some(base) && parts.all(|h| heads.contains(h))
+        });
+    }
+
+    /// Prune the cache to the current base and open pulls, and write it to the file.
+    pub fn save(&mut self, base: &str, heads: &HashSet<&str>) {
+        self.prune(base, heads);
+        let tmp = self.path.with_extension("tmp");
+        std::fs::write(&tmp, serde_json::to_vec(&self.entries).expect("json error"))
+            .expect("conflicts cache write error");
+        std::fs::rename(tmp, &self.path).expect("conflicts cache write error");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cache() {
+        let mut cache = ConflictCache {
+            path: std::path::PathBuf::new(),
+            entries: HashMap::new(),
+        };
+        let files = Some(vec!["src/init.cpp".to_string()]);
+        cache.insert("base", "a", "b", files.clone());
+        cache.insert("base", "a", "c", None);
+        cache.insert("old_base", "a", "b", None);
+        assert_eq!(cache.get("base", "b", "a"), Some(&files));
+        assert_eq!(cache.get("base", "a", "c"), Some(&None));
+        assert_eq!(cache.get("new_base", "a", "b"), None);
+
+        cache.prune("base", &HashSet::from(["a", "b"]));
+        assert_eq!(cache.get("base", "a", "b"), Some(&files));
+        assert_eq!(cache.get("base", "a", "c"), None);
+        assert_eq!(cache.get("old_base", "a", "b"), None);
+    }
+}
@@ -0,0 +1,871 @@
+mod cache;
+
+use std::io::Write;
+
+#[derive(clap::Parser)]
+#[command(about = "Determine conflicting pull requests.", long_about = None)]
+pub struct Args {
+    /// The access token for GitHub.
+    #[arg(long, env = "GITHUB_ACCESS_TOKEN", hide_env_values = true)]
+    github_access_token: Option<String>,
+    /// Cache the GET responses of the GitHub API in this dir, and only revalidate them on later
+    /// runs. May be shared with the other tools.
+    #[arg(long)]
+    github_cache_dir: Option<std::path::PathBuf>,
+    /// The repo slugs of the monotree remotes on GitHub. Format: owner/repo
+    #[arg(long)]
+    github_repo: Vec<util::Slug>,
+    /// Update the conflict comment and label for this pull request. Format: owner/repo/number
+    #[arg(long, value_parser=parse_pull_id)]
+    pull_id: Option<String>,
+    /// Update all conflicts comments and labels.
+    #[arg(long, default_value_t = false)]
+    update_comments: bool,
+    /// The local dir used for scratching.
+    #[arg(long)]
+    scratch_dir: std::path::PathBuf,
+    /// The path to the yaml config file.
+    #[arg(long)]
+    config_file: std::path::PathBuf,
+    /// Print changes/edits instead of calling the GitHub API.
+    #[arg(long, default_value_t = false)]
+    dry_run: bool,
+    /// The number of parallel merge workers, each in its own git worktree.
+    #[arg(long, default_value_t = 1)]
+    jobs: usize,
+    /// A clone of the reports repo, to publish the conflicts of each pull request to as json.
+    #[arg(long)]
+    export_dir: Option<std::path::PathBuf>,
+    /// Also write the dry-run preview (API calls and comment diffs) to this file.
+    #[arg(long, requires = "dry_run")]
+    dry_run_output: Option<std::path::PathBuf>,
+    /// Stop updating comments after this many minutes and resume from there on the next run.
+    #[arg(long)]
+    max_runtime: Option<u64>,
+    /// Stop updating comments after this many minutes. Unlike --max-runtime, the next run starts
+    /// over with the most recently updated pulls, so that they are always up to date. A stopped
+    /// run is not recorded as a complete run in the pull facts.
+    #[arg(long, conflicts_with = "max_runtime")]
+    budget_minutes: Option<u64>,
+    /// The sqlite database to record the conflicts of each pull request in, shared with the other
+    /// tools of the bot.
+    #[arg(long)]
+    pull_facts_file: Option<std::path::PathBuf>,
+    /// The format of the log output: pretty or json.
+    #[arg(long, default_value = "pretty")]
+    log_format: util::LogFormat,
+}
+
+fn parse_pull_id(val: &str) -> Result<String, String> {
+    if 3 == val.split('/').count() {
+        return Ok(val.to_string());
+    }
+    Err("".to_string())
+}
+
+#[derive(serde::Deserialize)]
+struct Config {
+    conflicts_heading: String,
+    conflicts_description: String,
+    conflicts_empty: String,
+}
+
+fn init_git(monotree_dir: &std::path::Path, repos: &Vec<util::Slug>) {
+    if monotree_dir.is_dir() {
+        return;
+    }
+    for sl in repos {
+        let sl = sl.str();
+        let url = format!("https://github.com/{sl}");
+        tracing::info!("Clone {url} repo to {dir}", dir = monotree_dir.display());
+        if !monotree_dir.is_dir() {
+            util::check_call(
+                util::git()
+                    .args(["clone", "--quiet", &url])
+                    .arg(monotree_dir),
+            );
+        }
+        tracing::info!("Set git metadata");
+        {
+            let err = "git config file error";
+            let mut f = std::fs::OpenOptions::new()
+                .append(true)
+                .open(monotree_dir.join(".git").join("config"))
+                .expect(err);
+            writeln!(f, "[remote \"con_pull_ref/{sl}\"]").expect(err);
+            writeln!(f, "    url = {url}").expect(err);
+            writeln!(f, "    fetch = +refs/pull/*:refs/remotes/upstream-pull/*").expect(err);
+        }
+        util::check_call(util::git().current_dir(monotree_dir).args([
+            "config",
+            "fetch.showForcedUpdates",
+            "false",
+        ]));
+        util::check_call(util::git().current_dir(monotree_dir).args([
+            "config",
+            "user.email",
+            "no@ne.nl",
+        ]));
+        util::check_call(util::git().current_dir(monotree_dir).args([
+            "config",
+            "user.name",
+            "none",
+        ]));
+        util::check_call(
+            util::git()
+                .current_dir(monotree_dir)
+                .args(["config", "gc.auto", "0"]),
+        );
+    }
+}
+
+struct MetaPull {
+    pull: octocrab::models::pulls::PullRequest,
+    head_commit: String,
+    slug: util::Slug,
+    slug_num: String,
+    merge_commit: Option<String>,
+}
+
+fn merge_strategy() -> &'static str {
+    // https://github.blog/changelog/2022-09-12-merge-commits-now-created-using-the-merge-ort-strategy/
+    "--strategy=ort"
+}
+
+/// Merge each pull on top of the commit `base_id` in the given work tree and return the ones that
+/// merge cleanly, along with their merge commit.
+fn calc_mergeable(
+    work_tree: &std::path::Path,
+    pulls: Vec<MetaPull>,
+    base_id: &str,
+) -> Vec<MetaPull> {
+    let mut ret = Vec::new();
+    for mut p in pulls {
+        util::check_call(
+            util::git()
+                .current_dir(work_tree)
+                .args(["checkout", base_id, "--quiet"]),
+        );
+        let mergeable = util::call(
+            util::git()
+                .current_dir(work_tree)
+                .args(["merge", merge_strategy(), "--quiet", &p.head_commit, "-m"])
+                .arg(format!("Prepare base for {id}", id = p.slug_num)),
+        );
+
+        if mergeable {
+            p.merge_commit = Some(util::check_output(
+                util::git()
+                    .current_dir(work_tree)
+                    .args(["log", "-1", "--format=%H", "HEAD"]),
+            ));
+            ret.push(p);
+        } else {
+            util::check_call(
+                util::git()
+                    .current_dir(work_tree)
+                    .args(["merge", "--abort"]),
+            );
+        }
+    }
+    ret
+}
+
+/// Merge `pull_other` on top of the commit `merge_id` in the given work tree and return the
+/// conflicting files, or None if they merge cleanly.
+fn merge_conflicts(
+    work_tree: &std::path::Path,
+    merge_id: &str,
+    pull_check: &MetaPull,
+    pull_other: &MetaPull,
+) -> Option<Vec<String>> {
+    util::check_call(
+        util::git()
+            .current_dir(work_tree)
+            .args(["checkout", merge_id, "--quiet"]),
+    );
+    let conflict = !util::call(
+        util::git()
+            .current_dir(work_tree)
+            .args([
+                "merge",
+                merge_strategy(),
+                "--quiet",
+                &pull_other.head_commit,
+                "-m",
+            ])
+            .arg(format!(
+                "Merge base_{pr_id}+{pr_o_id}",
+                pr_id = pull_check.slug_num,
+                pr_o_id = pull_other.slug_num
+            )),
+    );
+    if !conflict {
+        return None;
+    }
+    let files = util::check_output(util::git().current_dir(work_tree).args([
+        "diff",
+        "--name-only",
+        "--diff-filter=U",
+    ]));
+    util::check_call(
+        util::git()
+            .current_dir(work_tree)
+            .args(["merge", "--abort"]),
+    );
+    Some(files.lines().map(|f| f.to_string()).collect())
+}
+
+/// Return the pulls that conflict with `pull_check` with the conflicting files, when merged on top
+/// of the base commit `base_id`. The merges that are not cached yet are spread over the work trees,
+/// with one worker thread per work tree.
+fn calc_conflicts<'a>(
+    pulls_mergeable: &'a [MetaPull],
+    pull_check: &MetaPull,
+    base_id: &str,
+    cache: &mut cache::ConflictCache,
+    work_trees: &[std::path::PathBuf],
+) -> Vec<(&'a MetaPull, Vec<String>)> {
+    let merge_id = util::check_output(util::git().current_dir(&work_trees[0]).args([
+        "log",
+        "-1",
+        "--format=%H",
+        pull_check.merge_commit.as_ref().expect("merge id missing"),
+    ]));
+    let others = pulls_mergeable
+        .iter()
+        .filter(|p| p.slug_num != pull_check.slug_num)
+        .collect::<Vec<_>>();
+    let todo = others
+        .iter()
+        .filter(|p| {
+            cache
+                .get(base_id, &pull_check.head_commit, &p.head_commit)
+                .is_none()
+        })
+        .collect::<Vec<_>>();
+    let merged = std::thread::scope(|s| {
+        let workers = work_trees
+            .iter()
+            .enumerate()
+            .map(|(i, work_tree)| {
+                let (todo, merge_id) = (&todo, &merge_id);
+                s.spawn(move || {
+                    todo.iter()
+                        .skip(i)
+                        .step_by(work_trees.len())
+                        .map(|p| (p, merge_conflicts(work_tree, merge_id, pull_check, p)))
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect::<Vec<_>>();
+        workers
+            .into_iter()
+            .flat_map(|w| w.join().expect("merge worker panicked"))
+            .collect::<Vec<_>>()
+    });
+    for (p, conflict) in merged {
+        cache.insert(base_id, &pull_check.head_commit, &p.head_commit, conflict);
+    }
+    others
+        .into_iter()
+        .filter_map(|p| {
+            let files = cache.get(base_id, &pull_check.head_commit, &p.head_commit)?;
+            Some((p, files.clone()?))
+        })
+        .collect()
+}
+
+/// GitHub rejects comments longer than 65536 characters. Leave enough room for the other sections
+/// of the metadata comment.
+const MAX_CONFLICTS_LEN: usize = 30_000;
+/// Show this many conflicts directly, and the remaining ones in a collapsed block.
+const MAX_CONFLICTS_INLINE: usize = 10;
+
+/// List at most this many conflicting files per pull.
+const MAX_FILES: usize = 20;
+
+/// Render the conflicting files of a pull as a collapsed block below its list item.
+fn render_files(files: &[String]) -> String {
+    if files.is_empty() {
+        return String::new();
+    }
+    let mut list = files
+        .iter()
+        .take(MAX_FILES)
+        .map(|f| format!("`{f}`"))
+        .collect::<Vec<_>>()
+        .join(", ");
+    if files.len() > MAX_FILES {
+        list += &format!(" and {num} more", num = files.len() - MAX_FILES);
+    }
+    format!(
+        "\n  <details><summary>{num} conflicting file{s}</summary>\n\n  {list}\n  </details>",
+        num = files.len(),
+        s = if files.len() == 1 { "" } else { "s" },
+    )
+}
+
+/// Render the list of conflicts, so that the result is at most `max_len` bytes long.
+fn render_conflicts(lines: &[String], max_len: usize) -> String {
+    // Reserve space for the collapsed block and the "more" line
+    let mut len = 100;
+    let fit = lines
+        .iter()
+        .take_while(|l| {
+            len += 1 + l.len();
+            len <= max_len
+        })
+        .count();
+    let (shown, dropped) = lines.split_at(fit);
+    let (inline, details) = shown.split_at(shown.len().min(MAX_CONFLICTS_INLINE));
+    let mut txt = inline.iter().map(|l| format!("\n{l}")).collect::<String>();
+    if !details.is_empty() {
+        txt += &format!(
+            "\n\n<details><summary>{num} more</summary>\n{list}\n\n</details>\n",
+            num = details.len(),
+            list = details.iter().map(|l| format!("\n{l}")).collect::<String>(),
+        );
+    }
+    if !dropped.is_empty() {
+        txt += &format!("\n* ... and {num} more", num = dropped.len());
+    }
+    txt
+}
+
+fn export_conflicts(
+    pull: &MetaPull,
+    pulls_conflict: &[(&MetaPull, Vec<String>)],
+) -> serde_json::Value {
+    serde_json::json!({
+        "head": pull.head_commit,
+        "conflicts": pulls_conflict
+            .iter()
+            .map(|(p, files)| serde_json::json!({
+                "repo": p.slug.str(),
+                "number": p.pull.number,
+                "head": p.head_commit,
+                "files": files,
+            }))
+            .collect::<Vec<_>>(),
+    })
+}
+
+/// Remember the pulls that conflict with the pull in the pull facts.
+fn record_conflicts(
+    facts: Option<&state::Store>,
+    pull: &MetaPull,
+    pulls_conflict: &[(&MetaPull, Vec<String>)],
+) {
+    let Some(facts) = facts else {
+        return;
+    };
+    let conflicts = pulls_conflict
+        .iter()
+        .map(|(p, _)| p.slug_num.clone())
+        .collect::<Vec<_>>();
+    facts
+        .update(|f| f.pull_mut(&pull.slug_num).conflicts = Some(conflicts))
+        .expect("pull facts file error");
+}
+
+/// Remember that the comments of all pulls of the repos were updated against the base commit.
+fn record_run(facts: Option<&state::Store>, slugs: &[util::Slug], base_id: &str) {
+    let Some(facts) = facts else {
+        return;
+    };
+    let run = state::ConflictsRun {
+        date: chrono::Utc::now(),
+        base: base_id.to_string(),
+    };
+    facts
+        .update(|f| {
+            for s in slugs {
+                f.conflicts_runs.insert(s.str(), run.clone());
+            }
+        })
+        .expect("pull facts file error");
+}
+
+struct ConflictsSection<'a> {
+    config: &'a Config,
+    pull: &'a MetaPull,
+    pulls_conflict: &'a [(&'a MetaPull, Vec<String>)],
+    base_id: &'a str,
+}
+
+impl util::Section for ConflictsSection<'_> {
+    fn id(&self) -> util::IdComment {
+        util::IdComment::SecConflicts
+    }
+
+    fn title(&self) -> Option<&str> {
+        Some(&self.config.conflicts_heading)
+    }
+
+    fn render(&self) -> Option<String> {
+        if self.pulls_conflict.is_empty() {
+            return Some(self.config.conflicts_empty.clone());
+        }
+        Some(
+            self.config.conflicts_description.replace(
+                "{conflicts}",
+                &render_conflicts(
+                    &self
+                        .pulls_conflict
+                        .iter()
+                        .map(|(p, files)| {
+                            format!(
+                                "* [#{sn}]({url}) ({title} by {user}){files}",
+                                sn = p.slug_num.trim_start_matches(&format!(
+                                    "{sl}/",
+                                    sl = self.pull.slug.str()
+                                )),
+                                url = p.pull.html_url.as_ref().expect("remote api error"),
+                                title = p.pull.title.as_ref().expect("remote api error").trim(),
+                                user = p.pull.user.as_ref().expect("remote api error").login,
+                                files = render_files(files),
+                            )
+                        })
+                        .collect::<Vec<_>>(),
+                    MAX_CONFLICTS_LEN,
+                ),
+            ),
+        )
+    }
+
+    fn base_commit(&self) -> Option<&str> {
+        Some(self.base_id)
+    }
+}
+
+async fn update_comment(
+    config: &Config,
+    api: &octocrab::Octocrab,
+    dry_run: bool,
+    pull: &MetaPull,
+    pulls_conflict: &[(&MetaPull, Vec<String>)],
+    base_id: &str,
+) -> octocrab::Result<()> {
+    let api_issues = api.issues(&pull.slug.owner, &pull.slug.repo);
+    let mut cmt = util::get_metadata_sections(api, &api_issues, pull.pull.number).await?;
+    if pulls_conflict.is_empty()
+        && (cmt.id.is_none() || !cmt.has_section(&util::IdComment::SecConflicts))
+    {
+        // No conflict and no section to update
+        return Ok(());
+    }
+    util::update_metadata_comment(
+        &api_issues,
+        &mut cmt,
+        &ConflictsSection {
+            config,
+            pull,
+            pulls_conflict,
+            base_id,
+        },
+        dry_run,
+    )
+    .await
+}
+
+/// Run the tool with the given command line arguments.
+pub async fn run(args: Args) -> octocrab::Result<()> {
+    util::init_logging(args.log_format);
+    if let Some(path) = &args.dry_run_output {
+        util::set_dry_run_output(path).expect("dry-run output file error");
+    }
+
+    let config: Config = serde_yaml::from_reader(
+        std::fs::File::open(args.config_file).expect("config file path error"),
+    )
+    .expect("yaml error");
+
+    let github = util::get_octocrab(args.github_access_token, args.github_cache_dir)?;
+    let export = args.export_dir.map(util::DataExport::new);
+    let facts = args.pull_facts_file.map(state::Store::new);
+
+    std::fs::create_dir_all(&args.scratch_dir).expect("invalid scratch_dir");
+    // Runs for a single pull (from the webhook server) and full runs (from cron) share the dir
+    let _lock =
+        util::lock_file(&args.scratch_dir.join("conflicts.lock"), true).expect("lock file error");
+
+    let monotree_dir = args
+        .scratch_dir
+        .canonicalize()
+        .expect("invalid scratch_dir")
+        .join(
+            args.github_repo
+                .iter()
+                .map(|s| format!("{}_{}", s.owner, s.repo))
+                .collect::<Vec<_>>()
+                .join("_"),
+        )
+        .join("persist");
+    let temp_dir = monotree_dir.parent().unwrap().join("temp");
+    std::fs::create_dir_all(&temp_dir).expect("invalid temp_dir");
+
+    init_git(&monotree_dir, &args.github_repo);
+
+    tracing::info!("Fetching diffs ...");
+    util::check_call(
+        util::git()
+            .current_dir(&monotree_dir)
+            .args(["fetch", "--quiet", "--all"]),
+    );
+
+    let mut base_names = Vec::new();
+    let mut pull_blobs = Vec::new();
+    for s in &args.github_repo {
+        let util::Slug { owner, repo } = s;
+        tracing::info!("Fetching open pulls for {sl} ...", sl = s.str());
+        let repos_api = github.repos(owner, repo);
+        let base_name = util::with_retry(|| repos_api.get())
+            .await?
+            .default_branch
+            .expect("remote api error");
+        let pulls_api = github.pulls(owner, repo);
+        let pulls = util::with_retry(|| async {
+            github
+                .all_pages(
+                    pulls_api
+                        .list()
+                        .state(octocrab::params::State::Open)
+                        .base(&base_name)
+                        .send()
+                        .await?,
+                )
+                .await
+        })
+        .await?;
+        tracing::info!(
+            "Open {base_name}-pulls for {sl}: {len}",
+            sl = s.str(),
+            len = pulls.len()
+        );
+        base_names.push(base_name);
+        pull_blobs.push((pulls, s));
+    }
+    let mut mono_pulls = Vec::new();
+    for (ps, slug) in pull_blobs {
+        let sl = slug.str();
+        tracing::info!("Store diffs for {sl}");
+        util::check_call(
+            util::git()
+                .current_dir(&monotree_dir)
+                .args(["fetch", "--quiet"])
+                .arg(format!("con_pull_ref/{sl}")),
+        );
+        for p in ps {
+            let num = p.number;
+            mono_pulls.push(MetaPull {
+                pull: p,
+                head_commit: util::check_output(
+                    util::git()
+                        .current_dir(&monotree_dir)
+                        .args(["log", "-1", "--format=%H"])
+                        .arg(format!("upstream-pull/{num}/head")),
+                ),
+                slug: util::Slug {
+                    owner: slug.owner.clone(),
+                    repo: slug.repo.clone(),
+                },
+                slug_num: format!("{sl}/{num}"),
+                merge_commit: None,
+            })
+        }
+    }
+    let base_name = base_names.first().expect("no repos given");
+    util::check_call(
+        util::git()
+            .current_dir(&monotree_dir)
+            .args(["fetch", "--quiet", "origin"])
+            .arg(base_name),
+    );
+
+    {
+        let temp_git_work_tree_ctx = tempfile::TempDir::new_in(&temp_dir).expect("tempdir error");
+        let temp_git_work_tree = temp_git_work_tree_ctx.path();
+
+        util::check_call(
+            std::process::Command::new("cp")
+                .arg("-r")
+                .arg(monotree_dir.join(".git"))
+                .arg(temp_git_work_tree.join(".git")),
+        );
+
+        // Each merge worker needs its own work tree, the first one being the temp work tree
+        let worktrees_ctx = tempfile::TempDir::new_in(&temp_dir).expect("tempdir error");
+        let mut work_trees = vec![temp_git_work_tree.to_path_buf()];
+        for i in 1..args.jobs {
+            let work_tree = worktrees_ctx.path().join(i.to_string());
+            util::check_call(
+                util::git()
+                    .current_dir(temp_git_work_tree)
+                    .args(["worktree", "add", "--detach", "--quiet"])
+                    .arg(&work_tree),
+            );
+            work_trees.push(work_tree);
+        }
+        tracing::info!("Calculate mergeable pulls");
+
+        let base_id = util::check_output(
+            util::git()
+                .current_dir(temp_git_work_tree)
+                .args(["log", "-1", "--format=%H"])
+                .arg(format!("origin/{base_name}")),
+        );
+        let mono_pulls_mergeable = calc_mergeable(temp_git_work_tree, mono_pulls, &base_id);
+        let mut cache =
+            cache::ConflictCache::load(monotree_dir.parent().unwrap().join("conflicts_cache.json"));
+        let open_heads = mono_pulls_mergeable
+            .iter()
+            .map(|p| &p.head_commit[..])
+            .collect::<std::collections::HashSet<_>>();
+        if args.update_comments {
+            // Most recently updated first, as those are the most likely to be looked at
+            let mut sweep = mono_pulls_mergeable.iter().collect::<Vec<_>>();
+            sweep.sort_by_key(|p| std::cmp::Reverse(p.pull.updated_at));
+            let checkpoint = util::Checkpoint::new(
+                monotree_dir
+                    .parent()
+                    .unwrap()
+                    .join("update_comments.checkpoint"),
+            );
+            let skip = if args.budget_minutes.is_some() {
+                0
+            } else {
+                checkpoint.skip_count(sweep.iter().map(|p| &p.slug_num[..]))
+            };
+            if skip > 0 {
+                tracing::info!("Resume after {skip} pulls from the previous run");
+            }
+            let api_start = util::get_rate_limit_remaining(&github).await?;
+            let mut progress = util::Progress::new(
+                sweep.len() - skip,
+                util::Progress::deadline(args.max_runtime.or(args.budget_minutes)),
+            );
+            let mut last_done = None;
+            let mut interrupted = false;
+            for pull_update in sweep.iter().skip(skip) {
+                if progress.out_of_time() {
+                    interrupted = true;
+                    break;
+                }
+                progress.println(&format!(
+                    "Checking for conflicts {base_name} <> {pr_id} <> other_pulls ... ",
+                    pr_id = pull_update.slug_num
+                ));
+                let pulls_conflict = calc_conflicts(
+                    &mono_pulls_mergeable,
+                    pull_update,
+                    &base_id,
+                    &mut cache,
+                    &work_trees,
+                );
+                update_comment(
+                    &config,
+                    &github,
+                    args.dry_run,
+                    pull_update,
+                    &pulls_conflict,
+                    &base_id,
+                )
+                .await?;
+                record_conflicts(facts.as_ref(), pull_update, &pulls_conflict);
+                if let Some(export) = &export {
+                    export.write(
+                        &pull_update.slug.owner,
+                        &pull_update.slug.repo,
+                        pull_update.pull.number,
+                        "conflicts",
+                        &export_conflicts(pull_update, &pulls_conflict),
+                    );
+                }
+                last_done = Some(&pull_update.slug_num);
+                progress.set_api_calls(
+                    api_start.saturating_sub(util::get_rate_limit_remaining(&github).await?),
+                );
+                progress.inc(&pull_update.slug_num);
+            }
+            progress.finish();
+            if !interrupted || args.budget_minutes.is_some() {
+                if interrupted {
+                    tracing::info!(
+                        "Budget used up, the remaining pulls are updated on a later run"
+                    );
+                }
+                checkpoint.clear();
+            } else if let Some(last) = last_done {
+                tracing::info!("Max runtime reached, saving checkpoint after {last}");
+                checkpoint.save(last);
+            }
+            // Only a sweep over all pulls in this run updated them against the base commit
+            if !interrupted && skip == 0 {
+                record_run(facts.as_ref(), &args.github_repo, &base_id);
+            }
+            cache.save(&base_id, &open_heads);
+        }
+        if let Some(pull_id) = args.pull_id {
+            let found = mono_pulls_mergeable.iter().find(|p| p.slug_num == pull_id);
+            if found.is_none() {
+                tracing::info!(
+                    "{id} not found in all {len} open, mergeable {base_name} pulls",
+                    id = pull_id,
+                    len = mono_pulls_mergeable.len()
+                );
+                return Ok(());
+            }
+            let pull_merge = found.unwrap();
+            tracing::info!(
+                "Checking for conflicts {base_name} <> {id} <> other_pulls ... ",
+                id = pull_merge.slug_num
+            );
+            let conflicts = calc_conflicts(
+                &mono_pulls_mergeable,
+                pull_merge,
+                &base_id,
+                &mut cache,
+                &work_trees,
+            );
+            cache.save(&base_id, &open_heads);
+            update_comment(
+                &config,
+                &github,
+                args.dry_run,
+                pull_merge,
+                &conflicts,
+                &base_id,
+            )
+            .await?;
+            record_conflicts(facts.as_ref(), pull_merge, &conflicts);
+        }
+    }
+    if let Some(export) = &export {
+        if !args.dry_run {
+            export.publish("Update conflicts data");
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(n: usize) -> Vec<String> {
+        (0..n)
+            .map(|i| format!("* [#{i}](url) (title by user)"))
+            .collect()
+    }
+
+    #[test]
+    fn test_render_conflicts() {
+        assert_eq!(render_conflicts(&[], MAX_CONFLICTS_LEN), "");
+        assert_eq!(
+            render_conflicts(&lines(2), MAX_CONFLICTS_LEN),
+            "\n* [#0](url) (title by user)\n* [#1](url) (title by user)"
+        );
+        let all = lines(MAX_CONFLICTS_INLINE);
+        assert!(!render_conflicts(&all, MAX_CONFLICTS_LEN).contains("<details>"));
+
+        let all = lines(MAX_CONFLICTS_INLINE + 2);
+        let txt = render_conflicts(&all, MAX_CONFLICTS_LEN);
+        assert!(txt.contains("<details><summary>2 more</summary>"));
+        assert!(txt.contains(&all[MAX_CONFLICTS_INLINE + 1]));
+        assert!(!txt.contains("and"));
+    }
+
+    #[test]
+    fn test_render_files() {
+        assert_eq!(render_files(&[]), "");
+        assert_eq!(
+            render_files(&["src/init.cpp".to_string()]),
+            "\n  <details><summary>1 conflicting file</summary>\n\n  `src/init.cpp`\n  </details>"
+        );
+        let many = (0..MAX_FILES + 3)
+            .map(|i| format!("f{i}"))
+            .collect::<Vec<_>>();
+        let txt = render_files(&many);
+        assert!(txt.contains("<summary>23 conflicting files</summary>"));
+        assert!(txt.contains("`f19` and 3 more"));
+    }
+
+    #[test]
+    fn test_render_conflicts_truncated() {
+        let all = lines(5_000);
+        let txt = render_conflicts(&all, MAX_CONFLICTS_LEN);
+        assert!(txt.len() <= MAX_CONFLICTS_LEN);
+        assert!(txt.ends_with(" more"));
+        assert!(txt.contains("<details>"));
+
+        // Exactly at the boundary, nothing is dropped
+        let all = lines(MAX_CONFLICTS_INLINE);
+        let full = render_conflicts(&all, MAX_CONFLICTS_LEN);
+        assert_eq!(render_conflicts(&all, full.len() + 100), full);
+        let txt = render_conflicts(&all, full.len() + 99);
+        assert!(txt.ends_with("\n* ... and 1 more"));
+        assert!(txt.len() <= full.len() + 99);
+    }
+
+    fn meta_pull(number: u64, title: &str, user: &str) -> MetaPull {
+        let url = format!("https://github.com/{user}");
+        let author = serde_json::json!({
+            "login": user, "id": 1, "node_id": "", "avatar_url": url, "gravatar_id": "",
+            "url": url, "html_url": url, "followers_url": url, "following_url": url,
+            "gists_url": url, "starred_url": url, "subscriptions_url": url,
+            "organizations_url": url, "repos_url": url, "events_url": url,
+            "received_events_url": url, "type": "User", "site_admin": false,
+            "patch_url": null,
+        });
+        let pull = serde_json::from_value(serde_json::json!({
+            "url": "", "id": number, "number": number, "locked": false,
+            "maintainer_can_modify": false, "title": title, "user": author,
+            "html_url": format!("https://github.com/bitcoin/bitcoin/pull/{number}"),
+            "head": {"ref": "branch", "sha": "aa"}, "base": {"ref": "master", "sha": "bb"},
+        }))
+        .unwrap();
+        MetaPull {
+            pull,
+            head_commit: "aa".to_string(),
+            slug: "bitcoin/bitcoin".parse().unwrap(),
+            slug_num: format!("bitcoin/bitcoin/{number}"),
+            merge_commit: None,
+        }
+    }
+
+    #[test]
+    fn test_section_snapshots() {
+        let config: Config = serde_yaml::from_str(include_str!("../config.yml")).unwrap();
+        let pull = meta_pull(1, "wallet: Add feature", "alice");
+        let others = (2..30)
+            .map(|n| meta_pull(n, &format!("refactor: Change {n} "), "bob"))
+            .collect::<Vec<_>>();
+        let render = |pulls_conflict: &[(&MetaPull, Vec<String>)]| {
+            util::SECTION_REGISTRY.render(&ConflictsSection {
+                config: &config,
+                pull: &pull,
+                pulls_conflict,
+                base_id: "bb",
+            })
+        };
+        insta::assert_snapshot!("no_conflicts", render(&[]));
+        insta::assert_snapshot!(
+            "one_conflict",
+            render(&[(&others[0], vec!["src/wallet/wallet.cpp".to_string()])])
+        );
+        let files = (0..MAX_FILES + 2)
+            .map(|i| format!("src/file_{i}.cpp"))
+            .collect::<Vec<_>>();
+        insta::assert_snapshot!(
+            "many_conflicts",
+            render(
+                &others
+                    .iter()
+                    .enumerate()
+                    .map(|(i, p)| (p, files[..i % files.len()].to_vec()))
+                    .collect::<Vec<_>>()
+            )
+        );
+    }
+}
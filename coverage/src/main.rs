@@ -1,14 +1,118 @@
+mod regressions;
+mod targets;
+
 use clap::Parser;
-use util::{chdir, check_call, check_output, git};
+use util::{chdir, check_call, check_output, git, Container};
+
+// Use "devel" once and if https://github.com/bitcoin/bitcoin/issues/28468#issuecomment-1790901853 is fixed
+const DOCKER_IMAGE: &str = "ubuntu:lunar";
+const CODE_REPO: &str = "bitcoin/bitcoin";
+
+/// Which coverage reports to generate.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Mode {
+    /// The unit and functional tests (make cov).
+    Unit,
+    /// The fuzz targets on the qa-assets corpus (make cov_fuzz).
+    Fuzz,
+    Both,
+}
+
+impl std::str::FromStr for Mode {
+    type Err = &'static str;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "unit" => Ok(Self::Unit),
+            "fuzz" => Ok(Self::Fuzz),
+            "both" => Ok(Self::Both),
+            _ => Err("Wrong mode, expected 'unit', 'fuzz' or 'both'."),
+        }
+    }
+}
+
+impl Mode {
+    fn kinds(self) -> &'static [Kind] {
+        match self {
+            Self::Unit => &[Kind::Unit],
+            Self::Fuzz => &[Kind::Fuzz],
+            Self::Both => &[Kind::Unit, Kind::Fuzz],
+        }
+    }
+}
+
+/// A single coverage report.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Kind {
+    Unit,
+    Fuzz,
+}
+
+impl Kind {
+    /// The folder of the results in the reports repo.
+    fn folder(self) -> &'static str {
+        match self {
+            Self::Unit => "coverage",
+            Self::Fuzz => "coverage_fuzz",
+        }
+    }
+
+    /// The lcov tracefile, as generated by make.
+    fn tracefile(self) -> &'static str {
+        match self {
+            Self::Unit => "total_coverage.info",
+            Self::Fuzz => "fuzz_coverage.info",
+        }
+    }
+
+    /// The url of the html report of the result.
+    fn report_url(self, remote_url: &str, result_ref: &str) -> String {
+        let html = match self {
+            Self::Unit => "total.coverage",
+            Self::Fuzz => "fuzz.coverage",
+        };
+        format!(
+            "{remote_url}/{folder}/monotree/{result_ref}/{html}/index.html",
+            folder = self.folder()
+        )
+    }
+
+    /// The section of the metadata comment for the coverage of the added lines of a pull request.
+    fn section(self) -> util::IdComment {
+        match self {
+            Self::Unit => util::IdComment::SecCoverage,
+            Self::Fuzz => util::IdComment::SecFuzzCoverage,
+        }
+    }
+}
+
+impl std::fmt::Display for Kind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Unit => "coverage",
+            Self::Fuzz => "fuzz coverage",
+        })
+    }
+}
+
+/// The local git checkouts.
+struct Dirs {
+    code: std::path::PathBuf,
+    report: std::path::PathBuf,
+    assets: std::path::PathBuf,
+}
 
 fn gen_coverage(
-    docker_exec: &dyn Fn(&str),
-    dir_code: &std::path::Path,
+    kind: Kind,
+    container: &Container,
+    args: &Args,
+    dirs: &Dirs,
     dir_result: &std::path::Path,
     git_ref: &str,
-    make_jobs: u8,
+    manifest: util::ResultManifest,
 ) {
-    println!(
+    let dir_code = &dirs.code;
+    let start = std::time::Instant::now();
+    tracing::info!(
         "Generate coverage for {} in {} (ref: {}).",
         dir_code.display(),
         dir_result.display(),
@@ -17,118 +121,285 @@ fn gen_coverage(
     chdir(dir_code);
     let dir_build = dir_code.join("build");
 
-    println!("Clear previous build and result folders");
-
-    let clear_dir = |folder: &std::path::Path| {
-        std::fs::create_dir_all(folder).expect("Failed to create a folder");
-        docker_exec(&format!("rm -r {}", folder.display()));
-        std::fs::create_dir_all(folder).expect("Failed to create a folder");
-        // Must change to a dir that exists after this function call
-    };
-
-    clear_dir(&dir_build);
-    clear_dir(dir_result);
+    tracing::info!("Clear previous build and result folders");
+    container.clear_dir(&dir_build);
+    container.clear_dir(dir_result);
 
-    println!("Make coverage data in docker ...");
+    tracing::info!("Make coverage data in docker ...");
     chdir(dir_code);
-    docker_exec("./autogen.sh");
+    container.exec("./autogen.sh");
     chdir(&dir_build);
 
-    docker_exec("../configure --enable-zmq --with-incompatible-bdb --enable-lcov --enable-lcov-branch-coverage CC=clang CXX=clang++");
-    docker_exec(&format!("make -j{}", make_jobs));
+    match kind {
+        Kind::Unit => container.exec("../configure --enable-zmq --with-incompatible-bdb --enable-lcov --enable-lcov-branch-coverage CC=clang CXX=clang++"),
+        Kind::Fuzz => container.exec("../configure --enable-fuzz --with-sanitizers=fuzzer --enable-lcov --enable-lcov-branch-coverage CC=clang CXX=clang++"),
+    }
+    container.exec(&format!("make -j{}", args.make_jobs));
 
-    println!("Make coverage ...");
-    docker_exec("make cov");
-    docker_exec(&format!(
+    tracing::info!("Make coverage ...");
+    match kind {
+        Kind::Unit => container.exec("make cov"),
+        Kind::Fuzz => container.exec(&format!(
+            "make cov_fuzz DIR_FUZZ_SEED_CORPUS={}/fuzz_seed_corpus",
+            dirs.assets.display()
+        )),
+    }
+    container.exec(&format!(
         "mv {}/*coverage* {}/",
         dir_build.display(),
         dir_result.display()
     ));
+    let commit = manifest.commit.clone();
+    let mut targets = None;
+    if kind == Kind::Fuzz && args.per_target_pages {
+        tracing::info!("Make coverage per fuzz target ...");
+        targets = Some(targets::gen_target_pages(
+            container,
+            &dirs.assets.join("fuzz_seed_corpus"),
+            dir_code,
+            &dir_result.join("targets"),
+            &args.fuzz_targets,
+            git_ref,
+        ));
+    }
+    publish(
+        container,
+        dir_result,
+        &format!("Add {kind} results for {git_ref}"),
+        util::ResultManifest {
+            duration: start.elapsed(),
+            ..manifest
+        },
+        args.dry_run,
+    );
+    // Only runs on the main branch make up the trend, not runs on pull requests
+    if let (Some(targets), None) = (targets, args.pull_number) {
+        targets::update_trend(
+            &dirs.report.join(kind.folder()),
+            targets::TrendEntry {
+                date: chrono::Utc::now().date_naive(),
+                code_commit: commit,
+                assets_commit: targets::assets_commit(&dirs.assets),
+                targets,
+            },
+            args.regressions_threshold,
+        );
+    }
+}
+
+/// Commit the results to the reports repo and push them.
+fn publish(
+    container: &Container,
+    dir_result: &std::path::Path,
+    message: &str,
+    manifest: util::ResultManifest,
+    dry_run: bool,
+) {
+    manifest.write(dir_result);
+    if dry_run {
+        tracing::info!("Dry run: Skip the commit '{message}' to the reports repo");
+        return;
+    }
     chdir(dir_result);
     check_call(git().args(["checkout", "main"]));
     check_call(git().args(["add", "./"]));
-    check_call(git().args([
-        "commit",
-        "-m",
-        &format!("Add coverage results for {}", git_ref),
-    ]));
+    check_call(git().args(["commit", "-m", message]));
     check_call(git().args(["push", "origin", "main"]));
 
     // Work around permission errors
-    clear_dir(dir_result);
+    container.clear_dir(dir_result);
     chdir(dir_result);
     check_call(git().args(["reset", "--hard", "HEAD"]));
 }
 
-fn calc_coverage(
-    dir_code: &std::path::Path,
-    dir_cov_report: &std::path::Path,
-    make_jobs: u8,
-    remote_url: &str,
-) {
-    println!("Start docker process ...");
-    std::fs::create_dir_all(dir_cov_report).expect("Failed to create dir_cov_report");
-    let docker_id = check_output(std::process::Command::new("podman").args([
-        "run",
-        "-idt",
-        "--rm",
-        &format!(
-            "--volume={}:{}:rw,z",
-            dir_code.display(),
-            dir_code.display()
+/// The versions of the tools in the container that affect the coverage results.
+fn toolchain(container: &Container) -> Vec<(String, String)> {
+    vec![
+        ("image".to_string(), DOCKER_IMAGE.to_string()),
+        (
+            "clang".to_string(),
+            container.output("clang --version | head -1"),
         ),
-        &format!(
-            "--volume={}:{}:rw,z",
-            dir_cov_report.display(),
-            dir_cov_report.display()
+        (
+            "lcov".to_string(),
+            container.output("lcov --version | head -1"),
         ),
-        //'--mount', # Doesn't work with fedora (needs rw,z)
-        //'type=bind,src={},dst={}'.format(dir_code, dir_code),
-        //'--mount',
-        //'type=bind,src={},dst={}'.format(dir_cov_report, dir_cov_report),
-        "-e",
-        "LC_ALL=C.UTF-8",
-        "ubuntu:lunar", // Use "devel" once and if https://github.com/bitcoin/bitcoin/issues/28468#issuecomment-1790901853 is fixed
-    ]));
+    ]
+}
 
-    let docker_exec = |cmd: &str| {
-        check_call(std::process::Command::new("podman").args([
-            "exec",
-            &docker_id,
-            "bash",
-            "-c",
-            &format!(
-                "cd {} && {}",
-                std::env::current_dir().expect("Failed to getcwd").display(),
-                cmd
-            ),
-        ]))
-    };
+/// Start the container with the checkouts mounted, and install the build dependencies.
+fn start_container(args: &Args, dirs: &Dirs) -> Container {
+    let dir_reports = args
+        .mode
+        .kinds()
+        .iter()
+        .map(|k| dirs.report.join(k.folder()).join("monotree"))
+        .collect::<Vec<_>>();
+    let mut volumes = vec![dirs.code.as_path()];
+    volumes.extend(dir_reports.iter().map(|d| d.as_path()));
+    if args.mode.kinds().contains(&Kind::Fuzz) {
+        volumes.push(&dirs.assets);
+    }
+    for dir in &dir_reports {
+        std::fs::create_dir_all(dir).expect("Failed to create dir_cov_report");
+    }
+    let container = Container::run(args.container_runtime, DOCKER_IMAGE, &volumes);
 
-    println!("Docker running with id {}.", docker_id);
+    tracing::info!("Installing packages ...");
+    container.exec("apt-get update");
+    container.exec(&format!("apt-get install -qq {}", "clang llvm ccache python3-zmq libsqlite3-dev libevent-dev libboost-dev libdb5.3++-dev libminiupnpc-dev libzmq3-dev lcov build-essential libtool autotools-dev automake pkg-config bsdmainutils"));
+    container
+}
 
-    println!("Installing packages ...");
-    docker_exec("apt-get update");
-    docker_exec(&format!("apt-get install -qq {}", "clang llvm ccache python3-zmq libsqlite3-dev libevent-dev libboost-dev libdb5.3++-dev libminiupnpc-dev libzmq3-dev lcov build-essential libtool autotools-dev automake pkg-config bsdmainutils"));
+/// Generate the coverage for the checked out commit, and return the git ref of the result (the
+/// code commit, and the qa-assets commit for fuzz coverage) and the result folder.
+fn calc_coverage(
+    kind: Kind,
+    container: &Container,
+    args: &Args,
+    dirs: &Dirs,
+) -> (String, std::path::PathBuf) {
+    tracing::info!("Generate {kind}");
+    chdir(&dirs.code);
+    let base_commit = check_output(git().args(["log", "--format=%H", "-1", "HEAD"]));
+    let base_git_ref = &base_commit[..16];
+    let mut toolchain = toolchain(container);
+    let (result_ref, git_ref) = match kind {
+        Kind::Unit => (base_git_ref.to_string(), format!("{base_git_ref}-code")),
+        Kind::Fuzz => {
+            chdir(&dirs.assets);
+            let assets_commit = check_output(git().args(["log", "--format=%H", "-1", "HEAD"]));
+            let assets_git_ref = &assets_commit[..16];
+            toolchain.push(("qa-assets".to_string(), assets_commit.clone()));
+            (
+                format!("{base_git_ref}/{assets_git_ref}"),
+                format!("{base_git_ref}-code {assets_git_ref}-assets"),
+            )
+        }
+    };
+    let dir_result = dirs
+        .report
+        .join(kind.folder())
+        .join("monotree")
+        .join(&result_ref);
+    let report_url = kind.report_url(&args.remote_url, &result_ref);
+    let mut manifest = util::ResultManifest {
+        commit: base_commit.clone(),
+        duration: std::time::Duration::ZERO,
+        toolchain,
+        links: vec![("report".to_string(), report_url.clone())],
+    };
+    if kind == Kind::Fuzz && args.per_target_pages {
+        manifest.links.push((
+            "targets".to_string(),
+            format!(
+                "{}/{}/monotree/{result_ref}/targets/index.html",
+                args.remote_url,
+                kind.folder()
+            ),
+        ));
+    }
+    gen_coverage(kind, container, args, dirs, &dir_result, &git_ref, manifest);
 
-    println!("Generate coverage");
+    if let Some(store) = args.artifact_store.as_ref().filter(|_| !args.dry_run) {
+        store.publish(
+            &dir_result,
+            &format!(
+                "host_reports/{}/{}/monotree/{result_ref}",
+                args.repo_report.str(),
+                kind.folder()
+            ),
+            &[],
+        );
+    }
+    tracing::info!("{report_url}");
+    (result_ref, dir_result)
+}
+
+/// Check out the commit in the code dir, and only run the selected fuzz targets.
+fn checkout(dir_code: &std::path::Path, commit: &str, fuzz_targets: &str) {
     chdir(dir_code);
-    let base_git_ref = &check_output(git().args(["log", "--format=%H", "-1", "HEAD"]))[..16];
-    let dir_result_base = dir_cov_report.join(base_git_ref);
-    gen_coverage(
-        &docker_exec,
-        dir_code,
-        &dir_result_base,
-        &format!("{base_git_ref}-code"),
-        make_jobs,
-    );
+    check_call(git().args(["checkout", commit, "--force"]));
+    check_call(git().args(["clean", "-dfx"]));
+    if !fuzz_targets.is_empty() {
+        check_call(std::process::Command::new("sed").args([
+            "-i",
+            &format!(
+                "s/DIR_FUZZ_SEED_CORPUS) -l DEBUG/DIR_FUZZ_SEED_CORPUS) {fuzz_targets} -l DEBUG/g"
+            ),
+            "Makefile.am",
+        ]));
+    }
+}
 
-    println!("{remote_url}/coverage/monotree/{base_git_ref}/total.coverage/index.html");
+/// Read the lcov tracefile of the result.
+fn read_tracefile(kind: Kind, dir_result: &std::path::Path) -> String {
+    std::fs::read_to_string(dir_result.join(kind.tracefile()))
+        .expect("Failed to read lcov tracefile")
+}
+
+/// Generate the coverage for the checked out merge commit of the pull request and for its first
+/// parent, and show the coverage of the added lines in the metadata comment.
+async fn pull_coverage(
+    github: &octocrab::Octocrab,
+    container: &Container,
+    args: &Args,
+    dirs: &Dirs,
+    number: u64,
+) -> octocrab::Result<()> {
+    chdir(&dirs.code);
+    let head = check_output(git().args(["rev-parse", "HEAD"]));
+    let base = check_output(git().args(["rev-parse", "HEAD^1"]));
+    let pull_head = check_output(git().args(["rev-parse", "HEAD^2"]));
+    let kinds = args.mode.kinds();
+    // The result ref and the line coverage of each kind, for the base and the head commit
+    let mut coverage = Vec::new();
+    for commit in [&base, &head] {
+        checkout(&dirs.code, commit, &args.fuzz_targets);
+        let mut results = Vec::new();
+        for kind in kinds {
+            let (result_ref, dir_result) = calc_coverage(*kind, container, args, dirs);
+            let lines = util::parse_lcov_lines(
+                &read_tracefile(*kind, &dir_result),
+                &dirs.code.display().to_string(),
+            );
+            results.push((result_ref, lines));
+        }
+        coverage.push(results);
+    }
+    chdir(&dirs.code);
+    let added = util::added_lines(&check_output(git().args([
+        "diff",
+        "--unified=0",
+        &base,
+        &head,
+    ])));
+    let slug = CODE_REPO.parse::<util::Slug>().unwrap();
+    let issues_api = github.issues(&slug.owner, &slug.repo);
+    let mut cmt = util::get_metadata_sections(github, &issues_api, number).await?;
+    for (i, kind) in kinds.iter().enumerate() {
+        let (head_ref, head_lines) = &coverage[1][i];
+        let files = util::diff_coverage(&coverage[0][i].1, head_lines, &added);
+        tracing::info!("Update the {kind} of {number} ({} files)", files.len());
+        let section = util::DiffCoverageSection {
+            id: kind.section(),
+            files: &files,
+            merge_commit: &head,
+            head_commit: &pull_head,
+            report_url: &kind.report_url(&args.remote_url, head_ref),
+        };
+        util::update_metadata_comment(&issues_api, &mut cmt, &section, args.dry_run).await?;
+    }
+    Ok(())
 }
 
 #[derive(clap::Parser)]
 #[command(about = "Run coverage reports.", long_about = None)]
 struct Args {
+    /// Which coverage to generate: unit (the unit and functional tests), fuzz (the fuzz targets on
+    /// the qa-assets corpus) or both.
+    #[arg(long, default_value = "unit")]
+    mode: Mode,
     /// The repo slug of the remote on GitHub for reports.
     #[arg(long, default_value = "DrahtBot/reports")]
     repo_report: util::Slug,
@@ -138,6 +409,13 @@ struct Args {
         default_value = "https://drahtbot.space/host_reports/DrahtBot/reports"
     )]
     remote_url: String,
+    /// Publish the results to this artifact store directly, instead of waiting for host_reports
+    /// to pull them. Format: /local/dir, ssh://user@host:/dir or s3://bucket/prefix
+    #[arg(long)]
+    artifact_store: Option<util::ArtifactStore>,
+    /// The tool to run the build container with: docker or podman.
+    #[arg(long, default_value = "podman")]
+    container_runtime: util::ContainerRuntime,
     /// The number of make jobs.
     #[arg(long, default_value_t = 2)]
     make_jobs: u8,
@@ -147,20 +425,61 @@ struct Args {
     /// The ssh key for "repo_report".
     #[arg(long)]
     ssh_key: std::path::PathBuf,
-    /// Generate the coverage for this commit and exit.
+    /// Which git ref in the code repo to build.
+    #[arg(long, alias = "commit-only", default_value = "master")]
+    git_ref_code: String,
+    /// Generate the coverage for the merge commit of this pull request and its base commit, and
+    /// show the coverage of the added lines in the metadata comment of the pull request.
+    #[arg(long, conflicts_with = "git_ref_code")]
+    pull_number: Option<u64>,
+    /// Print changes/edits instead of calling the GitHub API, and do not publish the results.
+    #[arg(long, default_value_t = false)]
+    dry_run: bool,
+    /// The access token for GitHub.
+    #[arg(long)]
+    github_access_token: Option<String>,
+    /// Cache the GET responses of the GitHub API in this dir, and only revalidate them on later
+    /// runs. May be shared with the other tools.
     #[arg(long)]
-    commit_only: String,
+    github_cache_dir: Option<std::path::PathBuf>,
+    /// Which git ref in the qa-assets repo to use for fuzz coverage.
+    #[arg(long, default_value = "main")]
+    git_ref_qa_assets: String,
+    /// Which fuzz targets to run (all targets, if empty).
+    #[arg(long, default_value = "")]
+    fuzz_targets: String,
+    /// Also generate a coverage page for each fuzz target, from running only that target on its
+    /// corpus. This runs every target once more. Outside of pull requests, the coverage of each
+    /// target is also tracked over time in coverage_fuzz/targets_trend.md of the reports repo.
+    #[arg(long, default_value_t = false)]
+    per_target_pages: bool,
+    /// Compare the unit coverage per directory against an older snapshot and track regressions in
+    /// an issue in this repo. Format: owner/repo
+    #[arg(long)]
+    regressions_repo: Option<util::Slug>,
+    /// The age of the snapshot to compare against.
+    #[arg(long, default_value_t = 7)]
+    regressions_days: i64,
+    /// The drop in percentage points that is considered a regression, for a directory or for a
+    /// fuzz target.
+    #[arg(long, default_value_t = 1.0)]
+    regressions_threshold: f64,
+    /// The format of the log output: pretty or json.
+    #[arg(long, default_value = "pretty")]
+    log_format: util::LogFormat,
 }
 
 fn ensure_init_git(folder: &std::path::Path, url: &str) {
-    println!("Clone {url} repo to {dir}", dir = folder.display());
+    tracing::info!("Clone {url} repo to {dir}", dir = folder.display());
     if !folder.is_dir() {
         check_call(git().args(["clone", "--quiet", url]).arg(folder));
     }
 }
 
-fn main() {
+#[tokio::main]
+async fn main() -> octocrab::Result<()> {
     let args = Args::parse();
+    util::init_logging(args.log_format);
 
     std::fs::create_dir_all(&args.scratch_dir).expect("Failed to create scratch folder");
     let temp_dir = args
@@ -175,16 +494,24 @@ fn main() {
             .display()
     );
 
-    let code_dir = temp_dir.join("code").join("monotree");
-    let code_url = "https://github.com/bitcoin/bitcoin";
-    let report_dir = temp_dir.join("reports");
+    let dirs = Dirs {
+        code: temp_dir.join("code").join("monotree"),
+        report: temp_dir.join("reports"),
+        assets: temp_dir.join("assets"),
+    };
+    let code_url = format!("https://github.com/{CODE_REPO}");
     let report_url = format!("git@github.com:{}.git", args.repo_report.str());
+    let assets_url = "https://github.com/bitcoin-core/qa-assets";
+    let fuzz = args.mode.kinds().contains(&Kind::Fuzz);
 
-    ensure_init_git(&code_dir, code_url);
-    ensure_init_git(&report_dir, &report_url);
+    ensure_init_git(&dirs.code, &code_url);
+    ensure_init_git(&dirs.report, &report_url);
+    if fuzz {
+        ensure_init_git(&dirs.assets, assets_url);
+    }
 
-    println!("Set git metadata");
-    chdir(&report_dir);
+    tracing::info!("Set git metadata");
+    chdir(&dirs.report);
     check_call(git().args([
         "config",
         "user.email",
@@ -193,22 +520,96 @@ fn main() {
     check_call(git().args(["config", "user.name", "DrahtBot"]));
     check_call(git().args(["config", "core.sshCommand", &ssh_cmd]));
 
-    println!("Fetching diffs ...");
-    chdir(&code_dir);
-    check_call(git().args(["fetch", "origin", "--quiet", &args.commit_only]));
-    check_call(git().args(["checkout", "FETCH_HEAD", "--force"]));
+    tracing::info!("Fetching diffs ...");
+    chdir(&dirs.code);
+    let code_ref = match args.pull_number {
+        Some(number) => format!("pull/{number}/merge"),
+        None => args.git_ref_code.clone(),
+    };
+    check_call(git().args(["fetch", "origin", "--quiet", &code_ref]));
     check_call(git().args(["reset", "--hard", "HEAD"]));
-    check_call(git().args(["clean", "-dfx"]));
-    chdir(&report_dir);
+    checkout(&dirs.code, "FETCH_HEAD", &args.fuzz_targets);
+    chdir(&dirs.report);
     check_call(git().args(["fetch", "--quiet", "--all"]));
     check_call(git().args(["reset", "--hard", "HEAD"]));
     check_call(git().args(["checkout", "main"]));
     check_call(git().args(["reset", "--hard", "origin/main"]));
+    if fuzz {
+        chdir(&dirs.assets);
+        check_call(git().args(["fetch", "origin", "--quiet", &args.git_ref_qa_assets]));
+        check_call(git().args(["checkout", "FETCH_HEAD", "--force"]));
+        check_call(git().args(["clean", "-dfx"]));
+    }
 
-    calc_coverage(
-        &code_dir,
-        &report_dir.join("coverage").join("monotree"),
-        args.make_jobs,
-        &args.remote_url,
-    );
+    let container = start_container(&args, &dirs);
+
+    if let Some(number) = args.pull_number {
+        let github = util::get_octocrab(
+            args.github_access_token.clone(),
+            args.github_cache_dir.clone(),
+        )?;
+        return pull_coverage(&github, &container, &args, &dirs, number).await;
+    }
+
+    for kind in args.mode.kinds() {
+        let (commit, dir_result) = calc_coverage(*kind, &container, &args, &dirs);
+        if *kind != Kind::Unit {
+            continue;
+        }
+        let Some(slug) = &args.regressions_repo else {
+            continue;
+        };
+        let new = regressions::Snapshot {
+            commit,
+            date: chrono::Utc::now().date_naive(),
+            dirs: regressions::parse_lcov(
+                &read_tracefile(*kind, &dir_result),
+                &dirs.code.display().to_string(),
+            ),
+        };
+        let dir_snapshots = dirs.report.join("coverage").join("snapshots");
+        let old = regressions::store_snapshot(&dir_snapshots, &new, args.regressions_days);
+        regressions::push_snapshots(&dir_snapshots, new.date);
+        match old {
+            None => tracing::info!("No snapshot old enough to compare against."),
+            Some(old) => {
+                let github = util::get_octocrab(
+                    args.github_access_token.clone(),
+                    args.github_cache_dir.clone(),
+                )?;
+                regressions::update_issue(
+                    &github,
+                    slug,
+                    &dirs.code,
+                    &old,
+                    &new,
+                    args.regressions_threshold,
+                )
+                .await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mode() {
+        assert_eq!(
+            "both".parse::<Mode>().unwrap().kinds(),
+            [Kind::Unit, Kind::Fuzz]
+        );
+        assert!("all".parse::<Mode>().is_err());
+        assert_eq!(
+            Kind::Fuzz.report_url("https://r", "aa/bb"),
+            "https://r/coverage_fuzz/monotree/aa/bb/fuzz.coverage/index.html"
+        );
+        assert_eq!(
+            Kind::Unit.report_url("https://r", "aa"),
+            "https://r/coverage/monotree/aa/total.coverage/index.html"
+        );
+    }
 }
@@ -0,0 +1,212 @@
+use std::collections::BTreeMap;
+
+use util::{call, check_call, check_output, git};
+
+/// Lines found and lines hit, per directory.
+pub type DirCoverage = BTreeMap<String, (u64, u64)>;
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct Snapshot {
+    pub commit: String,
+    pub date: chrono::NaiveDate,
+    pub dirs: DirCoverage,
+}
+
+/// Sum up the line coverage of an lcov tracefile per directory of the source files.
+pub fn parse_lcov(info: &str, dir_code: &str) -> DirCoverage {
+    let mut dirs = DirCoverage::new();
+    let mut dir = String::new();
+    for line in info.lines() {
+        if let Some(file) = line.strip_prefix("SF:") {
+            let file = file.trim_start_matches(dir_code).trim_start_matches('/');
+            dir = std::path::Path::new(file)
+                .parent()
+                .map(|p| p.display().to_string())
+                .unwrap_or_default();
+        } else if let Some(found) = line.strip_prefix("LF:") {
+            dirs.entry(dir.clone()).or_default().0 += found.parse::<u64>().unwrap_or(0);
+        } else if let Some(hit) = line.strip_prefix("LH:") {
+            dirs.entry(dir.clone()).or_default().1 += hit.parse::<u64>().unwrap_or(0);
+        }
+    }
+    dirs
+}
+
+fn percent((found, hit): (u64, u64)) -> f64 {
+    if found == 0 {
+        return 100.0;
+    }
+    100.0 * hit as f64 / found as f64
+}
+
+/// Return the directories (with the old and new coverage) whose coverage dropped by more than
+/// `threshold` percentage points.
+pub fn find_regressions(
+    old: &DirCoverage,
+    new: &DirCoverage,
+    threshold: f64,
+) -> Vec<(String, f64, f64)> {
+    new.iter()
+        .filter_map(|(dir, cov)| {
+            let before = percent(*old.get(dir)?);
+            let after = percent(*cov);
+            (before - after > threshold).then(|| (dir.clone(), before, after))
+        })
+        .collect()
+}
+
+/// Write the snapshot for today and return the newest snapshot at least `min_age_days` old.
+pub fn store_snapshot(
+    dir_snapshots: &std::path::Path,
+    new: &Snapshot,
+    min_age_days: i64,
+) -> Option<Snapshot> {
+    std::fs::create_dir_all(dir_snapshots).expect("Failed to create snapshots dir");
+    std::fs::write(
+        dir_snapshots.join(format!("{}.json", new.date)),
+        serde_json::to_string_pretty(new).expect("json error"),
+    )
+    .expect("Failed to write snapshot");
+    let cutoff = new.date - chrono::Duration::days(min_age_days);
+    std::fs::read_dir(dir_snapshots)
+        .expect("Failed to read snapshots dir")
+        .filter_map(|e| {
+            let snap: Snapshot =
+                serde_json::from_str(&std::fs::read_to_string(e.ok()?.path()).ok()?).ok()?;
+            (snap.date <= cutoff).then_some(snap)
+        })
+        .max_by_key(|s| s.date)
+}
+
+fn issue_body(
+    dir_code: &std::path::Path,
+    old: &Snapshot,
+    new: &Snapshot,
+    regressions: &[(String, f64, f64)],
+    threshold: f64,
+) -> String {
+    let mut body = format!(
+        "{id}\nThe line coverage of the following directories dropped by more than {threshold}% between {old_commit} ({old_date}) and {new_commit} ({new_date}).\n\n",
+        id = util::IdComment::CoverageRegressions.str(),
+        old_commit = old.commit,
+        old_date = old.date,
+        new_commit = new.commit,
+        new_date = new.date,
+    );
+    body += "| Directory | Before | After |\n";
+    body += "| --------- | ------ | ----- |\n";
+    for (dir, before, after) in regressions {
+        body += &format!("| `{dir}` | {before:.1}% | {after:.1}% |\n");
+    }
+    util::chdir(dir_code);
+    let commits = check_output(
+        git()
+            .args(["log", "--oneline", "--no-merges", "-50"])
+            .arg(format!("{}..{}", old.commit, new.commit))
+            .arg("--")
+            .args(regressions.iter().map(|r| &r.0)),
+    );
+    body += "\nCommits in the range touching these directories (at most 50):\n\n";
+    for c in commits.lines() {
+        body += &format!("* {c}\n");
+    }
+    body
+}
+
+/// Open, update, or close the tracking issue for coverage regressions.
+pub async fn update_issue(
+    github: &octocrab::Octocrab,
+    slug: &util::Slug,
+    dir_code: &std::path::Path,
+    old: &Snapshot,
+    new: &Snapshot,
+    threshold: f64,
+) -> octocrab::Result<()> {
+    let title = "Coverage regressions";
+    let regressions = find_regressions(&old.dirs, &new.dirs, threshold);
+    let issues_api = github.issues(&slug.owner, &slug.repo);
+    let query = format!(
+        "repo:{sl} is:issue is:open in:title \"{title}\"",
+        sl = slug.str()
+    );
+    let existing = util::with_retry(|| async {
+        github
+            .all_pages(
+                github
+                    .search()
+                    .issues_and_pull_requests(&query)
+                    .send()
+                    .await?,
+            )
+            .await
+    })
+    .await?
+    .into_iter()
+    .find(|i| {
+        i.body
+            .as_ref()
+            .is_some_and(|b| util::IdComment::CoverageRegressions.is_start_of(b))
+    });
+    match (existing, regressions.is_empty()) {
+        (None, true) => {
+            tracing::info!("No coverage regressions.");
+        }
+        (Some(issue), true) => {
+            tracing::info!("Coverage recovered, closing issue #{}", issue.number);
+            issues_api
+                .update(issue.number)
+                .state(octocrab::models::IssueState::Closed)
+                .send()
+                .await?;
+        }
+        (existing, false) => {
+            let body = issue_body(dir_code, old, new, &regressions, threshold);
+            match existing {
+                Some(issue) => {
+                    tracing::info!("Update coverage regressions issue #{}", issue.number);
+                    util::with_retry(|| issues_api.update(issue.number).body(&body).send()).await?;
+                }
+                None => {
+                    tracing::info!("Create coverage regressions issue");
+                    issues_api.create(title).body(body).send().await?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Commit and push the snapshots in the reports repo, if they changed.
+pub fn push_snapshots(dir_snapshots: &std::path::Path, date: chrono::NaiveDate) {
+    util::chdir(dir_snapshots);
+    check_call(git().args(["add", "./"]));
+    // E.g. a re-run on the same day and commit
+    if call(git().args(["diff", "--cached", "--quiet"])) {
+        tracing::info!("Coverage snapshot for {date} unchanged, nothing to push");
+        return;
+    }
+    check_call(git().args(["commit", "-m", &format!("Add coverage snapshot for {date}")]));
+    check_call(git().args(["push", "origin", "main"]));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_regressions() {
+        let info = "SF:/code/src/wallet/a.cpp\nLF:10\nLH:8\nend_of_record\nSF:/code/src/wallet/b.cpp\nLF:10\nLH:10\nend_of_record\nSF:/code/src/init.cpp\nLF:4\nLH:1\nend_of_record\n";
+        let old = parse_lcov(info, "/code");
+        assert_eq!(old.get("src/wallet"), Some(&(20, 18)));
+        assert_eq!(old.get("src"), Some(&(4, 1)));
+
+        let mut new = old.clone();
+        new.insert("src/wallet".to_string(), (20, 17));
+        new.insert("src/new".to_string(), (20, 0));
+        assert!(find_regressions(&old, &new, 5.0).is_empty());
+        assert_eq!(
+            find_regressions(&old, &new, 4.0),
+            vec![("src/wallet".to_string(), 90.0, 85.0)]
+        );
+    }
+}
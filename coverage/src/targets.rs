@@ -0,0 +1,322 @@
+use util::{check_call, check_output, git, Container};
+
+/// The coverage of a single fuzz target, from running it on its own corpus.
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct TargetCoverage {
+    pub name: String,
+    pub lines_hit: u64,
+    pub lines_found: u64,
+}
+
+impl TargetCoverage {
+    fn percent(&self) -> f64 {
+        if self.lines_found == 0 {
+            return 0.0;
+        }
+        100.0 * self.lines_hit as f64 / self.lines_found as f64
+    }
+}
+
+/// Return the number of lines hit and found in an lcov tracefile.
+fn line_coverage(info: &str) -> (u64, u64) {
+    let sum = |prefix: &str| {
+        info.lines()
+            .filter_map(|l| l.strip_prefix(prefix))
+            .filter_map(|n| n.trim().parse::<u64>().ok())
+            .sum()
+    };
+    (sum("LH:"), sum("LF:"))
+}
+
+/// Render the index page, linking to the page of each target.
+fn render_index(targets: &[TargetCoverage], git_ref: &str) -> String {
+    let rows = targets
+        .iter()
+        .map(|t| {
+            format!(
+                "<tr><td><a href=\"{name}/index.html\">{name}</a></td><td>{percent:.1} %</td><td>{hit} / {found}</td></tr>\n",
+                name = t.name,
+                percent = t.percent(),
+                hit = t.lines_hit,
+                found = t.lines_found,
+            )
+        })
+        .collect::<String>();
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+<head><meta charset="utf-8"><title>Fuzz coverage per target ({git_ref})</title></head>
+<body>
+<h1>Fuzz coverage per target ({git_ref})</h1>
+<p>The line coverage of each fuzz target, when run on its own corpus.</p>
+<table>
+<tr><th>Target</th><th>Lines</th><th>Hit / Found</th></tr>
+{rows}</table>
+</body>
+</html>
+"#
+    )
+}
+
+/// Generate a coverage page for each fuzz target from a separate lcov capture of running only
+/// that target on its corpus, and an index page with the percentage of each target. Return the
+/// coverage of each target.
+///
+/// Must be called from the build dir, after the fuzz binary was built with coverage enabled.
+pub fn gen_target_pages(
+    container: &Container,
+    corpus_dir: &std::path::Path,
+    dir_code: &std::path::Path,
+    dir_targets: &std::path::Path,
+    fuzz_targets: &str,
+    git_ref: &str,
+) -> Vec<TargetCoverage> {
+    let only = fuzz_targets.split_whitespace().collect::<Vec<_>>();
+    let mut names = std::fs::read_dir(corpus_dir)
+        .expect("Failed to read corpus folder")
+        .map(|e| e.expect("Failed to read corpus folder").path())
+        .filter(|p| p.is_dir())
+        .filter_map(|p| p.file_name().map(|n| n.to_string_lossy().to_string()))
+        .filter(|n| only.is_empty() || only.contains(&n.as_str()))
+        .collect::<Vec<_>>();
+    names.sort();
+    std::fs::create_dir_all(dir_targets).expect("Failed to create a folder");
+
+    let mut targets = Vec::new();
+    for name in names {
+        tracing::info!("Coverage for fuzz target {name} ...");
+        let info = dir_targets.join(format!("{name}.info"));
+        container.exec("lcov --quiet --zerocounters --directory .");
+        // A crash should not abort the whole run, the inputs up to the crash are still counted
+        container.exec(&format!(
+            "FUZZ={name} ./src/test/fuzz/fuzz -runs=1 {corpus} || true",
+            corpus = corpus_dir.join(&name).display()
+        ));
+        container.exec(&format!(
+            "lcov --quiet --capture --directory . --rc lcov_branch_coverage=1 --output-file {info} && lcov --quiet --extract {info} '{src}/*' --rc lcov_branch_coverage=1 --output-file {info}",
+            info = info.display(),
+            src = dir_code.join("src").display(),
+        ));
+        container.exec(&format!(
+            "genhtml --quiet --branch-coverage {info} --output-directory {out}",
+            info = info.display(),
+            out = dir_targets.join(&name).display(),
+        ));
+        let (lines_hit, lines_found) =
+            line_coverage(&std::fs::read_to_string(&info).expect("Failed to read lcov tracefile"));
+        targets.push(TargetCoverage {
+            name,
+            lines_hit,
+            lines_found,
+        });
+    }
+    std::fs::write(
+        dir_targets.join("index.html"),
+        render_index(&targets, git_ref),
+    )
+    .expect("Failed to write index page");
+    targets
+}
+
+/// The coverage of the fuzz targets of one run. Stored as one json line per run in the trend file.
+#[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct TrendEntry {
+    pub date: chrono::NaiveDate,
+    pub code_commit: String,
+    pub assets_commit: String,
+    pub targets: Vec<TargetCoverage>,
+}
+
+/// The time series of the coverage per target, in the fuzz coverage folder of the reports repo.
+const TREND_FILE: &str = "targets_trend.jsonl";
+/// The page showing the regressions since the previous qa-assets update.
+const TREND_PAGE: &str = "targets_trend.md";
+
+/// Return the last run on an earlier qa-assets commit than the new run.
+fn previous_assets_update<'a>(
+    entries: &'a [TrendEntry],
+    new: &TrendEntry,
+) -> Option<&'a TrendEntry> {
+    entries
+        .iter()
+        .rev()
+        .find(|e| e.assets_commit != new.assets_commit)
+}
+
+/// Return the targets (with the old and new coverage) whose coverage dropped by more than
+/// `threshold` percentage points.
+fn find_regressions(
+    old: &[TargetCoverage],
+    new: &[TargetCoverage],
+    threshold: f64,
+) -> Vec<(String, f64, f64)> {
+    new.iter()
+        .filter_map(|t| {
+            let before = old.iter().find(|o| o.name == t.name)?.percent();
+            let after = t.percent();
+            (before - after > threshold).then(|| (t.name.clone(), before, after))
+        })
+        .collect()
+}
+
+fn describe(entry: &TrendEntry) -> String {
+    format!(
+        "{date} (code {code}, qa-assets {assets})",
+        date = entry.date,
+        code = &entry.code_commit[..entry.code_commit.len().min(12)],
+        assets = &entry.assets_commit[..entry.assets_commit.len().min(12)],
+    )
+}
+
+/// Render the trend page, comparing the new run against the last run before the qa-assets update.
+fn render_trend(old: Option<&TrendEntry>, new: &TrendEntry, threshold: f64) -> String {
+    let mut page = "# Fuzz coverage per target\n\n".to_string();
+    page += &format!("Latest run: {}\n\n", describe(new));
+    let Some(old) = old else {
+        page += "There is no run on an earlier qa-assets commit to compare against yet.\n";
+        return page;
+    };
+    page += &format!(
+        "Compared against the last run before the qa-assets update: {}\n\n",
+        describe(old)
+    );
+    let regressions = find_regressions(&old.targets, &new.targets, threshold);
+    page += &format!(
+        "## Regressions\n\nTargets whose line coverage dropped by more than {threshold} percentage points:\n\n"
+    );
+    if regressions.is_empty() {
+        page += "None.\n";
+    } else {
+        page += "| Target | Before | After |\n";
+        page += "| ------ | ------ | ----- |\n";
+        for (name, before, after) in &regressions {
+            page += &format!("| `{name}` | {before:.1}% | {after:.1}% |\n");
+        }
+    }
+    page += "\n## All targets\n\n";
+    page += "| Target | Before | After |\n";
+    page += "| ------ | ------ | ----- |\n";
+    for t in &new.targets {
+        let before = old
+            .targets
+            .iter()
+            .find(|o| o.name == t.name)
+            .map_or("(new)".to_string(), |o| format!("{:.1}%", o.percent()));
+        page += &format!(
+            "| `{name}` | {before} | {after:.1}% |\n",
+            name = t.name,
+            after = t.percent()
+        );
+    }
+    page
+}
+
+/// Append the run to the trend file in the folder, render the trend page, and push both to the
+/// reports repo.
+pub fn update_trend(dir: &std::path::Path, new: TrendEntry, threshold: f64) {
+    let path = dir.join(TREND_FILE);
+    let entries = std::fs::read_to_string(&path)
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|l| serde_json::from_str::<TrendEntry>(l).ok())
+        .collect::<Vec<_>>();
+    let old = previous_assets_update(&entries, &new);
+    if let Some(old) = old {
+        let count = find_regressions(&old.targets, &new.targets, threshold).len();
+        tracing::info!("{count} fuzz targets regressed since the qa-assets update.");
+    }
+    std::fs::write(dir.join(TREND_PAGE), render_trend(old, &new, threshold))
+        .expect("Failed to write trend page");
+    let mut lines = entries
+        .iter()
+        .chain([&new])
+        .map(|e| serde_json::to_string(e).expect("json error"))
+        .collect::<Vec<_>>()
+        .join("\n");
+    lines.push('\n');
+    std::fs::write(&path, lines).expect("Failed to write trend file");
+
+    util::chdir(dir);
+    check_call(git().args(["add", TREND_FILE, TREND_PAGE]));
+    check_call(git().args([
+        "commit",
+        "-m",
+        &format!("Add fuzz target coverage trend for {}", new.date),
+    ]));
+    check_call(git().args(["push", "origin", "main"]));
+}
+
+/// The commit of the checked out qa-assets repo.
+pub fn assets_commit(dir_assets: &std::path::Path) -> String {
+    util::chdir(dir_assets);
+    check_output(git().args(["log", "--format=%H", "-1", "HEAD"]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_line_coverage() {
+        let info =
+            "TN:\nSF:/a.cpp\nLF:10\nLH:4\nend_of_record\nSF:/b.cpp\nLF:5\nLH:5\nend_of_record\n";
+        assert_eq!(line_coverage(info), (9, 15));
+        assert_eq!(line_coverage(""), (0, 0));
+    }
+
+    #[test]
+    fn test_render_index() {
+        let html = render_index(
+            &[TargetCoverage {
+                name: "addrman".to_string(),
+                lines_hit: 1,
+                lines_found: 8,
+            }],
+            "abc",
+        );
+        assert!(html.contains(
+            "<tr><td><a href=\"addrman/index.html\">addrman</a></td><td>12.5 %</td><td>1 / 8</td></tr>"
+        ));
+    }
+
+    #[test]
+    fn test_trend() {
+        let target = |name: &str, lines_hit| TargetCoverage {
+            name: name.to_string(),
+            lines_hit,
+            lines_found: 100,
+        };
+        let entry = |assets: &str, targets| TrendEntry {
+            date: "2024-01-01".parse().unwrap(),
+            code_commit: "c".repeat(40),
+            assets_commit: assets.repeat(40),
+            targets,
+        };
+        let entries = [
+            entry("a", vec![target("addrman", 50), target("banman", 30)]),
+            entry("b", vec![target("addrman", 40), target("banman", 30)]),
+            entry("b", vec![target("addrman", 45), target("banman", 30)]),
+        ];
+        let new = entry(
+            "b",
+            vec![
+                target("addrman", 45),
+                target("banman", 20),
+                target("coins", 10),
+            ],
+        );
+        let old = previous_assets_update(&entries, &new).unwrap();
+        assert_eq!(*old, entries[0]);
+        assert!(previous_assets_update(&entries[1..], &new).is_none());
+        assert_eq!(
+            find_regressions(&old.targets, &new.targets, 5.0),
+            vec![("banman".to_string(), 30.0, 20.0)]
+        );
+        assert!(find_regressions(&old.targets, &new.targets, 10.0).is_empty());
+
+        let page = render_trend(Some(old), &new, 5.0);
+        assert!(page.contains("| `banman` | 30.0% | 20.0% |\n\n## All targets"));
+        assert!(page.contains("| `coins` | (new) | 10.0% |\n"));
+        assert!(render_trend(None, &new, 5.0).contains("no run on an earlier qa-assets commit"));
+    }
+}
@@ -15,14 +15,16 @@ struct Args {
     /// Print changes/edits instead of moving the files.
     #[arg(long, default_value_t = false)]
     dry_run: bool,
+    /// The format of the log output: pretty or json.
+    #[arg(long, default_value = "pretty")]
+    log_format: util::LogFormat,
 }
 
 fn main() -> Result<(), std::io::Error> {
     let args = Args::parse();
+    util::init_logging(args.log_format);
 
-    println!();
-    println!("Same setup as the guix builds.");
-    println!();
+    tracing::info!("Same setup as the guix builds.");
 
     let git_remote_url = format!("https://github.com/{}", args.github_repo.str());
     let www_folder_depends_caches =
@@ -36,14 +38,14 @@ fn main() -> Result<(), std::io::Error> {
     let temp_dir = git_repo_dir.parent().unwrap();
 
     if !args.dry_run {
-        println!(
+        tracing::info!(
             "Create folder {} if it does not exist",
             www_folder_depends_caches.display()
         );
         std::fs::create_dir_all(www_folder_depends_caches)?;
     }
     if !git_repo_dir.is_dir() {
-        println!(
+        tracing::info!(
             "Clone {} repo to {}",
             git_remote_url,
             git_repo_dir.display()
@@ -56,17 +58,17 @@ fn main() -> Result<(), std::io::Error> {
         );
     }
 
-    println!("Fetch upsteam, checkout {}", args.git_ref);
+    tracing::info!("Fetch upsteam, checkout {}", args.git_ref);
     util::chdir(&git_repo_dir);
     util::check_call(util::git().args(["fetch", "--quiet", "--all"]));
     util::check_call(util::git().args(["checkout", &args.git_ref]));
 
-    println!("Download dependencies ...");
+    tracing::info!("Download dependencies ...");
     util::chdir(&git_repo_dir.join("depends"));
     std::env::set_var("MULTIPROCESS", "1");
     util::check_call(std::process::Command::new("make").arg("download"));
     let source_dir = git_repo_dir.join("depends").join("sources");
-    println!(
+    tracing::info!(
         "Merging results of {} to {}",
         source_dir.display(),
         www_folder_depends_caches.display()
@@ -76,7 +78,7 @@ fn main() -> Result<(), std::io::Error> {
         if !entry.path().is_file() {
             continue;
         }
-        println!(" ... entry = {}", entry.file_name().to_string_lossy());
+        tracing::info!(" ... entry = {}", entry.file_name().to_string_lossy());
         if !args.dry_run {
             std::fs::copy(
                 entry.path(),
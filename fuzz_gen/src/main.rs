@@ -1,3 +1,5 @@
+mod triage;
+
 use clap::Parser;
 use std::process::Command;
 use util::{chdir, check_call, git};
@@ -7,6 +9,9 @@ use util::{chdir, check_call, git};
 
 Generate fuzz inputs until a crash.
 
+The crashing inputs are minimized and deduplicated, and each new crash gets a report folder (with
+the input, the sanitizer output and the target) in the crashes folder of the scratch folder.
+
 To prepare, install:
 wget cargo sed git python3 ccache screen + Bitcoin Core deps
 #
@@ -32,21 +37,115 @@ struct Args {
         default_value = "address,fuzzer,undefined,integer,float-divide-by-zero"
     )]
     sanitizers: String,
+    /// Only run these fuzz targets (comma separated), e.g. the new targets of a pull request.
+    /// Default: all targets.
+    #[arg(long, value_delimiter = ',')]
+    targets: Vec<String>,
+    /// Generate inputs for each target for this many seconds, with --jobs targets running at a
+    /// time, instead of the fixed number of runs per target of test_runner.py.
+    #[arg(long)]
+    time_budget_per_target: Option<u64>,
+    /// Open a draft issue for each new crash in this repo, with the minimized input to reproduce.
+    /// Should be a private repo, as a crash may be a security issue. Format: owner/repo
+    #[arg(long)]
+    crash_issue_repo: Option<util::Slug>,
+    /// The access token for GitHub.
+    #[arg(long)]
+    github_access_token: Option<String>,
+    /// Print changes/edits instead of calling the GitHub API.
+    #[arg(long, default_value_t = false)]
+    dry_run: bool,
+    /// The format of the log output: pretty or json.
+    #[arg(long, default_value = "pretty")]
+    log_format: util::LogFormat,
 }
 
 pub fn ensure_init_git(folder: &std::path::Path, url: &str) {
-    println!("Clone {url} repo to {dir}", dir = folder.display());
+    tracing::info!("Clone {url} repo to {dir}", dir = folder.display());
     if !folder.is_dir() {
         check_call(git().args(["clone", "--quiet", url]).arg(folder));
     }
-    println!("Set git metadata");
+    tracing::info!("Set git metadata");
     chdir(folder);
     check_call(git().args(["config", "user.email", "no@ne.nl"]));
     check_call(git().args(["config", "user.name", "none"]));
 }
 
-fn main() {
+/// Return the targets to run, or the unknown ones, if any. No selection means all targets.
+fn select_targets(all: &[String], selected: &[String]) -> Result<Vec<String>, Vec<String>> {
+    if selected.is_empty() {
+        return Ok(all.to_vec());
+    }
+    let unknown = selected
+        .iter()
+        .filter(|t| !all.contains(t))
+        .cloned()
+        .collect::<Vec<_>>();
+    if !unknown.is_empty() {
+        return Err(unknown);
+    }
+    Ok(selected.to_vec())
+}
+
+/// Generate inputs for each target for the given number of seconds, with `jobs` targets running
+/// at a time. Return false if any of them failed.
+fn generate_timed(
+    dir_code: &std::path::Path,
+    dir_generate_seeds: &std::path::Path,
+    targets: &[String],
+    budget_sec: u64,
+    jobs: u8,
+) -> bool {
+    let mut success = true;
+    for batch in targets.chunks(usize::from(jobs.max(1))) {
+        let children = batch
+            .iter()
+            .map(|target| {
+                tracing::info!("Generate inputs for {target} for {budget_sec} seconds ...");
+                let dir = dir_generate_seeds.join(target);
+                std::fs::create_dir_all(&dir).expect("Failed to create a folder");
+                triage::fuzz_binary(dir_code, target)
+                    .arg(format!("-max_total_time={budget_sec}"))
+                    .arg("-use_value_profile=1")
+                    .arg(dir)
+                    .spawn()
+                    .expect("command error")
+            })
+            .collect::<Vec<_>>();
+        for mut child in children {
+            success &= child.wait().expect("command error").success();
+        }
+    }
+    success
+}
+
+/// Triage the crashes of the failed fuzz run, and open an issue for each new one, if enabled.
+async fn report_crashes(
+    args: &Args,
+    dir_code: &std::path::Path,
+    dir_crashes: &std::path::Path,
+    targets: &[String],
+) -> octocrab::Result<()> {
+    tracing::error!("Fuzz run failed, triage the crashes ...");
+    let crashes = triage::triage(dir_code, dir_code, dir_crashes, targets);
+    tracing::info!(
+        "{} new crashes, see {}",
+        crashes.len(),
+        dir_crashes.display()
+    );
+    if let Some(slug) = &args.crash_issue_repo {
+        let github = util::get_octocrab(args.github_access_token.clone(), None)?;
+        for crash in &crashes {
+            triage::open_issue(&github, slug, crash, args.dry_run).await?;
+        }
+    }
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> octocrab::Result<()> {
     let args = Args::parse();
+    util::init_logging(args.log_format);
 
     let url_code = format!("https://github.com/{}", "bitcoin/bitcoin");
     let url_seed = format!("https://github.com/{}", "bitcoin-core/qa-assets");
@@ -58,11 +157,12 @@ fn main() {
     let dir_code = temp_dir.join("code");
     let dir_assets = temp_dir.join("assets");
     let dir_generate_seeds = temp_dir.join("fuzz_inputs_generate");
+    let dir_crashes = temp_dir.join("crashes");
 
     ensure_init_git(&dir_code, &url_code);
     ensure_init_git(&dir_assets, &url_seed);
 
-    println!("Fetch upsteam, checkout latest branch");
+    tracing::info!("Fetch upsteam, checkout latest branch");
     chdir(&dir_code);
     check_call(git().args(["fetch", "--quiet", "--all"]));
     check_call(git().args(["checkout", "origin/master", "--force"]));
@@ -92,27 +192,68 @@ fn main() {
     check_call(Command::new("make").arg("clean"));
     check_call(Command::new("make").arg(format!("-j{}", args.jobs)));
     check_call(Command::new("rm").arg("-rf").arg(&dir_generate_seeds));
-    let fuzz = || {
+    let targets = match select_targets(&triage::list_targets(&dir_code), &args.targets) {
+        Ok(targets) => targets,
+        Err(unknown) => {
+            tracing::error!("Unknown fuzz targets: {unknown:?}");
+            std::process::exit(1);
+        }
+    };
+    tracing::info!("Fuzz {} targets", targets.len());
+    let dir_seed_corpus = dir_assets.join("fuzz_seed_corpus");
+    let fuzz = |seed_dir: &std::path::Path| {
         let mut cmd = Command::new("python3");
         cmd.args([
             "test/fuzz/test_runner.py",
             "-l=DEBUG",
             //"--exclude=coinselection",
         ])
-        .arg(format!("--par={}", args.jobs));
+        .arg(format!("--par={}", args.jobs))
+        // The targets must follow the seed dir, as both are positional
+        .arg(seed_dir)
+        .args(&args.targets);
         cmd
     };
-    check_call(
-        fuzz()
-            .arg(&dir_generate_seeds)
-            .arg("--m_dir")
-            .arg(dir_assets.join("fuzz_seed_corpus")),
-    );
-    check_call(fuzz().arg(&dir_generate_seeds).arg("--generate"));
-    check_call(
-        fuzz()
-            .arg(dir_assets.join("fuzz_seed_corpus"))
+    let run = |cmd: &mut Command| cmd.status().expect("command error").success();
+    // libFuzzer writes the crashing inputs to the current dir
+    let success = run(fuzz(&dir_generate_seeds)
+        .arg("--m_dir")
+        .arg(&dir_seed_corpus))
+        && match args.time_budget_per_target {
+            Some(budget_sec) => generate_timed(
+                &dir_code,
+                &dir_generate_seeds,
+                &targets,
+                budget_sec,
+                args.jobs,
+            ),
+            None => run(fuzz(&dir_generate_seeds).arg("--generate")),
+        }
+        && run(fuzz(&dir_seed_corpus)
             .arg("--m_dir")
-            .arg(&dir_generate_seeds),
-    );
+            .arg(&dir_generate_seeds));
+    if !success {
+        report_crashes(&args, &dir_code, &dir_crashes, &targets).await?;
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_select_targets() {
+        let all = ["a", "b", "c"].map(String::from);
+        assert_eq!(select_targets(&all, &[]).unwrap(), all);
+        assert_eq!(
+            select_targets(&all, &["c".to_string(), "a".to_string()]).unwrap(),
+            ["c", "a"]
+        );
+        assert_eq!(
+            select_targets(&all, &["a".to_string(), "x".to_string()]).unwrap_err(),
+            ["x"]
+        );
+    }
 }
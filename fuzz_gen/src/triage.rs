@@ -0,0 +1,361 @@
+//! Triage of the crashes found by a fuzz run: find the target of each crashing input, minimize it,
+//! and deduplicate the crashes by their sanitizer stack signature.
+
+use std::process::Command;
+
+/// The prefixes of the files libFuzzer writes for a failing input.
+const ARTIFACT_PREFIXES: [&str; 4] = ["crash-", "leak-", "timeout-", "oom-"];
+/// The number of frames of the stack that make up the signature of a crash.
+const SIGNATURE_FRAMES: usize = 3;
+/// Frames of the fuzz engine and the sanitizer runtimes, which are the same for all crashes.
+const RUNTIME_FRAMES: [&str; 12] = [
+    "__sanitizer",
+    "__asan",
+    "__ubsan",
+    "__lsan",
+    "__msan",
+    "__interceptor",
+    "__libc",
+    "fuzzer::",
+    "LLVMFuzzerTestOneInput",
+    "abort",
+    "raise",
+    "std::",
+];
+/// The inputs up to this size are included in the issue, as hex.
+const MAX_ISSUE_INPUT_LEN: usize = 4096;
+
+/// A crash that was not seen before.
+pub struct Crash {
+    /// The name of the report dir, made of the target and the hash of the signature.
+    pub id: String,
+    pub target: String,
+    pub signature: String,
+    pub input: Vec<u8>,
+    pub stack: String,
+}
+
+pub fn fuzz_binary(dir_code: &std::path::Path, target: &str) -> Command {
+    let mut cmd = Command::new(dir_code.join("src").join("test").join("fuzz").join("fuzz"));
+    cmd.env("FUZZ", target);
+    cmd
+}
+
+/// Return the names of all fuzz targets of the fuzz binary.
+pub fn list_targets(dir_code: &std::path::Path) -> Vec<String> {
+    let out = fuzz_binary(dir_code, "")
+        .env("PRINT_ALL_FUZZ_TARGETS_AND_ABORT", "")
+        .output()
+        .expect("command error");
+    String::from_utf8_lossy(&out.stdout)
+        .lines()
+        .map(|l| l.trim().to_string())
+        .filter(|l| !l.is_empty())
+        .collect()
+}
+
+/// Return the crashing inputs libFuzzer wrote to the folder.
+fn collect_artifacts(dir: &std::path::Path) -> Vec<std::path::PathBuf> {
+    let mut found = std::fs::read_dir(dir)
+        .expect("Failed to read folder")
+        .map(|e| e.expect("Failed to read folder").path())
+        .filter(|p| {
+            p.is_file()
+                && p.file_name()
+                    .and_then(|n| n.to_str())
+                    .is_some_and(|n| ARTIFACT_PREFIXES.iter().any(|pre| n.starts_with(pre)))
+        })
+        .collect::<Vec<_>>();
+    found.sort();
+    found
+}
+
+/// Run the target on the input, and return the output of the sanitizer, if it fails.
+fn reproduce(dir_code: &std::path::Path, target: &str, input: &std::path::Path) -> Option<String> {
+    let out = fuzz_binary(dir_code, target)
+        .arg(input)
+        .output()
+        .expect("command error");
+    (!out.status.success()).then(|| String::from_utf8_lossy(&out.stderr).to_string())
+}
+
+/// Return the signature of the crash in the sanitizer output: the kind of the error, followed by
+/// the innermost frames of the first stack trace that are not part of the runtime.
+fn stack_signature(stderr: &str) -> Option<String> {
+    let kind = stderr.lines().find_map(|l| {
+        if let Some(summary) = l.strip_prefix("SUMMARY: ") {
+            // SUMMARY: AddressSanitizer: heap-buffer-overflow src/a.cpp:1:2 in f()
+            let (_, rest) = summary.split_once(": ")?;
+            return rest.split_whitespace().next();
+        }
+        let (_, error) = l.split_once("ERROR: libFuzzer: ")?;
+        error.split_whitespace().next()
+    });
+    let mut frames = Vec::new();
+    let mut in_stack = false;
+    for line in stderr.lines().map(str::trim) {
+        if !line.starts_with('#') {
+            if in_stack {
+                // Only the first stack trace
+                break;
+            }
+            continue;
+        }
+        in_stack = true;
+        let Some((_, func)) = line.split_once(" in ") else {
+            continue;
+        };
+        // Drop the arguments and the location, which may change with unrelated edits
+        let func = func.split(['(', ' ']).next().unwrap_or_default();
+        if func.is_empty() || RUNTIME_FRAMES.iter().any(|r| func.starts_with(r)) {
+            continue;
+        }
+        frames.push(func);
+        if frames.len() == SIGNATURE_FRAMES {
+            break;
+        }
+    }
+    if kind.is_none() && frames.is_empty() {
+        return None;
+    }
+    let mut signature = kind.unwrap_or("crash").to_string();
+    if !frames.is_empty() {
+        signature += &format!(" {}", frames.join(" < "));
+    }
+    Some(signature)
+}
+
+/// A hash that is stable across runs and builds (FNV-1a), to name the report dirs.
+fn stable_hash(text: &str) -> u64 {
+    text.bytes().fold(0xcbf29ce484222325, |h, b| {
+        (h ^ u64::from(b)).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// Minimize the crashing input of the target, and return the path of the minimized input, or the
+/// original input, if minimizing failed.
+fn minimize(
+    dir_code: &std::path::Path,
+    target: &str,
+    input: &std::path::Path,
+    dir_crashes: &std::path::Path,
+) -> std::path::PathBuf {
+    let minimized = dir_crashes.join(format!(
+        "{}.min",
+        input.file_name().unwrap().to_string_lossy()
+    ));
+    // Fails with the crash of the last run, so only check the result
+    let _ = fuzz_binary(dir_code, target)
+        .args(["-minimize_crash=1", "-runs=100000"])
+        .arg(format!("-exact_artifact_path={}", minimized.display()))
+        .arg(input)
+        .output()
+        .expect("command error");
+    if minimized.is_file() && reproduce(dir_code, target, &minimized).is_some() {
+        return minimized;
+    }
+    let _ = std::fs::remove_file(&minimized);
+    input.to_path_buf()
+}
+
+/// Triage the crashing inputs left in `dir_artifacts` by the fuzz run. Each crash gets a report
+/// dir in `dir_crashes` with the minimized input, the sanitizer output and the target. Return the
+/// crashes that do not have a report dir yet. The triaged inputs are removed.
+pub fn triage(
+    dir_code: &std::path::Path,
+    dir_artifacts: &std::path::Path,
+    dir_crashes: &std::path::Path,
+    targets: &[String],
+) -> Vec<Crash> {
+    std::fs::create_dir_all(dir_crashes).expect("Failed to create crashes folder");
+    let mut crashes = Vec::new();
+    for artifact in collect_artifacts(dir_artifacts) {
+        tracing::info!("Triage {} ...", artifact.display());
+        let Some(target) = targets
+            .iter()
+            .find(|t| reproduce(dir_code, t, &artifact).is_some())
+        else {
+            let dir = dir_crashes.join("unreproducible");
+            tracing::warn!(
+                "No target crashes on {}, moving it to {}",
+                artifact.display(),
+                dir.display()
+            );
+            std::fs::create_dir_all(&dir).expect("Failed to create crashes folder");
+            std::fs::rename(&artifact, dir.join(artifact.file_name().unwrap()))
+                .expect("Failed to move input");
+            continue;
+        };
+        let input = minimize(dir_code, target, &artifact, dir_crashes);
+        let stack = reproduce(dir_code, target, &input).unwrap_or_default();
+        let signature = stack_signature(&stack).unwrap_or_else(|| "unknown".to_string());
+        let id = format!(
+            "{target}-{:016x}",
+            stable_hash(&format!("{target} {signature}"))
+        );
+        let dir = dir_crashes.join(&id);
+        let data = std::fs::read(&input).expect("Failed to read input");
+        if dir.is_dir() {
+            tracing::info!("... Duplicate of {id}: {signature}");
+        } else {
+            tracing::info!("... New crash {id}: {signature}");
+            std::fs::create_dir_all(&dir).expect("Failed to create report folder");
+            std::fs::write(dir.join("input"), &data).expect("Failed to write report");
+            std::fs::write(dir.join("stack.txt"), &stack).expect("Failed to write report");
+            std::fs::write(dir.join("target.txt"), format!("{target}\n"))
+                .expect("Failed to write report");
+            crashes.push(Crash {
+                id,
+                target: target.clone(),
+                signature,
+                input: data,
+                stack,
+            });
+        }
+        if input != artifact {
+            std::fs::remove_file(&input).expect("Failed to remove input");
+        }
+        std::fs::remove_file(&artifact).expect("Failed to remove input");
+    }
+    crashes
+}
+
+fn issue_title(crash: &Crash) -> String {
+    let kind = crash.signature.split(' ').next().unwrap_or_default();
+    format!(
+        "[draft] Fuzz crash in {}: {kind} ({})",
+        crash.target, crash.id
+    )
+}
+
+fn issue_body(crash: &Crash) -> String {
+    let mut body = format!(
+        "Found by fuzz_gen, and not confirmed by a human yet.\n\nTarget: `{target}`\nSignature: `{signature}`\n\n",
+        target = crash.target,
+        signature = crash.signature,
+    );
+    if crash.input.len() <= MAX_ISSUE_INPUT_LEN {
+        let hex = crash
+            .input
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect::<String>();
+        body += &format!(
+            "To reproduce:\n\n```\necho '{hex}' | xxd -r -p > {id}\nFUZZ={target} ./src/test/fuzz/fuzz {id}\n```\n\n",
+            id = crash.id,
+            target = crash.target,
+        );
+    } else {
+        body += &format!(
+            "The input ({} bytes) is too large to include here. It is in the report dir `{}` of the fuzz_gen scratch folder.\n\n",
+            crash.input.len(),
+            crash.id
+        );
+    }
+    let stack = crash.stack.lines().take(80).collect::<Vec<_>>().join("\n");
+    body += &format!(
+        "<details><summary>Sanitizer output</summary>\n\n```\n{stack}\n```\n\n</details>\n"
+    );
+    body
+}
+
+/// Open an issue for the crash in the repo, unless there is one already.
+pub async fn open_issue(
+    github: &octocrab::Octocrab,
+    slug: &util::Slug,
+    crash: &Crash,
+    dry_run: bool,
+) -> octocrab::Result<()> {
+    let query = format!(
+        "repo:{sl} is:issue in:title \"{id}\"",
+        sl = slug.str(),
+        id = crash.id
+    );
+    let existing =
+        util::with_retry(|| github.search().issues_and_pull_requests(&query).send()).await?;
+    if let Some(issue) = existing.items.first() {
+        tracing::info!("... Issue #{} exists for {}", issue.number, crash.id);
+        return Ok(());
+    }
+    let title = issue_title(crash);
+    let body = issue_body(crash);
+    tracing::info!("... Open issue: {title}");
+    if dry_run {
+        util::dry_run_preview(
+            &format!("POST /repos/{}/issues", slug.str()),
+            Some(("", &body)),
+        );
+        return Ok(());
+    }
+    github
+        .issues(&slug.owner, &slug.repo)
+        .create(title)
+        .body(body)
+        .send()
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stack_signature() {
+        let asan = "\
+INFO: Running with entropic power schedule (0xFF, 100).
+=================================================================
+==12==ERROR: AddressSanitizer: heap-buffer-overflow on address 0x602000000011
+READ of size 1 at 0x602000000011 thread T0
+    #0 0x55d1 in __asan_memcpy (/fuzz+0x1)
+    #1 0x55d2 in CScript::GetOp(unsigned char*) const /code/src/script/script.h:10:3
+    #2 0x55d3 in EvalScript(std::vector<unsigned char> const&) /code/src/script/interpreter.cpp:20:5
+    #3 0x55d4 in script_fuzz_target(Span<unsigned char const>) /code/src/test/fuzz/script.cpp:30:7
+    #4 0x55d5 in VerifyScript /code/src/script/interpreter.cpp:40:1
+    #5 0x55d6 in LLVMFuzzerTestOneInput /code/src/test/fuzz/fuzz.cpp:50:1
+
+0x602000000011 is located 0 bytes after 1-byte region
+allocated by thread T0 here:
+    #0 0x55e1 in malloc (/fuzz+0x2)
+    #1 0x55e2 in Other /code/src/other.cpp:1:1
+
+SUMMARY: AddressSanitizer: heap-buffer-overflow /code/src/script/script.h:10:3 in CScript::GetOp(unsigned char*) const
+";
+        assert_eq!(
+            stack_signature(asan).unwrap(),
+            "heap-buffer-overflow CScript::GetOp < EvalScript < script_fuzz_target"
+        );
+        let ubsan = "/code/src/arith.cpp:5:7: runtime error: signed integer overflow\n    #0 0x1 in Mul(long, long) /code/src/arith.cpp:5:7\nSUMMARY: UndefinedBehaviorSanitizer: undefined-behavior /code/src/arith.cpp:5:7\n";
+        assert_eq!(stack_signature(ubsan).unwrap(), "undefined-behavior Mul");
+        assert_eq!(
+            stack_signature("==1== ERROR: libFuzzer: timeout after 30 seconds\n").unwrap(),
+            "timeout"
+        );
+        assert_eq!(stack_signature("Segmentation fault\n"), None);
+        assert_eq!(stable_hash(""), 0xcbf29ce484222325);
+        assert_ne!(stable_hash("a"), stable_hash("b"));
+    }
+
+    #[test]
+    fn test_issue() {
+        let crash = Crash {
+            id: "script-00000000000000ab".to_string(),
+            target: "script".to_string(),
+            signature: "heap-buffer-overflow CScript::GetOp".to_string(),
+            input: vec![0, 0xff],
+            stack: "==1==ERROR\n    #0 0x1 in f".to_string(),
+        };
+        assert_eq!(
+            issue_title(&crash),
+            "[draft] Fuzz crash in script: heap-buffer-overflow (script-00000000000000ab)"
+        );
+        let body = issue_body(&crash);
+        assert!(body.contains("echo '00ff' | xxd -r -p > script-00000000000000ab\n"));
+        assert!(body.contains("```\n==1==ERROR\n    #0 0x1 in f\n```"));
+        let crash = Crash {
+            input: vec![0; MAX_ISSUE_INPUT_LEN + 1],
+            ..crash
+        };
+        assert!(issue_body(&crash).contains("(4097 bytes) is too large"));
+    }
+}
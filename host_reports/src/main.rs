@@ -1,7 +1,7 @@
 use clap::Parser;
 
 #[derive(clap::Parser)]
-#[command(about = "Pull a git repository and move it to /var/www/... .", long_about = None)]
+#[command(about = "Pull a git repository and publish it to the artifact store.", long_about = None)]
 struct Args {
     /// The repo slug of the remote on GitHub for reports.
     #[arg(long)]
@@ -9,40 +9,54 @@ struct Args {
     /// The local scratch folder.
     #[arg(long)]
     host_reports_scratch: std::path::PathBuf,
+    /// Where to publish the reports. Format: /local/dir, ssh://user@host:/dir or s3://bucket/prefix
+    #[arg(long, default_value = "/var/www/html")]
+    artifact_store: util::ArtifactStore,
     /// Print changes/edits, only modify the scratch folder.
     #[arg(long, default_value_t = false)]
     dry_run: bool,
+    /// The format of the log output: pretty or json.
+    #[arg(long, default_value = "pretty")]
+    log_format: util::LogFormat,
 }
 
 fn main() {
     let args = Args::parse();
+    util::init_logging(args.log_format);
 
-    println!();
-    println!("See guix.py for instructions on how to add write permission for /var/www to the current user");
-    println!();
+    tracing::info!("See guix.py for instructions on how to add write permission for /var/www to the current user");
 
     let repo_url = format!("https://github.com/{}", args.repo_report.str());
-    let host_reports_www_folder = if args.dry_run {
-        args.host_reports_scratch.join("www_output/")
+    let host_reports_repo_folder = args.host_reports_scratch.join("repo");
+    let artifact_store = if args.dry_run {
+        util::ArtifactStore::Local(args.host_reports_scratch.join("www_output/"))
     } else {
-        std::path::Path::new("/var/www/html/host_reports/").join(args.repo_report.str())
+        args.artifact_store
     };
 
-    if !host_reports_www_folder.is_dir() {
-        println!(
+    if !host_reports_repo_folder.is_dir() {
+        tracing::info!(
             "Clone {repo_url} repo to {dir}",
-            dir = host_reports_www_folder.display()
+            dir = host_reports_repo_folder.display()
         );
         util::check_call(
             util::git()
                 .args(["clone", "--quiet", &repo_url])
-                .arg(&host_reports_www_folder),
+                .arg(&host_reports_repo_folder),
         );
     }
 
-    println!("Fetch upsteam, checkout latest `main` branch");
-    util::chdir(&host_reports_www_folder);
+    tracing::info!("Fetch upsteam, checkout latest `main` branch");
+    util::chdir(&host_reports_repo_folder);
     util::check_call(util::git().args(["fetch", "--quiet", "--all"]));
     util::check_call(util::git().args(["checkout", "origin/main"]));
     util::check_call(util::git().args(["reset", "--hard", "HEAD"]));
+
+    // The coverage reports are published to their subfolders directly, see
+    // coverage --artifact-store
+    artifact_store.publish(
+        &host_reports_repo_folder,
+        &format!("host_reports/{}", args.repo_report.str()),
+        &[".git", "coverage", "coverage_fuzz"],
+    );
 }
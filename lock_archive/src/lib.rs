@@ -0,0 +1,135 @@
+#[derive(clap::Parser)]
+#[command(about = "Lock discussion on inactive closed issues and pull requests.", long_about = None)]
+pub struct Args {
+    /// The access token for GitHub.
+    #[arg(long)]
+    github_access_token: Option<String>,
+    /// Cache the GET responses of the GitHub API in this dir, and only revalidate them on later
+    /// runs. May be shared with the other tools.
+    #[arg(long)]
+    github_cache_dir: Option<std::path::PathBuf>,
+    /// The repo slugs of the remotes on GitHub. Format: owner/repo
+    #[arg(long)]
+    github_repo: Vec<util::Slug>,
+    /// Lock a closed issue or pull request after this many days of inactivity
+    #[arg(long, default_value_t = 365)]
+    inactive_days: i64,
+    /// Print changes/edits instead of calling the GitHub API.
+    #[arg(long, default_value_t = false)]
+    dry_run: bool,
+    /// Only lock after a member of this team approved it. Format: org/team
+    #[arg(long)]
+    approval_team: Option<String>,
+    /// How long a proposal to lock stays valid, when --approval-team is set.
+    #[arg(long, default_value_t = 7)]
+    approval_days: i64,
+    /// Post at most this many new proposals per repo and run, when --approval-team is set, so
+    /// that the team is not mentioned on a large backlog at once.
+    #[arg(long, default_value_t = 5)]
+    max_proposals: usize,
+    /// The format of the log output: pretty or json.
+    #[arg(long, default_value = "pretty")]
+    log_format: util::LogFormat,
+}
+
+/// Run the tool with the given command line arguments.
+pub async fn run(args: Args) -> octocrab::Result<()> {
+    util::init_logging(args.log_format);
+
+    let github = util::get_octocrab(args.github_access_token, args.github_cache_dir)?;
+    let approval = args.approval_team.map(|team| util::ApprovalPolicy {
+        team,
+        validity: chrono::Duration::days(args.approval_days),
+    });
+
+    let cutoff = { chrono::Utc::now() - chrono::Duration::days(args.inactive_days) }.format("%F");
+    tracing::info!("Locking before date {} ...", cutoff);
+
+    for slug in args.github_repo {
+        let util::Slug { owner, repo } = &slug;
+        tracing::info!("Get closed issues and pull requests for {owner}/{repo} ...");
+        let search = |query: String| {
+            let github = &github;
+            async move {
+                util::with_retry(|| async {
+                    github
+                        .all_pages(
+                            github
+                                .search()
+                                .issues_and_pull_requests(&query)
+                                .send()
+                                .await?,
+                        )
+                        .await
+                })
+                .await
+            }
+        };
+        let mut items = search(format!(
+            "repo:{owner}/{repo} is:unlocked is:closed updated:<={cutoff}"
+        ))
+        .await?
+        .into_iter()
+        .map(|i| (i.number, true))
+        .collect::<Vec<_>>();
+        if approval.is_some() {
+            // Proposals bump the update date, so look them up separately
+            let proposed = search(format!(
+                "repo:{owner}/{repo} is:unlocked is:closed commenter:@me"
+            ))
+            .await?;
+            for item in proposed {
+                if !items.iter().any(|(n, _)| *n == item.number) {
+                    items.push((item.number, false));
+                }
+            }
+        }
+        let issues_api = github.issues(owner, repo);
+        let mut proposals = 0;
+        for (i, (number, inactive)) in items.iter().enumerate() {
+            tracing::info!(
+                "{}/{} (Item: {}/{}#{})",
+                i,
+                items.len(),
+                owner,
+                repo,
+                number,
+            );
+            if let Some(policy) = &approval {
+                let action = util::ProposedAction::Lock;
+                match util::check_approval(&github, &slug, *number, action, policy, args.dry_run)
+                    .await?
+                {
+                    util::Approval::Approved => {}
+                    util::Approval::NotProposed if *inactive => {
+                        if proposals >= args.max_proposals {
+                            tracing::info!("... skip, --max-proposals reached for this run");
+                            continue;
+                        }
+                        proposals += 1;
+                        util::propose_action(
+                            &github,
+                            &slug,
+                            *number,
+                            action,
+                            &format!(
+                                "the discussion has been inactive for {} days.",
+                                args.inactive_days
+                            ),
+                            policy,
+                            args.dry_run,
+                        )
+                        .await?;
+                        continue;
+                    }
+                    _ => continue,
+                }
+            }
+            if !args.dry_run {
+                issues_api.lock(*number, None).await?;
+            }
+        }
+    }
+    util::exit_on_quota_denials();
+    Ok(())
+}
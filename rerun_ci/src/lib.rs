@@ -0,0 +1,500 @@
+#[derive(Clone)]
+struct SlugTok {
+    owner: String,
+    repo: String,
+    ci_token: String,
+}
+
+impl std::str::FromStr for SlugTok {
+    type Err = &'static str;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // Format: a/b:c
+        let err = "Wrong format, see --help.";
+        let mut it = s.split(':');
+        let mut it_slug = it.next().ok_or(err)?.split('/');
+        let res = Self {
+            owner: it_slug.next().ok_or(err)?.to_string(),
+            repo: it_slug.next().ok_or(err)?.to_string(),
+            ci_token: it.next().ok_or(err)?.to_string(),
+        };
+        if it.next().is_none() && it_slug.next().is_none() {
+            return Ok(res);
+        }
+        Err(err)
+    }
+}
+
+#[derive(clap::Parser)]
+#[command(about = "Trigger Cirrus CI to re-run.", long_about = None)]
+pub struct Args {
+    /// The access token for GitHub.
+    #[arg(long)]
+    github_access_token: Option<String>,
+    /// Cache the GET responses of the GitHub API in this dir, and only revalidate them on later
+    /// runs. May be shared with the other tools.
+    #[arg(long)]
+    github_cache_dir: Option<std::path::PathBuf>,
+    /// The repo slugs of the remotes on GitHub. Format: owner/repo:cirrus_org_token
+    #[arg(long)]
+    github_repo: Vec<SlugTok>,
+    /// The task names to re-run.
+    #[arg(long)]
+    task: Vec<String>,
+    /// How many minutes to sleep between pulls.
+    #[arg(long, default_value_t = 25)]
+    sleep_min: u64,
+    /// Print changes/edits instead of calling the GitHub/CI API.
+    #[arg(long, default_value_t = false)]
+    dry_run: bool,
+    /// Exit cleanly after this many minutes.
+    #[arg(long)]
+    max_runtime: Option<u64>,
+    /// The file to remember the last processed pull in, when exiting due to --max-runtime.
+    #[arg(long)]
+    checkpoint_file: Option<std::path::PathBuf>,
+    /// A local git dir to compute mergeability in, when GitHub takes too long to compute it.
+    #[arg(long)]
+    mergeable_fallback_dir: Option<std::path::PathBuf>,
+    /// Only re-run pulls with the --ci-failed-label, most recently updated first.
+    #[arg(long, default_value_t = false)]
+    only_labeled: bool,
+    /// The label that indicates a failing CI.
+    #[arg(long, default_value = "CI failed")]
+    ci_failed_label: String,
+    /// The sqlite database with the facts about pull requests shared between the tools of the
+    /// bot.
+    #[arg(long)]
+    pull_facts_file: Option<std::path::PathBuf>,
+    /// With --only-labeled, take the pulls whose last CI run failed (as recorded by
+    /// webhook_features) from the pull facts, instead of listing the pulls with the label on
+    /// GitHub.
+    #[arg(long, default_value_t = false, requires_all = ["only_labeled", "pull_facts_file"])]
+    failed_from_pull_facts: bool,
+    /// Instead of going through all pulls once, keep watching the pull facts for CI runs that
+    /// finished with a failure (recorded by webhook_features on check_suite::completed), and only
+    /// re-run those of the tasks that failed.
+    #[arg(long, default_value_t = false, requires = "pull_facts_file", conflicts_with_all = ["only_labeled", "checkpoint_file"])]
+    watch: bool,
+    /// With --watch, how many minutes to sleep between checks of the pull facts.
+    #[arg(long, default_value_t = 5)]
+    poll_min: u64,
+    /// With --watch, re-run the tasks of a pull at most once in this many hours.
+    #[arg(long, default_value_t = 24)]
+    rerun_interval_hours: i64,
+    /// The format of the log output: pretty or json.
+    #[arg(long, default_value = "pretty")]
+    log_format: util::LogFormat,
+}
+
+static ERROR_JSON_FORMAT: &str = "json format error";
+
+/// Return the id and name of the first task whose name contains the given task name.
+fn find_task<'a>(
+    task_name: &str,
+    tasks: &'a [serde_json::Value],
+) -> Result<Option<(&'a str, &'a str)>, String> {
+    for t in tasks {
+        let name = t["name"].as_str().ok_or(format!(
+            "{ERROR_JSON_FORMAT}: Missing '{key}' in '{t}'",
+            key = "name",
+        ))?;
+        if name.contains(task_name) {
+            let id = t["id"].as_str().ok_or(format!(
+                "{ERROR_JSON_FORMAT}: Missing {key} in '{t}'",
+                key = "id",
+            ))?;
+            return Ok(Some((id, name)));
+        }
+    }
+    Ok(None)
+}
+
+/// The Cirrus CI API is GraphQL, like the one of GitHub, so it is called via octocrab as well.
+fn cirrus_api(token: &str) -> octocrab::Result<octocrab::Octocrab> {
+    octocrab::Octocrab::builder()
+        .base_uri("https://api.cirrus-ci.com")?
+        .personal_token(token.to_string())
+        .build()
+}
+
+#[derive(serde::Deserialize)]
+struct GraphQlResponse<T> {
+    data: Option<T>,
+    #[serde(default)]
+    errors: Vec<GraphQlError>,
+}
+
+#[derive(serde::Deserialize)]
+struct GraphQlError {
+    message: String,
+}
+
+#[derive(serde::Deserialize)]
+struct RerunData {
+    rerun: Option<RerunPayload>,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RerunPayload {
+    new_task: NewTask,
+}
+
+#[derive(serde::Deserialize)]
+struct NewTask {
+    id: String,
+}
+
+/// The outcome of a request to re-run a task.
+#[derive(Debug, PartialEq)]
+enum Rerun {
+    /// The re-run was started as a new task with the given id.
+    Accepted(String),
+    /// There is no task to re-run, or it was a dry run.
+    Skipped(String),
+    /// Cirrus CI refused the re-run with the given reason, e.g. because the task is too old or the
+    /// token lacks the permission.
+    Rejected(String),
+}
+
+fn rerun_outcome(res: GraphQlResponse<RerunData>) -> Rerun {
+    match res.data.and_then(|d| d.rerun) {
+        Some(r) if res.errors.is_empty() => Rerun::Accepted(r.new_task.id),
+        _ if res.errors.is_empty() => Rerun::Rejected("No new task in the response".to_string()),
+        _ => Rerun::Rejected(
+            res.errors
+                .into_iter()
+                .map(|e| e.message)
+                .collect::<Vec<_>>()
+                .join("; "),
+        ),
+    }
+}
+
+async fn rerun_first(
+    cirrus: &octocrab::Octocrab,
+    task_name: &str,
+    tasks: &[serde_json::Value],
+    dry_run: bool,
+) -> Result<Rerun, String> {
+    let Some((t_id, t_name)) = find_task(task_name, tasks)? else {
+        return Ok(Rerun::Skipped(format!("No task matching '{task_name}'")));
+    };
+    tracing::info!("Re-run task {t_name} (id: {t_id})");
+    if dry_run {
+        return Ok(Rerun::Skipped("Dry run".to_string()));
+    }
+    let res = cirrus
+        .graphql(&serde_json::json!({
+            "query": format!(
+                r#"mutation {{ rerun(input: {{ attachTerminal: false, clientMutationId: "rerun-{t_id}", taskId: "{t_id}" }}) {{ newTask {{ id }} }} }}"#
+            ),
+        }))
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(rerun_outcome(res))
+}
+
+/// Return the tasks of the last Cirrus CI build of the pull.
+async fn cirrus_tasks(
+    cirrus: &octocrab::Octocrab,
+    owner: &str,
+    repo: &str,
+    pull_num: u64,
+) -> Result<Vec<serde_json::Value>, String> {
+    let json_parsed: serde_json::Value = cirrus
+        .graphql(&serde_json::json!({
+            "query": format!(
+                r#"query {{ ownerRepository(platform: "github", owner: "{owner}", name: "{repo}") {{ viewerPermission builds(last: 1, branch: "pull/{pull_num}") {{ edges {{ node {{ tasks {{ id name status }} }} }} }} }} }}"#
+            ),
+        }))
+        .await
+        .map_err(|e| e.to_string())?;
+    json_parsed["data"]["ownerRepository"]["builds"]["edges"][0]["node"]["tasks"]
+        .as_array()
+        .cloned()
+        .ok_or(format!(
+            "{ERROR_JSON_FORMAT}: Missing keys in '{json_parsed}'"
+        ))
+}
+
+/// Return the tasks that finished with a failure.
+fn failed_tasks(tasks: &[serde_json::Value]) -> Vec<serde_json::Value> {
+    tasks
+        .iter()
+        .filter(|t| t["status"].as_str() == Some("FAILED"))
+        .cloned()
+        .collect()
+}
+
+/// Re-run the first of the tasks matching each of the task names.
+async fn rerun_tasks(
+    cirrus: &octocrab::Octocrab,
+    task_names: &[String],
+    tasks: &[serde_json::Value],
+    dry_run: bool,
+) {
+    for task_name in task_names {
+        match rerun_first(cirrus, task_name, tasks, dry_run).await {
+            Ok(Rerun::Accepted(id)) => tracing::info!("... accepted (new task id: {id})"),
+            Ok(Rerun::Skipped(reason)) => tracing::info!("... skipped: {reason}"),
+            Ok(Rerun::Rejected(reason)) => tracing::warn!("... rejected: {reason}"),
+            Err(msg) => tracing::warn!("{msg}"),
+        }
+    }
+}
+
+/// Re-run the failed tasks of the pulls whose last CI run failed, as they show up in the pull
+/// facts, until the deadline.
+async fn watch(
+    args: &Args,
+    facts: &state::Store,
+    deadline: Option<std::time::Instant>,
+) -> octocrab::Result<()> {
+    let poll = std::time::Duration::from_secs(args.poll_min * 60);
+    let interval = chrono::Duration::hours(args.rerun_interval_hours);
+    loop {
+        for SlugTok {
+            owner,
+            repo,
+            ci_token,
+        } in &args.github_repo
+        {
+            let cirrus = cirrus_api(ci_token)?;
+            let slug = util::Slug {
+                owner: owner.clone(),
+                repo: repo.clone(),
+            };
+            let due = facts.read().expect("pull facts file error").rerun_due(
+                &slug,
+                chrono::Utc::now(),
+                interval,
+            );
+            for pull_num in due {
+                tracing::info!("Re-run failed tasks of {owner}/{repo}/{pull_num}");
+                match cirrus_tasks(&cirrus, owner, repo, pull_num).await {
+                    Ok(tasks) => {
+                        rerun_tasks(&cirrus, &args.task, &failed_tasks(&tasks), args.dry_run).await
+                    }
+                    Err(msg) => tracing::info!("{msg}"),
+                }
+                if !args.dry_run {
+                    // Also on errors, to only retry once the CI failed again
+                    facts
+                        .update(|f| {
+                            f.pull_mut(&format!("{owner}/{repo}/{pull_num}")).ci_rerun =
+                                Some(chrono::Utc::now())
+                        })
+                        .expect("pull facts file error");
+                }
+            }
+        }
+        if deadline.is_some_and(|d| std::time::Instant::now() + poll >= d) {
+            tracing::info!("Max runtime reached, exiting");
+            return Ok(());
+        }
+        tokio::time::sleep(poll).await;
+    }
+}
+
+/// Run the tool with the given command line arguments.
+pub async fn run(args: Args) -> octocrab::Result<()> {
+    util::init_logging(args.log_format);
+    if args.watch {
+        // --watch requires --pull-facts-file
+        let facts = state::Store::new(args.pull_facts_file.clone().unwrap());
+        return watch(&args, &facts, util::Progress::deadline(args.max_runtime)).await;
+    }
+    let github = util::get_octocrab(args.github_access_token, args.github_cache_dir)?;
+
+    let mergeable_fallback = args
+        .mergeable_fallback_dir
+        .map(util::MergeableFallback::new);
+    let deadline = util::Progress::deadline(args.max_runtime);
+    let checkpoint = args.checkpoint_file.map(util::Checkpoint::new);
+    let facts = args.pull_facts_file.map(state::Store::new);
+    let mut resume = checkpoint.as_ref().and_then(|c| c.load()).filter(|id| {
+        args.github_repo
+            .iter()
+            .any(|s| id.starts_with(&format!("{}/{}/", s.owner, s.repo)))
+    });
+    let api_start = util::get_rate_limit_remaining(&github).await?;
+
+    for SlugTok {
+        owner,
+        repo,
+        ci_token,
+    } in args.github_repo
+    {
+        if resume
+            .as_ref()
+            .is_some_and(|id| !id.starts_with(&format!("{owner}/{repo}/")))
+        {
+            tracing::info!("Skip {owner}/{repo}, which was done in the previous run");
+            continue;
+        }
+        let cirrus = cirrus_api(&ci_token)?;
+        let pulls_api = github.pulls(&owner, &repo);
+        let slug = util::Slug {
+            owner: owner.clone(),
+            repo: repo.clone(),
+        };
+        // The number of each pull, and whether it is mergeable, if known
+        let pulls = if let (true, Some(facts)) = (args.failed_from_pull_facts, &facts) {
+            tracing::info!("Get pulls with failed CI for {owner}/{repo} from the pull facts ...");
+            facts
+                .read()
+                .expect("pull facts file error")
+                .failed_ci(&slug)
+                .into_iter()
+                .map(|number| (number, None))
+                .collect::<Vec<_>>()
+        } else if args.only_labeled {
+            tracing::info!(
+                "Get open pulls labeled '{}' for {}/{} ...",
+                args.ci_failed_label,
+                owner,
+                repo
+            );
+            let query = format!(
+                "repo:{owner}/{repo} is:open is:pr label:\"{label}\"",
+                label = args.ci_failed_label
+            );
+            util::with_retry(|| async {
+                github
+                    .all_pages(
+                        github
+                            .search()
+                            .issues_and_pull_requests(&query)
+                            .sort("updated")
+                            .order("desc")
+                            .send()
+                            .await?,
+                    )
+                    .await
+            })
+            .await?
+            .into_iter()
+            .map(|i| (i.number, None))
+            .collect::<Vec<_>>()
+        } else {
+            tracing::info!("Get open pulls for {}/{} ...", owner, repo);
+            util::open_pulls(&github, &slug)
+                .await?
+                .into_iter()
+                .map(|p| (p.number, p.mergeable))
+                .collect::<Vec<_>>()
+        };
+        tracing::info!("Open pulls: {}", pulls.len());
+        let pull_id = |number: u64| format!("{owner}/{repo}/{number}");
+        let skip = match resume.take() {
+            Some(id) => pulls
+                .iter()
+                .position(|(n, _)| pull_id(*n) == id)
+                .map_or(0, |pos| pos + 1),
+            None => 0,
+        };
+        let mut progress = util::Progress::new(pulls.len() - skip, deadline);
+        for (pull_num, mergeable) in pulls.iter().skip(skip) {
+            if progress.out_of_time() {
+                tracing::info!("Max runtime reached, exiting");
+                return Ok(());
+            }
+            let id = pull_id(*pull_num);
+            progress.set_api_calls(
+                api_start.saturating_sub(util::get_rate_limit_remaining(&github).await?),
+            );
+            progress.inc(&id);
+            if let Some(c) = &checkpoint {
+                c.save(&id);
+            }
+            let mergeable = match mergeable {
+                Some(mergeable) => *mergeable,
+                None => {
+                    let pull = util::get_pull_mergeable(
+                        &pulls_api,
+                        *pull_num,
+                        mergeable_fallback.as_ref(),
+                    )
+                    .await?;
+                    match pull {
+                        None => {
+                            continue;
+                        }
+                        Some(p) => p.mergeable.unwrap(),
+                    }
+                }
+            };
+            if !mergeable {
+                continue;
+            }
+            let tasks = match cirrus_tasks(&cirrus, &owner, &repo, *pull_num).await {
+                Ok(tasks) => tasks,
+                Err(msg) => {
+                    tracing::info!("{msg}");
+                    continue;
+                }
+            };
+            rerun_tasks(&cirrus, &args.task, &tasks, args.dry_run).await;
+            std::thread::sleep(std::time::Duration::from_secs(args.sleep_min * 60));
+        }
+        progress.finish();
+    }
+    if let Some(c) = &checkpoint {
+        c.clear();
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_task() {
+        let tasks = serde_json::json!([
+            {"id": "1", "name": "lint"},
+            {"id": "2", "name": "ARM [unit tests]"},
+            {"id": "3", "name": "ARM [functional tests]"},
+        ]);
+        let tasks = tasks.as_array().unwrap();
+        assert_eq!(find_task("ARM", tasks), Ok(Some(("2", "ARM [unit tests]"))));
+        assert_eq!(find_task("tsan", tasks), Ok(None));
+        assert!(find_task("ARM", &[serde_json::json!({"name": "ARM"})]).is_err());
+    }
+
+    #[test]
+    fn test_rerun_outcome() {
+        let outcome = |res: serde_json::Value| rerun_outcome(serde_json::from_value(res).unwrap());
+        assert_eq!(
+            outcome(serde_json::json!({"data": {"rerun": {"newTask": {"id": "7"}}}})),
+            Rerun::Accepted("7".to_string())
+        );
+        assert_eq!(
+            outcome(serde_json::json!({"data": {"rerun": null}, "errors": [
+                {"message": "Task is too old"},
+                {"message": "Try again"},
+            ]})),
+            Rerun::Rejected("Task is too old; Try again".to_string())
+        );
+        assert!(matches!(
+            outcome(serde_json::json!({"data": null})),
+            Rerun::Rejected(_)
+        ));
+    }
+
+    #[test]
+    fn test_failed_tasks() {
+        let tasks = serde_json::json!([
+            {"id": "1", "name": "lint", "status": "COMPLETED"},
+            {"id": "2", "name": "ARM [unit tests]", "status": "FAILED"},
+            {"id": "3", "name": "ARM [functional tests]", "status": "ABORTED"},
+        ]);
+        let failed = failed_tasks(tasks.as_array().unwrap());
+        assert_eq!(
+            find_task("ARM", &failed),
+            Ok(Some(("2", "ARM [unit tests]")))
+        );
+        assert_eq!(find_task("lint", &failed), Ok(None));
+    }
+}
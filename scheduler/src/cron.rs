@@ -0,0 +1,106 @@
+use chrono::{Datelike, Timelike};
+
+/// A parsed cron expression with the five classic fields: minute, hour, day of month, month, and
+/// day of week (0 or 7 is Sunday). Each field supports `*`, lists (`1,2`), ranges (`1-5`) and
+/// steps (`*/15`, `0-30/10`). Like in classic cron, when both the day of month and the day of
+/// week are restricted, either of them has to match. Times are evaluated in UTC.
+pub struct Schedule {
+    minutes: Vec<u32>,
+    hours: Vec<u32>,
+    days: Vec<u32>,
+    months: Vec<u32>,
+    weekdays: Vec<u32>,
+    /// Whether both the day of month and the day of week are restricted (do not start with `*`).
+    either_day: bool,
+}
+
+fn parse_field(field: &str, min: u32, max: u32) -> Result<Vec<u32>, String> {
+    let err = || format!("Invalid cron field '{field}'");
+    let mut values = Vec::new();
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u32>().map_err(|_| err())?),
+            None => (part, 1),
+        };
+        let (start, end) = if range == "*" {
+            (min, max)
+        } else if let Some((start, end)) = range.split_once('-') {
+            (
+                start.parse().map_err(|_| err())?,
+                end.parse().map_err(|_| err())?,
+            )
+        } else {
+            let v = range.parse().map_err(|_| err())?;
+            (v, if step == 1 { v } else { max })
+        };
+        if step == 0 || start < min || end > max || start > end {
+            return Err(err());
+        }
+        values.extend((start..=end).step_by(step as usize));
+    }
+    Ok(values)
+}
+
+impl std::str::FromStr for Schedule {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let fields = s.split_whitespace().collect::<Vec<_>>();
+        let [minutes, hours, days, months, weekdays] = fields[..] else {
+            return Err(format!("Expected five fields in cron expression '{s}'"));
+        };
+        Ok(Self {
+            minutes: parse_field(minutes, 0, 59)?,
+            hours: parse_field(hours, 0, 23)?,
+            days: parse_field(days, 1, 31)?,
+            months: parse_field(months, 1, 12)?,
+            weekdays: parse_field(weekdays, 0, 7)?
+                .into_iter()
+                .map(|d| d % 7)
+                .collect(),
+            either_day: !days.starts_with('*') && !weekdays.starts_with('*'),
+        })
+    }
+}
+
+impl Schedule {
+    /// Whether the schedule fires in the minute of the given time.
+    pub fn matches(&self, t: &chrono::DateTime<chrono::Utc>) -> bool {
+        let day = self.days.contains(&t.day());
+        let weekday = self.weekdays.contains(&t.weekday().num_days_from_sunday());
+        self.minutes.contains(&t.minute())
+            && self.hours.contains(&t.hour())
+            && self.months.contains(&t.month())
+            && if self.either_day {
+                day || weekday
+            } else {
+                day && weekday
+            }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(s: &str) -> chrono::DateTime<chrono::Utc> {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_schedule() {
+        let s: Schedule = "*/15 2-3 * * 1-5".parse().unwrap();
+        assert!(s.matches(&at("2024-05-06T02:30:00Z"))); // Monday
+        assert!(!s.matches(&at("2024-05-06T02:31:00Z")));
+        assert!(!s.matches(&at("2024-05-06T04:30:00Z")));
+        assert!(!s.matches(&at("2024-05-05T02:30:00Z"))); // Sunday
+
+        let s: Schedule = "0 0 1,15 * 7".parse().unwrap();
+        assert!(s.matches(&at("2024-09-08T00:00:00Z"))); // Sunday
+        assert!(s.matches(&at("2024-05-15T00:00:00Z"))); // Wednesday
+        assert!(!s.matches(&at("2024-05-14T00:00:00Z"))); // Tuesday
+
+        assert!("* * *".parse::<Schedule>().is_err());
+        assert!("60 * * * *".parse::<Schedule>().is_err());
+        assert!("*/0 * * * *".parse::<Schedule>().is_err());
+    }
+}
@@ -0,0 +1,111 @@
+use std::fmt::Write as _;
+use std::io::Write as _;
+
+use tracing_subscriber::registry::LookupSpan;
+
+/// The name of the span that a job runs in. Its `name` field is the name of the job.
+pub const SPAN: &str = "job";
+
+/// A log layer that appends the events inside a job span to the log file of the job, so that the
+/// output of a failed run can be looked at later.
+pub struct JobLog {
+    pub dir: std::path::PathBuf,
+}
+
+/// The name of the job, stored in the extensions of its span.
+struct JobName(String);
+
+#[derive(Default)]
+struct Fields {
+    job: Option<String>,
+    text: String,
+}
+
+impl tracing::field::Visit for Fields {
+    fn record_str(&mut self, field: &tracing::field::Field, value: &str) {
+        match field.name() {
+            "name" => self.job = Some(value.to_string()),
+            "message" => self.text.insert_str(0, value),
+            f => write!(self.text, " {f}={value}").unwrap(),
+        }
+    }
+
+    fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+        self.record_str(field, &format!("{value:?}"));
+    }
+}
+
+impl<S> tracing_subscriber::Layer<S> for JobLog
+where
+    S: tracing::Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(
+        &self,
+        attrs: &tracing::span::Attributes<'_>,
+        id: &tracing::span::Id,
+        ctx: tracing_subscriber::layer::Context<'_, S>,
+    ) {
+        if attrs.metadata().name() != SPAN {
+            return;
+        }
+        let mut fields = Fields::default();
+        attrs.record(&mut fields);
+        if let (Some(job), Some(span)) = (fields.job, ctx.span(id)) {
+            span.extensions_mut().insert(JobName(job));
+        }
+    }
+
+    fn on_event(&self, event: &tracing::Event<'_>, ctx: tracing_subscriber::layer::Context<'_, S>) {
+        let Some(job) = ctx.event_scope(event).and_then(|scope| {
+            scope
+                .from_root()
+                .find_map(|s| s.extensions().get::<JobName>().map(|j| j.0.clone()))
+        }) else {
+            return;
+        };
+        let mut fields = Fields::default();
+        event.record(&mut fields);
+        let line = format!(
+            "{} {} {}",
+            chrono::Utc::now().to_rfc3339(),
+            event.metadata().level(),
+            fields.text
+        );
+        let res = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.dir.join(format!("{job}.log")))
+            .and_then(|mut f| writeln!(f, "{line}"));
+        if let Err(e) = res {
+            eprintln!("Failed to write the log of job {job}: {e}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    #[test]
+    fn test_job_log() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let subscriber = tracing_subscriber::registry().with(JobLog {
+            dir: dir.path().to_path_buf(),
+        });
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!("Outside of any job");
+            let span = tracing::info_span!(SPAN, name = "stale");
+            let _enter = span.enter();
+            tracing::info!(pull = 5, "Mark {}", "stale");
+            tracing::info_span!("inner").in_scope(|| tracing::warn!("Nested"));
+        });
+        let log = std::fs::read_to_string(dir.path().join("stale.log")).unwrap();
+        let lines = log
+            .lines()
+            .map(|l| l.split_once(' ').unwrap().1)
+            .collect::<Vec<_>>();
+        assert_eq!(lines, ["INFO Mark stale pull=5", "WARN Nested"]);
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+}
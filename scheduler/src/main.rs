@@ -0,0 +1,276 @@
+mod cron;
+mod job_log;
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use chrono::DurationRound;
+use clap::Parser;
+use tracing::Instrument;
+
+#[derive(clap::Parser)]
+#[command(about = "Run the poll-based tools on a schedule, in one process.", long_about = None)]
+struct Args {
+    /// The path to the yaml config file of the bot, with the jobs in its schedule section.
+    #[arg(long)]
+    config_file: std::path::PathBuf,
+    /// The json file to write the status of each job to, for dashboards.
+    #[arg(long)]
+    status_file: Option<std::path::PathBuf>,
+    /// The folder to write the log of each job to, one file per job.
+    #[arg(long)]
+    log_dir: Option<std::path::PathBuf>,
+    /// The format of the log output: pretty or json.
+    #[arg(long, default_value = "pretty")]
+    log_format: util::LogFormat,
+}
+
+#[derive(serde::Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "snake_case")]
+enum Tool {
+    Conflicts,
+    LockArchive,
+    RerunCi,
+    Stale,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Job {
+    /// A unique name for the job.
+    name: String,
+    tool: Tool,
+    /// When to run. See cron::Schedule for the format.
+    cron: String,
+    /// The command line arguments to pass to the tool.
+    #[serde(default)]
+    args: Vec<String>,
+}
+
+/// The part of the config of the bot that is read by the scheduler. The other sections are read
+/// by webhook_features.
+#[derive(serde::Deserialize)]
+struct Config {
+    #[serde(default)]
+    schedule: Vec<Job>,
+}
+
+#[derive(serde::Serialize, Default, Clone)]
+struct JobStatus {
+    running: bool,
+    last_start: Option<chrono::DateTime<chrono::Utc>>,
+    last_end: Option<chrono::DateTime<chrono::Utc>>,
+    /// None on success, otherwise the error.
+    last_error: Option<String>,
+    runs: u64,
+    failures: u64,
+    /// Runs skipped, because the previous run was still going.
+    skipped_overlaps: u64,
+}
+
+type Status = Arc<Mutex<HashMap<String, JobStatus>>>;
+
+fn tool_name(tool: Tool) -> &'static str {
+    match tool {
+        Tool::Conflicts => "conflicts",
+        Tool::LockArchive => "lock_archive",
+        Tool::RerunCi => "rerun_ci",
+        Tool::Stale => "stale",
+    }
+}
+
+/// Parse the args early, so that a config error is reported on startup and not hours later.
+fn check_args(tool: Tool, args: &[String]) -> Result<(), String> {
+    let argv = std::iter::once(tool_name(tool).to_string()).chain(args.iter().cloned());
+    match tool {
+        Tool::Conflicts => conflicts::Args::try_parse_from(argv).map(|_| ()),
+        Tool::LockArchive => lock_archive::Args::try_parse_from(argv).map(|_| ()),
+        Tool::RerunCi => rerun_ci::Args::try_parse_from(argv).map(|_| ()),
+        Tool::Stale => stale::Args::try_parse_from(argv).map(|_| ()),
+    }
+    .map_err(|e| e.to_string())
+}
+
+/// Run the tool to completion, by calling its entry point.
+async fn run_tool(tool: Tool, args: Vec<String>) -> Result<(), String> {
+    let argv = std::iter::once(tool_name(tool).to_string()).chain(args);
+    match tool {
+        Tool::Conflicts => {
+            conflicts::run(conflicts::Args::try_parse_from(argv).map_err(|e| e.to_string())?).await
+        }
+        Tool::LockArchive => {
+            lock_archive::run(lock_archive::Args::try_parse_from(argv).map_err(|e| e.to_string())?)
+                .await
+        }
+        Tool::RerunCi => {
+            rerun_ci::run(rerun_ci::Args::try_parse_from(argv).map_err(|e| e.to_string())?).await
+        }
+        Tool::Stale => {
+            stale::run(stale::Args::try_parse_from(argv).map_err(|e| e.to_string())?).await
+        }
+    }
+    .map_err(|e| e.to_string())
+}
+
+fn write_status(status_file: &Option<std::path::PathBuf>, status: &Status) {
+    if let Some(path) = status_file {
+        let status = status.lock().unwrap().clone();
+        let tmp = path.with_extension("tmp");
+        std::fs::write(
+            &tmp,
+            serde_json::to_vec_pretty(&status).expect("json error"),
+        )
+        .expect("status write error");
+        std::fs::rename(tmp, path).expect("status write error");
+    }
+}
+
+fn start_job(
+    rt: &tokio::runtime::Runtime,
+    job: &Job,
+    status_file: &Option<std::path::PathBuf>,
+    status: &Status,
+) {
+    let span = tracing::info_span!(job_log::SPAN, name = %job.name);
+    let overlap = {
+        let mut all = status.lock().unwrap();
+        let s = all.get_mut(&job.name).unwrap();
+        if s.running {
+            s.skipped_overlaps += 1;
+            true
+        } else {
+            s.running = true;
+            s.last_start = Some(chrono::Utc::now());
+            false
+        }
+    };
+    write_status(status_file, status);
+    if overlap {
+        span.in_scope(|| tracing::warn!("Skip, previous run still going"));
+        return;
+    }
+
+    let (name, tool, tool_args) = (job.name.clone(), job.tool, job.args.clone());
+    let (status_file, status) = (status_file.clone(), status.clone());
+    rt.spawn(
+        async move {
+            tracing::info!("Start");
+            // Run the tool in its own task, to catch a panic
+            let res = tokio::spawn(run_tool(tool, tool_args).in_current_span())
+                .await
+                .unwrap_or_else(|_| Err("panicked".to_string()));
+            {
+                let mut status = status.lock().unwrap();
+                let s = status.get_mut(&name).unwrap();
+                s.running = false;
+                s.last_end = Some(chrono::Utc::now());
+                s.runs += 1;
+                if res.is_err() {
+                    s.failures += 1;
+                }
+                s.last_error = res.clone().err();
+            }
+            write_status(&status_file, &status);
+            match res {
+                Ok(()) => tracing::info!("Done"),
+                Err(e) => tracing::error!("FAILED: {e}"),
+            }
+        }
+        .instrument(span),
+    );
+}
+
+fn main() {
+    let args = Args::parse();
+    if let Some(dir) = &args.log_dir {
+        std::fs::create_dir_all(dir).expect("Failed to create log folder");
+    }
+    util::init_logging_with(
+        args.log_format,
+        args.log_dir.clone().map(|dir| {
+            let layer: util::LogLayer = Box::new(job_log::JobLog { dir });
+            layer
+        }),
+    );
+
+    let config: Config = serde_yaml::from_reader(
+        std::fs::File::open(&args.config_file).expect("config file path error"),
+    )
+    .expect("yaml error");
+    let schedules = parse_schedule(&config.schedule).unwrap_or_else(|e| panic!("{e}"));
+    let status: Status = Arc::new(Mutex::new(
+        config
+            .schedule
+            .iter()
+            .map(|j| (j.name.clone(), JobStatus::default()))
+            .collect(),
+    ));
+    write_status(&args.status_file, &status);
+
+    tracing::info!("Scheduled jobs:");
+    for job in &config.schedule {
+        tracing::info!(" - {} ({:?}): {}", job.name, job.tool, job.cron);
+    }
+
+    // The jobs run on the runtime, while this thread only starts them, so that a long blocking
+    // call in a job can not delay the start of another one.
+    let rt = tokio::runtime::Runtime::new().expect("runtime error");
+    loop {
+        // Sleep until the start of the next minute
+        let now = chrono::Utc::now();
+        let next = now
+            .duration_trunc(chrono::Duration::minutes(1))
+            .expect("time error")
+            + chrono::Duration::minutes(1);
+        std::thread::sleep((next - now).to_std().unwrap_or_default());
+
+        for (job, schedule) in config.schedule.iter().zip(&schedules) {
+            if schedule.matches(&next) {
+                start_job(&rt, job, &args.status_file, &status);
+            }
+        }
+    }
+}
+
+/// Parse the schedule and the args of each job.
+fn parse_schedule(jobs: &[Job]) -> Result<Vec<cron::Schedule>, String> {
+    let mut schedules = Vec::new();
+    for job in jobs {
+        if jobs.iter().filter(|j| j.name == job.name).count() > 1 {
+            return Err(format!("Job {}: duplicate name", job.name));
+        }
+        let schedule = job
+            .cron
+            .parse::<cron::Schedule>()
+            .map_err(|e| format!("Job {}: {e}", job.name))?;
+        check_args(job.tool, &job.args).map_err(|e| format!("Job {}: {e}", job.name))?;
+        schedules.push(schedule);
+    }
+    Ok(schedules)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config() {
+        let config: Config =
+            serde_yaml::from_str(include_str!("../../webhook_features/config.yml")).unwrap();
+        assert_eq!(
+            config
+                .schedule
+                .iter()
+                .map(|j| &j.name[..])
+                .collect::<Vec<_>>(),
+            ["stale", "lock_archive"]
+        );
+        assert_eq!(parse_schedule(&config.schedule).unwrap().len(), 2);
+
+        let config: Config = serde_yaml::from_str(
+            "schedule:\n  - {name: a, tool: stale, cron: '0 * * * *', args: [--unknown]}\n",
+        )
+        .unwrap();
+        assert!(matches!(parse_schedule(&config.schedule), Err(e) if e.starts_with("Job a: ")));
+    }
+}
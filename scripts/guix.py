@@ -1,23 +1,39 @@
 from github import Github, GithubException
+import hashlib
 import platform
 import time
 import itertools
 import shutil
 import argparse
+import fcntl
 import os
 import sys
 import tempfile
 import subprocess
 
-from util.util import return_with_pull_metadata, call_git, get_git, calculate_table
+from util.artifact_store import ArtifactStore
+from util.build_state import BuildState
+from util.health import check_builder_health, write_heartbeat
+from util.manifest import RESULT_MANIFEST, read_result_manifest, write_result_manifest
+from util.util import return_with_pull_metadata, call_git, get_git, calculate_table, head_commit_marker, with_footer
 
-ID_GUIX_COMMENT = '<!--9cd9c72976c961c55c7acef8f6ba82cd-->'
+ID_GUIX_COMMENT = '<!--drahtbot:guix-results:v1-->'
 UPSTREAM_PULL = 'upstream-pull'
+DOCKER_IMAGE = 'ubuntu:noble'
+# Set on the docker containers, with the guix folder as value, to find them on --cleanup
+DOCKER_LABEL = 'drahtbot.guix_folder'
+# Large artifacts can take diffoscope a long time, so give up after this many seconds per file
+DIFFOSCOPE_TIMEOUT = 900
+# The number of lines at the end of the build log to show, when the build failed
+FAILURE_LOG_LINES = 40
 
 # Only update this after the change is merged to the main development branch of --github_repo
 # wget https://bitcoincore.org/depends-sources/sdks/Xcode-15.0-15A240d-extracted-SDK-with-libcxx-headers.tar.gz
 CURRENT_XCODE_FILENAME = "Xcode-15.0-15A240d-extracted-SDK-with-libcxx-headers.tar.gz"
 
+GUIX_BINARY = 'guix-binary-1.4.0.x86_64-linux.tar.xz'
+GUIX_BINARY_URL = 'https://ftp.gnu.org/gnu/guix/{}'.format(GUIX_BINARY)
+
 
 def calculate_diffs(folder_1, folder_2):
     EXTENSIONS = ['.log']
@@ -30,17 +46,36 @@ def calculate_diffs(folder_1, folder_2):
         subprocess.call('diff --color {} {} > {}.diff'.format(file_1, file_2, f), shell=True)
 
 
+def log_tail(path, num_lines):
+    """Return the last lines of the log, or an empty string if there is none."""
+    if not os.path.isfile(path):
+        return ''
+    with open(path, errors='replace') as f:
+        lines = f.read().splitlines()[-num_lines:]
+    # Keep a long line from blowing up the comment
+    return '\n'.join(line[:500] for line in lines)
+
+
 def main():
     THIS_FILE_PATH = os.path.abspath(os.path.dirname(os.path.realpath(__file__)))
     parser = argparse.ArgumentParser(description='Guix build and create an issue comment to share the results.', formatter_class=argparse.ArgumentDefaultsHelpFormatter)
-    parser.add_argument('--github_access_token', help='The access token for GitHub.', default='')
+    parser.add_argument('--github_access_token', help='The access token for GitHub. Default: the GITHUB_ACCESS_TOKEN environment variable.', default=os.environ.get('GITHUB_ACCESS_TOKEN', ''))
     parser.add_argument('--github_repo', help='The repo slug of the remote on GitHub.', default='bitcoin/bitcoin')
     parser.add_argument('--base_name', help='The name of the base branch.', default='master')
     parser.add_argument('--guix_folder', help='The local scratch folder for temp guix results', default=os.path.join(THIS_FILE_PATH, '..', 'scratch', 'guix'))
     parser.add_argument('--guix_jobs', help='The number of jobs', default=2)
     parser.add_argument('--domain', help='Where the assets are reachable', default='http://127.0.0.1')
+    parser.add_argument('--artifact_store', help='Where to publish the results. Format: /local/dir, ssh://user@host:/dir or s3://bucket/prefix', default='/var/www/html')
     parser.add_argument('--dry_run', help='Print changes/edits instead of calling the GitHub API.', action='store_true', default=False)
     parser.add_argument('--build_one_commit', help='Only build this one commit and exit.', default='')
+    parser.add_argument('--pull_number', help='Only build this one pull, if it is still labeled, and exit. Set by the webhook server once the label is added.', type=int, default=0)
+    parser.add_argument('--min_free_gb', help='The free disk space (in GB) in the guix folder required to start a build.', type=int, default=50)
+    parser.add_argument('--xcode_sdk_sha256', help='The expected hash of the Xcode SDK. Only checked if set.', default='')
+    parser.add_argument('--detached_sigs_repo', help='A checkout of the detached signatures (e.g. bitcoin-core/bitcoin-detached-sigs) for the built version. If set, the macOS and Windows artifacts are also codesigned after the build, like in the release process.', default='')
+    parser.add_argument('--diffoscope', help='Compare the artifacts that differ from the base build with diffoscope, and link the reports in the table.', action='store_true', default=False)
+    parser.add_argument('--container_runtime', help='The tool to run the build container with.', choices=['docker', 'podman'], default='docker')
+    parser.add_argument('--cleanup', help='Remove the docker containers left behind by crashed runs in this guix folder, and the result folders of incomplete builds, and exit.', action='store_true', default=False)
+    parser.add_argument('--heartbeat_file', help='Where to record the health of the builder. Default: heartbeat.json in the guix folder.', default='')
     args = parser.parse_args()
 
     print()
@@ -51,12 +86,53 @@ def main():
     print('# Then reboot')
     print()
     url = 'https://github.com/{}'.format(args.github_repo)
-    guix_www_folder = '/var/www/html/guix/{}/'.format(args.github_repo)
+    artifact_store = ArtifactStore(args.artifact_store)
+    artifact_dest = 'guix/{}'.format(args.github_repo)
+    guix_www_folder = artifact_store.local_path(artifact_dest)
     external_url = '{}/guix/{}/'.format(args.domain, args.github_repo)
     temp_dir = os.path.abspath(os.path.join(args.guix_folder, ''))
 
+    # The webhook server and cron may run the script on the same guix folder, which holds the
+    # build state and the docker containers, so one run waits for the other
+    os.makedirs(temp_dir, exist_ok=True)
+    lock = open(os.path.join(temp_dir, 'guix.lock'), 'w')
+    try:
+        fcntl.flock(lock, fcntl.LOCK_EX | fcntl.LOCK_NB)
+    except BlockingIOError:
+        print('Wait for the other run in {} ...'.format(temp_dir))
+        fcntl.flock(lock, fcntl.LOCK_EX)
+    state = BuildState(os.path.join(temp_dir, 'build_state.json'))
+
+    if args.cleanup:
+        print('Remove docker containers of previous runs ...')
+        containers = subprocess.check_output([args.container_runtime, 'ps', '--all', '--quiet', '--filter', 'label={}={}'.format(DOCKER_LABEL, temp_dir)], universal_newlines=True).split()
+        for c in containers:
+            print('docker rm --force {}'.format(c))
+            if not args.dry_run:
+                subprocess.check_call([args.container_runtime, 'rm', '--force', c])
+        www_folders = [f for f in [guix_www_folder, os.path.join(temp_dir, 'www_staging')] if f and os.path.isdir(f)]
+        for www_folder in www_folders:
+            for commit in sorted(os.listdir(www_folder)):
+                folder = os.path.join(www_folder, commit)
+                if os.path.isdir(folder) and read_result_manifest(folder) is None:
+                    print('Remove incomplete results {}'.format(folder))
+                    if not args.dry_run:
+                        shutil.rmtree(folder)
+        print('Exit')
+        return
+
     if args.dry_run:
-        guix_www_folder = os.path.join(temp_dir, 'www_output')
+        artifact_store = ArtifactStore(os.path.join(temp_dir, 'www_output'))
+        guix_www_folder = artifact_store.local_path(artifact_dest)
+    elif guix_www_folder is None:
+        # Stage the results locally and publish them to the remote store after each build
+        guix_www_folder = os.path.join(temp_dir, 'www_staging')
+        print('Clean guix staging folder of old files')
+        # Keep the completed builds of an interrupted run, to resume from them
+        keep = state.commits()
+        for commit in os.listdir(guix_www_folder) if os.path.isdir(guix_www_folder) else []:
+            if commit not in keep:
+                shutil.rmtree(os.path.join(guix_www_folder, commit), ignore_errors=True)
     else:
         print('Clean guix folder of old files')
         subprocess.check_call('find {} -mindepth 1 -maxdepth 1 -type d -ctime +{} | xargs rm -rf'.format(guix_www_folder, 15), shell=True)
@@ -73,6 +149,20 @@ def main():
     os.makedirs(guix_store_dir, exist_ok=True)
     os.makedirs(guix_bin_dir, exist_ok=True)
 
+    print('Check builder health ...')
+    problems = check_builder_health(
+        work_dir=temp_dir,
+        min_free_gb=args.min_free_gb,
+        sdk_path=os.path.join(THIS_FILE_PATH, CURRENT_XCODE_FILENAME),
+        sdk_sha256=args.xcode_sdk_sha256,
+        guix_installed=bool(os.listdir(guix_store_dir)) or os.path.isfile(os.path.join(temp_dir, GUIX_BINARY)),
+        guix_url=GUIX_BINARY_URL,
+    )
+    write_heartbeat(args.heartbeat_file or os.path.join(temp_dir, 'heartbeat.json'), problems)
+    if problems:
+        print('Builder unhealthy. Exiting without starting a build ...')
+        sys.exit(1)
+
     if not os.path.isdir(git_repo_dir):
         print('Clone {} repo to {}'.format(url, git_repo_dir))
         os.chdir(temp_dir)
@@ -85,10 +175,11 @@ def main():
             f.write('    fetch = +refs/pull/*:refs/remotes/upstream-pull/*\n')
             f.flush()
 
+    detached_sigs_dir = os.path.abspath(args.detached_sigs_repo) if args.detached_sigs_repo else ''
     print('Start docker process ...')
     docker_id = subprocess.check_output(
         [
-            'docker',
+            args.container_runtime,
             'run',
             '-idt',
             '--rm',
@@ -96,11 +187,13 @@ def main():
             '--volume={}:{}:rw,z'.format(guix_store_dir, '/gnu'),
             '--volume={}:{}:rw,z'.format(guix_bin_dir, '/var/guix'),
             '--volume={}:{}:rw,z'.format(temp_dir, temp_dir),
+            '--label={}={}'.format(DOCKER_LABEL, temp_dir),
+        ] + (['--volume={}:{}:ro,z'.format(detached_sigs_dir, detached_sigs_dir)] if detached_sigs_dir else []) + [
             #'--mount', # Doesn't work with fedora (needs rw,z)
             #'type=bind,src={},dst={}'.format(dir_code, dir_code),
             #'-e',
             #'LC_ALL=C.UTF-8',
-            'ubuntu:noble',
+            DOCKER_IMAGE,
         ],
         universal_newlines=True,
     ).strip()
@@ -110,13 +203,18 @@ def main():
 
     def docker_exec(cmd, *, ignore_ret_code=False):
         scall = subprocess.call if ignore_ret_code else subprocess.check_call
-        scall(['docker', 'exec', docker_id, 'bash', '-c', 'export FORCE_DIRTY_WORKTREE=1 && export TMPDIR=/guix_temp_dir/ && {} && cd {} && {}'.format(docker_bash_prefix[0], os.getcwd(), cmd)], universal_newlines=True)
+        return scall([args.container_runtime, 'exec', docker_id, 'bash', '-c', 'export FORCE_DIRTY_WORKTREE=1 && export TMPDIR=/guix_temp_dir/ && {} && cd {} && {}'.format(docker_bash_prefix[0], os.getcwd(), cmd)], universal_newlines=True)
+
+    def docker_output(cmd):
+        return subprocess.check_output([args.container_runtime, 'exec', docker_id, 'bash', '-c', '{} && cd {} && {}'.format(docker_bash_prefix[0], os.getcwd(), cmd)], universal_newlines=True).strip()
 
     docker_exec('mkdir /guix_temp_dir/')
 
     print('Installing packages ...')
     docker_exec('apt-get update')
     docker_exec('apt-get install -qq {}'.format('netbase wget xz-utils git make curl'))
+    if args.diffoscope:
+        docker_exec('apt-get install -qq --no-install-recommends diffoscope-minimal')
 
     print('Fetch upsteam pulls')
     os.chdir(git_repo_dir)
@@ -125,9 +223,10 @@ def main():
     os.chdir(temp_dir)
     if not os.listdir(guix_store_dir):
         print('Install guix')
-        docker_exec("wget https://ftp.gnu.org/gnu/guix/guix-binary-1.4.0.x86_64-linux.tar.xz")
-        docker_exec('echo "236ca7c9c5958b1f396c2924fcc5bc9d6fdebcb1b4cf3c7c6d46d4bf660ed9c9  ./guix-binary-1.4.0.x86_64-linux.tar.xz" | sha256sum -c')
-        docker_exec("tar -xf ./guix-binary-1.4.0.x86_64-linux.tar.xz")
+        if not os.path.isfile(os.path.join(temp_dir, GUIX_BINARY)):
+            docker_exec("wget {}".format(GUIX_BINARY_URL))
+        docker_exec('echo "236ca7c9c5958b1f396c2924fcc5bc9d6fdebcb1b4cf3c7c6d46d4bf660ed9c9  ./{}" | sha256sum -c'.format(GUIX_BINARY))
+        docker_exec("tar -xf ./{}".format(GUIX_BINARY))
         docker_exec("mv var/guix/* /var/guix && mv gnu/* /gnu/")
 
     docker_exec('mkdir -p /config_guix/')
@@ -140,6 +239,10 @@ def main():
     docker_exec('guix archive --authorize < /config_guix/current/share/guix/ci.guix.info.pub')
 
     def call_guix_build(*, commit):
+        """
+        Return the output folder, whether the build succeeded, and whether the codesign succeeded
+        (None, if it did not run). On failure of the build, the folder only has the log.
+        """
         os.chdir(git_repo_dir)
         docker_exec("chown -R root:root ./")
         docker_exec("git clean -dfx")
@@ -152,20 +255,140 @@ def main():
         docker_exec(f"tar -xf {temp_dir}/{CURRENT_XCODE_FILENAME} --directory {git_repo_dir}/depends/SDKs/")
         docker_exec("sed -i -e 's/--disable-bench //g' $(git grep -l disable-bench ./contrib/guix/)")
         docker_exec("sed -i '/ x86_64-w64-mingw32$/d' ./contrib/guix/guix-build")  # For now, until guix 1.5
-        docker_exec(f"( guix-daemon --build-users-group=guixbuild & (export V=1 && export VERBOSE=1 && export MAX_JOBS={args.guix_jobs} && export SOURCES_PATH={depends_sources_dir} && ./contrib/guix/guix-build > {git_repo_dir}/outerr 2>&1 ) && kill %1 )", ignore_ret_code=True)
+        ret = docker_exec(f"( guix-daemon --build-users-group=guixbuild & (export V=1 && export VERBOSE=1 && export MAX_JOBS={args.guix_jobs} && export SOURCES_PATH={depends_sources_dir} && ./contrib/guix/guix-build > {git_repo_dir}/outerr 2>&1 ) ; ret=$? ; kill %1 ; exit $ret )", ignore_ret_code=True)
+        success = ret == 0
+        if not success:
+            print('Guix build of {} failed with exit code {}'.format(commit, ret))
+        codesigned = None
+        if success and detached_sigs_dir:
+            # Attach the detached signatures to the unsigned macOS and Windows artifacts. The
+            # codesigned artifacts end up in their own output folders, and thus in the result
+            # table. The signatures only match the release they were made for, so this is a
+            # separate step, whose failure does not fail the build.
+            ret = docker_exec(f"( guix-daemon --build-users-group=guixbuild & (export V=1 && export VERBOSE=1 && export DETACHED_SIGS_REPO={detached_sigs_dir} && ./contrib/guix/guix-codesign > {git_repo_dir}/codesign_outerr 2>&1 ) ; ret=$? ; kill %1 ; exit $ret )", ignore_ret_code=True)
+            codesigned = ret == 0
+            if not codesigned:
+                print('Guix codesign of {} failed with exit code {}'.format(commit, ret))
         docker_exec("rm -rf {}/*".format(depends_cache_dir))
         os.makedirs(depends_cache_subdir, exist_ok=True)
-        docker_exec(f"mv {git_repo_dir}/depends/built {depends_cache_subdir}/built")
+        # A failed build may have stopped before any of these exist
+        docker_exec(f"mv {git_repo_dir}/depends/built {depends_cache_subdir}/built", ignore_ret_code=not success)
         output_dir = os.path.join(git_repo_dir, 'guix-build-output')
-        docker_exec(f"mv {git_repo_dir}/guix-build-*/output {output_dir}")
-        docker_exec(f"mv {git_repo_dir}/outerr {output_dir}/guix_build.log")
+        docker_exec(f"mv {git_repo_dir}/guix-build-*/output {output_dir}", ignore_ret_code=not success)
+        docker_exec(f"mkdir -p {output_dir}")
         docker_exec(f"for i in {output_dir}/* ; do mv $i/* {output_dir}/ ; done", ignore_ret_code=True)
         docker_exec(f"for i in {output_dir}/* ; do rmdir $i ; done", ignore_ret_code=True)
-        return output_dir
+        docker_exec(f"mv {git_repo_dir}/outerr {output_dir}/guix_build.log")
+        if codesigned is not None:
+            docker_exec(f"mv {git_repo_dir}/codesign_outerr {output_dir}/guix_codesign.log")
+        return output_dir, success, codesigned
+
+    def get_environment():
+        environment = {
+            'guix_version': docker_output('guix --version | head -1'),
+            # The guix channel commit pins all compilers and other build tools
+            'guix_describe': docker_output('guix describe --format=channels'),
+            'image_digest': subprocess.check_output([args.container_runtime, 'image', 'inspect', '--format', '{{index .RepoDigests 0}}', DOCKER_IMAGE], universal_newlines=True).strip(),
+            'builder': hashlib.sha256(platform.node().encode()).hexdigest()[:16],
+            'machine': platform.machine(),
+        }
+        if detached_sigs_dir:
+            environment['detached_sigs'] = get_git(['-C', detached_sigs_dir, 'rev-parse', 'HEAD'])
+        return environment
+
+    def timed_guix_build(*, commit):
+        start = time.time()
+        output_dir, success, codesigned = call_guix_build(commit=commit)
+        return output_dir, int(time.time() - start), success, codesigned
+
+    def diffoscope_report(file_1, file_2):
+        if file_2.endswith(('.log', '.diff')):
+            return None
+        # Only the scratch folder is mounted in the container, so compare copies in there
+        work_dir = os.path.join(temp_dir, 'diffoscope')
+        shutil.rmtree(work_dir, ignore_errors=True)
+        os.makedirs(work_dir)
+        base_file = shutil.copy(file_1, os.path.join(work_dir, 'base_' + os.path.basename(file_1)))
+        pull_file = shutil.copy(file_2, os.path.join(work_dir, 'pull_' + os.path.basename(file_2)))
+        report = os.path.basename(file_2) + '.diffoscope.html'
+        os.chdir(work_dir)
+        print('Run diffoscope on {} ...'.format(os.path.basename(file_2)))
+        docker_exec('timeout {} diffoscope --html {} {} {}'.format(DIFFOSCOPE_TIMEOUT, report, base_file, pull_file), ignore_ret_code=True)
+        if not os.path.isfile(os.path.join(work_dir, report)):
+            return None
+        shutil.move(os.path.join(work_dir, report), os.path.join(os.path.dirname(file_2), report))
+        return report
+
+    def resumed_build(kind, commit, pulls):
+        """Return the folder and manifest of a completed build of one of the pulls, or None."""
+        folder = os.path.join(guix_www_folder, commit)
+        manifest = read_result_manifest(folder)
+        if manifest is None or not any(state.done(p.number, kind, commit) for p in pulls):
+            return None
+        print('Resume from the completed build of {} in {}'.format(commit, folder))
+        return folder, manifest
+
+    def write_manifest(folder, *, commit, environment, build_seconds, codesigned):
+        links = {'log': '{}{}/guix_build.log'.format(external_url, commit)}
+        if codesigned is False:
+            # Also marks the failure for resumed builds
+            links['codesign_log'] = '{}{}/guix_codesign.log'.format(external_url, commit)
+        return write_result_manifest(
+            folder,
+            commit=commit,
+            duration_seconds=build_seconds,
+            toolchain=environment,
+            links=links,
+        )
+
+    def codesign_notes(manifests):
+        text = ''
+        for m in manifests:
+            if 'codesign_log' in m['links']:
+                text += 'Codesigning the artifacts of commit {} with the detached signatures failed, see the [codesign log]({}). '.format(m['commit'], m['links']['codesign_log'])
+                text += 'This is expected, unless the signatures were made for this version. Only the unsigned artifacts are compared.\n\n'
+        return text
+
+    def environment_block(manifests):
+        environment = manifests[0]['toolchain']
+        text = '<details><summary>Build environment</summary>\n\n'
+        text += '| Key | Value |\n'
+        text += '|--|--|\n'
+        text += '| guix | `{}` |\n'.format(environment['guix_version'])
+        text += '| guix channel | `{}` |\n'.format(' '.join(environment['guix_describe'].split()))
+        text += '| image | `{}` |\n'.format(environment['image_digest'])
+        text += '| builder | `{}` ({}) |\n'.format(environment['builder'], environment['machine'])
+        if 'detached_sigs' in environment:
+            text += '| detached signatures | `{}` |\n'.format(environment['detached_sigs'])
+        for m in manifests:
+            text += '| build time {} | {} min |\n'.format(m['commit'][:16], m['duration_seconds'] // 60)
+        text += '\n</details>\n'
+        return text
+
+    def failure_comment(commit, head, folder):
+        text = ID_GUIX_COMMENT
+        text += '\n'
+        text += head_commit_marker(head)
+        text += '\n'
+        text += '### Guix build failed (on {})\n\n'.format(platform.machine())
+        text += 'The guix build of commit {} ({} and this pull) failed. '.format(commit, args.base_name)
+        text += 'These are the last lines of the [build log]({}{}/guix_build.log):\n\n'.format(external_url, commit)
+        text += '<details><summary>Build log excerpt</summary>\n\n'
+        text += '````\n{}\n````\n\n'.format(log_tail(os.path.join(folder, 'guix_build.log'), FAILURE_LOG_LINES))
+        text += '</details>\n\n'
+        text += 'The label is kept, and the build is retried once the pull request or the {} branch changes. '.format(args.base_name)
+        text += 'If the failure is unrelated to this pull request (e.g. a network error while downloading sources), remove and re-add the label "{}" to retry now.\n'.format(label_needs_guix.name)
+        return with_footer(text, 'guix')
 
     if args.build_one_commit:
         print('Starting guix build for one commit ({}) ...'.format(args.build_one_commit))
-        output_dir = call_guix_build(commit=args.build_one_commit)
+        output_dir, success, codesigned = call_guix_build(commit=args.build_one_commit)
+        if not success:
+            print(log_tail(os.path.join(output_dir, 'guix_build.log'), FAILURE_LOG_LINES))
+            print('Build failed')
+        elif codesigned is False:
+            print(log_tail(os.path.join(output_dir, 'guix_codesign.log'), FAILURE_LOG_LINES))
+            print('Codesign failed')
         print('See folder:\n{}'.format(output_dir))
         print('Exit')
         return
@@ -176,7 +399,10 @@ def main():
     label_needs_guix = github_repo.get_label('DrahtBot Guix build requested')
 
     print('Get open, mergeable {} pulls ...'.format(args.base_name))
-    pulls = return_with_pull_metadata(lambda: [p for p in github_repo.get_pulls(state='open', base=args.base_name)])
+    if args.pull_number:
+        pulls = return_with_pull_metadata(lambda: [p for p in [github_repo.get_pull(args.pull_number)] if p.state == 'open' and p.base.ref == args.base_name])
+    else:
+        pulls = return_with_pull_metadata(lambda: [p for p in github_repo.get_pulls(state='open', base=args.base_name)])
     os.chdir(git_repo_dir)
     docker_exec("git fetch --quiet --all")  # Do it again just to be safe
     docker_exec("git fetch --quiet origin")
@@ -191,12 +417,25 @@ def main():
 
     print('Num: {}'.format(len(pulls)))
 
-    print('Starting guix build for base branch ...')
-    base_folder = call_guix_build(commit=base_commit)
-
-    print('Moving results of {} to {}'.format(base_folder, guix_www_folder))
-    shutil.rmtree(os.path.join(guix_www_folder, base_commit), ignore_errors=True)
-    base_folder = shutil.move(src=base_folder, dst=os.path.join(guix_www_folder, base_commit))
+    environment = get_environment()
+    resumed = resumed_build('base', base_commit, pulls)
+    if resumed:
+        base_folder, base_manifest = resumed
+    else:
+        print('Starting guix build for base branch ...')
+        base_folder, base_seconds, success, codesigned = timed_guix_build(commit=base_commit)
+        if not success:
+            # Not the fault of any pull, so leave the labels for the next run
+            print(log_tail(os.path.join(base_folder, 'guix_build.log'), FAILURE_LOG_LINES))
+            sys.exit('Guix build of the base branch failed')
+
+        print('Moving results of {} to {}'.format(base_folder, guix_www_folder))
+        shutil.rmtree(os.path.join(guix_www_folder, base_commit), ignore_errors=True)
+        base_folder = shutil.move(src=base_folder, dst=os.path.join(guix_www_folder, base_commit))
+        base_manifest = write_manifest(base_folder, commit=base_commit, environment=environment, build_seconds=base_seconds, codesigned=codesigned)
+        artifact_store.publish(base_folder, '{}/{}'.format(artifact_dest, base_commit))
+    for p in pulls:
+        state.record(p.number, 'base', base_commit)
 
     for i, p in enumerate(pulls):
         print('{}/{}'.format(i, len(pulls)))
@@ -204,16 +443,38 @@ def main():
         print('Starting guix build ...')
         os.chdir(git_repo_dir)
         commit = get_git(['log', '-1', '--format=%H', '{}/{}/merge'.format(UPSTREAM_PULL, p.number)])
-        commit_folder = call_guix_build(commit=commit)
-
-        print('Moving results of {} to {}'.format(commit, guix_www_folder))
-        shutil.rmtree(os.path.join(guix_www_folder, commit), ignore_errors=True)
-        commit_folder = shutil.move(src=commit_folder, dst=os.path.join(guix_www_folder, commit))
+        head = get_git(['log', '-1', '--format=%H', '{}/{}/head'.format(UPSTREAM_PULL, p.number)])
+        # Only a run for this pull (i.e. the label was re-added) retries a failed build right away
+        if state.done(p.number, 'failed', commit) and p.number != args.pull_number:
+            print('Skip {}, the build of {} failed before'.format(p.number, commit))
+            continue
+        resumed = resumed_build('head', commit, [p])
+        if resumed:
+            commit_folder, commit_manifest = resumed
+        else:
+            commit_folder, commit_seconds, success, codesigned = timed_guix_build(commit=commit)
+
+            print('Moving results of {} to {}'.format(commit, guix_www_folder))
+            shutil.rmtree(os.path.join(guix_www_folder, commit), ignore_errors=True)
+            commit_folder = shutil.move(src=commit_folder, dst=os.path.join(guix_www_folder, commit))
+            if not success:
+                # Only the log, without a manifest, as the build is incomplete
+                artifact_store.publish(commit_folder, '{}/{}'.format(artifact_dest, commit))
+                text = failure_comment(commit, head, commit_folder)
+                print('{}\n    .create_comment({})'.format(p, text))
+                if not args.dry_run:
+                    p.create_comment(text)
+                state.record(p.number, 'failed', commit)
+                continue
+            commit_manifest = write_manifest(commit_folder, commit=commit, environment=environment, build_seconds=commit_seconds, codesigned=codesigned)
+            state.record(p.number, 'head', commit)
 
         calculate_diffs(base_folder, commit_folder)
 
         text = ID_GUIX_COMMENT
         text += '\n'
+        text += head_commit_marker(head)
+        text += '\n'
         text += '### Guix builds (on {})\n\n'.format(platform.machine())
         text += '| File '
         text += '| commit {}<br>({}) '.format(base_commit, args.base_name)
@@ -221,7 +482,13 @@ def main():
         text += '|\n'
         text += '|--|--|--|\n'
 
-        text += calculate_table(base_folder, commit_folder, external_url, base_commit, commit)
+        text += calculate_table(base_folder, commit_folder, external_url, base_commit, commit, exclude=(RESULT_MANIFEST, ), diff=diffoscope_report if args.diffoscope else None)
+        text += '\n'
+        text += codesign_notes([base_manifest, commit_manifest])
+        text += environment_block([base_manifest, commit_manifest])
+        # Published after the table, which adds the diffoscope reports to the folder
+        artifact_store.publish(commit_folder, '{}/{}'.format(artifact_dest, commit))
+        text = with_footer(text, 'guix')
 
         print('{}\n    .remove_from_labels({})'.format(p, label_needs_guix))
         print('    .create_comment({})'.format(text))
@@ -229,6 +496,7 @@ def main():
         if not args.dry_run:
             p.create_comment(text)
             p.remove_from_labels(label_needs_guix)
+        state.forget(p.number)
 
 
 if __name__ == '__main__':
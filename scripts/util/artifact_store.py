@@ -0,0 +1,46 @@
+import os
+import shutil
+import subprocess
+
+
+class ArtifactStore:
+    """
+    Where report producers publish their artifacts. See util/src/artifact_store.rs for the format
+    of the spec, which is shared with the Rust tools.
+    """
+
+    def __init__(self, spec):
+        self.local_root = None
+        self.rsync_root = None
+        self.s3_root = None
+        if spec.startswith('ssh://'):
+            self.rsync_root = spec[len('ssh://'):].rstrip('/')
+            if ':' not in self.rsync_root:
+                raise ValueError('Wrong artifact store format: {}'.format(spec))
+        elif spec.startswith('s3://'):
+            self.s3_root = spec.rstrip('/')
+        else:
+            path = spec[len('file://'):] if spec.startswith('file://') else spec
+            if not path.startswith('/'):
+                raise ValueError('Wrong artifact store format: {}'.format(spec))
+            self.local_root = path
+
+    def local_path(self, dest):
+        """Return the local folder for dest, if this is a local store. Otherwise, return None."""
+        if self.local_root is None:
+            return None
+        return os.path.join(self.local_root, dest)
+
+    def publish(self, src, dest):
+        """Replace the folder dest (relative to the store root) with the contents of src."""
+        print('Publish {} to {}'.format(src, dest))
+        if self.local_root is not None:
+            target = self.local_path(dest)
+            if os.path.abspath(src) == os.path.abspath(target):
+                return
+            shutil.rmtree(target, ignore_errors=True)
+            shutil.copytree(src, target)
+        elif self.rsync_root is not None:
+            subprocess.check_call(['rsync', '-a', '--delete', '--mkpath', src.rstrip('/') + '/', '{}/{}/'.format(self.rsync_root, dest)])
+        else:
+            subprocess.check_call(['aws', 's3', 'sync', '--delete', '--only-show-errors', src, '{}/{}'.format(self.s3_root, dest)])
@@ -0,0 +1,42 @@
+import json
+import os
+
+
+class BuildState:
+    """
+    The builds (base and head commit) that completed for each pull, so that a run after a crash
+    resumes from the finished half instead of starting over. Stored as json in the scratch folder.
+    """
+
+    def __init__(self, path):
+        self.path = path
+        self.pulls = {}
+        if os.path.isfile(path):
+            with open(path) as f:
+                self.pulls = json.load(f)
+
+    def _save(self):
+        with open(self.path + '.tmp', 'w') as f:
+            json.dump(self.pulls, f, indent=2)
+            f.write('\n')
+        os.replace(self.path + '.tmp', self.path)
+
+    def done(self, pull, kind, commit):
+        """
+        Whether the build of the commit (kind is 'base' or 'head') completed for the pull. Kind
+        'failed' records the last head commit that failed to build.
+        """
+        return self.pulls.get(str(pull), {}).get(kind) == commit
+
+    def record(self, pull, kind, commit):
+        self.pulls.setdefault(str(pull), {})[kind] = commit
+        self._save()
+
+    def forget(self, pull):
+        """Forget the builds of the pull, once its results are posted."""
+        if self.pulls.pop(str(pull), None) is not None:
+            self._save()
+
+    def commits(self):
+        """All commits with a completed build that may still be resumed from."""
+        return {c for builds in self.pulls.values() for c in builds.values()}
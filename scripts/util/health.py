@@ -0,0 +1,59 @@
+import hashlib
+import json
+import os
+import shutil
+import subprocess
+import sys
+import time
+import urllib.request
+
+
+def check_builder_health(*, work_dir, min_free_gb, sdk_path, sdk_sha256, guix_installed, guix_url):
+    """
+    Check the prerequisites of a build, before spending hours on it. Return the list of problems,
+    which is empty if the builder is healthy.
+    """
+    problems = []
+    try:
+        docker_ok = subprocess.call(['docker', 'info'], stdout=subprocess.DEVNULL, stderr=subprocess.DEVNULL) == 0
+    except OSError:
+        docker_ok = False
+    if not docker_ok:
+        problems.append('The docker daemon is not reachable')
+    free_gb = shutil.disk_usage(work_dir).free // (1 << 30)
+    if free_gb < min_free_gb:
+        problems.append('Only {} GB of {} GB free disk space left in {}'.format(free_gb, min_free_gb, work_dir))
+    if not os.path.isfile(sdk_path):
+        problems.append('The SDK {} is missing'.format(sdk_path))
+    elif sdk_sha256:
+        sha256 = hashlib.sha256()
+        with open(sdk_path, 'rb') as f:
+            for chunk in iter(lambda: f.read(1 << 20), b''):
+                sha256.update(chunk)
+        if sha256.hexdigest() != sdk_sha256:
+            problems.append('The SDK {} has the hash {}, expected {}'.format(sdk_path, sha256.hexdigest(), sdk_sha256))
+    if not guix_installed:
+        try:
+            urllib.request.urlopen(urllib.request.Request(guix_url, method='HEAD'), timeout=60)
+        except OSError as e:
+            problems.append('Guix is not installed and {} is not reachable: {}'.format(guix_url, e))
+    return problems
+
+
+def write_heartbeat(path, problems):
+    """
+    Record the health of the builder, for monitoring. Also raise an alert for the operator, if the
+    builder is unhealthy.
+    """
+    heartbeat = {
+        'time': int(time.time()),
+        'healthy': not problems,
+        'problems': problems,
+    }
+    os.makedirs(os.path.dirname(os.path.abspath(path)), exist_ok=True)
+    with open(path + '.tmp', 'w') as f:
+        json.dump(heartbeat, f, indent=2)
+        f.write('\n')
+    os.replace(path + '.tmp', path)
+    for p in problems:
+        print('ALERT: Builder unhealthy: {}'.format(p), file=sys.stderr)
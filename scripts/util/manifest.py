@@ -0,0 +1,42 @@
+import hashlib
+import json
+import os
+
+RESULT_MANIFEST = 'result.json'
+
+
+def write_result_manifest(folder, *, commit, duration_seconds, toolchain, links):
+    """
+    Write a machine-readable summary of a build next to its outputs. See util/src/manifest.rs for
+    the format, which is shared with the Rust tools.
+    """
+    artifacts = []
+    for name in sorted(os.listdir(folder)):
+        path = os.path.join(folder, name)
+        if name == RESULT_MANIFEST or not os.path.isfile(path):
+            continue
+        sha256 = hashlib.sha256()
+        with open(path, 'rb') as f:
+            for chunk in iter(lambda: f.read(1 << 20), b''):
+                sha256.update(chunk)
+        artifacts.append({'name': name, 'sha256': sha256.hexdigest(), 'size': os.path.getsize(path)})
+    manifest = {
+        'commit': commit,
+        'duration_seconds': int(duration_seconds),
+        'toolchain': toolchain,
+        'artifacts': artifacts,
+        'links': links,
+    }
+    with open(os.path.join(folder, RESULT_MANIFEST), 'w') as f:
+        json.dump(manifest, f, indent=2)
+        f.write('\n')
+    return manifest
+
+
+def read_result_manifest(folder):
+    """Return the manifest of a completed build in the folder, or None."""
+    path = os.path.join(folder, RESULT_MANIFEST)
+    if not os.path.isfile(path):
+        return None
+    with open(path) as f:
+        return json.load(f)
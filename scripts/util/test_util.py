@@ -0,0 +1,67 @@
+import os
+import tempfile
+import unittest
+
+from util.util import calculate_table
+
+SNAPSHOT_DIR = os.path.join(os.path.dirname(os.path.abspath(__file__)), 'snapshots')
+
+
+def assert_snapshot(test, name, text):
+    """
+    Compare the text with the snapshot file of the given name. With INSTA_UPDATE=always (the same
+    as for the Rust snapshot tests), write the text to the snapshot file instead.
+    """
+    path = os.path.join(SNAPSHOT_DIR, name)
+    if os.environ.get('INSTA_UPDATE') == 'always':
+        os.makedirs(SNAPSHOT_DIR, exist_ok=True)
+        with open(path, 'w', encoding='utf-8') as f:
+            f.write(text)
+        return
+    with open(path, encoding='utf-8') as f:
+        test.assertEqual(f.read(), text)
+
+
+class TestCalculateTable(unittest.TestCase):
+    def setUp(self):
+        self.cwd = os.getcwd()
+        self.tmp = tempfile.TemporaryDirectory()
+        self.addCleanup(self.tmp.cleanup)
+        self.addCleanup(os.chdir, self.cwd)
+
+    def folder(self, name, files):
+        path = os.path.join(self.tmp.name, name)
+        os.makedirs(path)
+        for f, content in files.items():
+            with open(os.path.join(path, f), 'w', encoding='utf-8') as fd:
+                fd.write(content)
+        return path
+
+    def test_guix_table(self):
+        base = self.folder('aaaaaaaaaaaa', {
+            'SHA256SUMS.part': 'sums\n',
+            'bitcoin-aaaaaaaaaaaa-x86_64-linux-gnu.tar.gz': 'same\n',
+            'bitcoin-aaaaaaaaaaaa-win64-setup-unsigned.exe': 'base\n',
+            'guix_build.log': 'removed\n',
+            'manifest.json': '{}\n',
+        })
+        commit = self.folder('bbbbbbbbbbbb', {
+            'SHA256SUMS.part': 'sums\n',
+            'bitcoin-bbbbbbbbbbbb-x86_64-linux-gnu.tar.gz': 'same\n',
+            'bitcoin-bbbbbbbbbbbb-win64-setup-unsigned.exe': 'pull\n',
+            'bitcoin-bbbbbbbbbbbb-arm64-apple-darwin.zip': 'added\n',
+            'manifest.json': '{}\n',
+        })
+        diffs = []
+
+        def diff(base_path, pull_path):
+            diffs.append((os.path.basename(base_path), os.path.basename(pull_path)))
+            return 'diffoscope/{}.html'.format(os.path.basename(pull_path))
+
+        text = calculate_table(base, commit, 'https://example.com/guix/', 'aaaaaaaaaaaa', 'bbbbbbbbbbbb', exclude=('manifest.json', ), diff=diff)
+        self.assertEqual(diffs, [('bitcoin-aaaaaaaaaaaa-win64-setup-unsigned.exe', 'bitcoin-bbbbbbbbbbbb-win64-setup-unsigned.exe')])
+        assert_snapshot(self, 'guix_table.md', text)
+
+
+if __name__ == '__main__':
+    unittest.main()
@@ -1,7 +1,32 @@
 from collections import defaultdict
+import json
 import re
 import os
 import subprocess
+import uuid
+
+# Shared by all comments of this run, see util/src/footer.rs
+RUN_ID = str(uuid.uuid4())
+
+
+def bot_version():
+    """The version of the bot, as in util/Cargo.toml."""
+    cargo_toml = os.path.join(os.path.dirname(__file__), '..', '..', 'util', 'Cargo.toml')
+    with open(cargo_toml) as f:
+        return re.search(r'^version = "(.*)"$', f.read(), re.MULTILINE).group(1)
+
+
+def with_footer(text, feature):
+    """Append the footer of the feature to the text of a comment, same as util::with_footer."""
+    version = bot_version()
+    blob = json.dumps({'version': version, 'feature': feature, 'run': RUN_ID}, separators=(',', ':'))
+    return text + '\n\n<sub>DrahtBot v{} • feature: {} • run: {}</sub>\n<!--drahtbot-footer:{}-->\n'.format(version, feature, RUN_ID, blob)
+
+
+def head_commit_marker(commit):
+    """Record the head commit of the pull request the results are for, same as util::head_commit_marker."""
+    return '<!--head-commit:{}-->'.format(commit)
+
 
 def return_with_pull_metadata(get_pulls):
     pulls = get_pulls()
@@ -14,24 +39,42 @@ def return_with_pull_metadata(get_pulls):
     return pulls
 
 
-def calculate_table(base_folder, commit_folder, external_url, base_commit, commit):
+def calculate_table(base_folder, commit_folder, external_url, base_commit, commit, exclude=(), diff=None):
+    """
+    diff: Called with the paths of an artifact that differs between both folders. Returns the name
+    of a report in the commit folder to link next to the row, or None.
+    """
     rows = defaultdict(lambda: ['', ''])  # map from abbrev file name to list of links
+    hashes = defaultdict(lambda: [None, None])  # map from abbrev file name to (sha256, path)
     for f in sorted(os.listdir(base_folder)):
+        if f in exclude:
+            continue
         short_file_name = re.sub(r'(bitcoin-)?[a-f0-9]{12}', '*', f)
         os.chdir(base_folder)
+        sha256 = subprocess.check_output(['sha256sum', f], universal_newlines=True).split()[0]
+        hashes[short_file_name][0] = (sha256, os.path.join(base_folder, f))
         left = rows[short_file_name]
-        left[0] = '[`{}...`]({}{}/{})'.format(subprocess.check_output(['sha256sum', f], universal_newlines=True)[:16], external_url, base_commit, f)
+        left[0] = '[`{}...`]({}{}/{})'.format(sha256[:16], external_url, base_commit, f)
         rows[short_file_name] = left
 
     for f in sorted(os.listdir(commit_folder)):
+        if f in exclude:
+            continue
         short_file_name = re.sub(r'(bitcoin-)?[a-f0-9]{12}', '*', f)
         os.chdir(commit_folder)
+        sha256 = subprocess.check_output(['sha256sum', f], universal_newlines=True).split()[0]
+        hashes[short_file_name][1] = (sha256, os.path.join(commit_folder, f))
         right = rows[short_file_name]
-        right[1] = '[`{}...`]({}{}/{})'.format(subprocess.check_output(['sha256sum', f], universal_newlines=True)[:16], external_url, commit, f)
+        right[1] = '[`{}...`]({}{}/{})'.format(sha256[:16], external_url, commit, f)
         rows[short_file_name] = right
 
     text = ''
     for f in rows:
+        base, pull = hashes[f]
+        if diff and base and pull and base[0] != pull[0]:
+            report = diff(base[1], pull[1])
+            if report:
+                rows[f][1] += ' ([diffoscope]({}{}/{}))'.format(external_url, commit, report)
         text += '| {} | {} | {} |\n'.format(f, rows[f][0], rows[f][1])
     text += '\n'
     return text
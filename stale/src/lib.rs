@@ -0,0 +1,1744 @@
+#[derive(clap::Parser)]
+#[command(about = "\
+Handle stale issues and pull requests:
+* Comment on pull requests that needed a rebase for too long.\n\
+* Comment on pull requests that a failing CI for too long.\n\
+* Comment on pull requests that are inactive for too long, including their review activity.\n\
+  Authors can opt out by placing \"<!-- DrahtBot not stale -->\" in the description.\n\
+* Close pull requests that stayed inactive after the final reminder, with --close-abandoned.\n\
+* Close issues waiting for a response of their author for too long, with --close-waiting-for-author.\n\
+* Update the label that indicates a rebase is required.\n\
+", long_about = None)]
+pub struct Args {
+    /// The access token for GitHub.
+    #[arg(long)]
+    github_access_token: Option<String>,
+    /// Cache the GET responses of the GitHub API in this dir, and only revalidate them on later
+    /// runs. May be shared with the other tools.
+    #[arg(long)]
+    github_cache_dir: Option<std::path::PathBuf>,
+    /// The repo slugs of the remotes on GitHub. Format: owner/repo
+    #[arg(long)]
+    github_repo: Vec<util::Slug>,
+    /// The path to the yaml config file.
+    #[arg(long)]
+    config_file: std::path::PathBuf,
+    /// Print changes/edits instead of calling the GitHub API.
+    #[arg(long, default_value_t = false)]
+    dry_run: bool,
+    /// Also write the dry-run preview (API calls and comment diffs) to this file.
+    #[arg(long, requires = "dry_run")]
+    dry_run_output: Option<std::path::PathBuf>,
+    /// A local git dir to compute mergeability in, when GitHub takes too long to compute it.
+    #[arg(long)]
+    mergeable_fallback_dir: Option<std::path::PathBuf>,
+    /// The sqlite database to record the applied edits in, shared with the other tools of the
+    /// bot.
+    #[arg(long)]
+    pull_facts_file: Option<std::path::PathBuf>,
+    /// The format of the log output: pretty or json.
+    #[arg(long, default_value = "pretty")]
+    log_format: util::LogFormat,
+    /// Never remind pull requests with this label of inactivity. May be given several times.
+    #[arg(long)]
+    exclude_label: Vec<String>,
+    /// Close pull requests that stayed inactive after the final reminder, see close_abandoned in
+    /// the config file.
+    #[arg(long, default_value_t = false)]
+    close_abandoned: bool,
+    /// Warn and close issues that are waiting for a response of their author for too long, see
+    /// waiting_for_author in the config file.
+    #[arg(long, default_value_t = false)]
+    close_waiting_for_author: bool,
+}
+
+/// Placed in the description of a pull request by its author, to opt out of the inactivity
+/// reminders. Unlike QUIET_MARKER, the other comments of the bot are still posted.
+const NOT_STALE_MARKER: &str = "<!-- DrahtBot not stale -->";
+
+#[derive(serde::Deserialize)]
+struct Config {
+    inactive_rebase_days: i64,
+    inactive_rebase_comment: String,
+    inactive_ci_days: i64,
+    inactive_ci_comment: String,
+    inactive_stale_days: i64,
+    inactive_stale_comment: String,
+    /// Extra search qualifiers for each of the inactivity policies, e.g.
+    /// `-label:"Pinned" draft:false`. See validate_search_filter.
+    #[serde(default)]
+    inactive_rebase_filter: String,
+    #[serde(default)]
+    inactive_ci_filter: String,
+    #[serde(default)]
+    inactive_stale_filter: String,
+    /// Further reminders for each of the inactivity policies, when the pull request stays
+    /// inactive after the first one.
+    #[serde(default)]
+    inactive_rebase_escalation: Vec<Rung>,
+    #[serde(default)]
+    inactive_ci_escalation: Vec<Rung>,
+    #[serde(default)]
+    inactive_stale_escalation: Vec<Rung>,
+    needs_rebase_label: String,
+    ci_failed_label: String,
+    needs_rebase_comment: String,
+    #[serde(default)]
+    behind_base: Option<BehindBase>,
+    #[serde(default)]
+    close_abandoned: Option<CloseAbandoned>,
+    #[serde(default)]
+    waiting_for_author: Option<WaitingForAuthor>,
+    #[serde(default)]
+    rebase_digest: Option<RebaseDigest>,
+    /// The maximum number of actions per repo per hour, kept in the pull facts across runs.
+    #[serde(default)]
+    quota: util::QuotaLimits,
+}
+
+/// Pace the needs-rebase comments of authors with many pull requests that conflict after the same
+/// merge.
+#[derive(serde::Deserialize)]
+struct RebaseDigest {
+    /// When more pull requests of an author need rebase in one run, post a single comment instead.
+    max_comments: usize,
+    /// May contain the DIGEST_VARS, which will be substituted.
+    comment: String,
+}
+
+/// The variables that can be used in the comment of the rebase_digest.
+const DIGEST_VARS: &[&str] = &["owner", "repo", "author", "count", "pulls"];
+
+/// Close pull requests that stayed inactive after the final reminder of an inactivity policy.
+#[derive(serde::Deserialize)]
+struct CloseAbandoned {
+    /// Days of inactivity after the final reminder, before closing.
+    grace_days: i64,
+    /// May contain the pull request variables, which will be substituted.
+    comment: String,
+    /// The label to add when closing, e.g. "Up for grabs".
+    label: String,
+    /// Only close after a member of the team approved it.
+    #[serde(default)]
+    approval: Option<util::ApprovalConfig>,
+}
+
+/// Close issues that are waiting for a response of their author for too long. Unlike the
+/// inactivity policies, only a comment by the author resets the clock.
+#[derive(serde::Deserialize)]
+struct WaitingForAuthor {
+    /// The label that marks an issue as waiting for its author, e.g. "Waiting for author".
+    label: String,
+    /// Days without a response of the author after the label was added, before closing.
+    close_days: i64,
+    /// Days before closing to post the warning.
+    warning_days: i64,
+    /// May contain the issue variables, which will be substituted.
+    warning_comment: String,
+    /// May contain {reopen_until} and the issue variables, which will be substituted.
+    close_comment: String,
+    /// Days after closing, during which a comment by the author reopens the issue. Requires
+    /// --reopen-on-comment in webhook_features.
+    reopen_days: i64,
+    /// Only close after a member of the team approved it.
+    #[serde(default)]
+    approval: Option<util::ApprovalConfig>,
+}
+
+/// The variables of an issue that can be used in the comments of the waiting_for_author policy.
+const ISSUE_VARS: &[&str] = &[
+    "owner",
+    "repo",
+    "number",
+    "author",
+    "labels",
+    "days_waiting",
+];
+
+/// Return the values of ISSUE_VARS for the issue.
+fn issue_vars(
+    slug: &util::Slug,
+    issue: &octocrab::models::issues::Issue,
+    days_waiting: i64,
+) -> Vec<(&'static str, String)> {
+    vec![
+        ("owner", slug.owner.clone()),
+        ("repo", slug.repo.clone()),
+        ("number", issue.number.to_string()),
+        ("author", issue.user.login.clone()),
+        (
+            "labels",
+            issue
+                .labels
+                .iter()
+                .map(|l| l.name.as_str())
+                .collect::<Vec<_>>()
+                .join(", "),
+        ),
+        ("days_waiting", days_waiting.to_string()),
+    ]
+}
+
+/// A reminder of an inactivity policy. The reminders of a policy form a ladder: each one is posted
+/// once the pull request was inactive for some days after the previous one.
+#[derive(Clone, serde::Deserialize)]
+struct Rung {
+    /// Days of inactivity after the previous reminder (or the last activity, for the first one).
+    days: i64,
+    /// May contain the pull request variables, which will be substituted.
+    comment: String,
+    /// A label to add along with the comment, e.g. "Up for grabs".
+    #[serde(default)]
+    label: Option<String>,
+    /// Whether to convert the pull request to a draft along with the comment.
+    #[serde(default)]
+    draft: bool,
+}
+
+/// A note for pull requests that merge cleanly, but are far behind the base branch.
+#[derive(serde::Deserialize)]
+struct BehindBase {
+    /// Only add the note when the pull request is at least this many commits behind.
+    commits: u64,
+    /// May contain {behind_by} and the pull request variables, which will be substituted. Shown
+    /// below the "Rebase" header.
+    text: String,
+}
+
+/// Check that the extra search qualifiers of a policy are well-formed and only narrow the scope.
+/// The repo, state, type and update date are set by the policy itself.
+fn validate_search_filter(filter: &str) -> Result<(), String> {
+    const RESERVED: [&str; 6] = ["repo", "org", "user", "is", "type", "updated"];
+    if !filter.matches('"').count().is_multiple_of(2) {
+        return Err(format!("Unbalanced quotes in '{filter}'"));
+    }
+    for term in search_terms(filter) {
+        let Some((name, value)) = term.trim_start_matches('-').split_once(':') else {
+            return Err(format!("'{term}' is not a qualifier (name:value)"));
+        };
+        if name.is_empty()
+            || value.is_empty()
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c == '-' || c == '_')
+        {
+            return Err(format!("'{term}' is not a qualifier (name:value)"));
+        }
+        if RESERVED.contains(&name) {
+            return Err(format!("'{term}' changes the scope set by the policy"));
+        }
+    }
+    Ok(())
+}
+
+/// Split the search qualifiers on whitespace outside of quotes.
+fn search_terms(filter: &str) -> impl Iterator<Item = &str> {
+    let mut quoted = false;
+    filter
+        .split(move |c: char| {
+            if c == '"' {
+                quoted = !quoted;
+            }
+            c.is_whitespace() && !quoted
+        })
+        .filter(|t| !t.is_empty())
+}
+
+/// Return the search qualifiers to skip pull requests with any of the labels.
+fn exclude_filter(labels: &[String]) -> String {
+    labels
+        .iter()
+        .map(|l| format!("-label:\"{l}\""))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Apply the plan, and record the applied edits in the pull facts.
+async fn apply_plan(
+    github: &octocrab::Octocrab,
+    slug: &util::Slug,
+    plan: &[util::Edit],
+    facts: Option<&state::Store>,
+    dry_run: bool,
+) -> octocrab::Result<Vec<util::Edit>> {
+    let applied = util::apply_plan(github, slug, plan, "stale", dry_run)
+        .await?
+        .done;
+    if let Some(facts) = facts {
+        facts
+            .update(|f| f.record_edits(slug, &applied, chrono::Utc::now()))
+            .expect("pull facts file error");
+    }
+    Ok(applied)
+}
+
+/// Activity within this time after a reminder is assumed to be caused by the bot itself (e.g.
+/// adding the label of the reminder).
+const LADDER_SLACK: chrono::Duration = chrono::Duration::hours(1);
+
+/// Return the marker of the reminder of the rung, placed after the marker of the policy.
+fn rung_marker(rung: usize) -> String {
+    if rung == 0 {
+        // Same as before there were ladders
+        return String::new();
+    }
+    format!("<!--rung:{rung}-->")
+}
+
+/// Return the rung and the date of the latest reminder of the policy among the comments.
+fn last_rung(
+    id: util::IdComment,
+    comments: &[octocrab::models::issues::Comment],
+) -> Option<(usize, chrono::DateTime<chrono::Utc>)> {
+    comments
+        .iter()
+        .filter_map(|c| {
+            let rest = id.strip(c.body.as_deref()?)?;
+            let rung = rest
+                .strip_prefix("<!--rung:")
+                .and_then(|r| r.split_once("-->"))
+                .and_then(|(r, _)| r.parse().ok())
+                .unwrap_or(0);
+            Some((rung, c.created_at))
+        })
+        .max_by_key(|(_, date)| *date)
+}
+
+/// The policies whose reminders do not count as activity for each other. Neither does the proposal
+/// to close an abandoned pull request.
+const REMINDERS: [util::IdComment; 4] = [
+    util::IdComment::InactiveRebase,
+    util::IdComment::InactiveCi,
+    util::IdComment::InactiveStale,
+    util::IdComment::ApprovalRequest,
+];
+
+/// Return the date of the latest reminder of any inactivity policy (or proposal) among the
+/// comments.
+fn last_reminder(
+    comments: &[octocrab::models::issues::Comment],
+) -> Option<chrono::DateTime<chrono::Utc>> {
+    comments
+        .iter()
+        .filter(|c| {
+            c.body
+                .as_deref()
+                .is_some_and(|b| REMINDERS.iter().any(|id| id.is_start_of(b)))
+        })
+        .map(|c| c.created_at)
+        .max()
+}
+
+/// Return the latest rung of the policy and its date, if there was no activity after it. The
+/// reminders of the other policies (last_any) update the pull request as well, but are not
+/// activity.
+fn pending_rung(
+    last: Option<(usize, chrono::DateTime<chrono::Utc>)>,
+    last_any: Option<chrono::DateTime<chrono::Utc>>,
+    updated_at: chrono::DateTime<chrono::Utc>,
+) -> Option<(usize, chrono::DateTime<chrono::Utc>)> {
+    let (rung, posted) = last?;
+    let quiet_since = last_any.map_or(posted, |a| a.max(posted));
+    (updated_at - quiet_since < LADDER_SLACK).then_some((rung, posted))
+}
+
+/// Return the rung of the ladder to post next, or None if the pull request is not due for a
+/// reminder. If there was activity since the last reminder, the ladder starts over. With repeat,
+/// the final rung is posted again each time its days passed, like the single reminder before
+/// there were ladders.
+fn next_rung(
+    ladder: &[Rung],
+    pending: Option<(usize, chrono::DateTime<chrono::Utc>)>,
+    updated_at: chrono::DateTime<chrono::Utc>,
+    now: chrono::DateTime<chrono::Utc>,
+    repeat: bool,
+) -> Option<usize> {
+    let (next, since) = match pending {
+        Some((rung, posted)) => (rung + 1, posted),
+        None => (0, updated_at),
+    };
+    let next = if repeat {
+        next.min(ladder.len() - 1)
+    } else {
+        next
+    };
+    let rung = ladder.get(next)?;
+    (now - since >= chrono::Duration::days(rung.days)).then_some(next)
+}
+
+/// Post the reminder of the rung, and apply its label and draft state.
+fn rung_plan(
+    id: util::IdComment,
+    ladder: &[Rung],
+    rung: usize,
+    number: u64,
+    vars: &[(&str, String)],
+) -> Vec<util::Edit> {
+    let r = &ladder[rung];
+    let mut plan = vec![util::Edit::Comment {
+        number,
+        text: format!(
+            "{}{}\n{}",
+            id.str(),
+            rung_marker(rung),
+            util::render_template(&r.comment, vars)
+        ),
+    }];
+    if let Some(label) = &r.label {
+        plan.push(util::Edit::AddLabel {
+            number,
+            label: label.to_string(),
+        });
+    }
+    if r.draft {
+        plan.push(util::Edit::ConvertToDraft { number });
+    }
+    plan
+}
+
+/// Whether the pull request stayed inactive for the grace period after the final reminder of the
+/// ladder.
+fn is_abandoned(
+    ladder: &[Rung],
+    pending: Option<(usize, chrono::DateTime<chrono::Utc>)>,
+    now: chrono::DateTime<chrono::Utc>,
+    grace_days: i64,
+) -> bool {
+    match pending {
+        Some((rung, posted)) => {
+            rung + 1 == ladder.len() && now - posted >= chrono::Duration::days(grace_days)
+        }
+        None => false,
+    }
+}
+
+/// Return the labels added by the reminders of the ladder and the close, which are removed again
+/// when the author reopens the pull request.
+fn closed_labels(config: &CloseAbandoned, ladder: &[Rung]) -> Vec<String> {
+    let mut labels = ladder
+        .iter()
+        .filter_map(|r| r.label.clone())
+        .chain([config.label.to_string()])
+        .collect::<Vec<_>>();
+    labels.sort();
+    labels.dedup();
+    labels
+}
+
+/// Check that the search of a policy still finds the pull requests after the final reminder
+/// (which may add a label or convert to a draft), so that they can be closed.
+fn check_close_scope(ladder: &[Rung], filter: &str) -> Result<(), String> {
+    let last = ladder.last().unwrap();
+    for term in search_terms(filter) {
+        let hides_label = last
+            .label
+            .as_ref()
+            .is_some_and(|l| term == format!("-label:\"{l}\"") || term == format!("-label:{l}"));
+        if hides_label || (last.draft && term == "draft:false") {
+            return Err(format!(
+                "'{term}' hides the pull requests after the final reminder, so they are never closed"
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Close the pull request with a comment, and add the label.
+fn close_plan(config: &CloseAbandoned, number: u64, vars: &[(&str, String)]) -> Vec<util::Edit> {
+    vec![
+        util::Edit::Comment {
+            number,
+            text: format!(
+                "{}\n{}",
+                util::IdComment::InactiveClose.str(),
+                util::render_template(&config.comment, vars)
+            ),
+        },
+        util::Edit::AddLabel {
+            number,
+            label: config.label.to_string(),
+        },
+        util::Edit::Close { number },
+    ]
+}
+
+#[derive(Debug, PartialEq)]
+enum WaitingStep {
+    Warn,
+    Close,
+}
+
+/// Return the next step for an issue that is waiting for its author since the date, or None if it
+/// is not due yet. Only a warning posted while waiting counts, and the issue is closed no earlier
+/// than warning_days after it.
+fn waiting_step(
+    config: &WaitingForAuthor,
+    since: chrono::DateTime<chrono::Utc>,
+    warned: Option<chrono::DateTime<chrono::Utc>>,
+    now: chrono::DateTime<chrono::Utc>,
+) -> Option<WaitingStep> {
+    let days = chrono::Duration::days;
+    match warned.filter(|w| *w >= since) {
+        Some(warned) => (now - since >= days(config.close_days)
+            && now - warned >= days(config.warning_days))
+        .then_some(WaitingStep::Close),
+        None => (now - since >= days(config.close_days - config.warning_days))
+            .then_some(WaitingStep::Warn),
+    }
+}
+
+/// Return the date the label was last added to the issue, from its events.
+fn last_labeled(
+    events: &[serde_json::Value],
+    label: &str,
+) -> Option<chrono::DateTime<chrono::Utc>> {
+    events
+        .iter()
+        .filter(|e| e["event"] == "labeled" && e["label"]["name"] == label)
+        .filter_map(|e| e["created_at"].as_str()?.parse().ok())
+        .max()
+}
+
+/// Post the warning, or close the issue with a comment that lets the author reopen it for
+/// reopen_days.
+fn waiting_plan(
+    config: &WaitingForAuthor,
+    step: WaitingStep,
+    number: u64,
+    vars: &[(&str, String)],
+    now: chrono::DateTime<chrono::Utc>,
+) -> Vec<util::Edit> {
+    match step {
+        WaitingStep::Warn => vec![util::Edit::Comment {
+            number,
+            text: format!(
+                "{}\n{}",
+                util::IdComment::WaitingAuthorWarning.str(),
+                util::render_template(&config.warning_comment, vars)
+            ),
+        }],
+        WaitingStep::Close => {
+            let reopen_until = now.date_naive() + chrono::Duration::days(config.reopen_days);
+            let text = util::render_template(&config.close_comment, vars);
+            vec![
+                util::Edit::Comment {
+                    number,
+                    text: format!(
+                        "{}{}\n{}",
+                        util::IdComment::WaitingAuthorClose.str(),
+                        util::reopen_until_marker(reopen_until),
+                        util::render_template(&text, &[("reopen_until", reopen_until.to_string())])
+                    ),
+                },
+                util::Edit::Close { number },
+            ]
+        }
+    }
+}
+
+/// Add or remove the rebase label on a pull request, depending on whether it is mergeable. When
+/// removing it, also delete the comments that asked for a rebase.
+fn rebase_label_plan(
+    config: &Config,
+    vars: &[(&str, String)],
+    number: u64,
+    mergeable: bool,
+    found_label_rebase: bool,
+    quiet: bool,
+    comments: &[(octocrab::models::CommentId, &str)],
+) -> Vec<util::Edit> {
+    let id_needs_rebase_comment = util::IdComment::NeedsRebase.str();
+
+    let mut plan = Vec::new();
+    if mergeable {
+        if found_label_rebase {
+            plan.push(util::Edit::RemoveLabel {
+                number,
+                label: config.needs_rebase_label.to_string(),
+            });
+            plan.extend(
+                comments
+                    .iter()
+                    .filter(|(_, b)| {
+                        util::IdComment::NeedsRebase.is_start_of(b)
+                            || util::IdComment::InactiveRebase.is_start_of(b)
+                            || util::IdComment::InactiveStale.is_start_of(b)
+                    })
+                    .map(|(id, _)| util::Edit::DeleteComment { number, id: *id }),
+            );
+        }
+    } else if !found_label_rebase {
+        plan.push(util::Edit::AddLabel {
+            number,
+            label: config.needs_rebase_label.to_string(),
+        });
+        if !quiet {
+            plan.push(util::Edit::Comment {
+                number,
+                text: format!(
+                    "{}\n{}",
+                    id_needs_rebase_comment,
+                    util::render_template(&config.needs_rebase_comment, vars)
+                ),
+            });
+        }
+    }
+    plan
+}
+
+/// Apply the held back plans (the needs-rebase label and comment of a pull request) of the authors
+/// with at most max_comments of them. For each other author, only apply the labels, and post a
+/// single comment on the newest pull request, that lists all of them.
+fn rebase_digest_plan(
+    config: &RebaseDigest,
+    slug: &util::Slug,
+    held: Vec<(String, Vec<util::Edit>)>,
+) -> Vec<util::Edit> {
+    let mut by_author = std::collections::BTreeMap::<String, Vec<Vec<util::Edit>>>::new();
+    for (author, edits) in held {
+        by_author.entry(author).or_default().push(edits);
+    }
+    let mut plan = Vec::new();
+    for (author, plans) in by_author {
+        if plans.len() <= config.max_comments {
+            plan.extend(plans.into_iter().flatten());
+            continue;
+        }
+        let mut numbers = Vec::new();
+        for edit in plans.into_iter().flatten() {
+            match edit {
+                util::Edit::Comment { number, .. } => numbers.push(number),
+                _ => plan.push(edit),
+            }
+        }
+        numbers.sort();
+        let vars = [
+            ("owner", slug.owner.clone()),
+            ("repo", slug.repo.clone()),
+            ("count", numbers.len().to_string()),
+            (
+                "pulls",
+                numbers
+                    .iter()
+                    .map(|n| format!("* #{n}\n"))
+                    .collect::<String>(),
+            ),
+            ("author", author),
+        ];
+        plan.push(util::Edit::Comment {
+            number: *numbers.last().unwrap(),
+            text: format!(
+                "{}\n{}",
+                util::IdComment::NeedsRebase.str(),
+                util::render_template(&config.comment, &vars)
+            ),
+        });
+    }
+    plan
+}
+
+/// Return the text of the behind-base section, or None if the pull request is not far enough
+/// behind the base branch.
+struct BehindBaseSection<'a> {
+    config: &'a BehindBase,
+    vars: &'a [(&'a str, String)],
+    behind_by: u64,
+}
+
+impl util::Section for BehindBaseSection<'_> {
+    fn id(&self) -> util::IdComment {
+        util::IdComment::SecBehindBase
+    }
+
+    fn render(&self) -> Option<String> {
+        if self.behind_by < self.config.commits {
+            return None;
+        }
+        let text = util::render_template(&self.config.text, self.vars);
+        Some(util::render_template(
+            &text,
+            &[("behind_by", self.behind_by.to_string())],
+        ))
+    }
+}
+
+/// Add a note to the metadata comment of a mergeable pull request that is far behind the base
+/// branch, suggesting a rebase before review. Unlike the rebase label, this is informational.
+async fn update_behind_base(
+    github: &octocrab::Octocrab,
+    config: &BehindBase,
+    slug: &util::Slug,
+    pull: &octocrab::models::pulls::PullRequest,
+    dry_run: bool,
+) -> octocrab::Result<()> {
+    let util::Slug { owner, repo } = slug;
+    let route = format!(
+        "/repos/{owner}/{repo}/compare/{base}...{head}",
+        base = pull.base.ref_field,
+        head = pull.head.sha
+    );
+    let compare: serde_json::Value = util::with_retry(|| {
+        // Only the counts are needed, not the list of commits
+        github.get(&route, Some(&[("per_page", "1")]))
+    })
+    .await?;
+    let behind_by = compare["behind_by"].as_u64().unwrap_or_default();
+    let section = BehindBaseSection {
+        config,
+        vars: &util::pull_vars(slug, pull, chrono::Utc::now()),
+        behind_by,
+    };
+    if behind_by >= config.commits {
+        tracing::info!("... {behind_by} commits behind the base branch");
+    }
+    let issues_api = github.issues(owner, repo);
+    let mut cmt = util::get_metadata_sections(github, &issues_api, pull.number).await?;
+    // A hidden section removes the note after a rebase
+    util::update_metadata_comment(&issues_api, &mut cmt, &section, dry_run).await
+}
+
+/// A policy to remind pull requests that are inactive for too long.
+struct Inactivity<'a> {
+    name: &'a str,
+    id: util::IdComment,
+    /// The search qualifiers of the policy, besides the repo, state, type and update date.
+    query: String,
+    filter: &'a str,
+    ladder: Vec<Rung>,
+}
+
+impl Config {
+    fn inactivity(&self) -> [Inactivity<'_>; 3] {
+        let ladder = |days, comment: &String, escalation: &Vec<Rung>| {
+            let first = Rung {
+                days,
+                comment: comment.to_string(),
+                label: None,
+                draft: false,
+            };
+            [vec![first], escalation.clone()].concat()
+        };
+        [
+            Inactivity {
+                name: "inactive_rebase",
+                id: util::IdComment::InactiveRebase,
+                query: format!("label:\"{}\"", self.needs_rebase_label),
+                filter: &self.inactive_rebase_filter,
+                ladder: ladder(
+                    self.inactive_rebase_days,
+                    &self.inactive_rebase_comment,
+                    &self.inactive_rebase_escalation,
+                ),
+            },
+            Inactivity {
+                name: "inactive_ci",
+                id: util::IdComment::InactiveCi,
+                query: format!("label:\"{}\"", self.ci_failed_label),
+                filter: &self.inactive_ci_filter,
+                ladder: ladder(
+                    self.inactive_ci_days,
+                    &self.inactive_ci_comment,
+                    &self.inactive_ci_escalation,
+                ),
+            },
+            Inactivity {
+                name: "inactive_stale",
+                id: util::IdComment::InactiveStale,
+                query: String::new(),
+                filter: &self.inactive_stale_filter,
+                ladder: ladder(
+                    self.inactive_stale_days,
+                    &self.inactive_stale_comment,
+                    &self.inactive_stale_escalation,
+                ),
+            },
+        ]
+    }
+}
+
+/// Return the date of the latest review activity on a pull request. Reviews in any state (e.g.
+/// requested changes) and review comments count, which the issue search by update date misses.
+fn last_review_activity(
+    reviews: &[octocrab::models::pulls::Review],
+    review_comments: &[octocrab::models::pulls::Comment],
+) -> Option<chrono::DateTime<chrono::Utc>> {
+    reviews
+        .iter()
+        .filter_map(|r| r.submitted_at)
+        .chain(review_comments.iter().map(|c| c.updated_at))
+        .max()
+}
+
+async fn inactive(
+    github: &octocrab::Octocrab,
+    policy: &Inactivity<'_>,
+    github_repo: &Vec<util::Slug>,
+    exclude: &str,
+    close: Option<&CloseAbandoned>,
+    facts: Option<&state::Store>,
+    dry_run: bool,
+) -> octocrab::Result<()> {
+    let now = chrono::Utc::now();
+    let min_days = policy
+        .ladder
+        .iter()
+        .map(|r| r.days)
+        .chain(close.map(|c| c.grace_days))
+        .min()
+        .unwrap();
+    let cutoff = { now - chrono::Duration::days(min_days) }.format("%F");
+    tracing::info!("Mark {} before date {} ...", policy.name, cutoff);
+
+    for slug in github_repo {
+        let util::Slug { owner, repo } = slug;
+        tracing::info!("Get {} pull requests for {owner}/{repo} ...", policy.name);
+        let search_fmt = format!(
+            "repo:{owner}/{repo} is:open is:pr {query} updated:<={cutoff} {filter} {exclude}",
+            owner = owner,
+            repo = repo,
+            query = policy.query,
+            cutoff = cutoff,
+            filter = policy.filter,
+        );
+        let items = util::with_retry(|| async {
+            github
+                .all_pages(
+                    github
+                        .search()
+                        .issues_and_pull_requests(&search_fmt)
+                        .send()
+                        .await?,
+                )
+                .await
+        })
+        .await?;
+        tracing::info!("Items: {}", items.len());
+        let issues_api = github.issues(owner, repo);
+        let pulls_api = github.pulls(owner, repo);
+        let known = facts.map(|f| f.read().expect("pull facts file error"));
+        let mut plan = Vec::new();
+        for item in items {
+            if util::is_quiet(&item.labels, item.body.as_deref())
+                || item
+                    .body
+                    .as_deref()
+                    .is_some_and(|b| b.contains(NOT_STALE_MARKER))
+            {
+                continue;
+            }
+            // The results of the build are activity on the pull request as well
+            if known
+                .as_ref()
+                .and_then(|k| k.pull(&format!("{}/{}", slug.str(), item.number)))
+                .is_some_and(|p| p.guix_pending(now))
+            {
+                tracing::info!("... Skip #{}, due to a pending guix build", item.number);
+                continue;
+            }
+            let comments = util::with_retry(|| async {
+                github
+                    .all_pages(issues_api.list_comments(item.number).send().await?)
+                    .await
+            })
+            .await?;
+            let last = last_rung(policy.id, &comments);
+            let last_any = last_reminder(&comments);
+            // Without closing, the final reminder is repeated
+            let due = |updated_at| {
+                let pending = pending_rung(last, last_any, updated_at);
+                let rung = next_rung(&policy.ladder, pending, updated_at, now, close.is_none());
+                let abandoned =
+                    close.is_some_and(|c| is_abandoned(&policy.ladder, pending, now, c.grace_days));
+                (rung, abandoned)
+            };
+            if due(item.updated_at) == (None, false) {
+                continue;
+            }
+            let reviews = util::with_retry(|| async {
+                github
+                    .all_pages(pulls_api.list_reviews(item.number).send().await?)
+                    .await
+            })
+            .await?;
+            let review_comments = util::with_retry(|| async {
+                github
+                    .all_pages(pulls_api.list_comments(Some(item.number)).send().await?)
+                    .await
+            })
+            .await?;
+            let updated_at = last_review_activity(&reviews, &review_comments)
+                .map_or(item.updated_at, |r| r.max(item.updated_at));
+            let (rung, abandoned) = due(updated_at);
+            if rung.is_none() && !abandoned {
+                tracing::info!("... Skip #{}, due to recent review activity", item.number);
+                continue;
+            }
+            // Search results are issues, which lack the branch and head of the pull request
+            let pull = util::with_retry(|| pulls_api.get(item.number)).await?;
+            let vars = util::pull_vars(slug, &pull, now);
+            if let Some(rung) = rung {
+                plan.extend(rung_plan(
+                    policy.id,
+                    &policy.ladder,
+                    rung,
+                    item.number,
+                    &vars,
+                ));
+            } else if let Some(close) = close {
+                plan.extend(close_plan(close, item.number, &vars));
+            }
+        }
+        if let Some(close) = close {
+            if let Some(approval) = &close.approval {
+                let reason = format!(
+                    "it stayed inactive for {} days after the final reminder.",
+                    close.grace_days
+                );
+                plan = util::approve_plan(github, slug, plan, &reason, &approval.policy(), dry_run)
+                    .await?;
+            }
+        }
+        let applied = apply_plan(github, slug, &plan, facts, dry_run).await?;
+        // Record the closes by the bot, to tell them apart from closes by maintainers. In dry-run
+        // mode, nothing was applied, so preview the records of the planned closes.
+        for edit in if dry_run { &plan } else { &applied } {
+            let util::Edit::Close { number } = edit else {
+                continue;
+            };
+            let mut cmt = util::get_metadata_sections(github, &issues_api, *number).await?;
+            let section = util::ClosedInactiveSection {
+                closed: Some(now.date_naive()),
+                labels: close
+                    .map(|c| closed_labels(c, &policy.ladder))
+                    .unwrap_or_default(),
+            };
+            util::update_metadata_comment(&issues_api, &mut cmt, &section, dry_run).await?;
+        }
+    }
+    Ok(())
+}
+
+async fn waiting_for_author(
+    github: &octocrab::Octocrab,
+    config: &WaitingForAuthor,
+    github_repo: &Vec<util::Slug>,
+    exclude: &str,
+    dry_run: bool,
+) -> octocrab::Result<()> {
+    let now = chrono::Utc::now();
+    // The warning updates the issue, so the close is only found after warning_days
+    let min_days = config
+        .warning_days
+        .min(config.close_days - config.warning_days);
+    let cutoff = { now - chrono::Duration::days(min_days) }.format("%F");
+    tracing::info!("Mark waiting_for_author before date {} ...", cutoff);
+
+    for slug in github_repo {
+        let util::Slug { owner, repo } = slug;
+        tracing::info!("Get waiting_for_author issues for {owner}/{repo} ...");
+        let search_fmt = format!(
+            "repo:{owner}/{repo} is:open is:issue label:\"{label}\" updated:<={cutoff} {exclude}",
+            label = config.label,
+        );
+        let items = util::with_retry(|| async {
+            github
+                .all_pages(
+                    github
+                        .search()
+                        .issues_and_pull_requests(&search_fmt)
+                        .send()
+                        .await?,
+                )
+                .await
+        })
+        .await?;
+        tracing::info!("Items: {}", items.len());
+        let issues_api = github.issues(owner, repo);
+        let mut plan = Vec::new();
+        for item in items {
+            if util::is_quiet(&item.labels, item.body.as_deref()) {
+                continue;
+            }
+            let route = format!("/repos/{owner}/{repo}/issues/{}/events", item.number);
+            let events = util::with_retry(|| async {
+                let page: octocrab::Page<serde_json::Value> =
+                    github.get(&route, Some(&[("per_page", "100")])).await?;
+                github.all_pages(page).await
+            })
+            .await?;
+            let Some(labeled) = last_labeled(&events, &config.label) else {
+                continue;
+            };
+            let comments = util::with_retry(|| async {
+                github
+                    .all_pages(issues_api.list_comments(item.number).send().await?)
+                    .await
+            })
+            .await?;
+            let since = comments
+                .iter()
+                .filter(|c| c.user.login == item.user.login)
+                .map(|c| c.created_at)
+                .chain([labeled])
+                .max()
+                .unwrap();
+            let warned = comments
+                .iter()
+                .filter(|c| {
+                    c.body
+                        .as_deref()
+                        .is_some_and(|b| util::IdComment::WaitingAuthorWarning.is_start_of(b))
+                })
+                .map(|c| c.created_at)
+                .max();
+            let Some(step) = waiting_step(config, since, warned, now) else {
+                continue;
+            };
+            tracing::info!("... #{} waiting since {since}: {step:?}", item.number);
+            let vars = issue_vars(slug, &item, (now - since).num_days());
+            plan.extend(waiting_plan(config, step, item.number, &vars, now));
+        }
+        if let Some(approval) = &config.approval {
+            let reason = format!("the author did not respond for {} days.", config.close_days);
+            plan = util::approve_plan(github, slug, plan, &reason, &approval.policy(), dry_run)
+                .await?;
+        }
+        // Not recorded in the pull facts, as these are issues
+        util::apply_plan(github, slug, &plan, "stale", dry_run).await?;
+    }
+    Ok(())
+}
+
+async fn rebase_label(
+    github: &octocrab::Octocrab,
+    config: &Config,
+    github_repo: &Vec<util::Slug>,
+    mergeable_fallback: Option<&util::MergeableFallback>,
+    facts: Option<&state::Store>,
+    dry_run: bool,
+) -> octocrab::Result<()> {
+    tracing::info!("Apply rebase label");
+
+    for slug in github_repo {
+        let util::Slug { owner, repo } = slug;
+        tracing::info!("Get open pulls for {}/{} ...", owner, repo);
+        let issues_api = github.issues(owner, repo);
+        let pulls_api = github.pulls(owner, repo);
+        let pulls = util::open_pulls(github, slug).await?;
+        tracing::info!("Open pulls: {}", pulls.len());
+        if let Some(facts) = facts {
+            let open = pulls.iter().map(|p| p.number).collect::<Vec<_>>();
+            facts
+                .update(|f| f.retain_open(slug, &open))
+                .expect("pull facts file error");
+        }
+        // The needs-rebase plans held back for the rebase digest, with the author
+        let mut held = Vec::new();
+        for (i, meta) in pulls.iter().enumerate() {
+            tracing::info!(
+                "{}/{} (Pull: {}/{}#{})",
+                i,
+                pulls.len(),
+                owner,
+                repo,
+                meta.number
+            );
+            let found_label_rebase = meta.labels.contains(&config.needs_rebase_label);
+            if let Some(mergeable) = meta.mergeable {
+                // Only fetch the full pull request if there is something to do
+                let edit = mergeable == found_label_rebase;
+                let behind_base = mergeable && config.behind_base.is_some();
+                if !edit && !behind_base {
+                    continue;
+                }
+            }
+            let pull =
+                util::get_pull_mergeable(&pulls_api, meta.number, mergeable_fallback).await?;
+            let pull = match pull {
+                None => {
+                    continue;
+                }
+                Some(p) => p,
+            };
+            let mergeable = pull.mergeable.unwrap();
+            let all_comments = if mergeable && found_label_rebase {
+                util::with_retry(|| async {
+                    github
+                        .all_pages(issues_api.list_comments(pull.number).send().await?)
+                        .await
+                })
+                .await?
+            } else {
+                Vec::new()
+            };
+            let comments = all_comments
+                .iter()
+                .map(|c| (c.id, c.body.as_deref().unwrap_or_default()))
+                .collect::<Vec<_>>();
+            let plan = rebase_label_plan(
+                config,
+                &util::pull_vars(slug, &pull, chrono::Utc::now()),
+                pull.number,
+                mergeable,
+                found_label_rebase,
+                util::is_quiet(
+                    pull.labels.as_deref().unwrap_or_default(),
+                    pull.body.as_deref(),
+                ),
+                &comments,
+            );
+            // Hold back the label along with the comment, so that an error later in the sweep
+            // leaves neither of them, and the next run retries both
+            let comment = plan.iter().any(|e| matches!(e, util::Edit::Comment { .. }));
+            match (&config.rebase_digest, &pull.user) {
+                (Some(_), Some(author)) if comment => held.push((author.login.clone(), plan)),
+                _ => {
+                    apply_plan(github, slug, &plan, facts, dry_run).await?;
+                }
+            }
+            if let (true, Some(behind_base)) = (mergeable, &config.behind_base) {
+                update_behind_base(github, behind_base, slug, &pull, dry_run).await?;
+            }
+        }
+        if let Some(digest) = &config.rebase_digest {
+            let plan = rebase_digest_plan(digest, slug, held);
+            apply_plan(github, slug, &plan, facts, dry_run).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Run the tool with the given command line arguments.
+pub async fn run(args: Args) -> octocrab::Result<()> {
+    util::init_logging(args.log_format);
+    if let Some(path) = &args.dry_run_output {
+        util::set_dry_run_output(path).expect("dry-run output file error");
+    }
+    let config: Config = serde_yaml::from_reader(
+        std::fs::File::open(args.config_file).expect("config file path error"),
+    )
+    .expect("yaml error");
+    for filter in [
+        &config.inactive_rebase_filter,
+        &config.inactive_ci_filter,
+        &config.inactive_stale_filter,
+    ] {
+        validate_search_filter(filter).expect("search filter config error");
+    }
+    util::check_template(&config.needs_rebase_comment, util::PULL_VARS)
+        .expect("comment template config error");
+    for policy in config.inactivity() {
+        for rung in &policy.ladder {
+            util::check_template(&rung.comment, util::PULL_VARS)
+                .expect("comment template config error");
+        }
+    }
+    if let Some(close) = &config.close_abandoned {
+        util::check_template(&close.comment, util::PULL_VARS)
+            .expect("comment template config error");
+    }
+    let close = args.close_abandoned.then(|| {
+        config
+            .close_abandoned
+            .as_ref()
+            .expect("close_abandoned missing in config file")
+    });
+    let exclude = exclude_filter(&args.exclude_label);
+    validate_search_filter(&exclude).expect("exclude label error");
+    if close.is_some() {
+        for policy in config.inactivity() {
+            check_close_scope(&policy.ladder, &format!("{} {exclude}", policy.filter))
+                .expect("close_abandoned config error");
+        }
+    }
+    if let Some(waiting) = &config.waiting_for_author {
+        assert!(
+            0 < waiting.warning_days && waiting.warning_days < waiting.close_days,
+            "waiting_for_author config error: warning_days must be between 0 and close_days"
+        );
+        util::check_template(&waiting.warning_comment, ISSUE_VARS)
+            .expect("comment template config error");
+        util::check_template(
+            &waiting.close_comment,
+            &[ISSUE_VARS, &["reopen_until"]].concat(),
+        )
+        .expect("comment template config error");
+    }
+    let waiting = args.close_waiting_for_author.then(|| {
+        config
+            .waiting_for_author
+            .as_ref()
+            .expect("waiting_for_author missing in config file")
+    });
+    if let Some(digest) = &config.rebase_digest {
+        util::check_template(&digest.comment, DIGEST_VARS).expect("comment template config error");
+    }
+    if let Some(behind_base) = &config.behind_base {
+        util::check_template(
+            &behind_base.text,
+            &[util::PULL_VARS, &["behind_by"]].concat(),
+        )
+        .expect("comment template config error");
+    }
+
+    let github = util::get_octocrab(args.github_access_token, args.github_cache_dir)?;
+    let facts = args.pull_facts_file.map(state::Store::new);
+    util::set_quota_limits(config.quota.clone());
+    if let Some(facts) = &facts {
+        util::set_quota_store(Box::new(facts.clone()));
+    }
+
+    for policy in config.inactivity() {
+        inactive(
+            &github,
+            &policy,
+            &args.github_repo,
+            &exclude,
+            close,
+            facts.as_ref(),
+            args.dry_run,
+        )
+        .await?;
+    }
+    if let Some(waiting) = waiting {
+        waiting_for_author(&github, waiting, &args.github_repo, &exclude, args.dry_run).await?;
+    }
+    let mergeable_fallback = args
+        .mergeable_fallback_dir
+        .map(util::MergeableFallback::new);
+    rebase_label(
+        &github,
+        &config,
+        &args.github_repo,
+        mergeable_fallback.as_ref(),
+        facts.as_ref(),
+        args.dry_run,
+    )
+    .await?;
+
+    util::exit_on_quota_denials();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> Config {
+        Config {
+            inactive_rebase_days: 90,
+            inactive_rebase_comment: String::new(),
+            inactive_ci_days: 90,
+            inactive_ci_comment: String::new(),
+            inactive_stale_days: 180,
+            inactive_stale_comment: String::new(),
+            inactive_rebase_filter: String::new(),
+            inactive_ci_filter: String::new(),
+            inactive_stale_filter: String::new(),
+            inactive_rebase_escalation: Vec::new(),
+            inactive_ci_escalation: Vec::new(),
+            inactive_stale_escalation: Vec::new(),
+            needs_rebase_label: "Needs rebase".to_string(),
+            ci_failed_label: "CI failed".to_string(),
+            needs_rebase_comment: "Please rebase {owner}/{repo}".to_string(),
+            behind_base: None,
+            close_abandoned: None,
+            waiting_for_author: None,
+            rebase_digest: None,
+            quota: util::QuotaLimits::default(),
+        }
+    }
+
+    #[test]
+    fn test_validate_search_filter() {
+        assert!(validate_search_filter("").is_ok());
+        assert!(validate_search_filter("-label:\"Pinned PR\" draft:false author:app/none").is_ok());
+        assert!(validate_search_filter("-label:\"Pinned").is_err());
+        assert!(validate_search_filter("draft:false pinned").is_err());
+        assert!(validate_search_filter("is:closed").is_err());
+        assert!(validate_search_filter("-repo:o/r").is_err());
+
+        assert_eq!(exclude_filter(&[]), "");
+        let exclude = exclude_filter(&["Pinned PR".to_string(), "Waiting".to_string()]);
+        assert_eq!(exclude, "-label:\"Pinned PR\" -label:\"Waiting\"");
+        assert!(validate_search_filter(&exclude).is_ok());
+    }
+
+    #[test]
+    fn test_behind_base_section() {
+        let config = BehindBase {
+            commits: 500,
+            text: "{behind_by} commits behind {owner}/{repo}".to_string(),
+        };
+        let vars = [("owner", "o".to_string()), ("repo", "r".to_string())];
+        let section = |behind_by| BehindBaseSection {
+            config: &config,
+            vars: &vars,
+            behind_by,
+        };
+        assert_eq!(util::Section::render(&section(499)), None);
+        assert_eq!(
+            util::SECTION_REGISTRY.render(&section(500)),
+            "\n### Rebase\n500 commits behind o/r"
+        );
+    }
+
+    #[test]
+    fn test_ladder() {
+        let rung = |days, comment: &str, label: Option<&str>, draft| Rung {
+            days,
+            comment: comment.to_string(),
+            label: label.map(|l| l.to_string()),
+            draft,
+        };
+        let ladder = [
+            rung(90, "Ping", None, false),
+            rung(30, "Up for grabs in 30 days", None, false),
+            rung(30, "Up for grabs", Some("Up for grabs"), true),
+        ];
+        let date = |s: &str| s.parse::<chrono::DateTime<chrono::Utc>>().unwrap();
+        let now = date("2024-06-01T00:00:00Z");
+
+        let next = |last, last_any, updated, now| {
+            let pending = pending_rung(last, last_any, updated);
+            next_rung(&ladder, pending, updated, now, false)
+        };
+        assert_eq!(next(None, None, date("2024-03-01T00:00:00Z"), now), Some(0));
+        assert_eq!(next(None, None, date("2024-04-01T00:00:00Z"), now), None);
+        // The last activity was the reminder itself
+        let posted = date("2024-05-01T00:00:00Z");
+        let updated = date("2024-05-01T00:00:05Z");
+        assert_eq!(next(Some((0, posted)), None, updated, now), Some(1));
+        assert_eq!(next(Some((1, posted)), None, updated, now), Some(2));
+        assert_eq!(next(Some((2, posted)), None, updated, now), None);
+        assert_eq!(
+            next(
+                Some((1, posted)),
+                None,
+                updated,
+                date("2024-05-20T00:00:00Z")
+            ),
+            None
+        );
+        // Activity after the reminder starts the ladder over
+        let active = date("2024-05-02T00:00:00Z");
+        assert_eq!(next(Some((1, posted)), None, active, now), None);
+        assert_eq!(
+            next(
+                Some((1, date("2024-01-01T00:00:00Z"))),
+                None,
+                date("2024-02-01T00:00:00Z"),
+                now
+            ),
+            Some(0)
+        );
+        // The reminder of another policy is not activity, and the days count from the reminder
+        // of this policy
+        assert_eq!(next(Some((0, posted)), Some(active), active, now), Some(1));
+        assert_eq!(
+            next(
+                Some((0, posted)),
+                Some(active),
+                active,
+                date("2024-05-31T12:00:00Z")
+            ),
+            Some(1)
+        );
+
+        // Without closing, the final reminder is repeated, and a single reminder works as before
+        // there were ladders
+        let pending = pending_rung(Some((2, posted)), None, updated);
+        assert_eq!(next_rung(&ladder, pending, updated, now, true), Some(2));
+        assert_eq!(
+            next_rung(
+                &ladder,
+                pending,
+                updated,
+                date("2024-05-20T00:00:00Z"),
+                true
+            ),
+            None
+        );
+        assert_eq!(next_rung(&ladder[..1], pending, updated, now, true), None);
+        let old = date("2024-02-01T00:00:00Z");
+        let pending = pending_rung(Some((0, old)), None, old);
+        assert_eq!(next_rung(&ladder[..1], pending, old, now, true), Some(0));
+
+        let id = util::IdComment::InactiveStale;
+        assert_eq!(
+            rung_plan(id, &ladder, 0, 1, &[]),
+            vec![util::Edit::Comment {
+                number: 1,
+                text: format!("{}\nPing", id.str()),
+            }]
+        );
+        assert_eq!(
+            rung_plan(id, &ladder, 2, 1, &[]),
+            vec![
+                util::Edit::Comment {
+                    number: 1,
+                    text: format!("{}<!--rung:2-->\nUp for grabs", id.str()),
+                },
+                util::Edit::AddLabel {
+                    number: 1,
+                    label: "Up for grabs".to_string(),
+                },
+                util::Edit::ConvertToDraft { number: 1 },
+            ]
+        );
+
+        // Closing after the final reminder
+        let abandoned = |last, updated, grace_days| {
+            is_abandoned(&ladder, pending_rung(last, None, updated), now, grace_days)
+        };
+        let final_rung = Some((2, posted));
+        assert!(abandoned(final_rung, updated, 30));
+        assert!(!abandoned(final_rung, updated, 60));
+        assert!(!abandoned(Some((1, posted)), updated, 30));
+        assert!(!abandoned(final_rung, active, 30));
+        assert!(!abandoned(None, updated, 30));
+        assert_eq!(
+            check_close_scope(&ladder, "-label:\"Pinned\" draft:true"),
+            Ok(())
+        );
+        assert!(check_close_scope(&ladder, "-label:\"Up for grabs\"").is_err());
+        assert!(check_close_scope(&ladder, "draft:false").is_err());
+        assert_eq!(check_close_scope(&ladder[..1], "draft:false"), Ok(()));
+        let close = CloseAbandoned {
+            grace_days: 30,
+            comment: "Closed #{number}".to_string(),
+            label: "Up for grabs".to_string(),
+            approval: None,
+        };
+        assert_eq!(
+            close_plan(&close, 1, &[("number", "1".to_string())]),
+            vec![
+                util::Edit::Comment {
+                    number: 1,
+                    text: format!("{}\nClosed #1", util::IdComment::InactiveClose.str()),
+                },
+                util::Edit::AddLabel {
+                    number: 1,
+                    label: "Up for grabs".to_string(),
+                },
+                util::Edit::Close { number: 1 },
+            ]
+        );
+        assert_eq!(closed_labels(&close, &ladder), ["Up for grabs"]);
+    }
+
+    #[test]
+    fn test_waiting_for_author() {
+        let config = WaitingForAuthor {
+            label: "Waiting for author".to_string(),
+            close_days: 30,
+            warning_days: 7,
+            warning_comment: "Ping {author}".to_string(),
+            close_comment: "Closed until {reopen_until}".to_string(),
+            reopen_days: 14,
+            approval: None,
+        };
+        let date = |s: &str| s.parse::<chrono::DateTime<chrono::Utc>>().unwrap();
+        let since = date("2024-05-01T00:00:00Z");
+        let step =
+            |warned: Option<&str>, now| waiting_step(&config, since, warned.map(date), date(now));
+
+        assert_eq!(step(None, "2024-05-23T00:00:00Z"), None);
+        assert_eq!(step(None, "2024-05-24T00:00:00Z"), Some(WaitingStep::Warn));
+        // A warning from before the author responded does not count
+        assert_eq!(
+            step(Some("2024-04-20T00:00:00Z"), "2024-06-01T00:00:00Z"),
+            Some(WaitingStep::Warn)
+        );
+        assert_eq!(
+            step(Some("2024-05-24T00:00:00Z"), "2024-05-30T00:00:00Z"),
+            None
+        );
+        assert_eq!(
+            step(Some("2024-05-24T00:00:00Z"), "2024-05-31T00:00:00Z"),
+            Some(WaitingStep::Close)
+        );
+        // A late warning still gives the author warning_days
+        assert_eq!(
+            step(Some("2024-06-10T00:00:00Z"), "2024-06-12T00:00:00Z"),
+            None
+        );
+
+        let events = serde_json::json!([
+            {"event": "labeled", "label": {"name": "Waiting for author"}, "created_at": "2024-04-01T00:00:00Z"},
+            {"event": "unlabeled", "label": {"name": "Waiting for author"}, "created_at": "2024-04-02T00:00:00Z"},
+            {"event": "labeled", "label": {"name": "Waiting for author"}, "created_at": "2024-05-01T00:00:00Z"},
+            {"event": "labeled", "label": {"name": "Bug"}, "created_at": "2024-05-02T00:00:00Z"},
+            {"event": "closed", "created_at": "2024-05-03T00:00:00Z"},
+        ]);
+        let events = events.as_array().unwrap();
+        assert_eq!(last_labeled(events, &config.label), Some(since));
+        assert_eq!(last_labeled(events, "Wallet"), None);
+
+        let vars = [("author", "alice".to_string())];
+        assert_eq!(
+            waiting_plan(&config, WaitingStep::Warn, 1, &vars, since),
+            vec![util::Edit::Comment {
+                number: 1,
+                text: format!(
+                    "{}\nPing alice",
+                    util::IdComment::WaitingAuthorWarning.str()
+                ),
+            }]
+        );
+        assert_eq!(
+            waiting_plan(&config, WaitingStep::Close, 1, &vars, since),
+            vec![
+                util::Edit::Comment {
+                    number: 1,
+                    text: format!(
+                        "{}<!--reopen-until:2024-05-15-->\nClosed until 2024-05-15",
+                        util::IdComment::WaitingAuthorClose.str()
+                    ),
+                },
+                util::Edit::Close { number: 1 },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rebase_label_plan() {
+        let config = config();
+        let vars = [("owner", "o".to_string()), ("repo", "r".to_string())];
+        let label = || "Needs rebase".to_string();
+
+        assert!(rebase_label_plan(&config, &vars, 1, true, false, false, &[]).is_empty());
+        assert!(rebase_label_plan(&config, &vars, 1, false, true, false, &[]).is_empty());
+
+        assert_eq!(
+            rebase_label_plan(&config, &vars, 1, false, false, false, &[]),
+            vec![
+                util::Edit::AddLabel {
+                    number: 1,
+                    label: label()
+                },
+                util::Edit::Comment {
+                    number: 1,
+                    text: format!("{}\nPlease rebase o/r", util::IdComment::NeedsRebase.str()),
+                },
+            ]
+        );
+        assert_eq!(
+            rebase_label_plan(&config, &vars, 1, false, false, true, &[]),
+            vec![util::Edit::AddLabel {
+                number: 1,
+                label: label()
+            }]
+        );
+
+        let nag = format!("{}\nPlease rebase", util::IdComment::NeedsRebase.str());
+        let comments = [(1.into(), nag.as_str()), (2.into(), "Concept ACK")];
+        assert_eq!(
+            rebase_label_plan(&config, &vars, 1, true, true, false, &comments),
+            vec![
+                util::Edit::RemoveLabel {
+                    number: 1,
+                    label: label()
+                },
+                util::Edit::DeleteComment {
+                    number: 1,
+                    id: 1.into()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rebase_digest_plan() {
+        let config = RebaseDigest {
+            max_comments: 1,
+            comment: "{author}: {count} in {owner}/{repo}\n{pulls}".to_string(),
+        };
+        let slug = "o/r".parse::<util::Slug>().unwrap();
+        let comment = |number| util::Edit::Comment {
+            number,
+            text: "Please rebase".to_string(),
+        };
+        let label = |number| util::Edit::AddLabel {
+            number,
+            label: "Needs rebase".to_string(),
+        };
+        let held = [7, 3, 9, 2]
+            .into_iter()
+            .map(|n| {
+                let author = if n == 3 { "bob" } else { "alice" };
+                (author.to_string(), vec![label(n), comment(n)])
+            })
+            .collect();
+        assert_eq!(
+            rebase_digest_plan(&config, &slug, held),
+            vec![
+                label(7),
+                label(9),
+                label(2),
+                util::Edit::Comment {
+                    number: 9,
+                    text: format!(
+                        "{}\nalice: 3 in o/r\n* #2\n* #7\n* #9\n",
+                        util::IdComment::NeedsRebase.str()
+                    ),
+                },
+                label(3),
+                comment(3),
+            ]
+        );
+        assert_eq!(rebase_digest_plan(&config, &slug, Vec::new()), []);
+    }
+
+    #[tokio::test]
+    async fn test_dry_run_plan_has_no_mutations() {
+        let config = config();
+        let slug = "o/r".parse::<util::Slug>().unwrap();
+        let vars = [("owner", "o".to_string()), ("repo", "r".to_string())];
+        let nag = format!("{}\nPlease rebase", util::IdComment::NeedsRebase.str());
+        let mut plan = rebase_label_plan(&config, &vars, 1, false, false, false, &[]);
+        plan.extend(rebase_label_plan(
+            &config,
+            &vars,
+            2,
+            true,
+            true,
+            false,
+            &[(1.into(), nag.as_str())],
+        ));
+        // Nothing listens on the port, so any API call fails the plan
+        let github = octocrab::Octocrab::builder()
+            .base_uri("http://127.0.0.1:1")
+            .unwrap()
+            .build()
+            .unwrap();
+        assert!(util::apply_plan(&github, &slug, &plan, "stale", false)
+            .await
+            .is_err());
+        assert_eq!(
+            util::apply_plan(&github, &slug, &plan, "stale", true)
+                .await
+                .unwrap(),
+            util::Applied::default()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_plan_over_quota_is_deferred() {
+        let slug = "o/test_plan_over_quota_is_deferred"
+            .parse::<util::Slug>()
+            .unwrap();
+        while util::quota_allows(&slug.str(), util::Action::Label) {}
+        let plan = [util::Edit::AddLabel {
+            number: 1,
+            label: "Needs rebase".to_string(),
+        }];
+        // Nothing listens on the port, so the plan must not be applied
+        let github = octocrab::Octocrab::builder()
+            .base_uri("http://127.0.0.1:1")
+            .unwrap()
+            .build()
+            .unwrap();
+        assert_eq!(
+            util::apply_plan(&github, &slug, &plan, "stale", false)
+                .await
+                .unwrap(),
+            util::Applied {
+                done: Vec::new(),
+                deferred: plan.to_vec(),
+            }
+        );
+    }
+
+    /// The comments of the plan, as posted.
+    fn comments(plan: &[util::Edit]) -> String {
+        plan.iter()
+            .filter_map(|e| match e {
+                util::Edit::Comment { text, .. } => Some(text.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_comment_snapshots() {
+        let config: Config = serde_yaml::from_str(include_str!("../config.yml")).unwrap();
+        let vars = [
+            ("owner", "bitcoin".to_string()),
+            ("repo", "bitcoin".to_string()),
+            ("number", "123".to_string()),
+            ("author", "alice".to_string()),
+            ("base_branch", "master".to_string()),
+            ("head_sha", "aa".repeat(20)),
+            ("labels", "Wallet, Needs rebase".to_string()),
+            ("days_inactive", "90".to_string()),
+        ];
+        for policy in config.inactivity() {
+            for rung in 0..policy.ladder.len() {
+                insta::assert_snapshot!(
+                    format!("{}_{rung}", policy.name),
+                    comments(&rung_plan(policy.id, &policy.ladder, rung, 123, &vars))
+                );
+            }
+        }
+        insta::assert_snapshot!(
+            "needs_rebase",
+            comments(&rebase_label_plan(
+                &config,
+                &vars,
+                123,
+                false,
+                false,
+                false,
+                &[]
+            ))
+        );
+        let comment = |number| util::Edit::Comment {
+            number,
+            text: String::new(),
+        };
+        insta::assert_snapshot!(
+            "rebase_digest",
+            comments(&rebase_digest_plan(
+                config.rebase_digest.as_ref().unwrap(),
+                &"bitcoin/bitcoin".parse().unwrap(),
+                (1..=4)
+                    .map(|n| ("alice".to_string(), vec![comment(n)]))
+                    .collect(),
+            ))
+        );
+        insta::assert_snapshot!(
+            "close_abandoned",
+            comments(&close_plan(
+                config.close_abandoned.as_ref().unwrap(),
+                123,
+                &vars
+            ))
+        );
+        let waiting = config.waiting_for_author.as_ref().unwrap();
+        let issue_vars = [
+            ("owner", "bitcoin".to_string()),
+            ("repo", "bitcoin".to_string()),
+            ("number", "123".to_string()),
+            ("author", "alice".to_string()),
+            ("labels", "Waiting for author".to_string()),
+            ("days_waiting", "23".to_string()),
+        ];
+        let now = "2024-06-01T00:00:00Z".parse().unwrap();
+        for (name, step) in [
+            ("waiting_for_author_warning", WaitingStep::Warn),
+            ("waiting_for_author_close", WaitingStep::Close),
+        ] {
+            insta::assert_snapshot!(
+                name,
+                comments(&waiting_plan(waiting, step, 123, &issue_vars, now))
+            );
+        }
+        let section = BehindBaseSection {
+            config: config.behind_base.as_ref().unwrap(),
+            vars: &vars,
+            behind_by: 789,
+        };
+        insta::assert_snapshot!("behind_base", util::SECTION_REGISTRY.render(&section));
+    }
+}
@@ -0,0 +1,561 @@
+//! Facts about pull requests, recorded by the tools of the bot as they go, and shared between
+//! them (the webhook server and the cron tools) via a sqlite database. This allows a tool to use
+//! what another one already found out, instead of listing all pulls of a repo on GitHub again, and
+//! to hold back while another tool is still busy with a pull request.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use rusqlite::OptionalExtension;
+
+/// The result of the last finished CI run of a pull request.
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct CiResult {
+    pub head: String,
+    pub success: bool,
+    pub date: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct PullFacts {
+    /// The labels added by the bot and not removed by it since.
+    pub labels: BTreeSet<String>,
+    /// The date of the last comment of the bot, by the util::IdComment marker of the comment (in
+    /// its current version).
+    pub comments: BTreeMap<String, chrono::DateTime<chrono::Utc>>,
+    pub ci: Option<CiResult>,
+    /// The date of the last push to the pull request.
+    pub last_push: Option<chrono::DateTime<chrono::Utc>>,
+    /// The date the guix build of the pull request was queued, until it finished.
+    pub guix_queued: Option<chrono::DateTime<chrono::Utc>>,
+    /// The date the failed CI tasks were last re-run by the rerun_ci tool.
+    pub ci_rerun: Option<chrono::DateTime<chrono::Utc>>,
+    /// The pulls (owner/repo/number) that conflict with this one, as of the last conflicts run.
+    pub conflicts: Option<Vec<String>>,
+}
+
+impl PullFacts {
+    /// The date of the last comment of the given kind.
+    pub fn commented(&self, id: util::IdComment) -> Option<chrono::DateTime<chrono::Utc>> {
+        self.comments.get(id.str()).copied()
+    }
+
+    /// Whether a guix build of the pull request is queued or running. A build that did not finish
+    /// within GUIX_MAX_DURATION is assumed to be lost, e.g. on a restart of the webhook server.
+    pub fn guix_pending(&self, now: chrono::DateTime<chrono::Utc>) -> bool {
+        self.guix_queued
+            .is_some_and(|queued| now - queued < GUIX_MAX_DURATION)
+    }
+}
+
+pub const GUIX_MAX_DURATION: chrono::Duration = chrono::Duration::days(1);
+
+/// A run of the conflicts tool that updated the comments of all pulls of a repo.
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct ConflictsRun {
+    pub date: chrono::DateTime<chrono::Utc>,
+    /// The base commit the conflicts were computed against.
+    pub base: String,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Facts {
+    /// By pull id (owner/repo/number).
+    pub pulls: BTreeMap<String, PullFacts>,
+    /// The last conflicts run, by repo slug (owner/repo).
+    pub conflicts_runs: BTreeMap<String, ConflictsRun>,
+    /// The recent actions of the tools, to apply the quota across their runs.
+    pub quota: util::QuotaLog,
+}
+
+impl Facts {
+    pub fn pull(&self, pull_id: &str) -> Option<&PullFacts> {
+        self.pulls.get(pull_id)
+    }
+
+    pub fn pull_mut(&mut self, pull_id: &str) -> &mut PullFacts {
+        self.pulls.entry(pull_id.to_string()).or_default()
+    }
+
+    /// Forget a closed pull request.
+    pub fn forget(&mut self, pull_id: &str) {
+        self.pulls.remove(pull_id);
+    }
+
+    /// Forget the pull requests of the repo that are no longer open.
+    pub fn retain_open(&mut self, slug: &util::Slug, open: &[u64]) {
+        let prefix = format!("{}/", slug.str());
+        self.pulls.retain(|pull_id, _| {
+            pull_id
+                .strip_prefix(&prefix)
+                .and_then(|n| n.parse::<u64>().ok())
+                .map_or(true, |n| open.contains(&n))
+        });
+    }
+
+    /// Remember the edits that were applied to pull requests of the repo.
+    pub fn record_edits(
+        &mut self,
+        slug: &util::Slug,
+        edits: &[util::Edit],
+        now: chrono::DateTime<chrono::Utc>,
+    ) {
+        for edit in edits {
+            match edit {
+                util::Edit::Comment { number, text } => {
+                    // Keyed by the kind, so that a new version of the marker or a text after it
+                    // (e.g. the rung of a reminder) does not change the key
+                    let Some((kind, _)) = util::IdComment::parse(text) else {
+                        continue;
+                    };
+                    self.pull_mut(&format!("{}/{number}", slug.str()))
+                        .comments
+                        .insert(kind.str().to_string(), now);
+                }
+                util::Edit::DeleteComment { .. }
+                | util::Edit::ConvertToDraft { .. }
+                | util::Edit::Close { .. }
+                | util::Edit::Reopen { .. }
+                | util::Edit::SetTitle { .. }
+                | util::Edit::RequestReview { .. }
+                | util::Edit::RemoveReviewRequest { .. } => {}
+                util::Edit::AddLabel { number, label } => {
+                    self.pull_mut(&format!("{}/{number}", slug.str()))
+                        .labels
+                        .insert(label.to_string());
+                }
+                util::Edit::RemoveLabel { number, label } => {
+                    self.pull_mut(&format!("{}/{number}", slug.str()))
+                        .labels
+                        .remove(label);
+                }
+            }
+        }
+    }
+
+    /// The pull requests of the repo whose last CI run failed, most recent failure first.
+    pub fn failed_ci(&self, slug: &util::Slug) -> Vec<u64> {
+        let prefix = format!("{}/", slug.str());
+        let mut failed = self
+            .pulls
+            .iter()
+            .filter_map(|(pull_id, facts)| {
+                let number = pull_id.strip_prefix(&prefix)?.parse::<u64>().ok()?;
+                let ci = facts.ci.as_ref().filter(|ci| !ci.success)?;
+                Some((ci.date, number))
+            })
+            .collect::<Vec<_>>();
+        failed.sort_by_key(|(date, _)| std::cmp::Reverse(*date));
+        failed.into_iter().map(|(_, number)| number).collect()
+    }
+
+    /// The pull requests of the repo whose last CI run failed after the last re-run, and were not
+    /// re-run within the interval, most recent failure first.
+    pub fn rerun_due(
+        &self,
+        slug: &util::Slug,
+        now: chrono::DateTime<chrono::Utc>,
+        interval: chrono::Duration,
+    ) -> Vec<u64> {
+        self.failed_ci(slug)
+            .into_iter()
+            .filter(|number| {
+                let facts = &self.pulls[&format!("{}/{number}", slug.str())];
+                match (facts.ci_rerun, &facts.ci) {
+                    (Some(rerun), Some(ci)) => ci.date > rerun && now - rerun >= interval,
+                    _ => true,
+                }
+            })
+            .collect()
+    }
+}
+
+/// The facts, stored in a sqlite database. Several tools may use the database at the same time.
+/// Waiting for the lock of the database blocks the thread, so async code should call the methods
+/// via `spawn_blocking`.
+#[derive(Clone)]
+pub struct Store {
+    path: std::path::PathBuf,
+}
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS pulls (
+    pull_id TEXT PRIMARY KEY,
+    ci_head TEXT,
+    ci_success INTEGER,
+    ci_date TEXT,
+    ci_rerun TEXT,
+    conflicts TEXT,
+    last_push TEXT,
+    guix_queued TEXT
+);
+CREATE TABLE IF NOT EXISTS labels (
+    pull_id TEXT NOT NULL,
+    label TEXT NOT NULL,
+    PRIMARY KEY (pull_id, label)
+);
+CREATE TABLE IF NOT EXISTS comments (
+    pull_id TEXT NOT NULL,
+    kind TEXT NOT NULL,
+    date TEXT NOT NULL,
+    PRIMARY KEY (pull_id, kind)
+);
+CREATE TABLE IF NOT EXISTS conflicts_runs (
+    slug TEXT PRIMARY KEY,
+    date TEXT NOT NULL,
+    base TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS quota (
+    id INTEGER PRIMARY KEY CHECK (id = 0),
+    log TEXT NOT NULL
+);
+";
+
+/// Another tool may hold the write lock for the duration of its update.
+const BUSY_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(600);
+
+fn io_error(err: rusqlite::Error) -> std::io::Error {
+    std::io::Error::other(err)
+}
+
+fn from_json<T: serde::de::DeserializeOwned>(column: usize, text: &str) -> rusqlite::Result<T> {
+    serde_json::from_str(text).map_err(|err| {
+        rusqlite::Error::FromSqlConversionFailure(column, rusqlite::types::Type::Text, err.into())
+    })
+}
+
+fn load(db: &rusqlite::Connection) -> rusqlite::Result<Facts> {
+    let mut facts = Facts::default();
+    let mut stmt = db.prepare(
+        "SELECT pull_id, ci_head, ci_success, ci_date, ci_rerun, conflicts, last_push, guix_queued
+         FROM pulls",
+    )?;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        let ci = match (row.get(1)?, row.get(2)?, row.get(3)?) {
+            (Some(head), Some(success), Some(date)) => Some(CiResult {
+                head,
+                success,
+                date,
+            }),
+            _ => None,
+        };
+        let conflicts = row
+            .get::<_, Option<String>>(5)?
+            .map(|c| from_json(5, &c))
+            .transpose()?;
+        facts.pulls.insert(
+            row.get(0)?,
+            PullFacts {
+                ci,
+                ci_rerun: row.get(4)?,
+                conflicts,
+                last_push: row.get(6)?,
+                guix_queued: row.get(7)?,
+                ..PullFacts::default()
+            },
+        );
+    }
+    let mut stmt = db.prepare("SELECT pull_id, label FROM labels")?;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        facts
+            .pull_mut(&row.get::<_, String>(0)?)
+            .labels
+            .insert(row.get(1)?);
+    }
+    let mut stmt = db.prepare("SELECT pull_id, kind, date FROM comments")?;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        facts
+            .pull_mut(&row.get::<_, String>(0)?)
+            .comments
+            .insert(row.get(1)?, row.get(2)?);
+    }
+    let mut stmt = db.prepare("SELECT slug, date, base FROM conflicts_runs")?;
+    let mut rows = stmt.query([])?;
+    while let Some(row) = rows.next()? {
+        facts.conflicts_runs.insert(
+            row.get(0)?,
+            ConflictsRun {
+                date: row.get(1)?,
+                base: row.get(2)?,
+            },
+        );
+    }
+    let quota = db
+        .query_row("SELECT log FROM quota WHERE id = 0", [], |row| {
+            row.get::<_, String>(0)
+        })
+        .optional()?;
+    if let Some(quota) = quota {
+        facts.quota = from_json(0, &quota)?;
+    }
+    Ok(facts)
+}
+
+/// Write the changes from the old facts to the new ones.
+fn save(db: &rusqlite::Connection, old: &Facts, new: &Facts) -> rusqlite::Result<()> {
+    for pull_id in old.pulls.keys().filter(|p| !new.pulls.contains_key(*p)) {
+        for table in ["pulls", "labels", "comments"] {
+            db.execute(
+                &format!("DELETE FROM {table} WHERE pull_id = ?1"),
+                [pull_id],
+            )?;
+        }
+    }
+    for (pull_id, facts) in &new.pulls {
+        if old.pulls.get(pull_id) == Some(facts) {
+            continue;
+        }
+        let conflicts = facts
+            .conflicts
+            .as_ref()
+            .map(|c| serde_json::to_string(c).expect("json serialize error"));
+        db.execute(
+            "INSERT OR REPLACE INTO pulls
+             (pull_id, ci_head, ci_success, ci_date, ci_rerun, conflicts, last_push, guix_queued)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            rusqlite::params![
+                pull_id,
+                facts.ci.as_ref().map(|ci| &ci.head),
+                facts.ci.as_ref().map(|ci| ci.success),
+                facts.ci.as_ref().map(|ci| ci.date),
+                facts.ci_rerun,
+                conflicts,
+                facts.last_push,
+                facts.guix_queued,
+            ],
+        )?;
+        db.execute("DELETE FROM labels WHERE pull_id = ?1", [pull_id])?;
+        for label in &facts.labels {
+            db.execute(
+                "INSERT INTO labels (pull_id, label) VALUES (?1, ?2)",
+                [pull_id, label],
+            )?;
+        }
+        db.execute("DELETE FROM comments WHERE pull_id = ?1", [pull_id])?;
+        for (kind, date) in &facts.comments {
+            db.execute(
+                "INSERT INTO comments (pull_id, kind, date) VALUES (?1, ?2, ?3)",
+                rusqlite::params![pull_id, kind, date],
+            )?;
+        }
+    }
+    if old.conflicts_runs != new.conflicts_runs {
+        db.execute("DELETE FROM conflicts_runs", [])?;
+        for (slug, run) in &new.conflicts_runs {
+            db.execute(
+                "INSERT INTO conflicts_runs (slug, date, base) VALUES (?1, ?2, ?3)",
+                rusqlite::params![slug, run.date, run.base],
+            )?;
+        }
+    }
+    if old.quota != new.quota {
+        db.execute(
+            "INSERT OR REPLACE INTO quota (id, log) VALUES (0, ?1)",
+            [serde_json::to_string(&new.quota).expect("json serialize error")],
+        )?;
+    }
+    Ok(())
+}
+
+impl Store {
+    pub fn new(path: std::path::PathBuf) -> Self {
+        Self { path }
+    }
+
+    fn open(&self) -> rusqlite::Result<rusqlite::Connection> {
+        let db = rusqlite::Connection::open(&self.path)?;
+        db.busy_timeout(BUSY_TIMEOUT)?;
+        db.execute_batch(SCHEMA)?;
+        Ok(db)
+    }
+
+    /// Read the facts.
+    pub fn read(&self) -> std::io::Result<Facts> {
+        let mut db = self.open().map_err(io_error)?;
+        let tx = db.transaction().map_err(io_error)?;
+        load(&tx).map_err(io_error)
+    }
+
+    /// Modify the facts and write the changes back to the database.
+    pub fn update<R>(&self, f: impl FnOnce(&mut Facts) -> R) -> std::io::Result<R> {
+        let mut db = self.open().map_err(io_error)?;
+        let tx = db
+            .transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)
+            .map_err(io_error)?;
+        let old = load(&tx).map_err(io_error)?;
+        let mut facts = old.clone();
+        let ret = f(&mut facts);
+        save(&tx, &old, &facts).map_err(io_error)?;
+        tx.commit().map_err(io_error)?;
+        Ok(ret)
+    }
+}
+
+impl util::QuotaStore for Store {
+    fn update_quota(&self, f: &mut dyn FnMut(&mut util::QuotaLog)) -> std::io::Result<()> {
+        self.update(|facts| f(&mut facts.quota))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_edits() {
+        let slug = "owner/repo".parse::<util::Slug>().unwrap();
+        let date = |s: &str| s.parse::<chrono::DateTime<chrono::Utc>>().unwrap();
+        let mut facts = Facts::default();
+        facts.record_edits(
+            &slug,
+            &[
+                util::Edit::AddLabel {
+                    number: 1,
+                    label: "Needs rebase".to_string(),
+                },
+                util::Edit::Comment {
+                    number: 1,
+                    text: format!("{}\nPlease rebase", util::IdComment::NeedsRebase.str()),
+                },
+                util::Edit::AddLabel {
+                    number: 2,
+                    label: "Needs rebase".to_string(),
+                },
+                util::Edit::RemoveLabel {
+                    number: 2,
+                    label: "Needs rebase".to_string(),
+                },
+            ],
+            date("2024-01-01T00:00:00Z"),
+        );
+        // The rung after the marker does not change the key
+        for rung in ["", "<!--rung:1-->"] {
+            facts.record_edits(
+                &slug,
+                &[util::Edit::Comment {
+                    number: 1,
+                    text: format!("{}{rung}\nPing", util::IdComment::InactiveStale.str()),
+                }],
+                date("2024-01-02T00:00:00Z"),
+            );
+        }
+        let pull = facts.pull("owner/repo/1").unwrap();
+        assert_eq!(pull.labels, BTreeSet::from(["Needs rebase".to_string()]));
+        assert_eq!(
+            pull.commented(util::IdComment::NeedsRebase),
+            Some(date("2024-01-01T00:00:00Z"))
+        );
+        assert_eq!(
+            pull.comments.keys().collect::<Vec<_>>(),
+            [
+                util::IdComment::InactiveStale.str(),
+                util::IdComment::NeedsRebase.str()
+            ]
+        );
+        assert!(facts.pull("owner/repo/2").unwrap().labels.is_empty());
+
+        let pull = facts.pull_mut("owner/repo/1");
+        let now = date("2024-01-02T00:00:00Z");
+        assert!(!pull.guix_pending(now));
+        pull.guix_queued = Some(date("2024-01-01T12:00:00Z"));
+        assert!(pull.guix_pending(now));
+        assert!(!pull.guix_pending(now + GUIX_MAX_DURATION));
+
+        facts.pull_mut("other/repo/3");
+        facts.retain_open(&slug, &[2]);
+        assert_eq!(
+            facts.pulls.keys().collect::<Vec<_>>(),
+            ["other/repo/3", "owner/repo/2"]
+        );
+    }
+
+    #[test]
+    fn test_store() {
+        let path = std::env::temp_dir().join(format!("state_test_{}.sqlite", std::process::id()));
+        let store = Store::new(path.clone());
+        assert_eq!(store.read().unwrap(), Facts::default());
+        let date = "2024-01-01T00:00:00Z".parse().unwrap();
+        let slug = "owner/repo".parse::<util::Slug>().unwrap();
+        let written = store
+            .update(|f| {
+                f.record_edits(
+                    &slug,
+                    &[
+                        util::Edit::AddLabel {
+                            number: 1,
+                            label: "CI failed".to_string(),
+                        },
+                        util::Edit::Comment {
+                            number: 1,
+                            text: util::IdComment::NeedsRebase.str().to_string(),
+                        },
+                    ],
+                    date,
+                );
+                let pull = f.pull_mut("owner/repo/1");
+                pull.ci = Some(CiResult {
+                    head: "aa".to_string(),
+                    success: false,
+                    date,
+                });
+                pull.ci_rerun = Some(date);
+                pull.conflicts = Some(vec!["owner/repo/2".to_string()]);
+                pull.last_push = Some(date);
+                pull.guix_queued = Some(date);
+                f.pull_mut("owner/repo/2");
+                f.conflicts_runs.insert(
+                    slug.str(),
+                    ConflictsRun {
+                        date,
+                        base: "bb".to_string(),
+                    },
+                );
+                f.clone()
+            })
+            .unwrap();
+        assert_eq!(store.clone().read().unwrap(), written);
+
+        store.update(|f| f.forget("owner/repo/1")).unwrap();
+        let facts = store.read().unwrap();
+        assert_eq!(facts.pulls.keys().collect::<Vec<_>>(), ["owner/repo/2"]);
+        assert_eq!(facts.conflicts_runs, written.conflicts_runs);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_failed_ci() {
+        let slug = "owner/repo".parse::<util::Slug>().unwrap();
+        let mut facts = Facts::default();
+        for (number, success, date) in [
+            (1, false, "2024-01-01T00:00:00Z"),
+            (2, true, "2024-01-03T00:00:00Z"),
+            (3, false, "2024-01-02T00:00:00Z"),
+        ] {
+            facts.pull_mut(&format!("owner/repo/{number}")).ci = Some(CiResult {
+                head: "aa".to_string(),
+                success,
+                date: date.parse().unwrap(),
+            });
+        }
+        facts.pull_mut("owner/repo/4");
+        assert_eq!(facts.failed_ci(&slug), [3, 1]);
+
+        let now = "2024-01-05T00:00:00Z".parse().unwrap();
+        let day = chrono::Duration::days(1);
+        assert_eq!(facts.rerun_due(&slug, now, day), [3, 1]);
+        // Not failed again since the re-run
+        facts.pull_mut("owner/repo/3").ci_rerun = Some("2024-01-02T12:00:00Z".parse().unwrap());
+        facts.pull_mut("owner/repo/1").ci_rerun = Some("2024-01-04T12:00:00Z".parse().unwrap());
+        assert_eq!(facts.rerun_due(&slug, now, day), Vec::<u64>::new());
+        // Failed again since the re-run, due once the interval passed
+        facts.pull_mut("owner/repo/1").ci_rerun = Some("2023-12-31T00:00:00Z".parse().unwrap());
+        assert_eq!(facts.rerun_due(&slug, now, day), [1]);
+        assert_eq!(
+            facts.rerun_due(&slug, now, chrono::Duration::days(7)),
+            Vec::<u64>::new()
+        );
+    }
+}
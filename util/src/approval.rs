@@ -0,0 +1,282 @@
+use crate::IdComment;
+
+/// High-impact actions, which may require a maintainer to approve them first.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProposedAction {
+    Close,
+    Lock,
+    SetTitle,
+}
+
+impl ProposedAction {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Close => "close",
+            Self::Lock => "lock",
+            Self::SetTitle => "set-title",
+        }
+    }
+
+    /// The action, as it reads in the proposal.
+    fn verb(&self) -> &'static str {
+        match self {
+            Self::Close => "close",
+            Self::Lock => "lock",
+            Self::SetTitle => "overwrite the title of",
+        }
+    }
+
+    /// The action that the edit of a plan needs approval for, if any.
+    fn of(edit: &crate::Edit) -> Option<Self> {
+        match edit {
+            crate::Edit::Close { .. } => Some(Self::Close),
+            crate::Edit::SetTitle { .. } => Some(Self::SetTitle),
+            _ => None,
+        }
+    }
+}
+
+/// The maintainers that can approve actions, and for how long a proposal is valid.
+#[derive(Clone)]
+pub struct ApprovalPolicy {
+    /// The team to mention in the proposal. Format: org/team
+    pub team: String,
+    pub validity: chrono::Duration,
+}
+
+/// The approval policy, as given in the config file of a tool.
+#[derive(Clone, serde::Deserialize)]
+pub struct ApprovalConfig {
+    /// Format: org/team
+    pub team: String,
+    /// How long a proposal stays valid.
+    pub validity_days: i64,
+}
+
+impl ApprovalConfig {
+    pub fn policy(&self) -> ApprovalPolicy {
+        ApprovalPolicy {
+            team: self.team.to_string(),
+            validity: chrono::Duration::days(self.validity_days),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Approval {
+    /// There is no proposal for the action.
+    NotProposed,
+    /// A maintainer approved the proposal. The action can be executed.
+    Approved,
+    /// The proposal is waiting for approval.
+    Pending,
+    /// Nobody approved the proposal in time. It was removed, so that it can be proposed again
+    /// later, if still relevant.
+    Expired,
+}
+
+/// The marker of the action, placed after the ApprovalRequest marker.
+fn marker(action: ProposedAction) -> String {
+    format!("<!--{}-->", action.as_str())
+}
+
+/// Post a comment proposing the action, asking the team to approve it.
+pub async fn propose_action(
+    github: &octocrab::Octocrab,
+    slug: &crate::Slug,
+    number: u64,
+    action: ProposedAction,
+    reason: &str,
+    policy: &ApprovalPolicy,
+    dry_run: bool,
+) -> octocrab::Result<()> {
+    let text = format!(
+        "{id}{marker}\n@{team} I would like to {action} this, because {reason}\n\nPlease react with 👍 to this comment within {days} days to approve. Otherwise, nothing will happen.",
+        id = IdComment::ApprovalRequest.str(),
+        marker = marker(action),
+        team = policy.team,
+        action = action.verb(),
+        days = policy.validity.num_days(),
+    );
+    tracing::info!("... propose to {}", action.as_str());
+    if !dry_run && crate::quota_allows(&slug.str(), crate::Action::Comment) {
+        github
+            .issues(&slug.owner, &slug.repo)
+            .create_comment(
+                number,
+                crate::with_footer(&text, IdComment::ApprovalRequest.name()),
+            )
+            .await?;
+    }
+    Ok(())
+}
+
+/// Return the API route of the team membership of the user, or None if the team is not of the
+/// format org/team.
+fn membership_route(team: &str, login: &str) -> Option<String> {
+    let (org, team) = team.trim_start_matches('@').split_once('/')?;
+    (!org.is_empty() && !team.is_empty())
+        .then(|| format!("/orgs/{org}/teams/{team}/memberships/{login}"))
+}
+
+/// Whether the user is an active member of the team. Format: org/team
+async fn is_team_member(
+    github: &octocrab::Octocrab,
+    team: &str,
+    login: &str,
+) -> octocrab::Result<bool> {
+    let Some(route) = membership_route(team, login) else {
+        tracing::error!("The approval team '{team}' is not of the format org/team");
+        return Ok(false);
+    };
+    // Not retried, as a 404 is the common answer for users outside of the team
+    match github.get::<serde_json::Value, _, ()>(&route, None).await {
+        Ok(membership) => Ok(membership["state"] == "active"),
+        Err(err) if crate::is_not_found(&err) => Ok(false),
+        Err(err) => Err(err),
+    }
+}
+
+/// Check whether a member of the team of the policy approved the proposed action on the issue or
+/// pull request, by reacting 👍 to the proposal comment.
+pub async fn check_approval(
+    github: &octocrab::Octocrab,
+    slug: &crate::Slug,
+    number: u64,
+    action: ProposedAction,
+    policy: &ApprovalPolicy,
+    dry_run: bool,
+) -> octocrab::Result<Approval> {
+    let issues_api = github.issues(&slug.owner, &slug.repo);
+    let marker = marker(action);
+    let comments = crate::with_retry(|| async {
+        github
+            .all_pages(issues_api.list_comments(number).send().await?)
+            .await
+    })
+    .await?;
+    let Some(proposal) = comments.iter().find(|c| {
+        c.body.as_ref().is_some_and(|b| {
+            IdComment::ApprovalRequest
+                .strip(b)
+                .is_some_and(|b| b.starts_with(&marker))
+        })
+    }) else {
+        return Ok(Approval::NotProposed);
+    };
+
+    let reactions = crate::with_retry(|| async {
+        github
+            .all_pages(
+                issues_api
+                    .list_comment_reactions(proposal.id)
+                    .send()
+                    .await?,
+            )
+            .await
+    })
+    .await?;
+    for r in reactions
+        .iter()
+        .filter(|r| r.content == octocrab::models::reactions::ReactionContent::PlusOne)
+    {
+        if is_team_member(github, &policy.team, &r.user.login).await? {
+            tracing::info!("... {} approved by {}", action.as_str(), r.user.login);
+            return Ok(Approval::Approved);
+        }
+    }
+
+    if chrono::Utc::now() - proposal.created_at > policy.validity {
+        tracing::info!("... proposal to {} expired", action.as_str());
+        if !dry_run {
+            issues_api.delete_comment(proposal.id).await?;
+        }
+        return Ok(Approval::Expired);
+    }
+    Ok(Approval::Pending)
+}
+
+/// Hold back the edits of the plan that need approval (closes and title changes), until a member
+/// of the team of the policy approved them, and propose them if they were not proposed yet. A held
+/// back close also holds back the other edits of the issue or pull request, such as the comment
+/// that explains the close.
+pub async fn approve_plan(
+    github: &octocrab::Octocrab,
+    slug: &crate::Slug,
+    plan: Vec<crate::Edit>,
+    reason: &str,
+    policy: &ApprovalPolicy,
+    dry_run: bool,
+) -> octocrab::Result<Vec<crate::Edit>> {
+    let mut checked = Vec::new();
+    let mut held = Vec::new();
+    for edit in &plan {
+        let Some(action) = ProposedAction::of(edit) else {
+            continue;
+        };
+        let number = edit.number();
+        if checked.contains(&(number, action)) {
+            continue;
+        }
+        checked.push((number, action));
+        match check_approval(github, slug, number, action, policy, dry_run).await? {
+            Approval::Approved => continue,
+            Approval::NotProposed => {
+                propose_action(github, slug, number, action, reason, policy, dry_run).await?
+            }
+            Approval::Pending | Approval::Expired => {}
+        }
+        tracing::info!("... hold back the edits of #{number} until approved");
+        held.push((number, action));
+    }
+    Ok(plan.into_iter().filter(|e| !is_held(&held, e)).collect())
+}
+
+fn is_held(held: &[(u64, ProposedAction)], edit: &crate::Edit) -> bool {
+    held.iter().any(|(number, action)| {
+        *number == edit.number()
+            && (*action == ProposedAction::Close || ProposedAction::of(edit) == Some(*action))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_membership_route() {
+        assert_eq!(
+            membership_route("org/maintainers", "alice").unwrap(),
+            "/orgs/org/teams/maintainers/memberships/alice"
+        );
+        assert_eq!(
+            membership_route("@org/maintainers", "alice").unwrap(),
+            "/orgs/org/teams/maintainers/memberships/alice"
+        );
+        assert!(membership_route("maintainers", "alice").is_none());
+        assert!(membership_route("org/", "alice").is_none());
+    }
+
+    #[test]
+    fn test_is_held() {
+        let comment = crate::Edit::Comment {
+            number: 1,
+            text: "Closing".to_string(),
+        };
+        let close = crate::Edit::Close { number: 1 };
+        let title = crate::Edit::SetTitle {
+            number: 2,
+            title: "Title".to_string(),
+        };
+        let label = crate::Edit::AddLabel {
+            number: 2,
+            label: "Docs".to_string(),
+        };
+        let held = [(1, ProposedAction::Close), (2, ProposedAction::SetTitle)];
+        assert!(is_held(&held, &comment));
+        assert!(is_held(&held, &close));
+        assert!(is_held(&held, &title));
+        assert!(!is_held(&held, &label));
+        assert!(!is_held(&held[..1], &title));
+    }
+}
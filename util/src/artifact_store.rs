@@ -0,0 +1,142 @@
+use crate::check_call;
+
+/// Where report producers publish their artifacts (html reports, guix builds, ...), so that the
+/// builders do not have to run on the web host.
+///
+/// Format of the string representation:
+/// * `/var/www/html` (or `file:///var/www/html`): a local folder
+/// * `ssh://user@host:/var/www/html`: a folder on a remote host, synced via rsync over ssh
+/// * `s3://bucket/prefix`: an S3 bucket, synced via the aws cli
+///
+/// The same format is understood by `scripts/util/artifact_store.py`.
+#[derive(Clone)]
+pub enum ArtifactStore {
+    Local(std::path::PathBuf),
+    Rsync(String),
+    S3(String),
+}
+
+impl std::str::FromStr for ArtifactStore {
+    type Err = &'static str;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = "Wrong format, see --help.";
+        if let Some(dest) = s.strip_prefix("ssh://") {
+            if !dest.contains(':') {
+                return Err(err);
+            }
+            return Ok(Self::Rsync(dest.trim_end_matches('/').to_string()));
+        }
+        if s.starts_with("s3://") {
+            return Ok(Self::S3(s.trim_end_matches('/').to_string()));
+        }
+        let path = s.strip_prefix("file://").unwrap_or(s);
+        if !path.starts_with('/') {
+            return Err(err);
+        }
+        Ok(Self::Local(std::path::PathBuf::from(path)))
+    }
+}
+
+impl ArtifactStore {
+    /// Replace the folder `dest` (relative to the store root) with the contents of the local
+    /// folder `src`. The top-level entries named in `exclude` are neither copied from `src` nor
+    /// removed from `dest`, e.g. the `.git` folder of a checkout, or a subfolder that another
+    /// tool publishes to.
+    pub fn publish(&self, src: &std::path::Path, dest: &str, exclude: &[&str]) {
+        tracing::info!(
+            "Publish {} to {}/{dest}",
+            src.display(),
+            self.display_root()
+        );
+        let entries = |dir: &std::path::Path| {
+            std::fs::read_dir(dir)
+                .expect("Failed to read a folder")
+                .map(|e| e.expect("Failed to read a folder").path())
+                .filter(|p| !exclude.iter().any(|x| p.file_name() == Some(x.as_ref())))
+                .collect::<Vec<_>>()
+        };
+        match self {
+            Self::Local(root) => {
+                let dest = root.join(dest);
+                std::fs::create_dir_all(&dest).expect("Failed to create a folder");
+                for old in entries(&dest) {
+                    if old.is_dir() && !old.is_symlink() {
+                        std::fs::remove_dir_all(&old)
+                    } else {
+                        std::fs::remove_file(&old)
+                    }
+                    .expect("Failed to remove old artifacts");
+                }
+                for new in entries(src) {
+                    check_call(
+                        std::process::Command::new("cp")
+                            .arg("-a")
+                            .arg(new)
+                            .arg(&dest),
+                    );
+                }
+            }
+            Self::Rsync(root) => {
+                check_call(
+                    std::process::Command::new("rsync")
+                        .args(["-a", "--delete", "--mkpath"])
+                        // Excluded paths are also kept on the receiving side
+                        .args(exclude.iter().map(|x| format!("--exclude=/{x}")))
+                        .arg(format!("{}/", src.display()))
+                        .arg(format!("{root}/{dest}/")),
+                );
+            }
+            Self::S3(root) => {
+                check_call(
+                    std::process::Command::new("aws")
+                        .args(["s3", "sync", "--delete", "--only-show-errors"])
+                        .args(
+                            exclude
+                                .iter()
+                                .flat_map(|x| ["--exclude".to_string(), format!("{x}/*")]),
+                        )
+                        .arg(src)
+                        .arg(format!("{root}/{dest}")),
+                );
+            }
+        }
+    }
+
+    fn display_root(&self) -> String {
+        match self {
+            Self::Local(root) => root.display().to_string(),
+            Self::Rsync(root) => format!("ssh://{root}"),
+            Self::S3(root) => root.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_publish_local() {
+        let dir = std::env::temp_dir().join(format!("artifact_store_test_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let src = dir.join("src");
+        std::fs::create_dir_all(src.join(".git")).unwrap();
+        std::fs::write(src.join(".git").join("HEAD"), "new").unwrap();
+        std::fs::write(src.join("index.html"), "new").unwrap();
+        let dest = dir.join("www").join("reports");
+        std::fs::create_dir_all(dest.join("coverage")).unwrap();
+        std::fs::write(dest.join("coverage").join("index.html"), "foreign").unwrap();
+        std::fs::write(dest.join("old.html"), "old").unwrap();
+
+        let store = format!("{}", dir.join("www").display())
+            .parse::<ArtifactStore>()
+            .unwrap();
+        store.publish(&src, "reports", &[".git", "coverage"]);
+        let read = |p: &str| std::fs::read_to_string(dest.join(p)).ok();
+        assert_eq!(read("index.html").as_deref(), Some("new"));
+        assert_eq!(read("coverage/index.html").as_deref(), Some("foreign"));
+        assert_eq!(read("old.html"), None);
+        assert!(!dest.join(".git").exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
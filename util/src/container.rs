@@ -0,0 +1,117 @@
+use crate::{check_call, check_output};
+
+/// The tool to run containers with. Both take the same arguments.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ContainerRuntime {
+    Docker,
+    Podman,
+}
+
+impl std::str::FromStr for ContainerRuntime {
+    type Err = &'static str;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "docker" => Ok(Self::Docker),
+            "podman" => Ok(Self::Podman),
+            _ => Err("Wrong runtime, expected 'docker' or 'podman'."),
+        }
+    }
+}
+
+impl ContainerRuntime {
+    fn command(self) -> std::process::Command {
+        std::process::Command::new(match self {
+            Self::Docker => "docker",
+            Self::Podman => "podman",
+        })
+    }
+}
+
+/// A running container, with folders of the host mounted at the same path. The container is
+/// removed when this is dropped.
+pub struct Container {
+    runtime: ContainerRuntime,
+    id: String,
+}
+
+impl Container {
+    /// Start the container in the background.
+    pub fn run(runtime: ContainerRuntime, image: &str, volumes: &[&std::path::Path]) -> Self {
+        tracing::info!("Start docker process ...");
+        let mut cmd = runtime.command();
+        cmd.args(["run", "-idt", "--rm"]);
+        for dir in volumes {
+            //'--mount', # Doesn't work with fedora (needs rw,z)
+            //'type=bind,src={},dst={}'.format(dir_code, dir_code),
+            cmd.arg(format!("--volume={}:{}:rw,z", dir.display(), dir.display()));
+        }
+        cmd.args(["-e", "LC_ALL=C.UTF-8", image]);
+        let id = check_output(&mut cmd);
+        tracing::info!("Docker running with id {}.", id);
+        Self { runtime, id }
+    }
+
+    /// Run the command in the container, from the current working dir of the host.
+    pub fn exec(&self, cmd: &str) {
+        check_call(self.runtime.command().args([
+            "exec",
+            &self.id,
+            "bash",
+            "-c",
+            &format!(
+                "cd {} && {}",
+                std::env::current_dir().expect("Failed to getcwd").display(),
+                cmd
+            ),
+        ]))
+    }
+
+    /// Return the output of the command in the container.
+    pub fn output(&self, cmd: &str) -> String {
+        check_output(
+            self.runtime
+                .command()
+                .args(["exec", &self.id, "bash", "-c", cmd]),
+        )
+    }
+
+    /// Copy a file or folder out of the container to the host.
+    pub fn copy(&self, src: &std::path::Path, dest: &std::path::Path) {
+        check_call(
+            self.runtime
+                .command()
+                .arg("cp")
+                .arg(format!("{}:{}", self.id, src.display()))
+                .arg(dest),
+        )
+    }
+
+    /// Remove the folder and create it again, empty. Files created in the container may not be
+    /// writable from the host, so they are removed in the container.
+    pub fn clear_dir(&self, folder: &std::path::Path) {
+        std::fs::create_dir_all(folder).expect("Failed to create a folder");
+        self.exec(&format!("rm -r {}", folder.display()));
+        std::fs::create_dir_all(folder).expect("Failed to create a folder");
+        // Must change to a dir that exists after this function call
+    }
+}
+
+impl Drop for Container {
+    fn drop(&mut self) {
+        tracing::info!("Remove docker container {}.", self.id);
+        let res = self
+            .runtime
+            .command()
+            .args(["rm", "--force", &self.id])
+            .output();
+        match res {
+            Ok(out) if out.status.success() => {}
+            Ok(out) => tracing::warn!(
+                "Failed to remove the container {}: {}",
+                self.id,
+                String::from_utf8_lossy(&out.stderr).trim()
+            ),
+            Err(err) => tracing::warn!("Failed to remove the container {}: {err}", self.id),
+        }
+    }
+}
@@ -0,0 +1,286 @@
+//! The coverage of the lines added by a pull request, from the lcov tracefiles of the base commit
+//! and of the merge commit of the pull request.
+
+use std::collections::BTreeMap;
+
+use crate::{IdComment, Section};
+
+/// The hit count of each instrumented line, per source file.
+pub type LineCoverage = BTreeMap<String, BTreeMap<u32, u64>>;
+
+/// Parse the line hit counts (`DA:<line>,<hits>`) of an lcov tracefile. The file names are made
+/// relative to `dir_code`.
+pub fn parse_lcov_lines(info: &str, dir_code: &str) -> LineCoverage {
+    let mut files = LineCoverage::new();
+    let mut file = String::new();
+    for line in info.lines() {
+        if let Some(f) = line.strip_prefix("SF:") {
+            file = f
+                .trim_start_matches(dir_code)
+                .trim_start_matches('/')
+                .to_string();
+        } else if let Some(da) = line.strip_prefix("DA:") {
+            let mut parts = da.split(',');
+            let (Some(Ok(num)), Some(Ok(hits))) = (
+                parts.next().map(str::parse::<u32>),
+                parts.next().map(str::parse::<u64>),
+            ) else {
+                continue;
+            };
+            // A line may be listed more than once, e.g. for templates
+            *files
+                .entry(file.clone())
+                .or_default()
+                .entry(num)
+                .or_default() += hits;
+        }
+    }
+    files
+}
+
+/// Return the added lines per file from the output of `git diff --unified=0`.
+pub fn added_lines(diff: &str) -> BTreeMap<String, Vec<u32>> {
+    let mut added = BTreeMap::<String, Vec<u32>>::new();
+    let mut file = None;
+    for line in diff.lines() {
+        if let Some(f) = line.strip_prefix("+++ ") {
+            file = f.strip_prefix("b/").map(|f| f.to_string());
+        } else if let Some(hunk) = line.strip_prefix("@@ ") {
+            // @@ -<start>[,<count>] +<start>[,<count>] @@
+            let Some(new) = hunk.split(' ').find_map(|r| r.strip_prefix('+')) else {
+                continue;
+            };
+            let (start, count) = new.split_once(',').unwrap_or((new, "1"));
+            let (Ok(start), Ok(count), Some(file)) =
+                (start.parse::<u32>(), count.parse::<u32>(), &file)
+            else {
+                continue;
+            };
+            added
+                .entry(file.clone())
+                .or_default()
+                .extend(start..start + count);
+        }
+    }
+    added.retain(|_, lines| !lines.is_empty());
+    added
+}
+
+/// The coverage of a file touched by the pull request.
+#[derive(Debug, PartialEq)]
+pub struct FileDelta {
+    pub file: String,
+    /// Lines found and lines hit in the base commit, None for new files.
+    pub base: Option<(u64, u64)>,
+    /// Lines found and lines hit in the merge commit.
+    pub head: (u64, u64),
+    /// The number of added lines that are instrumented.
+    pub added: u64,
+    /// The added lines that are instrumented, but not hit.
+    pub uncovered: Vec<u32>,
+}
+
+fn totals(lines: &BTreeMap<u32, u64>) -> (u64, u64) {
+    let hit = lines.values().filter(|h| **h > 0).count();
+    (lines.len() as u64, hit as u64)
+}
+
+/// Compute the coverage of the files with added lines that are instrumented in the merge commit.
+pub fn diff_coverage(
+    base: &LineCoverage,
+    head: &LineCoverage,
+    added: &BTreeMap<String, Vec<u32>>,
+) -> Vec<FileDelta> {
+    added
+        .iter()
+        .filter_map(|(file, lines)| {
+            let cov = head.get(file)?;
+            let instrumented = lines
+                .iter()
+                .filter_map(|l| Some((*l, *cov.get(l)?)))
+                .collect::<Vec<_>>();
+            if instrumented.is_empty() {
+                return None;
+            }
+            Some(FileDelta {
+                file: file.clone(),
+                base: base.get(file).map(totals),
+                head: totals(cov),
+                added: instrumented.len() as u64,
+                uncovered: instrumented
+                    .into_iter()
+                    .filter(|(_, hits)| *hits == 0)
+                    .map(|(l, _)| l)
+                    .collect(),
+            })
+        })
+        .collect()
+}
+
+/// Format sorted line numbers as ranges, e.g. "3-5, 9".
+fn line_ranges(lines: &[u32]) -> String {
+    let mut ranges: Vec<(u32, u32)> = Vec::new();
+    for l in lines {
+        match ranges.last_mut() {
+            Some((_, end)) if *end + 1 == *l => *end = *l,
+            _ => ranges.push((*l, *l)),
+        }
+    }
+    ranges
+        .iter()
+        .map(|(s, e)| {
+            if s == e {
+                s.to_string()
+            } else {
+                format!("{s}-{e}")
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn percent((found, hit): (u64, u64)) -> String {
+    if found == 0 {
+        return "-".to_string();
+    }
+    format!("{:.1}%", 100.0 * hit as f64 / found as f64)
+}
+
+/// The number of files to list.
+const MAX_FILES: usize = 30;
+
+/// The coverage of the lines added by the pull request, similar to the report on corecheck.dev.
+pub struct DiffCoverageSection<'a> {
+    pub id: IdComment,
+    pub files: &'a [FileDelta],
+    /// The merge commit the coverage was computed for.
+    pub merge_commit: &'a str,
+    /// The head commit of the pull request, which is the second parent of the merge commit.
+    pub head_commit: &'a str,
+    /// The url of the full report of the merge commit.
+    pub report_url: &'a str,
+}
+
+impl Section for DiffCoverageSection<'_> {
+    fn id(&self) -> IdComment {
+        self.id
+    }
+
+    fn render(&self) -> Option<String> {
+        if self.files.is_empty() {
+            return None;
+        }
+        let added = self.files.iter().map(|f| f.added).sum::<u64>();
+        let uncovered = self
+            .files
+            .iter()
+            .map(|f| f.uncovered.len() as u64)
+            .sum::<u64>();
+        let mut text = if uncovered == 0 {
+            format!("✅ All {added} added lines that are instrumented are covered")
+        } else {
+            format!("⚠️ {uncovered} of {added} added lines that are instrumented are uncovered")
+        };
+        text += &format!(
+            " ([report]({url}) for {commit}).\n\n",
+            url = self.report_url,
+            commit = &self.merge_commit[..self.merge_commit.len().min(16)],
+        );
+        text += "| File | Coverage before | Coverage after | Uncovered added lines |\n";
+        text += "| ---- | --------------- | -------------- | --------------------- |\n";
+        for f in self.files.iter().take(MAX_FILES) {
+            text += &format!(
+                "| `{file}` | {before} | {after} | {lines} |\n",
+                file = f.file,
+                before = f.base.map_or("(new)".to_string(), percent),
+                after = percent(f.head),
+                lines = line_ranges(&f.uncovered),
+            );
+        }
+        if self.files.len() > MAX_FILES {
+            text += &format!("\n({} more files omitted)\n", self.files.len() - MAX_FILES);
+        }
+        Some(text)
+    }
+
+    fn head_commit(&self) -> Option<&str> {
+        Some(self.head_commit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff_coverage() {
+        let base = parse_lcov_lines(
+            "TN:\nSF:/code/src/init.cpp\nDA:10,1\nDA:11,0\nend_of_record\n",
+            "/code",
+        );
+        let head = parse_lcov_lines(
+            "TN:\nSF:/code/src/init.cpp\nDA:10,1\nDA:11,0\nDA:12,2\nDA:13,0\nDA:14,0\nDA:13,0\nend_of_record\nSF:/code/src/new.cpp\nDA:1,0\nDA:2,3\nend_of_record\n",
+            "/code",
+        );
+        assert_eq!(head["src/init.cpp"][&12], 2);
+        let diff = "\
+diff --git a/src/init.cpp b/src/init.cpp
+--- a/src/init.cpp
++++ b/src/init.cpp
+@@ -11,0 +12,3 @@ void Init()
++    a();
++    b();
++    c();
+@@ -20 +23 @@ void Shutdown()
+-    x();
++    y();
+diff --git a/src/new.cpp b/src/new.cpp
+new file mode 100644
+--- /dev/null
++++ b/src/new.cpp
+@@ -0,0 +1,2 @@
++int f();
++int g();
+diff --git a/README.md b/README.md
+--- a/README.md
++++ /dev/null
+@@ -1,2 +0,0 @@
+-Gone
+-Too
+";
+        let added = added_lines(diff);
+        assert_eq!(added["src/init.cpp"], [12, 13, 14, 23]);
+        assert_eq!(added.len(), 2);
+
+        let files = diff_coverage(&base, &head, &added);
+        assert_eq!(
+            files[0],
+            FileDelta {
+                file: "src/init.cpp".to_string(),
+                base: Some((2, 1)),
+                head: (5, 2),
+                added: 3,
+                uncovered: vec![13, 14],
+            }
+        );
+        assert_eq!(files[1].uncovered, [1]);
+        assert_eq!(line_ranges(&[1, 3, 4, 5, 9]), "1, 3-5, 9");
+
+        let section = DiffCoverageSection {
+            id: IdComment::SecCoverage,
+            files: &files,
+            merge_commit: &"ab".repeat(20),
+            head_commit: &"cd".repeat(20),
+            report_url: "https://example.org/report",
+        };
+        assert_eq!(
+            crate::SECTION_REGISTRY.render(&section),
+            "\n### Coverage\n<!--head-commit:cdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcdcd-->\n⚠️ 3 of 5 added lines that are instrumented are uncovered ([report](https://example.org/report) for abababababababab).\n\n| File | Coverage before | Coverage after | Uncovered added lines |\n| ---- | --------------- | -------------- | --------------------- |\n| `src/init.cpp` | 50.0% | 40.0% | 13-14 |\n| `src/new.cpp` | (new) | 50.0% | 1 |\n"
+        );
+        let section = DiffCoverageSection {
+            files: &[],
+            ..section
+        };
+        assert_eq!(section.render(), None);
+    }
+}
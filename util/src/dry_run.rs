@@ -0,0 +1,125 @@
+use std::io::Write;
+use std::sync::{Mutex, OnceLock};
+
+/// The lines of context around each change in a unified diff.
+const DIFF_CONTEXT: usize = 3;
+
+fn output() -> &'static Mutex<Option<std::fs::File>> {
+    static OUTPUT: OnceLock<Mutex<Option<std::fs::File>>> = OnceLock::new();
+    OUTPUT.get_or_init(|| Mutex::new(None))
+}
+
+/// Also write the dry-run preview to the given file, so that it can be reviewed before a new
+/// config is enabled.
+pub fn set_dry_run_output(path: &std::path::Path) -> std::io::Result<()> {
+    *output().lock().unwrap() = Some(std::fs::File::create(path)?);
+    Ok(())
+}
+
+/// Report an API call that was skipped due to --dry-run, e.g. "POST
+/// /repos/owner/repo/issues/1/comments". If the call writes a text, pass the old and new text, to
+/// show the diff.
+pub fn dry_run_preview(call: &str, text: Option<(&str, &str)>) {
+    let diff = text
+        .map(|(old, new)| unified_diff(old, new))
+        .unwrap_or_default();
+    tracing::info!("... [dry-run] {call}\n{diff}");
+    if let Some(f) = output().lock().unwrap().as_mut() {
+        write!(f, "=== {call}\n{diff}").expect("dry-run output write error");
+    }
+}
+
+/// Return the line-based unified diff between the two texts, or an empty string if they are
+/// equal.
+pub fn unified_diff(old: &str, new: &str) -> String {
+    let old = old.lines().collect::<Vec<_>>();
+    let new = new.lines().collect::<Vec<_>>();
+
+    // Longest common subsequence, lcs[i][j] being the length for old[i..] and new[j..]
+    let mut lcs = vec![vec![0; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+    let mut ops = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            ops.push((' ', old[i]));
+            (i, j) = (i + 1, j + 1);
+        } else if i < old.len() && (j == new.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            ops.push(('-', old[i]));
+            i += 1;
+        } else {
+            ops.push(('+', new[j]));
+            j += 1;
+        }
+    }
+
+    let changes = ops
+        .iter()
+        .enumerate()
+        .filter(|(_, (tag, _))| *tag != ' ')
+        .map(|(pos, _)| pos)
+        .collect::<Vec<_>>();
+    let Some(&first) = changes.first() else {
+        return String::new();
+    };
+    // Group the changes into hunks, merging them if their context would overlap
+    let mut hunks = vec![(first, first)];
+    for &pos in &changes[1..] {
+        let last = hunks.last_mut().unwrap();
+        if pos - last.1 <= 2 * DIFF_CONTEXT + 1 {
+            last.1 = pos;
+        } else {
+            hunks.push((pos, pos));
+        }
+    }
+
+    let mut out = "--- old\n+++ new\n".to_string();
+    for (first, last) in hunks {
+        let start = first.saturating_sub(DIFF_CONTEXT);
+        let end = (last + DIFF_CONTEXT + 1).min(ops.len());
+        let count = |range: &[(char, &str)], skip: char| {
+            range.iter().filter(|(tag, _)| *tag != skip).count()
+        };
+        let (old_before, new_before) = (count(&ops[..start], '+'), count(&ops[..start], '-'));
+        let (old_len, new_len) = (count(&ops[start..end], '+'), count(&ops[start..end], '-'));
+        out += &format!(
+            "@@ -{},{old_len} +{},{new_len} @@\n",
+            old_before + usize::from(old_len > 0),
+            new_before + usize::from(new_len > 0),
+        );
+        for (tag, line) in &ops[start..end] {
+            out += &format!("{tag}{line}\n");
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unified_diff() {
+        assert_eq!(unified_diff("a\nb\n", "a\nb\n"), "");
+        assert_eq!(
+            unified_diff("", "new\ncomment\n"),
+            "--- old\n+++ new\n@@ -0,0 +1,2 @@\n+new\n+comment\n"
+        );
+        let old = "1\n2\n3\n4\n5\n6\n7\n8\n9\n10\n11\n12\n";
+        let new = "1\n2x\n3\n4\n5\n6\n7\n8\n9\n10\n11\n12\n13\n";
+        assert_eq!(
+            unified_diff(old, new),
+            "--- old\n+++ new\n\
+             @@ -1,5 +1,5 @@\n 1\n-2\n+2x\n 3\n 4\n 5\n\
+             @@ -10,3 +10,4 @@\n 10\n 11\n 12\n+13\n"
+        );
+    }
+}
@@ -0,0 +1,102 @@
+/// Public, read-only data about pull requests (parsed reviews, conflicts), committed to the
+/// reports repo, so that researchers and dashboard authors have stable urls without API access to
+/// the bot. The files of a pull request are stored in:
+///
+/// data/{owner}/{repo}/pulls/{number}/{name}.json
+///
+/// Reports computed from that data are stored in:
+///
+/// data/{owner}/{repo}/reports/{name}
+pub struct DataExport {
+    /// A clone of the reports repo with push access.
+    reports_dir: std::path::PathBuf,
+}
+
+impl DataExport {
+    pub fn new(reports_dir: std::path::PathBuf) -> Self {
+        Self { reports_dir }
+    }
+
+    /// Write the data of a pull request. The file is only rewritten when the data changed, so
+    /// that the history of the reports repo stays small.
+    pub fn write(
+        &self,
+        owner: &str,
+        repo: &str,
+        number: u64,
+        name: &str,
+        data: &serde_json::Value,
+    ) {
+        let dir = self
+            .reports_dir
+            .join("data")
+            .join(owner)
+            .join(repo)
+            .join("pulls")
+            .join(number.to_string());
+        std::fs::create_dir_all(&dir).expect("Failed to create export folder");
+        let path = dir.join(format!("{name}.json"));
+        let text = serde_json::to_string_pretty(data).expect("json error") + "\n";
+        if std::fs::read_to_string(&path).is_ok_and(|old| old == text) {
+            return;
+        }
+        std::fs::write(path, text).expect("Failed to write export file");
+    }
+
+    /// Read the data of the given name of all pull requests of the repo that have it.
+    pub fn read_all(&self, owner: &str, repo: &str, name: &str) -> Vec<(u64, serde_json::Value)> {
+        let dir = self
+            .reports_dir
+            .join("data")
+            .join(owner)
+            .join(repo)
+            .join("pulls");
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            return Vec::new();
+        };
+        let mut all = entries
+            .filter_map(|e| {
+                let entry = e.expect("Failed to read export folder");
+                let number = entry.file_name().to_str()?.parse().ok()?;
+                let text =
+                    std::fs::read_to_string(entry.path().join(format!("{name}.json"))).ok()?;
+                Some((
+                    number,
+                    serde_json::from_str(&text).expect("Invalid export file"),
+                ))
+            })
+            .collect::<Vec<_>>();
+        all.sort_by_key(|(number, _)| *number);
+        all
+    }
+
+    /// Write a report about the repo.
+    pub fn write_report(&self, owner: &str, repo: &str, name: &str, text: &str) {
+        let dir = self
+            .reports_dir
+            .join("data")
+            .join(owner)
+            .join(repo)
+            .join("reports");
+        std::fs::create_dir_all(&dir).expect("Failed to create export folder");
+        std::fs::write(dir.join(name), text).expect("Failed to write export file");
+    }
+
+    /// Commit and push all changes in the data folder, if any.
+    pub fn publish(&self, message: &str) {
+        let git = || {
+            let mut git = crate::git();
+            git.current_dir(&self.reports_dir);
+            git
+        };
+        crate::check_call(git().args(["add", "data"]));
+        if crate::call(git().args(["diff", "--cached", "--quiet"])) {
+            tracing::info!("No changes in the exported data");
+            return;
+        }
+        crate::check_call(git().args(["commit", "--quiet", "-m", message]));
+        // Other tools push to the reports repo as well, but never to the same files
+        crate::check_call(git().args(["pull", "--quiet", "--rebase"]));
+        crate::check_call(git().args(["push", "--quiet", "origin", "HEAD"]));
+    }
+}
@@ -0,0 +1,39 @@
+use fs2::FileExt;
+
+/// Open the lock file (created if missing) and lock it, waiting while another process holds the
+/// lock. The lock is released once the returned file is dropped.
+///
+/// This blocks the thread, so async code should call it via `spawn_blocking`.
+pub fn lock_file(path: &std::path::Path, exclusive: bool) -> std::io::Result<std::fs::File> {
+    let file = std::fs::OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(path)?;
+    // Call the trait methods explicitly, as newer versions of std have inherent ones
+    if exclusive {
+        if FileExt::try_lock_exclusive(&file).is_err() {
+            tracing::info!("Wait for the lock on {} ...", path.display());
+            FileExt::lock_exclusive(&file)?;
+        }
+    } else {
+        FileExt::lock_shared(&file)?;
+    }
+    Ok(file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lock_file() {
+        let path = std::env::temp_dir().join("drahtbot_test_util_lock_file.lock");
+        let lock = lock_file(&path, true).unwrap();
+        let other = std::fs::File::open(&path).unwrap();
+        assert!(FileExt::try_lock_shared(&other).is_err());
+        drop(lock);
+        assert!(FileExt::try_lock_shared(&other).is_ok());
+        let _shared = lock_file(&path, false).unwrap();
+    }
+}
@@ -0,0 +1,114 @@
+//! A footer appended to every comment of the bot, to tell which feature and version of the bot
+//! created it. The visible line helps users to file precise bug reports, and the hidden json blob
+//! lets the bot parse its own older comments.
+
+use std::sync::OnceLock;
+
+pub const BOT_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+const LINE_PREFIX: &str = "\n\n<sub>DrahtBot v";
+const BLOB_PREFIX: &str = "<!--drahtbot-footer:";
+
+#[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Footer {
+    pub version: String,
+    /// The feature or tool that created the comment, e.g. "ci_status" or "stale".
+    pub feature: String,
+    /// The run of the bot that created the comment, see `run_id`.
+    pub run: String,
+}
+
+/// The id of this run (process) of the bot, shared by all comments it creates. A random uuid (v4),
+/// to find the run in the logs.
+pub fn run_id() -> &'static str {
+    static RUN_ID: OnceLock<String> = OnceLock::new();
+    RUN_ID.get_or_init(|| {
+        let mut b = [0u8; 16];
+        getrandom::getrandom(&mut b).expect("random source error");
+        b[6] = (b[6] & 0x0f) | 0x40;
+        b[8] = (b[8] & 0x3f) | 0x80;
+        let hex = b.iter().map(|x| format!("{x:02x}")).collect::<String>();
+        format!(
+            "{}-{}-{}-{}-{}",
+            &hex[..8],
+            &hex[8..12],
+            &hex[12..16],
+            &hex[16..20],
+            &hex[20..]
+        )
+    })
+}
+
+impl Footer {
+    pub fn new(feature: &str) -> Self {
+        Self {
+            version: BOT_VERSION.to_string(),
+            feature: feature.to_string(),
+            run: run_id().to_string(),
+        }
+    }
+
+    fn render(&self) -> String {
+        format!(
+            "{LINE_PREFIX}{version} • feature: {feature} • run: {run}</sub>\n{BLOB_PREFIX}{json}-->\n",
+            version = self.version,
+            feature = self.feature,
+            run = self.run,
+            json = serde_json::to_string(self).expect("json error"),
+        )
+    }
+}
+
+/// Append the footer of the feature to the text of a comment.
+pub fn with_footer(text: &str, feature: &str) -> String {
+    format!("{text}{}", Footer::new(feature).render())
+}
+
+/// Split the text of a comment into the text before the footer and the footer, if it has one.
+fn split_footer(text: &str) -> Option<(&str, Footer)> {
+    let (before, line) = text.rsplit_once(LINE_PREFIX)?;
+    let (_, blob) = line.split_once(BLOB_PREFIX)?;
+    let (json, rest) = blob.split_once("-->")?;
+    if !rest.trim().is_empty() {
+        return None;
+    }
+    Some((before, serde_json::from_str(json).ok()?))
+}
+
+/// Return the footer of the comment, if it has one.
+pub fn parse_footer(text: &str) -> Option<Footer> {
+    split_footer(text).map(|(_, f)| f)
+}
+
+/// Return the text of the comment without its footer.
+pub fn strip_footer(text: &str) -> &str {
+    split_footer(text).map_or(text, |(t, _)| t)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_footer() {
+        let text = "<!--drahtbot:needs-rebase:v1-->\nPlease rebase\n";
+        let full = with_footer(text, "stale");
+        assert!(full.contains(&format!(
+            "\n<sub>DrahtBot v{BOT_VERSION} • feature: stale • run: {}</sub>\n",
+            run_id()
+        )));
+        assert_eq!(strip_footer(&full), text);
+        assert_eq!(parse_footer(&full), Some(Footer::new("stale")));
+        assert_eq!(strip_footer(text), text);
+        assert_eq!(parse_footer(text), None);
+        // Only the last footer counts, and only at the end
+        assert_eq!(strip_footer(&with_footer(&full, "guix")), full);
+        let quoted = format!("{full}\nQuoted by a user");
+        assert_eq!(strip_footer(&quoted), quoted);
+
+        let id = run_id();
+        assert_eq!(id.len(), 36);
+        assert_eq!(&id[14..15], "4");
+        assert_eq!(id, run_id());
+    }
+}
@@ -0,0 +1,227 @@
+/// The metadata of an open pull request, as returned by `open_pulls`.
+#[derive(Debug, PartialEq)]
+pub struct PullMeta {
+    pub number: u64,
+    /// None while GitHub is still computing it. Use `get_pull_mergeable` to wait for it.
+    pub mergeable: Option<bool>,
+    pub labels: Vec<String>,
+    pub body: String,
+    pub head_sha: String,
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl PullMeta {
+    /// Same as `is_quiet`.
+    pub fn is_quiet(&self) -> bool {
+        self.labels.iter().any(|l| l == crate::QUIET_LABEL)
+            || self.body.contains(crate::QUIET_MARKER)
+    }
+}
+
+const OPEN_PULLS_QUERY: &str = r#"
+query ($owner: String!, $repo: String!, $cursor: String) {
+  repository(owner: $owner, name: $repo) {
+    pullRequests(states: OPEN, first: 100, after: $cursor) {
+      pageInfo { hasNextPage endCursor }
+      nodes {
+        number
+        mergeable
+        body
+        headRefOid
+        updatedAt
+        labels(first: 100) { nodes { name } }
+      }
+    }
+  }
+}
+"#;
+
+/// Return the error of a GraphQL response. GitHub answers with a success status and puts the errors
+/// (e.g. a rate limit or a timeout) into the payload.
+fn graphql_error(page: &serde_json::Value) -> octocrab::Error {
+    octocrab::Error::Other {
+        source: format!("GraphQL error: {}", page["errors"]).into(),
+        backtrace: std::backtrace::Backtrace::capture(),
+    }
+}
+
+/// Parse a page of the open pulls query, and return the pulls and the cursor of the next page.
+fn parse_open_pulls(page: &serde_json::Value) -> octocrab::Result<(Vec<PullMeta>, Option<String>)> {
+    let pulls = &page["data"]["repository"]["pullRequests"];
+    let nodes = pulls["nodes"]
+        .as_array()
+        .ok_or_else(|| graphql_error(page))?;
+    let metas = nodes
+        .iter()
+        .map(|n| {
+            Some(PullMeta {
+                number: n["number"].as_u64()?,
+                mergeable: match n["mergeable"].as_str() {
+                    Some("MERGEABLE") => Some(true),
+                    Some("CONFLICTING") => Some(false),
+                    _ => None, // UNKNOWN
+                },
+                labels: n["labels"]["nodes"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|l| l["name"].as_str())
+                    .map(|l| l.to_string())
+                    .collect(),
+                body: n["body"].as_str().unwrap_or_default().to_string(),
+                head_sha: n["headRefOid"].as_str().unwrap_or_default().to_string(),
+                updated_at: n["updatedAt"].as_str()?.parse().ok()?,
+            })
+        })
+        .collect::<Option<Vec<_>>>()
+        .ok_or_else(|| graphql_error(page))?;
+    let cursor = if pulls["pageInfo"]["hasNextPage"].as_bool() == Some(true) {
+        pulls["pageInfo"]["endCursor"]
+            .as_str()
+            .map(|c| c.to_string())
+    } else {
+        None
+    };
+    Ok((metas, cursor))
+}
+
+/// Return the metadata of all open pull requests of the repo, using one GraphQL request per 100
+/// pulls, instead of several REST requests per pull.
+pub async fn open_pulls(
+    github: &octocrab::Octocrab,
+    slug: &crate::Slug,
+) -> octocrab::Result<Vec<PullMeta>> {
+    let mut pulls = Vec::new();
+    let mut cursor = None;
+    loop {
+        let payload = serde_json::json!({
+            "query": OPEN_PULLS_QUERY,
+            "variables": {"owner": slug.owner, "repo": slug.repo, "cursor": cursor},
+        });
+        let page: serde_json::Value = crate::with_retry(|| github.graphql(&payload)).await?;
+        let (metas, next) = parse_open_pulls(&page)?;
+        pulls.extend(metas);
+        match next {
+            Some(next) => cursor = Some(next),
+            None => return Ok(pulls),
+        }
+    }
+}
+
+const REVIEW_THREADS_QUERY: &str = r#"
+query ($owner: String!, $repo: String!, $number: Int!, $cursor: String) {
+  repository(owner: $owner, name: $repo) {
+    pullRequest(number: $number) {
+      reviewThreads(first: 100, after: $cursor) {
+        pageInfo { hasNextPage endCursor }
+        nodes { isResolved }
+      }
+    }
+  }
+}
+"#;
+
+/// Parse a page of the review threads query, and return the number of unresolved threads and the
+/// cursor of the next page.
+fn parse_review_threads(page: &serde_json::Value) -> octocrab::Result<(u64, Option<String>)> {
+    let threads = &page["data"]["repository"]["pullRequest"]["reviewThreads"];
+    let nodes = threads["nodes"]
+        .as_array()
+        .ok_or_else(|| graphql_error(page))?;
+    let unresolved = nodes
+        .iter()
+        .filter(|n| n["isResolved"].as_bool() == Some(false))
+        .count();
+    let cursor = if threads["pageInfo"]["hasNextPage"].as_bool() == Some(true) {
+        threads["pageInfo"]["endCursor"]
+            .as_str()
+            .map(|c| c.to_string())
+    } else {
+        None
+    };
+    Ok((unresolved as u64, cursor))
+}
+
+/// Return the number of unresolved review threads (conversations) of the pull request. The REST
+/// API does not tell whether a thread is resolved.
+pub async fn unresolved_review_threads(
+    github: &octocrab::Octocrab,
+    slug: &crate::Slug,
+    number: u64,
+) -> octocrab::Result<u64> {
+    let mut unresolved = 0;
+    let mut cursor = None;
+    loop {
+        let payload = serde_json::json!({
+            "query": REVIEW_THREADS_QUERY,
+            "variables": {"owner": slug.owner, "repo": slug.repo, "number": number, "cursor": cursor},
+        });
+        let page: serde_json::Value = crate::with_retry(|| github.graphql(&payload)).await?;
+        let (count, next) = parse_review_threads(&page)?;
+        unresolved += count;
+        match next {
+            Some(next) => cursor = Some(next),
+            None => return Ok(unresolved),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_open_pulls() {
+        let page = serde_json::json!({"data": {"repository": {"pullRequests": {
+            "pageInfo": {"hasNextPage": true, "endCursor": "Y3Vyc29y"},
+            "nodes": [
+                {"number": 1, "mergeable": "CONFLICTING", "body": "", "headRefOid": "aa", "updatedAt": "2024-01-01T00:00:00Z", "labels": {"nodes": [{"name": "Wallet"}, {"name": "DrahtBot quiet"}]}},
+                {"number": 2, "mergeable": "UNKNOWN", "body": null, "headRefOid": "bb", "updatedAt": "2024-01-02T00:00:00Z", "labels": {"nodes": []}},
+            ],
+        }}}});
+        let (pulls, cursor) = parse_open_pulls(&page).unwrap();
+        assert_eq!(cursor.as_deref(), Some("Y3Vyc29y"));
+        assert_eq!(
+            pulls[0],
+            PullMeta {
+                number: 1,
+                mergeable: Some(false),
+                labels: vec!["Wallet".to_string(), "DrahtBot quiet".to_string()],
+                body: String::new(),
+                head_sha: "aa".to_string(),
+                updated_at: "2024-01-01T00:00:00Z".parse().unwrap(),
+            }
+        );
+        assert!(pulls[0].is_quiet());
+        assert_eq!(pulls[1].mergeable, None);
+        assert!(!pulls[1].is_quiet());
+
+        let page = serde_json::json!({"data": {"repository": {"pullRequests": {
+            "pageInfo": {"hasNextPage": false, "endCursor": "Y3Vyc29y"},
+            "nodes": [],
+        }}}});
+        assert_eq!(parse_open_pulls(&page).unwrap(), (Vec::new(), None));
+        let page = serde_json::json!({"data": null, "errors": [{"type": "RATE_LIMITED"}]});
+        let err = parse_open_pulls(&page).unwrap_err();
+        assert!(format!("{err:?}").contains("RATE_LIMITED"));
+    }
+
+    #[test]
+    fn test_parse_review_threads() {
+        let page = serde_json::json!({"data": {"repository": {"pullRequest": {"reviewThreads": {
+            "pageInfo": {"hasNextPage": true, "endCursor": "Y3Vyc29y"},
+            "nodes": [{"isResolved": false}, {"isResolved": true}, {"isResolved": false}],
+        }}}}});
+        assert_eq!(
+            parse_review_threads(&page).unwrap(),
+            (2, Some("Y3Vyc29y".to_string()))
+        );
+        let page = serde_json::json!({"data": {"repository": {"pullRequest": {"reviewThreads": {
+            "pageInfo": {"hasNextPage": false, "endCursor": null},
+            "nodes": [],
+        }}}}});
+        assert_eq!(parse_review_threads(&page).unwrap(), (0, None));
+        let page = serde_json::json!({"data": {"repository": {"pullRequest": null}}, "errors": [{"type": "NOT_FOUND"}]});
+        assert!(parse_review_threads(&page).is_err());
+    }
+}
@@ -0,0 +1,263 @@
+//! A disk cache for the GET requests of octocrab. Cached responses are revalidated with their ETag
+//! (or Last-Modified date), and GitHub does not count a `304 Not Modified` against the rate limit,
+//! so repeated sweeps over mostly unchanged pulls become cheap.
+
+use std::future::Future;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::Poll;
+
+use bytes::Bytes;
+use http::{header, Method, Request, Response, StatusCode};
+use http_body_util::{BodyExt, Full};
+use tower::BoxError;
+
+/// A response, as stored in the cache dir.
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Entry {
+    /// All headers of the response, including the Link header needed for paging.
+    headers: Vec<(String, String)>,
+    body: String,
+}
+
+impl Entry {
+    fn validator(&self, name: header::HeaderName) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name.as_str()))
+            .map(|(_, v)| v.as_str())
+    }
+
+    fn response(self) -> Response<Full<Bytes>> {
+        let mut res = Response::new(Full::new(Bytes::from(self.body)));
+        for (name, value) in self.headers {
+            if let (Ok(n), Ok(v)) = (
+                header::HeaderName::from_bytes(name.as_bytes()),
+                header::HeaderValue::from_str(&value),
+            ) {
+                res.headers_mut().append(n, v);
+            }
+        }
+        res
+    }
+}
+
+/// The cache file of the request. The token is part of the key, because the response may
+/// depend on the permissions of the token.
+fn cache_file(dir: &Path, req: &Request<String>) -> PathBuf {
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    req.uri().to_string().hash(&mut hasher);
+    req.headers()
+        .get(header::AUTHORIZATION)
+        .map(|v| v.as_bytes())
+        .hash(&mut hasher);
+    dir.join(format!("{:016x}.json", hasher.finish()))
+}
+
+fn load(path: &Path) -> Option<Entry> {
+    serde_json::from_slice(&std::fs::read(path).ok()?).ok()
+}
+
+/// Store the response, if it can be revalidated later on.
+fn store(path: &Path, headers: &http::HeaderMap, body: &Bytes) {
+    if !headers.contains_key(header::ETAG) && !headers.contains_key(header::LAST_MODIFIED) {
+        return;
+    }
+    let Ok(body) = std::str::from_utf8(body) else {
+        return;
+    };
+    let entry = Entry {
+        headers: headers
+            .iter()
+            .filter_map(|(n, v)| Some((n.to_string(), v.to_str().ok()?.to_string())))
+            .collect(),
+        body: body.to_string(),
+    };
+    let tmp = path.with_extension("tmp");
+    let res = std::fs::write(&tmp, serde_json::to_vec(&entry).expect("json error"))
+        .and_then(|_| std::fs::rename(&tmp, path));
+    if let Err(err) = res {
+        tracing::warn!("Failed to write the cache file {}: {err}", path.display());
+    }
+}
+
+#[derive(Clone)]
+pub struct CacheLayer {
+    dir: Arc<PathBuf>,
+}
+
+impl CacheLayer {
+    pub fn new(dir: PathBuf) -> Self {
+        std::fs::create_dir_all(&dir).expect("cache dir error");
+        Self { dir: Arc::new(dir) }
+    }
+}
+
+impl<S> tower::Layer<S> for CacheLayer {
+    type Service = CacheService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        CacheService {
+            inner,
+            dir: self.dir.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct CacheService<S> {
+    inner: S,
+    dir: Arc<PathBuf>,
+}
+
+impl<S, B> tower::Service<Request<String>> for CacheService<S>
+where
+    S: tower::Service<Request<String>, Response = Response<B>> + Clone + Send + 'static,
+    S::Future: Send,
+    S::Error: Into<BoxError>,
+    B: http_body::Body<Data = Bytes> + Send + 'static,
+    B::Error: Into<BoxError>,
+{
+    type Response = Response<Full<Bytes>>;
+    type Error = BoxError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, BoxError>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut std::task::Context<'_>) -> Poll<Result<(), BoxError>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, mut req: Request<String>) -> Self::Future {
+        // Use the service that was polled ready, and leave a clone in its place
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let path = (req.method() == Method::GET).then(|| cache_file(&self.dir, &req));
+        let cached = path.as_deref().and_then(load);
+        if let Some(entry) = &cached {
+            for (validator, condition) in [
+                (header::ETAG, header::IF_NONE_MATCH),
+                (header::LAST_MODIFIED, header::IF_MODIFIED_SINCE),
+            ] {
+                if let Some(v) = entry
+                    .validator(validator)
+                    .and_then(|v| header::HeaderValue::from_str(v).ok())
+                {
+                    req.headers_mut().insert(condition, v);
+                }
+            }
+        }
+        Box::pin(async move {
+            let (parts, body) = inner.call(req).await.map_err(Into::into)?.into_parts();
+            let body = body.collect().await.map_err(Into::into)?.to_bytes();
+            match (path, cached) {
+                (Some(_), Some(entry)) if parts.status == StatusCode::NOT_MODIFIED => {
+                    return Ok(entry.response());
+                }
+                (Some(path), _) if parts.status == StatusCode::OK => {
+                    store(&path, &parts.headers, &body);
+                }
+                _ => {}
+            }
+            Ok(Response::from_parts(parts, Full::new(body)))
+        })
+    }
+}
+
+/// Return an octocrab client that caches GET responses in the dir. Unlike the default client, it
+/// does not retry failed requests on its own, see `with_retry`.
+pub fn cached_octocrab(
+    token: Option<String>,
+    dir: PathBuf,
+) -> octocrab::Result<octocrab::Octocrab> {
+    let connector = hyper_rustls::HttpsConnectorBuilder::new()
+        .with_native_roots()
+        .expect("tls root certificates error")
+        .https_or_http()
+        .enable_http1()
+        .build();
+    let client = hyper_util::client::legacy::Client::builder(hyper_util::rt::TokioExecutor::new())
+        .build(connector);
+    let mut headers = vec![(
+        header::USER_AGENT,
+        header::HeaderValue::from_static("octocrab"),
+    )];
+    if let Some(tok) = token {
+        headers.push((
+            header::AUTHORIZATION,
+            format!("Bearer {tok}").parse().expect("token error"),
+        ));
+    }
+    let octocrab = octocrab::OctocrabBuilder::new_empty()
+        .with_service(client)
+        .with_layer(&CacheLayer::new(dir))
+        .with_layer(&octocrab::service::middleware::base_uri::BaseUriLayer::new(
+            http::Uri::from_static("https://api.github.com"),
+        ))
+        .with_layer(
+            &octocrab::service::middleware::extra_headers::ExtraHeadersLayer::new(Arc::new(
+                headers,
+            )),
+        )
+        .with_auth(octocrab::AuthState::None)
+        .build()
+        .unwrap_or_else(|infallible| match infallible {});
+    Ok(octocrab)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tower::{Layer, Service, ServiceExt};
+
+    async fn get<S>(service: &mut S, path: &str) -> (Bytes, Option<header::HeaderValue>)
+    where
+        S: Service<Request<String>, Response = Response<Full<Bytes>>, Error = BoxError>,
+    {
+        let req = Request::get(path).body(String::new()).unwrap();
+        let res = service.ready().await.unwrap().call(req).await.unwrap();
+        let link = res.headers().get(header::LINK).cloned();
+        (res.into_body().collect().await.unwrap().to_bytes(), link)
+    }
+
+    #[tokio::test]
+    async fn test_cache() {
+        let dir = std::env::temp_dir().join(format!("http_cache_test_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let calls = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let server = {
+            let calls = calls.clone();
+            tower::service_fn(move |req: Request<String>| {
+                let if_none_match = req
+                    .headers()
+                    .get(header::IF_NONE_MATCH)
+                    .map(|v| v.to_str().unwrap().to_string());
+                calls.lock().unwrap().push(if_none_match.clone());
+                let res = match (req.uri().path(), if_none_match.as_deref()) {
+                    ("/labels", Some("\"v1\"")) => Response::builder()
+                        .status(StatusCode::NOT_MODIFIED)
+                        .body(Full::new(Bytes::new())),
+                    ("/labels", _) => Response::builder()
+                        .header(header::ETAG, "\"v1\"")
+                        .header(header::LINK, "<next>; rel=\"next\"")
+                        .body(Full::new(Bytes::from("[\"Wallet\"]"))),
+                    _ => Response::builder().body(Full::new(Bytes::from("uncached"))),
+                };
+                async move { Ok::<_, std::convert::Infallible>(res.unwrap()) }
+            })
+        };
+        let mut service = CacheLayer::new(dir.clone()).layer(server);
+        let (body, link) = get(&mut service, "/labels").await;
+        assert_eq!(body, "[\"Wallet\"]");
+        assert!(link.is_some());
+        // Revalidated, and served from the cache with all headers
+        assert_eq!(get(&mut service, "/labels").await, (body, link));
+        assert_eq!(get(&mut service, "/other").await.0, "uncached");
+        assert_eq!(get(&mut service, "/other").await.0, "uncached");
+        assert_eq!(
+            *calls.lock().unwrap(),
+            [None, Some("\"v1\"".to_string()), None, None]
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
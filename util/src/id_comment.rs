@@ -0,0 +1,277 @@
+/// The kind of a comment of the bot, or of a section in its metadata comment. It is identified by
+/// a hidden html marker at the start of the text: `<!--drahtbot:<name>:v<version>-->`.
+///
+/// Bump the version in the marker when the layout of the text changes, so that texts created by
+/// an older version of the bot can be told apart and migrated. Texts created before the markers
+/// were versioned start with an opaque hash instead, which is still recognized as version 0.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IdComment {
+    ApprovalRequest,
+    NeedsRebase,
+    CoverageRegressions,
+    InactiveRebase,
+    InactiveCi,
+    InactiveStale,
+    InactiveClose,
+    WaitingAuthorWarning,
+    WaitingAuthorClose,
+    MergeSummary,
+    GuixResults, // Created by scripts/guix.py
+    LabelMention,
+    Metadata, // The "root" section
+    MetadataContinued,
+    SecBaseCi,
+    SecBehindBase,
+    SecBotActivity,
+    SecCiFailed,
+    SecClosedInactive,
+    SecCodeCoverage,
+    SecConflicts,
+    SecConflictsOutdated,
+    SecCoverage,
+    SecFuzzCoverage,
+    SecGuixQueue,
+    SecOutdatedResults,
+    SecReviewRequests,
+    SecReviews,
+    SecSize,
+}
+
+const MARKER_PREFIX: &str = "<!--drahtbot:";
+
+impl IdComment {
+    pub const ALL: [IdComment; 29] = [
+        Self::ApprovalRequest,
+        Self::NeedsRebase,
+        Self::CoverageRegressions,
+        Self::InactiveRebase,
+        Self::InactiveCi,
+        Self::InactiveStale,
+        Self::InactiveClose,
+        Self::WaitingAuthorWarning,
+        Self::WaitingAuthorClose,
+        Self::MergeSummary,
+        Self::GuixResults,
+        Self::LabelMention,
+        Self::Metadata,
+        Self::MetadataContinued,
+        Self::SecBaseCi,
+        Self::SecBehindBase,
+        Self::SecBotActivity,
+        Self::SecCiFailed,
+        Self::SecClosedInactive,
+        Self::SecCodeCoverage,
+        Self::SecConflicts,
+        Self::SecConflictsOutdated,
+        Self::SecCoverage,
+        Self::SecFuzzCoverage,
+        Self::SecGuixQueue,
+        Self::SecOutdatedResults,
+        Self::SecReviewRequests,
+        Self::SecReviews,
+        Self::SecSize,
+    ];
+
+    /// The marker of texts created now.
+    pub fn str(&self) -> &'static str {
+        match self {
+            Self::ApprovalRequest => "<!--drahtbot:approval-request:v1-->",
+            Self::NeedsRebase => "<!--drahtbot:needs-rebase:v1-->",
+            Self::CoverageRegressions => "<!--drahtbot:coverage-regressions:v1-->",
+            Self::InactiveRebase => "<!--drahtbot:inactive-rebase:v1-->",
+            Self::InactiveCi => "<!--drahtbot:inactive-ci:v1-->",
+            Self::InactiveStale => "<!--drahtbot:inactive-stale:v1-->",
+            Self::InactiveClose => "<!--drahtbot:inactive-close:v1-->",
+            Self::WaitingAuthorWarning => "<!--drahtbot:waiting-author-warning:v1-->",
+            Self::WaitingAuthorClose => "<!--drahtbot:waiting-author-close:v1-->",
+            Self::MergeSummary => "<!--drahtbot:merge-summary:v1-->",
+            Self::GuixResults => "<!--drahtbot:guix-results:v1-->",
+            Self::LabelMention => "<!--drahtbot:label-mention:v1-->",
+            Self::Metadata => "<!--drahtbot:metadata:v1-->",
+            Self::MetadataContinued => "<!--drahtbot:metadata-continued:v1-->",
+            Self::SecBaseCi => "<!--drahtbot:base-ci:v1-->",
+            Self::SecBehindBase => "<!--drahtbot:behind-base:v1-->",
+            Self::SecBotActivity => "<!--drahtbot:bot-activity:v1-->",
+            Self::SecCiFailed => "<!--drahtbot:ci-failed:v1-->",
+            Self::SecClosedInactive => "<!--drahtbot:closed-inactive:v1-->",
+            Self::SecCodeCoverage => "<!--drahtbot:code-coverage:v1-->",
+            Self::SecConflicts => "<!--drahtbot:conflicts:v1-->",
+            Self::SecConflictsOutdated => "<!--drahtbot:conflicts-outdated:v1-->",
+            Self::SecCoverage => "<!--drahtbot:coverage:v1-->",
+            Self::SecFuzzCoverage => "<!--drahtbot:fuzz-coverage:v1-->",
+            Self::SecGuixQueue => "<!--drahtbot:guix-queue:v1-->",
+            Self::SecOutdatedResults => "<!--drahtbot:outdated-results:v1-->",
+            Self::SecReviewRequests => "<!--drahtbot:review-requests:v1-->",
+            Self::SecReviews => "<!--drahtbot:reviews:v1-->",
+            Self::SecSize => "<!--drahtbot:size:v1-->",
+        }
+    }
+
+    /// The name in the marker, e.g. "needs-rebase".
+    pub fn name(&self) -> &'static str {
+        let marker = self.str().strip_prefix(MARKER_PREFIX).unwrap();
+        marker.rsplit_once(":v").unwrap().0
+    }
+
+    /// The marker of texts created before the markers were versioned, if the kind existed back
+    /// then.
+    pub fn legacy_str(&self) -> Option<&'static str> {
+        Some(match self {
+            Self::ApprovalRequest => "<!--c3a1f08e5b7d4e2f9a6b0d1c8e7f5a24-->",
+            Self::NeedsRebase => "<!--cf906140f33d8803c4a75a2196329ecb-->",
+            Self::CoverageRegressions => "<!--9597789f347edf9fbd9eef86f32eba33-->",
+            Self::InactiveRebase => "<!--13523179cfe9479db18ec6c5d236f789-->",
+            Self::InactiveCi => "<!--2e250dc3d92b2c9115b66051148d6e47-->",
+            Self::InactiveStale => "<!--8ac04cdde196e94527acabf64b896448-->",
+            Self::MergeSummary => "<!--771124d6bbadc2a263ce8c26dd465282-->",
+            Self::GuixResults => "<!--9cd9c72976c961c55c7acef8f6ba82cd-->",
+            Self::LabelMention => "<!--4e8b1d7a0c2f9e35b6a1d8c4f7e0b923-->",
+            Self::Metadata => "<!--e57a25ab6845829454e8d69fc972939a-->",
+            Self::SecBaseCi => "<!--3c1f8e0b7a9d24f6e5b1c8a0d7f2e934-->",
+            Self::SecBehindBase => "<!--5b0e7c4a2d9f13e86a7c0b4d2e1f9a35-->",
+            Self::SecBotActivity => "<!--f6d2e1c0a4b3c5d29e6f1a7b8c0d3e42-->",
+            Self::SecCiFailed => "<!--85328a0da195eb286784d51f73fa0af9-->",
+            Self::SecCodeCoverage => "<!--006a51241073e994b41acfe9ec718e94-->",
+            Self::SecConflicts => "<!--174a7506f384e20aa4161008e828411d-->",
+            Self::SecCoverage => "<!--2502f1a698b3751726fa55edcda76cd3-->",
+            Self::SecReviewRequests => "<!--02b7e4c19f3a8d56e0c2a9b41d7f3e68-->",
+            Self::SecReviews => "<!--021abf342d371248e50ceaed478a90ca-->",
+            Self::InactiveClose
+            | Self::MetadataContinued
+            | Self::WaitingAuthorWarning
+            | Self::WaitingAuthorClose
+            | Self::SecClosedInactive
+            | Self::SecConflictsOutdated
+            | Self::SecFuzzCoverage
+            | Self::SecGuixQueue
+            | Self::SecOutdatedResults
+            | Self::SecSize => return None,
+        })
+    }
+
+    /// Return the kind, the version and the length of the marker at the start of the text.
+    fn parse_marker(text: &str) -> Option<(IdComment, u32, usize)> {
+        if let Some(rest) = text.strip_prefix(MARKER_PREFIX) {
+            let (marker, _) = rest.split_once("-->")?;
+            let (name, version) = marker.rsplit_once(":v")?;
+            let version = version.parse().ok()?;
+            let id = Self::ALL.into_iter().find(|id| {
+                id.str()
+                    .strip_prefix(MARKER_PREFIX)
+                    .is_some_and(|m| m.starts_with(&format!("{name}:v")))
+            })?;
+            return Some((
+                id,
+                version,
+                MARKER_PREFIX.len() + marker.len() + "-->".len(),
+            ));
+        }
+        Self::ALL
+            .into_iter()
+            .filter_map(|id| Some((id, id.legacy_str()?)))
+            .find(|(_, legacy)| text.starts_with(legacy))
+            .map(|(id, legacy)| (id, 0, legacy.len()))
+    }
+
+    /// Return the kind and the version of the marker at the start of the text.
+    pub fn parse(text: &str) -> Option<(IdComment, u32)> {
+        Self::parse_marker(text).map(|(id, version, _)| (id, version))
+    }
+
+    /// The version of texts created now.
+    pub fn version(&self) -> u32 {
+        Self::parse(self.str()).unwrap().1
+    }
+
+    /// If the text starts with a marker of this kind (of any version), return the rest of it.
+    pub fn strip<'a>(&self, text: &'a str) -> Option<&'a str> {
+        match Self::parse_marker(text) {
+            Some((id, _, len)) if id == *self => Some(&text[len..]),
+            _ => None,
+        }
+    }
+
+    /// Whether the text starts with a marker of this kind, of any version.
+    pub fn is_start_of(&self, text: &str) -> bool {
+        self.strip(text).is_some()
+    }
+
+    /// Replace an outdated marker at the start of the text with the current one. Return None, if
+    /// the marker is up to date or unknown.
+    ///
+    /// When the layout of a kind of text changes, its old layouts should be converted here as
+    /// well, based on the old version.
+    pub fn migrate(text: &str) -> Option<String> {
+        let (id, version, len) = Self::parse_marker(text)?;
+        if version == id.version() {
+            return None;
+        }
+        Some(format!("{}{}", id.str(), &text[len..]))
+    }
+}
+
+const REOPEN_UNTIL_PREFIX: &str = "<!--reopen-until:";
+
+/// Placed after the marker of a close comment, to let the author reopen the issue by commenting
+/// until the date (inclusive).
+pub fn reopen_until_marker(date: chrono::NaiveDate) -> String {
+    format!("{REOPEN_UNTIL_PREFIX}{date}-->")
+}
+
+/// Return the date of the reopen-until marker at the start of the text.
+pub fn parse_reopen_until(text: &str) -> Option<chrono::NaiveDate> {
+    let (date, _) = text.strip_prefix(REOPEN_UNTIL_PREFIX)?.split_once("-->")?;
+    date.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_markers() {
+        for id in IdComment::ALL {
+            assert_eq!(IdComment::parse(id.str()), Some((id, id.version())));
+            assert!(id.version() > 0);
+            assert_eq!(IdComment::migrate(id.str()), None);
+            let Some(legacy) = id.legacy_str() else {
+                continue;
+            };
+            assert_eq!(IdComment::parse(legacy), Some((id, 0)));
+            assert_eq!(
+                IdComment::migrate(&format!("{legacy}\nText")),
+                Some(format!("{}\nText", id.str()))
+            );
+        }
+        let id = IdComment::SecCoverage;
+        assert_eq!(id.name(), "coverage");
+        assert_eq!(
+            id.strip("<!--drahtbot:coverage:v7-->\nText"),
+            Some("\nText")
+        );
+        assert_eq!(
+            IdComment::migrate("<!--drahtbot:coverage:v0-->\nText"),
+            Some(format!("{}\nText", id.str()))
+        );
+        assert!(!id.is_start_of(IdComment::CoverageRegressions.str()));
+        assert!(!id.is_start_of("\n<!--drahtbot:coverage:v1-->"));
+        assert_eq!(IdComment::parse("<!--drahtbot:unknown:v1-->"), None);
+        assert_eq!(IdComment::parse("<!--drahtbot:coverage:vx-->"), None);
+        assert_eq!(IdComment::parse("<!-- DrahtBot quiet -->"), None);
+        assert_eq!(IdComment::migrate("Concept ACK"), None);
+    }
+
+    #[test]
+    fn test_reopen_until() {
+        let date = "2024-07-01".parse().unwrap();
+        let text = format!(
+            "{}{}\nClosed",
+            IdComment::WaitingAuthorClose.str(),
+            reopen_until_marker(date)
+        );
+        let rest = IdComment::WaitingAuthorClose.strip(&text).unwrap();
+        assert_eq!(parse_reopen_until(rest), Some(date));
+        assert_eq!(parse_reopen_until("\nClosed"), None);
+        assert_eq!(parse_reopen_until("<!--reopen-until:soon-->"), None);
+    }
+}
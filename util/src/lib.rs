@@ -1,3 +1,74 @@
+#[cfg(feature = "github")]
+mod approval;
+mod artifact_store;
+mod container;
+#[cfg(feature = "github")]
+mod diff_coverage;
+mod dry_run;
+mod export;
+mod file_lock;
+#[cfg(feature = "github")]
+mod footer;
+#[cfg(feature = "github")]
+mod graphql;
+#[cfg(feature = "github")]
+mod http_cache;
+#[cfg(feature = "github")]
+mod id_comment;
+mod logging;
+mod manifest;
+#[cfg(feature = "github")]
+mod plan;
+mod progress;
+#[cfg(feature = "github")]
+mod quota;
+#[cfg(feature = "github")]
+mod retry;
+#[cfg(feature = "github")]
+mod section;
+mod template;
+
+#[cfg(feature = "github")]
+pub use approval::{
+    approve_plan, check_approval, propose_action, Approval, ApprovalConfig, ApprovalPolicy,
+    ProposedAction,
+};
+pub use artifact_store::ArtifactStore;
+pub use container::{Container, ContainerRuntime};
+#[cfg(feature = "github")]
+pub use diff_coverage::{
+    added_lines, diff_coverage, parse_lcov_lines, DiffCoverageSection, FileDelta, LineCoverage,
+};
+pub use dry_run::{dry_run_preview, set_dry_run_output, unified_diff};
+pub use export::DataExport;
+pub use file_lock::lock_file;
+#[cfg(feature = "github")]
+pub use footer::{parse_footer, run_id, strip_footer, with_footer, Footer, BOT_VERSION};
+#[cfg(feature = "github")]
+pub use graphql::{open_pulls, unresolved_review_threads, PullMeta};
+#[cfg(feature = "github")]
+pub use id_comment::{parse_reopen_until, reopen_until_marker, IdComment};
+pub use logging::{init_logging, init_logging_with, LogFormat, LogLayer};
+pub use manifest::{ResultManifest, RESULT_MANIFEST};
+#[cfg(feature = "github")]
+pub use plan::{apply_plan, Applied, Edit};
+pub use progress::{Checkpoint, Progress};
+#[cfg(feature = "github")]
+pub use quota::{
+    exit_on_quota_denials, quota_allows, quota_allows_all, quota_denials, set_quota_limits, set_quota_store, Action,
+    QuotaLimits, QuotaLog, QuotaStore,
+};
+#[cfg(feature = "github")]
+pub use retry::{retry_without_delay, with_retry};
+#[cfg(feature = "github")]
+pub use section::{
+    head_commit_marker, parse_closed_labels, parse_head_commit, ClosedInactiveSection, Section,
+    SectionInfo, SectionRegistry, SECTION_REGISTRY,
+};
+#[cfg(feature = "github")]
+pub use template::pull_vars;
+pub use template::{check_template, render_template, PULL_VARS};
+
 #[derive(Clone)]
 pub struct Slug {
     pub owner: String,
@@ -28,7 +99,15 @@ impl std::str::FromStr for Slug {
 }
 
 #[cfg(feature = "github")]
-pub fn get_octocrab(token: Option<String>) -> octocrab::Result<octocrab::Octocrab> {
+/// Return a client for the GitHub API. If a cache dir is given, GET responses are cached there
+/// and revalidated, see `http_cache`.
+pub fn get_octocrab(
+    token: Option<String>,
+    cache_dir: Option<std::path::PathBuf>,
+) -> octocrab::Result<octocrab::Octocrab> {
+    if let Some(dir) = cache_dir {
+        return http_cache::cached_octocrab(token, dir);
+    }
     let build = octocrab::Octocrab::builder();
     match token {
         Some(tok) => build.personal_token(tok),
@@ -37,36 +116,11 @@ pub fn get_octocrab(token: Option<String>) -> octocrab::Result<octocrab::Octocra
     .build()
 }
 
+/// Return the number of remaining core API calls for the current token.
 #[cfg(feature = "github")]
-pub enum IdComment {
-    NeedsRebase,
-    CiFailed,
-    InactiveRebase,
-    InactiveCi,
-    InactiveStale,
-    Metadata, // The "root" section
-    SecCodeCoverage,
-    SecConflicts,
-    SecCoverage,
-    SecReviews,
-}
-
-#[cfg(feature = "github")]
-impl IdComment {
-    pub fn str(&self) -> &'static str {
-        match self {
-            Self::NeedsRebase => "<!--cf906140f33d8803c4a75a2196329ecb-->",
-            Self::CiFailed=> "<!--85328a0da195eb286784d51f73fa0af9-->",
-            Self::InactiveRebase => "<!--13523179cfe9479db18ec6c5d236f789-->",
-            Self::InactiveCi=> "<!--2e250dc3d92b2c9115b66051148d6e47-->",
-            Self::InactiveStale => "<!--8ac04cdde196e94527acabf64b896448-->",
-            Self::Metadata => "<!--e57a25ab6845829454e8d69fc972939a-->",
-            Self::SecCodeCoverage => "<!--006a51241073e994b41acfe9ec718e94-->",
-            Self::SecConflicts => "<!--174a7506f384e20aa4161008e828411d-->",
-            Self::SecCoverage => "<!--2502f1a698b3751726fa55edcda76cd3-->",
-            Self::SecReviews => "<!--021abf342d371248e50ceaed478a90ca-->",
-        }
-    }
+pub async fn get_rate_limit_remaining(api: &octocrab::Octocrab) -> octocrab::Result<u64> {
+    let limits = with_retry(|| async { api.ratelimit().get().await }).await?;
+    Ok(limits.resources.core.remaining as u64)
 }
 
 pub fn git() -> std::process::Command {
@@ -96,38 +150,76 @@ pub fn chdir(p: &std::path::Path) {
     std::env::set_current_dir(p).expect("chdir error")
 }
 
+/// The maximum length of the text of a metadata comment, before the footer. Sections that do not
+/// fit are moved to continuation comments. GitHub rejects comments longer than 65536 characters.
+#[cfg(feature = "github")]
+const MAX_METADATA_LEN: usize = 60_000;
+
 #[cfg(feature = "github")]
 pub struct MetaComment {
     pull_num: u64,
     pub id: Option<octocrab::models::CommentId>,
+    /// The continuation comments, oldest first, holding the sections that did not fit into the
+    /// comment above.
+    continuations: Vec<octocrab::models::CommentId>,
     sections: Vec<String>,
+    /// Older metadata comments, which should be deleted
+    duplicates: Vec<octocrab::models::CommentId>,
+    /// Whether the comment has outdated markers, which should be migrated on the next update
+    outdated: bool,
 }
 
 #[cfg(feature = "github")]
 impl MetaComment {
     pub fn has_section(&self, section_id: &IdComment) -> bool {
-        let id = section_id.str();
-        self.sections.iter().any(|s| s.starts_with(id))
+        self.sections.iter().any(|s| section_id.is_start_of(s))
     }
 
-    fn join_metadata_comment(&mut self) -> String {
-        self.sections.sort();
+    /// Return the rendered section, which is empty if the section is hidden.
+    pub fn section(&self, section_id: &IdComment) -> Option<&str> {
+        self.sections.iter().find_map(|s| section_id.strip(s))
+    }
+
+    /// The ids of the metadata comment and its continuation comments.
+    pub fn comment_ids(&self) -> Vec<octocrab::models::CommentId> {
+        self.id.iter().chain(&self.continuations).copied().collect()
+    }
+
+    /// Return the text of the metadata comment, followed by the texts of the continuation
+    /// comments, if the sections do not fit into MAX_METADATA_LEN. A single section longer than
+    /// that is not split.
+    fn shards(&mut self) -> Vec<String> {
+        self.sections
+            .sort_by_key(|s| (SECTION_REGISTRY.priority_of(s), s.clone()));
         let desc = "The following sections might be updated with supplementary metadata relevant to reviewers and maintainers.";
-        format!(
-            "{root_id}\n\n{desc}\n\n{sec}",
-            root_id = IdComment::Metadata.str(),
-            sec = self.sections.join("")
-        )
+        let mut shards = vec![format!("{}\n\n{desc}\n\n", IdComment::Metadata.str())];
+        let mut empty = true;
+        for s in &self.sections {
+            let last = shards.last_mut().unwrap();
+            if !empty && last.len() + s.len() > MAX_METADATA_LEN {
+                shards.push(format!(
+                    "{}\n\nContinued from the comment above.\n\n{s}",
+                    IdComment::MetadataContinued.str()
+                ));
+            } else {
+                *last += s;
+            }
+            empty = false;
+        }
+        shards
     }
 
-    fn update(&mut self, id: IdComment, new_text: &str) -> bool {
-        let needle = id.str();
-        let new_section = format!("{}{}", needle, new_text);
+    /// Replace the section with the given rendered text, followed by the updated line. Return
+    /// false, if nothing changed.
+    fn update(&mut self, id: IdComment, new_text: &str, updated: &str) -> bool {
+        let new_section = format!("{}{}{}", id.str(), new_text, updated);
         for s in self.sections.iter_mut() {
-            if s.starts_with(needle) {
-                // Section exists
-                let orig = s.split(needle).nth(1).unwrap();
-                if orig == new_text {
+            if let Some(orig) = id.strip(s) {
+                // Section exists. The updated line must name the base commit the section was
+                // last computed against, even if the content stayed the same.
+                if section::strip_updated_line(orig) == new_text
+                    && section::updated_base(orig) == section::updated_base(updated)
+                {
                     // Section up to date
                     return false;
                 }
@@ -136,6 +228,10 @@ impl MetaComment {
                 return true;
             }
         }
+        if new_text.is_empty() {
+            // Hidden section, which does not exist
+            return false;
+        }
         // Create missing section
         self.sections.push(new_section);
         true
@@ -148,89 +244,290 @@ pub async fn get_metadata_sections(
     api_issues: &octocrab::issues::IssueHandler<'_>,
     pull_nr: u64,
 ) -> octocrab::Result<MetaComment> {
-    let comments = api
-        .all_pages(api_issues.list_comments(pull_nr).send().await?)
-        .await?;
+    let comments = with_retry(|| async {
+        api.all_pages(api_issues.list_comments(pull_nr).send().await?)
+            .await
+    })
+    .await?;
 
     Ok(get_metadata_sections_from_comments(&comments, pull_nr))
 }
 
 #[cfg(feature = "github")]
 pub fn get_metadata_sections_from_comments(
-    comments: &Vec<octocrab::models::issues::Comment>,
+    comments: &[octocrab::models::issues::Comment],
     pull_nr: u64,
 ) -> MetaComment {
-    for c in comments {
-        let b = c.body.as_ref().expect("remote api error");
-        if b.starts_with(IdComment::Metadata.str()) {
-            let sections = b
-                .split("<!--")
-                .skip(2)
-                .map(|s| format!("<!--{}", s))
-                .collect::<Vec<_>>();
-
-            return MetaComment {
-                pull_num: pull_nr,
-                id: Some(c.id),
-                sections,
-            };
+    let with_marker = |id: IdComment| {
+        let mut found = comments
+            .iter()
+            .filter(|c| {
+                let b = c.body.as_ref().expect("remote api error");
+                id.is_start_of(b)
+            })
+            .collect::<Vec<_>>();
+        found.sort_by_key(|c| c.created_at);
+        found
+    };
+    let mut meta_comments = with_marker(IdComment::Metadata);
+    let continuations = with_marker(IdComment::MetadataContinued);
+    // Keep the newest one, if there are several
+    let primary = meta_comments.pop();
+    let mut sections = Vec::new();
+    let mut outdated = false;
+    for (c, id) in primary.iter().map(|c| (c, IdComment::Metadata)).chain(
+        continuations
+            .iter()
+            .map(|c| (c, IdComment::MetadataContinued)),
+    ) {
+        let body = strip_footer(c.body.as_ref().unwrap());
+        outdated |= IdComment::migrate(body).is_some();
+        for s in section::split_sections(id.strip(body).expect("Must be a metadata comment")) {
+            match IdComment::migrate(&s) {
+                Some(migrated) => {
+                    outdated = true;
+                    sections.push(migrated);
+                }
+                None => sections.push(s),
+            }
         }
     }
     MetaComment {
         pull_num: pull_nr,
-        id: None,
-        sections: Vec::new(),
+        id: primary.map(|c| c.id),
+        continuations: continuations.into_iter().map(|c| c.id).collect(),
+        sections,
+        duplicates: meta_comments.into_iter().map(|c| c.id).collect(),
+        outdated,
     }
 }
 
+/// A label that asks the bot to not post any non-essential comments (nags, review requests, ...)
+/// on a pull request. Labels and the metadata comment are still maintained.
+pub const QUIET_LABEL: &str = "DrahtBot quiet";
+/// Same as QUIET_LABEL, but for authors without permission to set labels. Placed in the
+/// description of the pull request.
+pub const QUIET_MARKER: &str = "<!-- DrahtBot quiet -->";
+
+#[cfg(feature = "github")]
+pub fn is_quiet(labels: &[octocrab::models::Label], body: Option<&str>) -> bool {
+    labels.iter().any(|l| l.name == QUIET_LABEL)
+        || body.is_some_and(|b| b.contains(QUIET_MARKER))
+}
+
+#[cfg(feature = "github")]
+pub fn is_not_found(err: &octocrab::Error) -> bool {
+    matches!(err, octocrab::Error::GitHub { source, .. } if source.status_code == 404)
+}
+
 #[cfg(feature = "github")]
 pub async fn update_metadata_comment(
     api_issues: &octocrab::issues::IssueHandler<'_>,
     comment: &mut MetaComment,
-    text: &str,
-    section: IdComment,
+    section: &dyn Section,
     dry_run: bool,
 ) -> octocrab::Result<()> {
-    if !comment.update(section, text) {
+    for dup in std::mem::take(&mut comment.duplicates) {
+        tracing::info!("... Delete duplicate metadata comment {dup}");
+        if dry_run {
+            dry_run_preview(&format!("DELETE issues/comments/{dup}"), None);
+        } else {
+            with_retry(|| api_issues.delete_comment(dup)).await?;
+        }
+    }
+    let old_shards = comment.shards();
+    let outdated = std::mem::take(&mut comment.outdated);
+    let text = SECTION_REGISTRY.render(section);
+    let updated = if text.is_empty() {
+        String::new()
+    } else {
+        SECTION_REGISTRY.updated_line(section.id(), chrono::Utc::now(), section.base_commit())
+    };
+    if !comment.update(section.id(), &text, &updated) && !outdated {
         // Section up to date
         return Ok(());
     }
-    if comment.id.is_none() {
-        // Create new metadata comment
-        let full_text = comment.join_metadata_comment();
-        println!("... Create new metadata comment");
-        if !dry_run {
-            let c = api_issues
-                .create_comment(comment.pull_num, full_text)
-                .await?;
+    // The footer names the section that was updated last
+    let feature = section.id().name();
+    let shards = comment.shards();
+    for (i, text) in shards.iter().enumerate() {
+        let id = match i {
+            0 => comment.id,
+            _ => comment.continuations.get(i - 1).copied(),
+        };
+        let old_text = old_shards.get(i).map_or("", |t| t.as_str());
+        if id.is_some() && old_text == text && !outdated {
+            continue;
+        }
+        let id = upsert_comment(
+            api_issues,
+            comment.pull_num,
+            id,
+            old_text,
+            with_footer(text, feature),
+            dry_run,
+        )
+        .await?;
+        match (i, id) {
+            (0, id) => comment.id = id,
+            (_, Some(id)) if comment.continuations.len() < i => comment.continuations.push(id),
+            (_, Some(id)) => comment.continuations[i - 1] = id,
+            (_, None) => {}
+        }
+    }
+    // The sections fit into fewer comments now
+    for id in comment
+        .continuations
+        .split_off((shards.len() - 1).min(comment.continuations.len()))
+    {
+        tracing::info!("... Delete unused metadata continuation comment {id}");
+        if dry_run {
+            dry_run_preview(&format!("DELETE issues/comments/{id}"), None);
+        } else {
+            with_retry(|| api_issues.delete_comment(id)).await?;
+        }
+    }
+    Ok(())
+}
 
-            comment.id = Some(c.id);
+/// Update the metadata comment (or a continuation comment) with the text, or create it, if it
+/// does not exist (anymore). Return the id of the comment, which is None for a comment created in
+/// dry-run mode.
+#[cfg(feature = "github")]
+async fn upsert_comment(
+    api_issues: &octocrab::issues::IssueHandler<'_>,
+    pull_num: u64,
+    id: Option<octocrab::models::CommentId>,
+    old_text: &str,
+    full_text: String,
+    dry_run: bool,
+) -> octocrab::Result<Option<octocrab::models::CommentId>> {
+    if let Some(id) = id {
+        tracing::info!("... Update comment");
+        if dry_run {
+            dry_run_preview(
+                &format!("PATCH issues/comments/{id}"),
+                Some((old_text, &full_text)),
+            );
+            return Ok(Some(id));
         }
+        match with_retry(|| api_issues.update_comment(id, full_text.clone())).await {
+            Err(err) if is_not_found(&err) => {
+                // The comment was deleted in the meantime, so create a new one below
+                tracing::info!("... Metadata comment {id} not found");
+            }
+            res => {
+                res?;
+                return Ok(Some(id));
+            }
+        }
+    }
+    tracing::info!("... Create new metadata comment");
+    if dry_run {
+        dry_run_preview(
+            &format!("POST issues/{pull_num}/comments"),
+            Some(("", &full_text)),
+        );
+        return Ok(None);
+    }
+    let c = api_issues.create_comment(pull_num, full_text).await?;
+    Ok(Some(c.id))
+}
 
-        return Ok(());
+/// Compute mergeability in a local git dir, if GitHub did not compute it within `timeout`.
+#[cfg(feature = "github")]
+pub struct MergeableFallback {
+    pub git_dir: std::path::PathBuf,
+    pub timeout: std::time::Duration,
+}
+
+#[cfg(feature = "github")]
+impl MergeableFallback {
+    pub fn new(git_dir: std::path::PathBuf) -> Self {
+        Self {
+            git_dir,
+            timeout: std::time::Duration::from_secs(120),
+        }
     }
-    let full_text = comment.join_metadata_comment();
-    println!("... Update comment");
-    if !dry_run {
-        api_issues
-            .update_comment(comment.id.unwrap(), full_text)
-            .await?;
+
+    /// Return whether the pull request merges cleanly into its base branch, by fetching both into
+    /// the local git dir and running merge-tree (which does not need a worktree).
+    pub fn calc_mergeable(
+        &self,
+        pull: &octocrab::models::pulls::PullRequest,
+    ) -> std::io::Result<bool> {
+        let run = |cmd: &mut std::process::Command| {
+            let status = cmd.status()?;
+            if !status.success() {
+                return Err(std::io::Error::other(format!("{cmd:?} failed: {status}")));
+            }
+            Ok(())
+        };
+        if !self.git_dir.is_dir() {
+            run(git().args(["init", "--quiet", "--bare"]).arg(&self.git_dir))?;
+        }
+        let url = pull
+            .base
+            .repo
+            .as_ref()
+            .and_then(|r| r.clone_url.as_ref())
+            .ok_or_else(|| std::io::Error::other("Missing clone url of the base repo"))?;
+        let git_c = || {
+            let mut cmd = git();
+            cmd.arg("-C").arg(&self.git_dir);
+            cmd
+        };
+        run(git_c().args(["fetch", "--quiet", "--force", url.as_str()]).args([
+            format!("refs/pull/{}/head:refs/mergeable/head", pull.number),
+            format!("refs/heads/{}:refs/mergeable/base", pull.base.ref_field),
+        ]))?;
+        let mut merge_tree = git_c();
+        merge_tree.args([
+            "merge-tree",
+            "--write-tree",
+            "--quiet",
+            "refs/mergeable/base",
+            "refs/mergeable/head",
+        ]);
+        let status = merge_tree.status()?;
+        match status.code() {
+            Some(0) => Ok(true),
+            // Conflicts. Any other exit code is an error, e.g. a git without --write-tree.
+            Some(1) => Ok(false),
+            _ => Err(std::io::Error::other(format!(
+                "{merge_tree:?} failed: {status}"
+            ))),
+        }
     }
-    Ok(())
 }
 
+/// Return the pull request once its mergeability is known. None, if it is closed or the local
+/// fallback failed.
 #[cfg(feature = "github")]
 pub async fn get_pull_mergeable(
     api: &octocrab::pulls::PullRequestHandler<'_>,
     number: u64,
+    fallback: Option<&MergeableFallback>,
 ) -> octocrab::Result<Option<octocrab::models::pulls::PullRequest>> {
     // https://docs.github.com/en/rest/guides/getting-started-with-the-git-database-api#checking-mergeability-of-pull-requests
+    let start = std::time::Instant::now();
     loop {
-        let pull = api.get(number).await?;
+        let mut pull = with_retry(|| api.get(number)).await?;
         if pull.state.as_ref().unwrap() != &octocrab::models::IssueState::Open {
             return Ok(None);
         }
         if pull.mergeable.is_none() {
+            if let Some(fallback) = fallback.filter(|f| start.elapsed() >= f.timeout) {
+                tracing::info!("... GitHub did not compute mergeable, falling back to local merge-tree");
+                match fallback.calc_mergeable(&pull) {
+                    Ok(mergeable) => pull.mergeable = Some(mergeable),
+                    Err(err) => {
+                        tracing::error!("... Skip, as the local merge-tree failed: {err}");
+                        return Ok(None);
+                    }
+                }
+                return Ok(Some(pull));
+            }
             std::thread::sleep(std::time::Duration::from_secs(3));
             continue;
         }
@@ -0,0 +1,53 @@
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::Layer;
+
+/// The format of the log output. See init_logging.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Human readable lines.
+    Pretty,
+    /// One json object per line, including the fields of the current spans (repo, pull request,
+    /// feature, ...), for log aggregation.
+    Json,
+}
+
+impl std::str::FromStr for LogFormat {
+    type Err = &'static str;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pretty" => Ok(Self::Pretty),
+            "json" => Ok(Self::Json),
+            _ => Err("Wrong format, expected 'pretty' or 'json'."),
+        }
+    }
+}
+
+/// An extra destination for the log, see init_logging_with.
+pub type LogLayer = Box<dyn Layer<tracing_subscriber::Registry> + Send + Sync>;
+
+/// Install the global logger. The verbosity can be set with RUST_LOG and defaults to info.
+///
+/// Does nothing if a logger is already installed, for example when a tool runs inside the
+/// scheduler.
+pub fn init_logging(format: LogFormat) {
+    init_logging_with(format, None)
+}
+
+/// Same as init_logging, but also pass the log to the extra layer, e.g. to write it to files.
+pub fn init_logging_with(format: LogFormat, extra: Option<LogLayer>) {
+    let filter = tracing_subscriber::EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new("info"));
+    let fmt = match format {
+        LogFormat::Pretty => tracing_subscriber::fmt::layer().boxed(),
+        LogFormat::Json => tracing_subscriber::fmt::layer()
+            .json()
+            .with_span_list(true)
+            .boxed(),
+    };
+    let _ = tracing_subscriber::registry()
+        .with(extra)
+        .with(fmt)
+        .with(filter)
+        .try_init();
+}
@@ -0,0 +1,61 @@
+/// The file name of the manifest, stored next to the outputs it describes.
+pub const RESULT_MANIFEST: &str = "result.json";
+
+/// A machine-readable summary of a build (commit, artifacts, duration, toolchain, links), so that
+/// comments, dashboards, and reproducibility checks all derive from one canonical file.
+///
+/// Keep the format in sync with scripts/util/manifest.py.
+pub struct ResultManifest {
+    pub commit: String,
+    pub duration: std::time::Duration,
+    /// Name and version of each tool that affects the outputs.
+    pub toolchain: Vec<(String, String)>,
+    /// Name and url of logs and reports.
+    pub links: Vec<(String, String)>,
+}
+
+impl ResultManifest {
+    /// Write the manifest into the folder, listing the files at the top level of the folder as
+    /// artifacts. Sub-folders (e.g. html reports) are only reachable through the links.
+    pub fn write(&self, dir: &std::path::Path) {
+        let mut names = std::fs::read_dir(dir)
+            .expect("Failed to read result folder")
+            .map(|e| e.expect("Failed to read result folder").path())
+            .filter(|p| p.is_file())
+            .filter_map(|p| p.file_name().map(|n| n.to_string_lossy().to_string()))
+            .filter(|n| n != RESULT_MANIFEST)
+            .collect::<Vec<_>>();
+        names.sort();
+        let artifacts = names
+            .iter()
+            .map(|name| {
+                let path = dir.join(name);
+                let sha256 =
+                    crate::check_output(std::process::Command::new("sha256sum").arg(&path));
+                serde_json::json!({
+                    "name": name,
+                    "sha256": sha256.split_whitespace().next().unwrap_or_default(),
+                    "size": path.metadata().expect("Failed to read file size").len(),
+                })
+            })
+            .collect::<Vec<_>>();
+        let to_map = |pairs: &[(String, String)]| {
+            pairs
+                .iter()
+                .map(|(k, v)| (k.clone(), serde_json::Value::from(v.as_str())))
+                .collect::<serde_json::Map<_, _>>()
+        };
+        let json = serde_json::json!({
+            "commit": self.commit,
+            "duration_seconds": self.duration.as_secs(),
+            "toolchain": to_map(&self.toolchain),
+            "artifacts": artifacts,
+            "links": to_map(&self.links),
+        });
+        std::fs::write(
+            dir.join(RESULT_MANIFEST),
+            serde_json::to_string_pretty(&json).expect("json error") + "\n",
+        )
+        .expect("Failed to write result manifest");
+    }
+}
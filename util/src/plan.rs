@@ -0,0 +1,252 @@
+/// A single edit of an issue or pull request on GitHub.
+///
+/// Tools first compute a plan (a list of edits) from the data they fetched, and then apply it.
+/// This keeps the decisions in pure functions, which can be tested and printed in dry-run mode.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Edit {
+    Comment {
+        number: u64,
+        text: String,
+    },
+    DeleteComment {
+        number: u64,
+        id: octocrab::models::CommentId,
+    },
+    AddLabel {
+        number: u64,
+        label: String,
+    },
+    RemoveLabel {
+        number: u64,
+        label: String,
+    },
+    ConvertToDraft {
+        number: u64,
+    },
+    Close {
+        number: u64,
+    },
+    Reopen {
+        number: u64,
+    },
+    SetTitle {
+        number: u64,
+        title: String,
+    },
+    RequestReview {
+        number: u64,
+        reviewer: String,
+    },
+    RemoveReviewRequest {
+        number: u64,
+        reviewer: String,
+    },
+}
+
+/// The outcome of apply_plan.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Applied {
+    /// The edits that were applied, in order.
+    pub done: Vec<Edit>,
+    /// The edits that were not applied, because the quota was reached. They have to be applied on
+    /// a later run.
+    pub deferred: Vec<Edit>,
+}
+
+impl Edit {
+    /// The issue or pull request of the edit.
+    pub(crate) fn number(&self) -> u64 {
+        match self {
+            Self::Comment { number, .. }
+            | Self::DeleteComment { number, .. }
+            | Self::AddLabel { number, .. }
+            | Self::RemoveLabel { number, .. }
+            | Self::ConvertToDraft { number }
+            | Self::Close { number }
+            | Self::Reopen { number }
+            | Self::SetTitle { number, .. }
+            | Self::RequestReview { number, .. }
+            | Self::RemoveReviewRequest { number, .. } => *number,
+        }
+    }
+
+    /// The quota the edit counts against, if any. Deleting comments only cleans up after the bot
+    /// and is not limited. Neither are review requests, which only follow the activity of
+    /// reviewers. Title edits count as label changes, as both only change the metadata.
+    fn quota_action(&self) -> Option<crate::Action> {
+        match self {
+            Self::Comment { .. } => Some(crate::Action::Comment),
+            Self::DeleteComment { .. }
+            | Self::RequestReview { .. }
+            | Self::RemoveReviewRequest { .. } => None,
+            Self::AddLabel { .. } | Self::RemoveLabel { .. } | Self::SetTitle { .. } => {
+                Some(crate::Action::Label)
+            }
+            Self::ConvertToDraft { .. } | Self::Close { .. } | Self::Reopen { .. } => {
+                Some(crate::Action::Close)
+            }
+        }
+    }
+
+    /// The REST API call that applies the edit, for the dry-run preview.
+    fn api_call(&self, slug: &crate::Slug) -> String {
+        let repo = format!("/repos/{}/{}", slug.owner, slug.repo);
+        match self {
+            Self::Comment { number, .. } => format!("POST {repo}/issues/{number}/comments"),
+            Self::DeleteComment { id, .. } => format!("DELETE {repo}/issues/comments/{id}"),
+            Self::AddLabel { number, label } => {
+                format!("POST {repo}/issues/{number}/labels [\"{label}\"]")
+            }
+            Self::RemoveLabel { number, label } => {
+                format!("DELETE {repo}/issues/{number}/labels/{label}")
+            }
+            Self::ConvertToDraft { number } => {
+                format!("POST /graphql convertPullRequestToDraft {repo}/pulls/{number}")
+            }
+            Self::Close { number } => format!("PATCH {repo}/issues/{number} state=closed"),
+            Self::Reopen { number } => format!("PATCH {repo}/issues/{number} state=open"),
+            Self::SetTitle { number, .. } => format!("PATCH {repo}/issues/{number} title"),
+            Self::RequestReview { number, reviewer } => {
+                format!("POST {repo}/pulls/{number}/requested_reviewers [\"{reviewer}\"]")
+            }
+            Self::RemoveReviewRequest { number, reviewer } => {
+                format!("DELETE {repo}/pulls/{number}/requested_reviewers [\"{reviewer}\"]")
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for Edit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Comment { number, text } => {
+                write!(f, "#{number}: comment ({} bytes)", text.len())
+            }
+            Self::DeleteComment { number, id } => write!(f, "#{number}: delete comment {id}"),
+            Self::AddLabel { number, label } => write!(f, "#{number}: add label '{label}'"),
+            Self::RemoveLabel { number, label } => write!(f, "#{number}: remove label '{label}'"),
+            Self::ConvertToDraft { number } => write!(f, "#{number}: convert to draft"),
+            Self::Close { number } => write!(f, "#{number}: close"),
+            Self::Reopen { number } => write!(f, "#{number}: reopen"),
+            Self::SetTitle { number, title } => write!(f, "#{number}: set title '{title}'"),
+            Self::RequestReview { number, reviewer } => {
+                write!(f, "#{number}: request review from {reviewer}")
+            }
+            Self::RemoveReviewRequest { number, reviewer } => {
+                write!(f, "#{number}: remove review request of {reviewer}")
+            }
+        }
+    }
+}
+
+/// Apply the edits in order. In dry-run mode, only preview them. The comments are signed with the
+/// footer of the feature.
+///
+/// The edits of an issue or pull request depend on each other (e.g. a label marks that a comment
+/// was posted), so the quota is checked for all edits of an issue at once. If it does not allow
+/// all of them, none are applied, and they are deferred to a later run.
+pub async fn apply_plan(
+    github: &octocrab::Octocrab,
+    slug: &crate::Slug,
+    plan: &[Edit],
+    feature: &str,
+    dry_run: bool,
+) -> octocrab::Result<Applied> {
+    let issues_api = github.issues(&slug.owner, &slug.repo);
+    let pulls_api = github.pulls(&slug.owner, &slug.repo);
+    let mut applied = Applied::default();
+    let mut denied = Vec::new();
+    let mut checked = Vec::new();
+    for edit in plan {
+        tracing::info!("... {edit}");
+        if dry_run {
+            let text = match edit {
+                Edit::Comment { text, .. } => Some(crate::with_footer(text, feature)),
+                Edit::SetTitle { title, .. } => Some(title.to_string()),
+                _ => None,
+            };
+            crate::dry_run_preview(&edit.api_call(slug), text.as_deref().map(|t| ("", t)));
+            continue;
+        }
+        let number = edit.number();
+        if !checked.contains(&number) {
+            checked.push(number);
+            let actions = plan
+                .iter()
+                .filter(|e| e.number() == number)
+                .filter_map(|e| e.quota_action())
+                .collect::<Vec<_>>();
+            if !crate::quota_allows_all(&slug.str(), &actions) {
+                denied.push(number);
+            }
+        }
+        if denied.contains(&number) {
+            tracing::info!("... deferred, quota reached");
+            applied.deferred.push(edit.clone());
+            continue;
+        }
+        match edit {
+            Edit::Comment { number, text } => {
+                issues_api
+                    .create_comment(*number, crate::with_footer(text, feature))
+                    .await?;
+            }
+            Edit::DeleteComment { id, .. } => {
+                crate::with_retry(|| issues_api.delete_comment(*id)).await?;
+            }
+            Edit::AddLabel { number, label } => {
+                let labels = [label.to_string()];
+                crate::with_retry(|| issues_api.add_labels(*number, &labels)).await?;
+            }
+            Edit::RemoveLabel { number, label } => {
+                crate::with_retry(|| issues_api.remove_label(*number, label)).await?;
+            }
+            Edit::ConvertToDraft { number } => {
+                // Only possible via GraphQL, which needs the node id of the pull request
+                let pull = crate::with_retry(|| pulls_api.get(*number)).await?;
+                let payload = serde_json::json!({
+                    "query": "mutation ($id: ID!) { convertPullRequestToDraft(input: {pullRequestId: $id}) { pullRequest { isDraft } } }",
+                    "variables": {"id": pull.node_id},
+                });
+                let res: serde_json::Value = github.graphql(&payload).await?;
+                if res["errors"].is_array() {
+                    tracing::warn!("... convert to draft failed: {}", res["errors"]);
+                    continue;
+                }
+            }
+            Edit::Close { number } => {
+                crate::with_retry(|| {
+                    issues_api
+                        .update(*number)
+                        .state(octocrab::models::IssueState::Closed)
+                        .send()
+                })
+                .await?;
+            }
+            Edit::Reopen { number } => {
+                crate::with_retry(|| {
+                    issues_api
+                        .update(*number)
+                        .state(octocrab::models::IssueState::Open)
+                        .send()
+                })
+                .await?;
+            }
+            Edit::SetTitle { number, title } => {
+                crate::with_retry(|| issues_api.update(*number).title(title).send()).await?;
+            }
+            Edit::RequestReview { number, reviewer } => {
+                crate::with_retry(|| pulls_api.request_reviews(*number, [reviewer.clone()], []))
+                    .await?;
+            }
+            Edit::RemoveReviewRequest { number, reviewer } => {
+                crate::with_retry(|| {
+                    pulls_api.remove_requested_reviewers(*number, [reviewer.clone()], [])
+                })
+                .await?;
+            }
+        }
+        applied.done.push(edit.clone());
+    }
+    Ok(applied)
+}
@@ -0,0 +1,168 @@
+use std::io::IsTerminal;
+use std::time::{Duration, Instant};
+
+/// Report progress of a long-running loop, along with an ETA.
+///
+/// When stdout is a terminal, a progress bar is drawn. Otherwise (e.g. when running from cron), a
+/// single `key=value` log line is printed periodically.
+pub struct Progress {
+    total: u64,
+    done: u64,
+    api_calls: Option<u64>,
+    start: Instant,
+    deadline: Option<Instant>,
+    last_log: Option<Instant>,
+    bar: Option<indicatif::ProgressBar>,
+}
+
+impl Progress {
+    const LOG_INTERVAL: Duration = Duration::from_secs(60);
+
+    /// Create a new reporter for `total` items. If `deadline` is set, `out_of_time` will return
+    /// true once it is near.
+    pub fn new(total: usize, deadline: Option<Instant>) -> Self {
+        let start = Instant::now();
+        let bar = if std::io::stdout().is_terminal() {
+            let bar = indicatif::ProgressBar::new(total as u64);
+            bar.set_style(
+                indicatif::ProgressStyle::with_template(
+                    "[{elapsed_precise}] {bar:40} {pos}/{len} (ETA {eta}) {msg}",
+                )
+                .expect("progress template error"),
+            );
+            Some(bar)
+        } else {
+            None
+        };
+        Self {
+            total: total as u64,
+            done: 0,
+            api_calls: None,
+            start,
+            deadline,
+            last_log: None,
+            bar,
+        }
+    }
+
+    /// Set the number of API calls used since the start of the run.
+    pub fn set_api_calls(&mut self, api_calls: u64) {
+        self.api_calls = Some(api_calls);
+    }
+
+    /// Print a message without disturbing the progress bar.
+    pub fn println(&self, msg: &str) {
+        match &self.bar {
+            Some(bar) => bar.println(msg),
+            None => tracing::info!("{msg}"),
+        }
+    }
+
+    /// Mark one more item as done. `item` is a short identifier of the item that was processed.
+    pub fn inc(&mut self, item: &str) {
+        self.done += 1;
+        if let Some(bar) = &self.bar {
+            bar.set_message(match self.api_calls {
+                Some(calls) => format!("{item} (api calls: {calls})"),
+                None => item.to_string(),
+            });
+            bar.inc(1);
+            return;
+        }
+        let now = Instant::now();
+        let due = !matches!(self.last_log, Some(l) if now.duration_since(l) < Self::LOG_INTERVAL);
+        if due || self.done == self.total {
+            self.last_log = Some(now);
+            tracing::info!("{}", self.log_line(item));
+        }
+    }
+
+    fn log_line(&self, item: &str) -> String {
+        let mut line = format!(
+            "progress done={done} total={total} elapsed_s={elapsed}",
+            done = self.done,
+            total = self.total,
+            elapsed = self.start.elapsed().as_secs(),
+        );
+        if let Some(eta) = self.eta() {
+            line += &format!(" eta_s={}", eta.as_secs());
+        }
+        if let Some(calls) = self.api_calls {
+            line += &format!(" api_calls={calls}");
+        }
+        line += &format!(" item={item}");
+        line
+    }
+
+    /// The estimated remaining time, based on the average time per item so far.
+    pub fn eta(&self) -> Option<Duration> {
+        if self.done == 0 {
+            return None;
+        }
+        let per_item = self.start.elapsed() / self.done as u32;
+        Some(per_item * self.total.saturating_sub(self.done) as u32)
+    }
+
+    /// Whether the deadline passed, or will pass before the next item is likely done.
+    pub fn out_of_time(&self) -> bool {
+        let Some(deadline) = self.deadline else {
+            return false;
+        };
+        let per_item = if self.done == 0 {
+            Duration::ZERO
+        } else {
+            self.start.elapsed() / self.done as u32
+        };
+        Instant::now() + per_item >= deadline
+    }
+
+    /// Return the deadline for a run that may take `max_runtime_min` minutes from now.
+    pub fn deadline(max_runtime_min: Option<u64>) -> Option<Instant> {
+        max_runtime_min.map(|m| Instant::now() + Duration::from_secs(m * 60))
+    }
+
+    pub fn finish(&self) {
+        if let Some(bar) = &self.bar {
+            bar.finish();
+        }
+    }
+}
+
+/// A file remembering the last item processed by an interrupted run, so that the next run can
+/// resume after it.
+pub struct Checkpoint {
+    path: std::path::PathBuf,
+}
+
+impl Checkpoint {
+    pub fn new(path: std::path::PathBuf) -> Self {
+        Self { path }
+    }
+
+    pub fn load(&self) -> Option<String> {
+        std::fs::read_to_string(&self.path)
+            .ok()
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty())
+    }
+
+    pub fn save(&self, last_done: &str) {
+        std::fs::write(&self.path, last_done).expect("checkpoint write error");
+    }
+
+    pub fn clear(&self) {
+        if self.path.exists() {
+            std::fs::remove_file(&self.path).expect("checkpoint remove error");
+        }
+    }
+
+    /// Return the number of leading items to skip, given the ids of all items in processing order.
+    pub fn skip_count<'a>(&self, ids: impl Iterator<Item = &'a str>) -> usize {
+        let Some(last_done) = self.load() else {
+            return 0;
+        };
+        ids.into_iter()
+            .position(|id| id == last_done)
+            .map_or(0, |pos| pos + 1)
+    }
+}
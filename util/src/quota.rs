@@ -0,0 +1,241 @@
+use std::collections::BTreeMap;
+use std::sync::{Mutex, OnceLock};
+
+/// The kinds of mutating actions that are rate limited per repository.
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize,
+)]
+pub enum Action {
+    Comment,
+    Close,
+    Label,
+}
+
+/// The maximum number of actions of each kind per repository per hour.
+///
+/// This is a safety brake against a bug or a bad config making the bot spam hundreds of pull
+/// requests. Legit runs should never come close to the limits.
+#[derive(Clone, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct QuotaLimits {
+    pub comments: usize,
+    pub closes: usize,
+    pub labels: usize,
+}
+
+impl Default for QuotaLimits {
+    fn default() -> Self {
+        Self {
+            comments: 60,
+            closes: 20,
+            labels: 120,
+        }
+    }
+}
+
+impl QuotaLimits {
+    fn get(&self, action: Action) -> usize {
+        match action {
+            Action::Comment => self.comments,
+            Action::Close => self.closes,
+            Action::Label => self.labels,
+        }
+    }
+}
+
+/// The actions of the last hour, by repo slug and kind.
+#[derive(Clone, Default, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct QuotaLog(BTreeMap<String, BTreeMap<Action, Vec<chrono::DateTime<chrono::Utc>>>>);
+
+const WINDOW: chrono::Duration = chrono::Duration::hours(1);
+
+impl QuotaLog {
+    /// Record the actions on the repo and return true, if none of them exceeds its limit.
+    /// Otherwise, record nothing and return false.
+    fn record(
+        &mut self,
+        repo_slug: &str,
+        actions: &[Action],
+        limits: &QuotaLimits,
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> bool {
+        // Forget the actions that fell out of the window, also of other repos
+        for done in self.0.values_mut() {
+            for dates in done.values_mut() {
+                dates.retain(|d| now - *d < WINDOW);
+            }
+            done.retain(|_, dates| !dates.is_empty());
+        }
+        self.0.retain(|_, done| !done.is_empty());
+
+        let done = self.0.entry(repo_slug.to_string()).or_default();
+        for action in actions {
+            let needed = actions.iter().filter(|a| *a == action).count();
+            let count = done.get(action).map_or(0, |d| d.len());
+            if count + needed > limits.get(*action) {
+                return false;
+            }
+        }
+        for action in actions {
+            done.entry(*action).or_default().push(now);
+        }
+        true
+    }
+}
+
+/// Where the log is kept, to apply the limits across the runs of a tool and the tools of the bot.
+/// Otherwise, the log is only kept in memory, and the limits only apply per process.
+pub trait QuotaStore: Send {
+    /// Modify the stored log.
+    fn update_quota(&self, f: &mut dyn FnMut(&mut QuotaLog)) -> std::io::Result<()>;
+}
+
+struct Quota {
+    limits: QuotaLimits,
+    log: QuotaLog,
+    store: Option<Box<dyn QuotaStore>>,
+    denied: BTreeMap<(String, Action), u64>,
+}
+
+fn quota() -> &'static Mutex<Quota> {
+    static QUOTA: OnceLock<Mutex<Quota>> = OnceLock::new();
+    QUOTA.get_or_init(|| {
+        Mutex::new(Quota {
+            limits: QuotaLimits::default(),
+            log: QuotaLog::default(),
+            store: None,
+            denied: BTreeMap::new(),
+        })
+    })
+}
+
+/// Replace the default limits for this process.
+pub fn set_quota_limits(limits: QuotaLimits) {
+    quota().lock().unwrap().limits = limits;
+}
+
+/// Keep the log in the store from now on.
+pub fn set_quota_store(store: Box<dyn QuotaStore>) {
+    quota().lock().unwrap().store = Some(store);
+}
+
+/// Record an action on the repo and return true, if the hourly limit was not reached yet.
+/// Otherwise, raise an alert and return false, in which case the caller must defer the action to
+/// a later run.
+pub fn quota_allows(repo_slug: &str, action: Action) -> bool {
+    quota_allows_all(repo_slug, &[action])
+}
+
+/// Same as quota_allows, but for actions that only make sense together (e.g. a comment and the
+/// label that keeps it from being posted again). Either all of them are recorded, or none.
+pub fn quota_allows_all(repo_slug: &str, actions: &[Action]) -> bool {
+    let mut quota = quota().lock().unwrap();
+    let quota = &mut *quota;
+    let now = chrono::Utc::now();
+    let mut allowed = false;
+    let mut record = |log: &mut QuotaLog| {
+        allowed = log.record(repo_slug, actions, &quota.limits, now);
+    };
+    match &quota.store {
+        Some(store) => {
+            if let Err(err) = store.update_quota(&mut record) {
+                tracing::error!("When updating the quota store, using the in-memory one: {err}");
+                record(&mut quota.log);
+            }
+        }
+        None => record(&mut quota.log),
+    }
+    if !allowed {
+        tracing::error!(
+            "ALERT: Quota of {actions:?} actions per hour reached for {repo_slug}. Deferring action."
+        );
+        for action in actions {
+            *quota
+                .denied
+                .entry((repo_slug.to_string(), *action))
+                .or_default() += 1;
+        }
+    }
+    allowed
+}
+
+/// The number of denied actions of this process, by repo slug and kind.
+pub fn quota_denials() -> Vec<(String, Action, u64)> {
+    quota()
+        .lock()
+        .unwrap()
+        .denied
+        .iter()
+        .map(|((slug, action), n)| (slug.clone(), *action, *n))
+        .collect()
+}
+
+/// Exit with an error at the end of a run, if actions were deferred due to the quota. This
+/// surfaces them to the scheduler as a failed job, instead of only in the log.
+pub fn exit_on_quota_denials() {
+    let denials = quota_denials();
+    if denials.is_empty() {
+        return;
+    }
+    for (repo, action, n) in denials {
+        tracing::error!("ALERT: Deferred {n} {action:?} actions on {repo}, due to the quota.");
+    }
+    std::process::exit(1);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quota_allows_all() {
+        let slug = "owner/test_quota_allows_all";
+        let closes = QuotaLimits::default().closes;
+        for _ in 0..closes - 1 {
+            assert!(quota_allows(slug, Action::Close));
+        }
+        // Only one close is left, so neither is recorded
+        assert!(!quota_allows_all(
+            slug,
+            &[Action::Comment, Action::Close, Action::Close]
+        ));
+        assert!(quota_allows_all(slug, &[Action::Comment, Action::Close]));
+        assert!(!quota_allows(slug, Action::Close));
+        assert!(quota_allows(slug, Action::Comment));
+        let denied = quota_denials()
+            .into_iter()
+            .filter(|(s, ..)| s == slug)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            denied,
+            [
+                (slug.to_string(), Action::Comment, 1),
+                (slug.to_string(), Action::Close, 3),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_quota_log() {
+        let limits = QuotaLimits {
+            comments: 2,
+            closes: 0,
+            labels: 1,
+        };
+        let date = |s: &str| s.parse::<chrono::DateTime<chrono::Utc>>().unwrap();
+        let mut log = QuotaLog::default();
+        let now = date("2024-01-01T00:00:00Z");
+        assert!(log.record("o/r", &[Action::Comment, Action::Label], &limits, now));
+        assert!(!log.record("o/r", &[Action::Comment, Action::Label], &limits, now));
+        assert!(log.record("o/other", &[Action::Label], &limits, now));
+        assert!(!log.record("o/r", &[Action::Close], &limits, now));
+        // Survives a round trip through the store
+        let json = serde_json::to_string(&log).unwrap();
+        let mut log: QuotaLog = serde_json::from_str(&json).unwrap();
+        assert!(!log.record("o/r", &[Action::Label], &limits, now));
+        // The window passed
+        let later = date("2024-01-01T01:00:00Z");
+        assert!(log.record("o/r", &[Action::Label], &limits, later));
+        assert!(!log.0.contains_key("o/other"));
+    }
+}
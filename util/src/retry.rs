@@ -0,0 +1,70 @@
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+/// The number of attempts, including the first one, before the error is returned.
+const MAX_ATTEMPTS: u32 = 5;
+
+static NO_DELAY: AtomicBool = AtomicBool::new(false);
+
+/// Retry right away, instead of waiting between the attempts. Meant for tests against a local
+/// mock of the GitHub API.
+pub fn retry_without_delay() {
+    NO_DELAY.store(true, Ordering::Relaxed);
+}
+
+/// Return how long to wait before the given attempt (starting at 1) is retried, or None if the
+/// error is not worth retrying.
+fn retry_delay(err: &octocrab::Error, attempt: u32) -> Option<Duration> {
+    let octocrab::Error::GitHub { source, .. } = err else {
+        return None;
+    };
+    let (base, max_attempts) = match source.status_code.as_u16() {
+        // GitHub only sets Retry-After on some of these and asks to wait at least a minute
+        // otherwise. The header is not passed through octocrab, so always wait a minute.
+        403 if source.message.contains("secondary rate limit") => (60, MAX_ATTEMPTS),
+        502 => (5, MAX_ATTEMPTS),
+        // Sometimes returned shortly after an object was created, or while GitHub replicates
+        // it. A real 404 should not delay the run for long, so only retry once.
+        404 => (2, 2),
+        _ => return None,
+    };
+    if attempt >= max_attempts {
+        return None;
+    }
+    let backoff = base * 2u64.pow(attempt - 1);
+    // Spread out the retries of parallel runs that hit the same error
+    let jitter = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .subsec_millis() as u64
+        % (backoff * 1000 / 2 + 1);
+    Some(Duration::from_secs(backoff) + Duration::from_millis(jitter))
+}
+
+/// Run a GitHub API call and retry it with a jittered backoff on errors that usually go away on
+/// their own (secondary rate limits, 502, transient 404), instead of aborting the whole run.
+///
+/// Only use this for idempotent calls, as a 502 does not mean that the request had no effect.
+pub async fn with_retry<T, F, Fut>(mut call: F) -> octocrab::Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = octocrab::Result<T>>,
+{
+    let mut attempt = 1;
+    loop {
+        match call().await {
+            Err(err) => {
+                let Some(delay) = retry_delay(&err, attempt) else {
+                    return Err(err);
+                };
+                tracing::warn!("... Retry in {delay:?} after attempt {attempt} failed: {err}");
+                if !NO_DELAY.load(Ordering::Relaxed) {
+                    tokio::time::sleep(delay).await;
+                }
+                attempt += 1;
+            }
+            res => return res,
+        }
+    }
+}
@@ -0,0 +1,510 @@
+use crate::IdComment;
+
+/// The content of a section of the metadata comment. The section is rendered below a header with
+/// its title, see `SECTION_REGISTRY`.
+pub trait Section: Sync {
+    fn id(&self) -> IdComment;
+
+    /// The title of the section, if it differs from the one in the registry.
+    fn title(&self) -> Option<&str> {
+        None
+    }
+
+    /// The markdown below the title. None hides the section.
+    fn render(&self) -> Option<String>;
+
+    /// The base commit the content was computed against, shown along with the time of the last
+    /// update.
+    fn base_commit(&self) -> Option<&str> {
+        None
+    }
+
+    /// The head commit of the pull request the content was computed for. Recorded in a hidden
+    /// marker, so that the content can be told apart from results for an earlier push.
+    fn head_commit(&self) -> Option<&str> {
+        None
+    }
+}
+
+pub struct SectionInfo {
+    pub id: IdComment,
+    pub title: &'static str,
+    /// Sections with a lower priority are shown first.
+    pub priority: u32,
+    /// Whether to show when the content of the section last changed. Useful for sections that
+    /// are computed by a periodic job, rather than on a webhook event.
+    pub show_updated: bool,
+}
+
+pub struct SectionRegistry {
+    sections: &'static [SectionInfo],
+}
+
+/// All sections of the metadata comment.
+pub static SECTION_REGISTRY: SectionRegistry = SectionRegistry {
+    sections: &[
+        SectionInfo {
+            id: IdComment::SecCodeCoverage,
+            title: "Code Coverage",
+            priority: 10,
+            show_updated: false,
+        },
+        SectionInfo {
+            id: IdComment::SecReviews,
+            title: "Reviews",
+            priority: 20,
+            show_updated: false,
+        },
+        SectionInfo {
+            id: IdComment::SecReviewRequests,
+            title: "Review requests",
+            priority: 30,
+            show_updated: false,
+        },
+        SectionInfo {
+            id: IdComment::SecConflicts,
+            title: "Conflicts",
+            priority: 40,
+            show_updated: true,
+        },
+        SectionInfo {
+            id: IdComment::SecConflictsOutdated,
+            title: "Outdated conflicts",
+            priority: 45,
+            show_updated: false,
+        },
+        SectionInfo {
+            id: IdComment::SecCoverage,
+            title: "Coverage",
+            priority: 50,
+            show_updated: false,
+        },
+        SectionInfo {
+            id: IdComment::SecFuzzCoverage,
+            title: "Fuzz coverage",
+            priority: 52,
+            show_updated: false,
+        },
+        SectionInfo {
+            id: IdComment::SecCiFailed,
+            title: "CI failure",
+            priority: 55,
+            show_updated: false,
+        },
+        SectionInfo {
+            id: IdComment::SecBaseCi,
+            title: "CI",
+            priority: 60,
+            show_updated: false,
+        },
+        SectionInfo {
+            id: IdComment::SecGuixQueue,
+            title: "Guix build",
+            priority: 65,
+            show_updated: false,
+        },
+        SectionInfo {
+            id: IdComment::SecOutdatedResults,
+            title: "Outdated results",
+            priority: 67,
+            show_updated: false,
+        },
+        SectionInfo {
+            id: IdComment::SecBehindBase,
+            title: "Rebase",
+            priority: 70,
+            show_updated: true,
+        },
+        SectionInfo {
+            id: IdComment::SecSize,
+            title: "Size",
+            priority: 75,
+            show_updated: false,
+        },
+        SectionInfo {
+            id: IdComment::SecBotActivity,
+            title: "Bot activity",
+            priority: 80,
+            show_updated: false,
+        },
+        SectionInfo {
+            id: IdComment::SecClosedInactive,
+            title: "Closed due to inactivity",
+            priority: 90,
+            show_updated: false,
+        },
+    ],
+};
+
+const UPDATED_PREFIX: &str = "<sub>Last updated: ";
+const AGAINST_PREFIX: &str = " against base commit ";
+
+impl SectionRegistry {
+    pub fn get(&self, id: IdComment) -> Option<&SectionInfo> {
+        self.sections.iter().find(|s| s.id == id)
+    }
+
+    /// The priority of a section of the metadata comment, by its marker. Unknown sections are
+    /// shown last.
+    pub fn priority_of(&self, section: &str) -> u32 {
+        IdComment::parse(section)
+            .and_then(|(id, _)| self.get(id))
+            .map_or(u32::MAX, |s| s.priority)
+    }
+
+    /// Render the section with its header. Return an empty text, if the section is hidden.
+    pub fn render(&self, section: &dyn Section) -> String {
+        let info = self.get(section.id()).expect("unregistered section");
+        match section.render() {
+            None => String::new(),
+            Some(body) => format!(
+                "\n### {title}\n{head}{body}",
+                title = section.title().unwrap_or(info.title),
+                head = section
+                    .head_commit()
+                    .map_or(String::new(), |h| head_commit_marker(h) + "\n"),
+            ),
+        }
+    }
+
+    /// The line to append to the rendered section, if it shows when it was last updated.
+    pub fn updated_line(
+        &self,
+        id: IdComment,
+        now: chrono::DateTime<chrono::Utc>,
+        base: Option<&str>,
+    ) -> String {
+        match self.get(id) {
+            Some(info) if info.show_updated => {
+                let against = base.map_or(String::new(), |b| {
+                    format!("{AGAINST_PREFIX}{}", &b[..b.len().min(12)])
+                });
+                format!(
+                    "{UPDATED_PREFIX}{}{against}</sub>\n",
+                    now.format("%Y-%m-%d %H:%M UTC")
+                )
+            }
+            _ => String::new(),
+        }
+    }
+}
+
+/// Records that the bot closed a pull request due to inactivity, to tell it apart from closes by
+/// maintainers. Hidden once the pull request is reopened.
+pub struct ClosedInactiveSection {
+    pub closed: Option<chrono::NaiveDate>,
+    /// The labels added by the bot along with the close (e.g. "Up for grabs"), to remove them
+    /// once the pull request is reopened.
+    pub labels: Vec<String>,
+}
+
+const CLOSED_LABEL_PREFIX: &str = "<!--closed-label:";
+
+impl Section for ClosedInactiveSection {
+    fn id(&self) -> IdComment {
+        IdComment::SecClosedInactive
+    }
+
+    fn render(&self) -> Option<String> {
+        let date = self.closed?;
+        let labels = self
+            .labels
+            .iter()
+            .map(|l| format!("{CLOSED_LABEL_PREFIX}{l}-->\n"))
+            .collect::<String>();
+        Some(format!(
+            "{labels}Closed by the bot on {date}, because there was no activity after the final reminder. This is not a decision on the merits of the change; it may be reopened at any time.\n"
+        ))
+    }
+}
+
+/// Return the labels recorded in the text of a ClosedInactiveSection.
+pub fn parse_closed_labels(text: &str) -> Vec<&str> {
+    text.split(CLOSED_LABEL_PREFIX)
+        .skip(1)
+        .filter_map(|rest| Some(rest.split_once("-->")?.0))
+        .collect()
+}
+
+const HEAD_COMMIT_PREFIX: &str = "<!--head-commit:";
+
+/// Records the head commit of the pull request a result was computed for, see
+/// `Section::head_commit`. Also placed in comments with results, e.g. by scripts/guix.py.
+pub fn head_commit_marker(commit: &str) -> String {
+    format!("{HEAD_COMMIT_PREFIX}{commit}-->")
+}
+
+/// Return the head commit of the first head-commit marker in the text.
+pub fn parse_head_commit(text: &str) -> Option<&str> {
+    let (_, rest) = text.split_once(HEAD_COMMIT_PREFIX)?;
+    let (commit, _) = rest.split_once("-->")?;
+    Some(commit)
+}
+
+/// Split the sections of the metadata comment, given without the root marker. Hidden markers
+/// that are not markers of a section, such as the head-commit marker, stay part of the section
+/// they are in.
+pub fn split_sections(text: &str) -> Vec<String> {
+    let mut sections: Vec<String> = Vec::new();
+    for part in text.split("<!--").skip(1).map(|s| format!("<!--{s}")) {
+        match sections.last_mut() {
+            Some(last) if IdComment::parse(&part).is_none() => *last += &part,
+            _ => sections.push(part),
+        }
+    }
+    sections
+}
+
+/// Return the rendered section without the line showing when it was last updated.
+pub fn strip_updated_line(text: &str) -> &str {
+    text.rsplit_once(UPDATED_PREFIX).map_or(text, |(t, _)| t)
+}
+
+/// Return the base commit of the line showing when the section was last updated, if any.
+pub fn updated_base(text: &str) -> Option<&str> {
+    let (_, line) = text.rsplit_once(UPDATED_PREFIX)?;
+    let (_, base) = line.split_once(AGAINST_PREFIX)?;
+    base.split_once("</sub>").map(|(b, _)| b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Conflicts(Vec<&'static str>);
+
+    impl Section for Conflicts {
+        fn id(&self) -> IdComment {
+            IdComment::SecConflicts
+        }
+        fn render(&self) -> Option<String> {
+            if self.0.is_empty() {
+                return None;
+            }
+            Some(self.0.iter().map(|c| format!("* {c}\n")).collect())
+        }
+    }
+
+    #[test]
+    fn test_registry() {
+        let mut priorities = SECTION_REGISTRY
+            .sections
+            .iter()
+            .map(|s| s.priority)
+            .collect::<Vec<_>>();
+        priorities.dedup();
+        assert_eq!(priorities.len(), SECTION_REGISTRY.sections.len());
+        assert!(priorities.is_sorted());
+        for id in IdComment::ALL {
+            let is_section = format!("{id:?}").starts_with("Sec");
+            assert_eq!(SECTION_REGISTRY.get(id).is_some(), is_section);
+        }
+        assert!(
+            SECTION_REGISTRY.priority_of(IdComment::SecReviews.str())
+                < SECTION_REGISTRY.priority_of(IdComment::SecBotActivity.str())
+        );
+        assert_eq!(
+            SECTION_REGISTRY.priority_of("<!--unknown-->"),
+            SECTION_REGISTRY.priority_of(IdComment::NeedsRebase.str())
+        );
+    }
+
+    #[test]
+    fn test_render() {
+        let section = Conflicts(vec!["#1", "#2"]);
+        assert_eq!(
+            SECTION_REGISTRY.render(&section),
+            "\n### Conflicts\n* #1\n* #2\n"
+        );
+        assert_eq!(SECTION_REGISTRY.render(&Conflicts(vec![])), "");
+
+        let now = "2024-01-02T03:04:05Z".parse().unwrap();
+        let updated = SECTION_REGISTRY.updated_line(IdComment::SecConflicts, now, None);
+        assert_eq!(updated, "<sub>Last updated: 2024-01-02 03:04 UTC</sub>\n");
+        assert_eq!(
+            SECTION_REGISTRY.updated_line(IdComment::SecReviews, now, None),
+            ""
+        );
+        assert_eq!(
+            SECTION_REGISTRY.updated_line(IdComment::SecConflicts, now, Some(&"ab".repeat(20))),
+            "<sub>Last updated: 2024-01-02 03:04 UTC against base commit abababababab</sub>\n"
+        );
+        let text = SECTION_REGISTRY.render(&section);
+        assert_eq!(strip_updated_line(&(text.clone() + &updated)), text);
+        assert_eq!(strip_updated_line(&text), text);
+        assert_eq!(updated_base(&(text.clone() + &updated)), None);
+        let against = SECTION_REGISTRY.updated_line(IdComment::SecConflicts, now, Some("cd"));
+        assert_eq!(updated_base(&(text.clone() + &against)), Some("cd"));
+    }
+
+    struct Coverage(&'static str);
+
+    impl Section for Coverage {
+        fn id(&self) -> IdComment {
+            IdComment::SecCoverage
+        }
+        fn render(&self) -> Option<String> {
+            Some("All covered\n".to_string())
+        }
+        fn head_commit(&self) -> Option<&str> {
+            Some(self.0)
+        }
+    }
+
+    #[test]
+    fn test_head_commit() {
+        let text = SECTION_REGISTRY.render(&Coverage("abc"));
+        assert_eq!(
+            text,
+            "\n### Coverage\n<!--head-commit:abc-->\nAll covered\n"
+        );
+        assert_eq!(parse_head_commit(&text), Some("abc"));
+        assert_eq!(parse_head_commit("\n### Coverage\nAll covered\n"), None);
+
+        let conflicts = format!("{}\n### Conflicts\n* #1\n", IdComment::SecConflicts.str());
+        let coverage = format!("{}{text}", IdComment::SecCoverage.str());
+        let legacy = "<!--021abf342d371248e50ceaed478a90ca-->\n### Reviews\n";
+        assert_eq!(
+            split_sections(&format!("\n\nDesc\n\n{conflicts}{coverage}{legacy}")),
+            [conflicts, coverage, legacy.to_string()]
+        );
+        assert!(split_sections("\n\nDesc\n\n").is_empty());
+    }
+
+    #[test]
+    fn test_closed_inactive() {
+        let section = ClosedInactiveSection {
+            closed: Some("2024-05-01".parse().unwrap()),
+            labels: vec!["Up for grabs".to_string(), "Stale".to_string()],
+        };
+        let text = SECTION_REGISTRY.render(&section);
+        assert_eq!(parse_closed_labels(&text), ["Up for grabs", "Stale"]);
+        let id = IdComment::SecClosedInactive;
+        assert_eq!(
+            split_sections(&format!("{}{text}", id.str())),
+            [format!("{}{text}", id.str())]
+        );
+        let reopened = ClosedInactiveSection {
+            closed: None,
+            labels: Vec::new(),
+        };
+        assert_eq!(SECTION_REGISTRY.render(&reopened), "");
+        assert!(parse_closed_labels("Closed by the bot").is_empty());
+    }
+
+    #[test]
+    fn test_update_metadata_comment() {
+        let mut cmt = crate::MetaComment {
+            pull_num: 1,
+            id: None,
+            continuations: Vec::new(),
+            sections: Vec::new(),
+            duplicates: Vec::new(),
+            outdated: false,
+        };
+        let id = IdComment::SecConflicts;
+        let date = |d: &str| d.parse().unwrap();
+        // Hidden sections are not created
+        assert!(!cmt.update(id, "", ""));
+        let text = SECTION_REGISTRY.render(&Conflicts(vec!["#1"]));
+        let updated = SECTION_REGISTRY.updated_line(id, date("2024-01-01T00:00:00Z"), None);
+        assert!(cmt.update(id, &text, &updated));
+        // The timestamp only changes along with the content
+        let updated = SECTION_REGISTRY.updated_line(id, date("2024-01-02T00:00:00Z"), None);
+        assert!(!cmt.update(id, &text, &updated));
+        // ... or the base commit it was computed against
+        let against = |d, b| SECTION_REGISTRY.updated_line(id, date(d), Some(b));
+        assert!(cmt.update(id, &text, &against("2024-01-01T00:00:00Z", "aa")));
+        assert!(!cmt.update(id, &text, &against("2024-01-02T00:00:00Z", "aa")));
+        assert!(cmt.update(id, &text, &against("2024-01-03T00:00:00Z", "bb")));
+        let updated = SECTION_REGISTRY.updated_line(id, date("2024-01-01T00:00:00Z"), None);
+        assert!(cmt.update(id, &text, &updated));
+        assert!(cmt.update(IdComment::SecReviews, "\n### Reviews\n", ""));
+        assert_eq!(
+            cmt.shards(),
+            [
+            format!(
+                "{}\n\nThe following sections might be updated with supplementary metadata relevant to reviewers and maintainers.\n\n{}\n### Reviews\n{}\n### Conflicts\n* #1\n<sub>Last updated: 2024-01-01 00:00 UTC</sub>\n",
+                IdComment::Metadata.str(),
+                IdComment::SecReviews.str(),
+                id.str(),
+            )]
+        );
+        assert!(cmt.update(id, "", ""));
+        assert!(cmt.has_section(&id));
+    }
+
+    #[test]
+    fn test_shards() {
+        let mut cmt = crate::MetaComment {
+            pull_num: 1,
+            id: None,
+            continuations: Vec::new(),
+            sections: Vec::new(),
+            duplicates: Vec::new(),
+            outdated: false,
+        };
+        let header = cmt.shards().remove(0);
+        let body = |id: IdComment, len: usize| "x".repeat(len - id.str().len());
+        // Fills the first comment up to the limit
+        let reviews = IdComment::SecReviews;
+        cmt.update(
+            reviews,
+            &body(reviews, crate::MAX_METADATA_LEN - header.len()),
+            "",
+        );
+        assert_eq!(cmt.shards().len(), 1);
+        assert_eq!(cmt.shards()[0].len(), crate::MAX_METADATA_LEN);
+        let conflicts = IdComment::SecConflicts;
+        cmt.update(conflicts, &body(conflicts, 100), "");
+        // A section longer than the limit gets a comment of its own
+        let coverage = IdComment::SecCoverage;
+        cmt.update(coverage, &body(coverage, crate::MAX_METADATA_LEN + 1), "");
+        let shards = cmt.shards();
+        assert_eq!(shards.len(), 3);
+        assert!(IdComment::Metadata.is_start_of(&shards[0]));
+        assert!(shards[1].starts_with(&format!(
+            "{}\n\nContinued from the comment above.\n\n{}x",
+            IdComment::MetadataContinued.str(),
+            conflicts.str()
+        )));
+        assert!(IdComment::MetadataContinued.is_start_of(&shards[2]));
+        assert!(shards[2].ends_with(&format!(
+            "{}{}",
+            coverage.str(),
+            body(coverage, crate::MAX_METADATA_LEN + 1)
+        )));
+
+        // Read back, regardless of the order of the comments
+        let comment = |id: u64, text: &str, created: &str| {
+            serde_json::from_value::<octocrab::models::issues::Comment>(serde_json::json!({
+                "id": id,
+                "node_id": "",
+                "url": "https://api.github.com/repos/o/r/issues/comments/1",
+                "html_url": "https://github.com/o/r/pull/1#issuecomment-1",
+                "body": crate::with_footer(text, "reviews"),
+                "user": {
+                    "login": "DrahtBot", "id": 1, "node_id": "", "avatar_url": "https://x/",
+                    "gravatar_id": "", "url": "https://x/", "html_url": "https://x/",
+                    "followers_url": "https://x/", "following_url": "https://x/",
+                    "gists_url": "https://x/", "starred_url": "https://x/",
+                    "subscriptions_url": "https://x/", "organizations_url": "https://x/",
+                    "repos_url": "https://x/", "events_url": "https://x/",
+                    "received_events_url": "https://x/", "type": "Bot", "site_admin": false
+                },
+                "created_at": created,
+                "author_association": "NONE"
+            }))
+            .unwrap()
+        };
+        let comments = [
+            comment(3, &shards[2], "2024-01-03T00:00:00Z"),
+            comment(1, &shards[0], "2024-01-01T00:00:00Z"),
+            comment(2, &shards[1], "2024-01-02T00:00:00Z"),
+        ];
+        let mut read = crate::get_metadata_sections_from_comments(&comments, 1);
+        assert_eq!(read.comment_ids(), [1, 2, 3].map(Into::into));
+        assert_eq!(read.shards(), shards);
+        assert!(read.section(&conflicts).is_some());
+    }
+}
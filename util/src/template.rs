@@ -0,0 +1,110 @@
+/// The variables of a pull request that can be used in configurable comments, e.g. "{author}".
+pub const PULL_VARS: &[&str] = &[
+    "owner",
+    "repo",
+    "number",
+    "author",
+    "base_branch",
+    "head_sha",
+    "labels",
+    "days_inactive",
+];
+
+/// Return the names of all variables used in the template. A variable is a lowercase name in
+/// braces, other braces are kept as they are.
+fn template_vars(template: &str) -> Vec<&str> {
+    template
+        .split('{')
+        .skip(1)
+        .filter_map(|s| s.split_once('}'))
+        .map(|(name, _)| name)
+        .filter(|name| !name.is_empty() && name.chars().all(|c| c.is_ascii_lowercase() || c == '_'))
+        .collect()
+}
+
+/// Check that the template only uses the given variables, so that a typo in the config is caught
+/// when it is loaded, instead of being posted as is.
+pub fn check_template(template: &str, vars: &[&str]) -> Result<(), String> {
+    match template_vars(template)
+        .into_iter()
+        .find(|v| !vars.contains(v))
+    {
+        Some(unknown) => Err(format!(
+            "Undefined variable {{{unknown}}}, available are: {}",
+            vars.iter()
+                .map(|v| format!("{{{v}}}"))
+                .collect::<Vec<_>>()
+                .join(", ")
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Substitute the variables in the template.
+pub fn render_template(template: &str, vars: &[(&str, String)]) -> String {
+    vars.iter()
+        .fold(template.to_string(), |text, (name, value)| {
+            text.replace(&format!("{{{name}}}"), value)
+        })
+}
+
+/// Return the values of PULL_VARS for the pull request.
+#[cfg(feature = "github")]
+pub fn pull_vars(
+    slug: &crate::Slug,
+    pull: &octocrab::models::pulls::PullRequest,
+    now: chrono::DateTime<chrono::Utc>,
+) -> Vec<(&'static str, String)> {
+    let days_inactive = pull
+        .updated_at
+        .map_or(0, |updated| (now - updated).num_days());
+    vec![
+        ("owner", slug.owner.clone()),
+        ("repo", slug.repo.clone()),
+        ("number", pull.number.to_string()),
+        (
+            "author",
+            pull.user
+                .as_ref()
+                .map(|u| u.login.clone())
+                .unwrap_or_default(),
+        ),
+        ("base_branch", pull.base.ref_field.clone()),
+        ("head_sha", pull.head.sha.clone()),
+        (
+            "labels",
+            pull.labels
+                .iter()
+                .flatten()
+                .map(|l| l.name.as_str())
+                .collect::<Vec<_>>()
+                .join(", "),
+        ),
+        ("days_inactive", days_inactive.to_string()),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_template() {
+        let template = "Hi {author}, please rebase on {owner}/{repo}. {} {A} {no-var}";
+        assert_eq!(template_vars(template), ["author", "owner", "repo"]);
+        assert!(check_template(template, PULL_VARS).is_ok());
+        assert_eq!(
+            check_template("{behind_by} commits", &["owner"]),
+            Err("Undefined variable {behind_by}, available are: {owner}".to_string())
+        );
+    }
+
+    #[test]
+    fn test_render_template() {
+        let vars = [("owner", "o".to_string()), ("repo", "r".to_string())];
+        assert_eq!(
+            render_template("{owner}/{repo} {owner} {other}", &vars),
+            "o/r o {other}"
+        );
+    }
+}
@@ -0,0 +1,40 @@
+/// Return the listening socket passed by systemd socket activation, if any.
+///
+/// With socket activation, systemd owns the socket and keeps it open while the server restarts.
+/// Webhooks delivered during a deploy wait in the backlog of the socket until the new instance
+/// accepts them, instead of being refused. Events that were accepted, but not handled before the
+/// old instance stopped, are picked up from the --queue-file. Example units:
+///
+/// ```text
+/// # drahtbot.socket
+/// [Socket]
+/// ListenStream=127.0.0.1:1337
+///
+/// # drahtbot.service
+/// [Service]
+/// ExecStart=/usr/local/bin/webhook_features --queue-file=/var/lib/drahtbot/queue.json ...
+/// ```
+pub fn activated_listener() -> Option<std::net::TcpListener> {
+    // See sd_listen_fds(3). The variables are meant for this process only, not for children.
+    let pid = std::env::var("LISTEN_PID").ok()?.parse::<u32>().ok()?;
+    let fds = std::env::var("LISTEN_FDS").ok()?.parse::<u32>().ok()?;
+    if pid != std::process::id() || fds == 0 {
+        return None;
+    }
+    if fds > 1 {
+        tracing::warn!("Got {fds} sockets from systemd, only using the first one");
+    }
+    const SD_LISTEN_FDS_START: std::os::fd::RawFd = 3;
+    // SAFETY: systemd passes the sockets as the first file descriptors after stdio, and nothing
+    // else in this process uses them.
+    let listener = unsafe {
+        <std::net::TcpListener as std::os::fd::FromRawFd>::from_raw_fd(SD_LISTEN_FDS_START)
+    };
+    // The passed socket is inherited by child processes (e.g. the conflicts tool), which would
+    // keep it open. A clone is closed on exec.
+    let listener = listener.try_clone().expect("socket activation error");
+    listener
+        .set_nonblocking(true)
+        .expect("socket activation error");
+    Some(listener)
+}
@@ -0,0 +1,89 @@
+use crate::errors::Result;
+use crate::features::summary_comment::{refresh_summary_comment, Repository};
+use crate::Context;
+
+#[derive(clap::Args)]
+pub struct BackfillArgs {
+    /// The repo slug of the remote on GitHub. Format: owner/repo
+    #[arg(long)]
+    repo: util::Slug,
+    /// How many seconds to sleep between pulls, to spread out the API calls.
+    #[arg(long, default_value_t = 5)]
+    sleep_sec: u64,
+    /// Exit cleanly after this many minutes.
+    #[arg(long)]
+    max_runtime: Option<u64>,
+    /// The file to remember the last processed pull in, to resume an interrupted run.
+    #[arg(long)]
+    checkpoint_file: Option<std::path::PathBuf>,
+}
+
+/// Create or update the summary comment on all open pull requests of a repo, oldest first. This is
+/// meant for repos that just enabled the feature and have many pull requests without a summary.
+///
+/// Reviewers are not re-requested, to avoid notifying them about many old pull requests at once.
+pub async fn backfill_summaries(ctx: &Context, args: BackfillArgs) -> Result<()> {
+    let util::Slug { owner, repo } = &args.repo;
+    tracing::info!("Get open pulls for {owner}/{repo} ...");
+    let pulls_api = ctx.octocrab.pulls(owner, repo);
+    let pulls = util::with_retry(|| async {
+        ctx.octocrab
+            .all_pages(
+                pulls_api
+                    .list()
+                    .state(octocrab::params::State::Open)
+                    .sort(octocrab::params::pulls::Sort::Created)
+                    .direction(octocrab::params::Direction::Ascending)
+                    .send()
+                    .await?,
+            )
+            .await
+    })
+    .await?
+    .into_iter()
+    .map(|p| p.number)
+    .collect::<Vec<_>>();
+    tracing::info!("Open pulls: {}", pulls.len());
+
+    let pull_id = |number: u64| format!("{owner}/{repo}/{number}");
+    let ids = pulls.iter().map(|n| pull_id(*n)).collect::<Vec<_>>();
+    let checkpoint = args.checkpoint_file.map(util::Checkpoint::new);
+    let skip = checkpoint
+        .as_ref()
+        .map_or(0, |c| c.skip_count(ids.iter().map(|id| id.as_str())));
+    if skip > 0 {
+        tracing::info!("Resume after {skip} pulls from the previous run");
+    }
+    let mut progress = util::Progress::new(
+        pulls.len() - skip,
+        util::Progress::deadline(args.max_runtime),
+    );
+    // The checkpoint stays at the last pull before the first failed one, so that a resumed run
+    // retries it
+    let mut failed = false;
+    for (number, id) in pulls.iter().zip(&ids).skip(skip) {
+        if progress.out_of_time() {
+            tracing::info!("Max runtime reached, exiting");
+            return Ok(());
+        }
+        let repository = Repository {
+            owner: owner.to_string(),
+            name: repo.to_string(),
+        };
+        // Keep going, so that one broken pull request does not block the others
+        if let Err(err) = refresh_summary_comment(ctx, repository, *number, false).await {
+            tracing::error!("... when refreshing the summary of {id}: {err:?}");
+            failed = true;
+        }
+        progress.inc(id);
+        if let Some(c) = checkpoint.as_ref().filter(|_| !failed) {
+            c.save(id);
+        }
+        actix_web::rt::time::sleep(std::time::Duration::from_secs(args.sleep_sec)).await;
+    }
+    progress.finish();
+    if let Some(c) = checkpoint.as_ref().filter(|_| !failed) {
+        c.clear();
+    }
+    Ok(())
+}
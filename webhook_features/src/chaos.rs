@@ -0,0 +1,95 @@
+use std::hash::{BuildHasher, Hasher};
+
+use actix_web::{web, App, HttpRequest, HttpResponse, HttpServer};
+
+/// A local proxy in front of the GitHub API, which randomly replaces responses with errors.
+///
+/// This is a development aid to check that features degrade gracefully when the GitHub API
+/// misbehaves, instead of aborting the handling of an event.
+struct Chaos {
+    upstream: String,
+    /// The probability to inject an error, between 0 and 1.
+    rate: f64,
+    client: reqwest::Client,
+}
+
+/// The errors to inject: status, message, and whether it is a rate limit.
+const ERRORS: &[(u16, &str, bool)] = &[
+    (404, "Not Found", false),
+    (403, "Resource not accessible by integration", false),
+    (500, "Server Error", false),
+    (
+        403,
+        "You have exceeded a secondary rate limit. Please wait a few minutes before you try again.",
+        true,
+    ),
+];
+
+fn random() -> u64 {
+    std::collections::hash_map::RandomState::new()
+        .build_hasher()
+        .finish()
+}
+
+async fn proxy(chaos: web::Data<Chaos>, req: HttpRequest, body: web::Bytes) -> HttpResponse {
+    if (random() as f64 / u64::MAX as f64) < chaos.rate {
+        let (status, message, rate_limit) = ERRORS[random() as usize % ERRORS.len()];
+        tracing::info!("CHAOS: Inject {status} for {} {}", req.method(), req.uri());
+        let mut resp = HttpResponse::build(
+            actix_web::http::StatusCode::from_u16(status).expect("invalid status"),
+        );
+        if rate_limit {
+            resp.insert_header(("retry-after", "60"));
+        }
+        return resp.json(serde_json::json!({
+            "message": message,
+            "documentation_url": "https://docs.github.com/rest",
+        }));
+    }
+
+    let url = format!("{}{}", chaos.upstream, req.uri());
+    let method =
+        reqwest::Method::from_bytes(req.method().as_str().as_bytes()).expect("invalid method");
+    let mut upstream_req = chaos.client.request(method, url).body(body.to_vec());
+    for (name, value) in req.headers() {
+        if name != "host" {
+            upstream_req = upstream_req.header(name.as_str(), value.as_bytes());
+        }
+    }
+    let upstream_resp = match upstream_req.send().await {
+        Ok(r) => r,
+        Err(e) => return HttpResponse::BadGateway().body(e.to_string()),
+    };
+    let mut resp = HttpResponse::build(
+        actix_web::http::StatusCode::from_u16(upstream_resp.status().as_u16())
+            .expect("invalid status"),
+    );
+    for (name, value) in upstream_resp.headers() {
+        if name != "content-length" && name != "transfer-encoding" {
+            resp.insert_header((name.as_str(), value.as_bytes()));
+        }
+    }
+    match upstream_resp.bytes().await {
+        Ok(b) => resp.body(b),
+        Err(e) => HttpResponse::BadGateway().body(e.to_string()),
+    }
+}
+
+/// Start the proxy in the background and return its base url.
+pub fn start(upstream: &str, rate: f64) -> std::io::Result<String> {
+    let chaos = web::Data::new(Chaos {
+        upstream: upstream.trim_end_matches('/').to_string(),
+        rate,
+        client: reqwest::Client::new(),
+    });
+    let server = HttpServer::new(move || {
+        App::new()
+            .app_data(chaos.clone())
+            .default_service(web::to(proxy))
+    })
+    .workers(1)
+    .bind(("127.0.0.1", 0))?;
+    let addr = server.addrs()[0];
+    actix_web::rt::spawn(server.run());
+    Ok(format!("http://{addr}"))
+}
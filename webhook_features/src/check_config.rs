@@ -0,0 +1,168 @@
+use crate::config::Config;
+use crate::errors::Result;
+
+/// Return the problems of the config that would otherwise only show up at runtime, once an event
+/// hits them.
+pub fn check_config(config: &Config) -> Vec<String> {
+    let mut problems = Vec::new();
+    for (i, repo) in config.repositories.iter().enumerate() {
+        let slug = &repo.repo_slug;
+        match slug.parse::<util::Slug>() {
+            Ok(s) if !s.owner.is_empty() && !s.repo.is_empty() => {}
+            _ => problems.push(format!(
+                "repositories[{i}]: repo_slug '{slug}' is not of the format owner/repo"
+            )),
+        }
+        if config.repositories[..i]
+            .iter()
+            .any(|r| &r.repo_slug == slug)
+        {
+            problems.push(format!("{slug}: Listed more than once"));
+        }
+        for (label, regexes) in &repo.repo_labels {
+            for r in regexes {
+                if let Err(err) = regex::Regex::new(r) {
+                    problems.push(format!("{slug}: repo_labels.{label}: {err}"));
+                }
+            }
+        }
+        for (name, text, vars) in [
+            (
+                "guideline_text",
+                &repo.summary.guideline_text,
+                &["guideline_url"][..],
+            ),
+            ("empty_text", &repo.summary.empty_text, &[]),
+            ("footer_text", &repo.summary.footer_text, &[]),
+        ] {
+            if let Err(err) = util::check_template(text, vars) {
+                problems.push(format!("{slug}: summary.{name}: {err}"));
+            }
+        }
+        let ci_labels = [crate::features::ci_status::CI_FAILED_LABEL]
+            .into_iter()
+            .chain(
+                crate::features::ci_status::FailureCategory::ALL
+                    .iter()
+                    .map(|c| c.label()),
+            );
+        for label in ci_labels.filter(|l| !repo.bot_labels.iter().any(|b| b == l)) {
+            problems.push(format!(
+                "{slug}: bot_labels: The label '{label}' is set by the CI status feature, but missing, so it is not removed once a pull request is closed"
+            ));
+        }
+        for (label, mentions) in &repo.label_mentions {
+            for m in mentions.iter().filter(|m| !m.starts_with('@')) {
+                problems.push(format!(
+                    "{slug}: label_mentions.{label}: '{m}' is not a mention (@user or @org/team)"
+                ));
+            }
+        }
+        if repo
+            .conflicts_command
+            .iter()
+            .any(|a| a.starts_with("--pull-id"))
+        {
+            problems.push(format!(
+                "{slug}: conflicts_command: --pull-id is added for each push, and must not be set"
+            ));
+        }
+        if repo
+            .guix_command
+            .iter()
+            .any(|a| a.starts_with("--pull_number"))
+        {
+            problems.push(format!(
+                "{slug}: guix_command: --pull_number is added for each label, and must not be set"
+            ));
+        }
+        if !repo.guix_workers.is_empty()
+            && repo
+                .guix_command
+                .iter()
+                .any(|a| a.starts_with("--guix_folder"))
+        {
+            problems.push(format!(
+                "{slug}: guix_command: --guix_folder is added for each of the guix_workers, and must not be set"
+            ));
+        }
+    }
+    problems
+}
+
+/// Load the config file and fail on any of its problems.
+pub fn load_config(path: &std::path::Path) -> Result<Config> {
+    let config: Config = serde_yaml::from_reader(std::fs::File::open(path)?)
+        .map_err(|err| anyhow::anyhow!("{}: {err}", path.display()))?;
+    let problems = check_config(&config);
+    for p in &problems {
+        tracing::error!("{p}");
+    }
+    if !problems.is_empty() {
+        anyhow::bail!("{} problems found in {}", problems.len(), path.display());
+    }
+    Ok(config)
+}
+
+/// Load the config file and report its problems, to catch them before deployment.
+pub fn check_config_file(path: &std::path::Path) -> Result<()> {
+    tracing::info!("Check config file {} ...", path.display());
+    load_config(path)?;
+    tracing::info!("No problems found");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_config() {
+        let config = serde_yaml::from_str(include_str!("../config.yml")).unwrap();
+        assert_eq!(check_config(&config), Vec::<String>::new());
+
+        let config = serde_yaml::from_str(
+            r#"
+repositories:
+  - repo_slug: "owner/repo"
+    backport_label: "Backport"
+    repo_labels:
+      Wallet:
+        - '^wallet:('
+    corecheck: false
+    label_mentions:
+      Wallet:
+        - '@org/wallet'
+        - 'alice'
+    summary:
+      guideline_text: "See {guideline}"
+  - repo_slug: "owner/repo"
+    backport_label: "Backport"
+    repo_labels: {}
+    corecheck: false
+    bot_labels:
+      - CI failed
+      - "CI failed: build"
+      - "CI failed: tests"
+      - "CI failed: lint"
+"#,
+        )
+        .unwrap();
+        let problems = check_config(&config);
+        assert_eq!(problems.len(), 8, "{problems:?}");
+        for p in [
+            "owner/repo: repo_labels.Wallet: regex parse error",
+            "owner/repo: summary.guideline_text: Undefined variable {guideline}",
+            "owner/repo: bot_labels: The label 'CI failed'",
+            "owner/repo: label_mentions.Wallet: 'alice'",
+            "owner/repo: Listed more than once",
+        ] {
+            assert!(problems.iter().any(|m| m.starts_with(p)), "{p}");
+        }
+
+        assert!(serde_yaml::from_str::<Config>(
+            "repositories:\n  - repo_slug: o/r\n    backport_label: B\n    repo_labels: {}\n    corecheck: false\n    merge_sumary: true\n"
+        )
+        .is_err());
+    }
+}
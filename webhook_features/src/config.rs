@@ -1,12 +1,152 @@
 #[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct Repo {
     pub repo_slug: String,
     pub backport_label: String,
     pub repo_labels: std::collections::HashMap<String, Vec<String>>,
     pub corecheck: bool,
+    /// Labels set by the bot (or requesting work from it), removed once a pull request is closed.
+    #[serde(default)]
+    pub bot_labels: Vec<String>,
+    /// Post the final list of ACKs once a pull request is merged.
+    #[serde(default)]
+    pub merge_summary: bool,
+    /// Wording and sections of the summary comment.
+    #[serde(default)]
+    pub summary: Summary,
+    /// Patterns to detect reviews in comments, tried in order. If empty, the patterns of the
+    /// Bitcoin Core review process are used.
+    #[serde(default)]
+    pub ack_patterns: Vec<AckPattern>,
+    /// The command line of the conflicts tool, without `--pull-id`. If set, it is run for each
+    /// push to a pull request, to update its conflicts right away. The token of the server is
+    /// passed in the GITHUB_ACCESS_TOKEN environment variable.
+    #[serde(default)]
+    pub conflicts_command: Vec<String>,
+    /// Add a note to the pull requests with conflicts, once the last run of the conflicts tool (as
+    /// recorded in the pull facts) is older than this, e.g. because its cron job stopped.
+    #[serde(default)]
+    pub conflicts_max_age_hours: Option<u32>,
+    /// The command line of the guix script, without `--pull_number`. If set, it is run once the
+    /// guix label is added to a pull request, with the token of the server in the
+    /// GITHUB_ACCESS_TOKEN environment variable. A cron job of the script is then only a fallback
+    /// for missed webhook deliveries. Runs in the same guix folder wait for each other.
+    #[serde(default)]
+    pub guix_command: Vec<String>,
+    /// The scratch folders of the guix script (`--guix_folder`), one per concurrent build, each
+    /// with its own store cache. If empty, one build runs at a time in the folder of the command.
+    #[serde(default)]
+    pub guix_workers: Vec<std::path::PathBuf>,
+    /// Teams or users to mention once a label is added to an issue or pull request, by label, e.g.
+    /// `Wallet: ["@org/wallet-reviewers"]`. Each is mentioned at most once per issue.
+    #[serde(default)]
+    pub label_mentions: std::collections::HashMap<String, Vec<String>>,
+    /// Suggest splitting pull requests above any of the thresholds.
+    #[serde(default)]
+    pub size_advisor: Option<SizeAdvisor>,
+    /// Only overwrite the title of a pull request (to trim it) after a member of the team approved
+    /// it. The approval is picked up by the next event of the pull request.
+    #[serde(default)]
+    pub title_approval: Option<util::ApprovalConfig>,
 }
 
 #[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SizeAdvisor {
+    pub max_files: u64,
+    /// The maximum number of added lines.
+    pub max_additions: u64,
+    pub max_commits: u64,
+    /// The maximum number of touched subsystems, i.e. directories up to a depth of two, e.g.
+    /// `src/wallet`.
+    pub max_subsystems: usize,
+    /// The label to set on pull requests above a threshold, e.g. "Large".
+    pub label: String,
+    /// The guideline on splitting pull requests.
+    pub guideline_url: String,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AckPattern {
+    /// The regex to find the review in a line of a comment. A second capture group, if any, is the
+    /// reviewed commit. An ACK without a commit never goes stale.
+    #[serde(deserialize_with = "deserialize_regex")]
+    pub regex: regex::Regex,
+    /// The row of the summary table, e.g. `ack`, `concept_ack` or `concept_nack`.
+    #[serde(rename = "type")]
+    pub ack_type: crate::features::summary_comment::AckType,
+}
+
+/// Compile the regex when the config is loaded, so that a bad pattern is an error of the config.
+fn deserialize_regex<'de, D: serde::Deserializer<'de>>(d: D) -> Result<regex::Regex, D::Error> {
+    let s = <String as serde::Deserialize>::deserialize(d)?;
+    regex::Regex::new(&s).map_err(serde::de::Error::custom)
+}
+
+#[derive(serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Summary {
+    /// The review process guideline of the project.
+    pub guideline_url: String,
+    /// The sentence pointing to the guideline. `{guideline_url}` is replaced.
+    pub guideline_text: String,
+    /// Shown while there are no reviews yet.
+    pub empty_text: String,
+    /// Shown below the table of reviews.
+    pub footer_text: String,
+    /// Whether to maintain the reviews section.
+    pub reviews: bool,
+    /// Whether to maintain the bot activity section.
+    pub bot_activity: bool,
+    /// Whether to maintain the section of pending review requests and assignees.
+    pub review_requests: bool,
+    /// Stale reviewers are only requested again once the last push is this many days old, so
+    /// that CI finished and the author had time to address other comments.
+    pub review_request_delay_days: u32,
+    /// Whether to list the earlier reviews of each reviewer below the table of reviews.
+    pub ack_history: bool,
+    /// Whether to show the number of unresolved review threads in the reviews section, so that
+    /// review feedback that was not addressed yet is visible even when there are ACKs.
+    pub unresolved_threads: bool,
+    /// Whether to list the results (guix builds, coverage) that were computed for an earlier head
+    /// commit of the pull request, along with how to refresh them.
+    pub outdated_results: bool,
+}
+
+impl Default for Summary {
+    fn default() -> Self {
+        Self {
+            guideline_url:
+                "https://github.com/bitcoin/bitcoin/blob/master/CONTRIBUTING.md#code-review"
+                    .to_string(),
+            guideline_text:
+                "See [the guideline]({guideline_url}) for information on the review process."
+                    .to_string(),
+            empty_text: "A summary of reviews will appear here.".to_string(),
+            footer_text: "If your review is incorrectly listed, please react with 👎 to this comment and the bot will ignore it on the next update.".to_string(),
+            reviews: true,
+            bot_activity: true,
+            review_requests: true,
+            review_request_delay_days: 7,
+            ack_history: false,
+            unresolved_threads: true,
+            outdated_results: true,
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct Config {
     pub repositories: Vec<Repo>,
+    /// The public url of this server, to link to its dashboards from comments.
+    #[serde(default)]
+    pub dashboard_url: Option<String>,
+    /// The maximum number of comments, closes and label changes per repository per hour.
+    #[serde(default)]
+    pub quota: util::QuotaLimits,
+    /// The jobs of the scheduler, which reads this section itself.
+    #[serde(default)]
+    pub schedule: serde_yaml::Value,
 }
@@ -10,4 +10,6 @@ pub enum DrahtBotError {
     GitHubError(#[from] octocrab::Error),
     #[error("Key not found")]
     KeyNotFound,
+    #[error("Deferred, because the hourly quota of the repo was reached")]
+    QuotaDeferred,
 }
@@ -0,0 +1,206 @@
+use crate::errors::Result;
+
+/// A webhook event with the features that still have to handle it.
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct QueuedEvent {
+    pub id: u64,
+    pub event: String,
+    /// The GitHub delivery id, to correlate logs with the webhook deliveries on GitHub.
+    #[serde(default)]
+    pub delivery: Option<String>,
+    pub payload: serde_json::Value,
+    /// The names of the features that have not handled the event successfully yet.
+    pub features: Vec<String>,
+    pub attempts: u32,
+    pub next_attempt: chrono::DateTime<chrono::Utc>,
+    /// Whether an attempt is running, so that the retry loop does not run the features again at
+    /// the same time. Not persisted, so that events interrupted by a restart are retried.
+    #[serde(skip)]
+    in_flight: bool,
+}
+
+#[derive(Default, serde::Serialize, serde::Deserialize)]
+struct Queue {
+    next_id: u64,
+    events: Vec<QueuedEvent>,
+}
+
+/// Incoming events, stored in a json file until all features handled them, so that a transient
+/// GitHub error or a restart does not lose them. Without a file, the queue is only kept in memory.
+pub struct EventQueue {
+    path: Option<std::path::PathBuf>,
+    queue: tokio::sync::Mutex<Queue>,
+}
+
+impl EventQueue {
+    /// Give up on an event after this many failed attempts.
+    const MAX_ATTEMPTS: u32 = 8;
+
+    pub fn load(path: Option<std::path::PathBuf>) -> Result<Self> {
+        let queue = match &path {
+            Some(p) if p.exists() => serde_json::from_reader(std::fs::File::open(p)?)?,
+            _ => Queue::default(),
+        };
+        Ok(Self {
+            path,
+            queue: tokio::sync::Mutex::new(queue),
+        })
+    }
+
+    fn write(&self, queue: &Queue) -> Result<()> {
+        if let Some(path) = &self.path {
+            let tmp = path.with_extension("tmp");
+            std::fs::write(&tmp, serde_json::to_vec_pretty(queue)?)?;
+            std::fs::rename(tmp, path)?;
+        }
+        Ok(())
+    }
+
+    /// Store a new event, before it is handled, and return it. The event is in flight until
+    /// finish_attempt is called. The first retry is scheduled in a minute, in case the server
+    /// stops while handling it.
+    pub async fn push(
+        &self,
+        event: String,
+        delivery: Option<String>,
+        payload: serde_json::Value,
+        features: Vec<String>,
+    ) -> Result<QueuedEvent> {
+        let mut queue = self.queue.lock().await;
+        let queued = QueuedEvent {
+            id: queue.next_id,
+            event,
+            delivery,
+            payload,
+            features,
+            attempts: 0,
+            next_attempt: chrono::Utc::now() + chrono::Duration::minutes(1),
+            in_flight: true,
+        };
+        queue.next_id += 1;
+        queue.events.push(queued.clone());
+        self.write(&queue)?;
+        Ok(queued)
+    }
+
+    /// Return the events that are due for a retry, and mark them as in flight.
+    pub async fn due(&self, now: chrono::DateTime<chrono::Utc>) -> Vec<QueuedEvent> {
+        let mut queue = self.queue.lock().await;
+        queue
+            .events
+            .iter_mut()
+            .filter(|e| !e.in_flight && e.next_attempt <= now)
+            .map(|e| {
+                e.in_flight = true;
+                e.clone()
+            })
+            .collect()
+    }
+
+    /// Record the result of an attempt: Remove the event, if all features succeeded. Otherwise,
+    /// schedule the failed features for a retry with exponential backoff.
+    pub async fn finish_attempt(&self, id: u64, failed: Vec<String>) -> Result<()> {
+        let mut queue = self.queue.lock().await;
+        if let Some(pos) = queue.events.iter().position(|e| e.id == id) {
+            let e = &mut queue.events[pos];
+            e.attempts += 1;
+            e.in_flight = false;
+            if failed.is_empty() {
+                queue.events.remove(pos);
+            } else if e.attempts >= Self::MAX_ATTEMPTS {
+                tracing::error!(
+                    "Dropping {} event {} after {} attempts, failed features: {:?}",
+                    e.event,
+                    e.id,
+                    e.attempts,
+                    failed
+                );
+                queue.events.remove(pos);
+            } else {
+                let backoff = chrono::Duration::minutes(1 << (e.attempts - 1));
+                tracing::info!(
+                    "Retrying {} event {} for {:?} in {} minutes",
+                    e.event,
+                    e.id,
+                    failed,
+                    backoff.num_minutes()
+                );
+                e.features = failed;
+                e.next_attempt = chrono::Utc::now() + backoff;
+            }
+        }
+        self.write(&queue)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[actix_web::test]
+    async fn test_retry() {
+        let queue = EventQueue::load(None).unwrap();
+        let later = chrono::Utc::now() + chrono::Duration::hours(24);
+        let e = queue
+            .push(
+                "check_suite".to_string(),
+                None,
+                serde_json::json!({}),
+                vec!["A".to_string(), "B".to_string()],
+            )
+            .await
+            .unwrap();
+        assert!(queue.due(chrono::Utc::now()).await.is_empty());
+        // The first attempt is still running
+        assert!(queue.due(later).await.is_empty());
+
+        queue
+            .finish_attempt(e.id, vec!["B".to_string()])
+            .await
+            .unwrap();
+        let due = queue.due(later).await;
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].features, vec!["B".to_string()]);
+        assert!(queue.due(later).await.is_empty());
+
+        for _ in 1..EventQueue::MAX_ATTEMPTS {
+            queue
+                .finish_attempt(e.id, vec!["B".to_string()])
+                .await
+                .unwrap();
+        }
+        assert!(queue.due(later).await.is_empty());
+
+        let e = queue
+            .push(
+                "check_suite".to_string(),
+                None,
+                serde_json::json!({}),
+                vec![],
+            )
+            .await
+            .unwrap();
+        queue.finish_attempt(e.id, vec![]).await.unwrap();
+        assert!(queue.due(later).await.is_empty());
+    }
+
+    #[actix_web::test]
+    async fn test_restart() {
+        let path = std::env::temp_dir().join("drahtbot_test_event_queue.json");
+        let queue = EventQueue::load(Some(path.clone())).unwrap();
+        queue
+            .push(
+                "check_suite".to_string(),
+                None,
+                serde_json::json!({}),
+                vec!["A".to_string()],
+            )
+            .await
+            .unwrap();
+        // The server stopped while the event was in flight
+        let queue = EventQueue::load(Some(path.clone())).unwrap();
+        let later = chrono::Utc::now() + chrono::Duration::hours(24);
+        assert_eq!(queue.due(later).await.len(), 1);
+        std::fs::remove_file(path).unwrap();
+    }
+}
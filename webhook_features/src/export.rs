@@ -0,0 +1,70 @@
+use crate::errors::Result;
+use crate::features::summary_comment::{export_reviews, Repository};
+use crate::Context;
+
+#[derive(clap::Args)]
+pub struct ExportArgs {
+    /// The repo slug of the remote on GitHub. Format: owner/repo
+    #[arg(long)]
+    repo: util::Slug,
+    /// A clone of the reports repo to publish the reviews to.
+    #[arg(long)]
+    export_dir: std::path::PathBuf,
+    /// Also export the pull requests closed in the last days, to keep the reviews right before
+    /// the merge.
+    #[arg(long, default_value_t = 45)]
+    closed_days: i64,
+}
+
+/// Publish the parsed reviews of all open and recently closed pull requests of a repo as json to
+/// the reports repo. Run this periodically (e.g. from cron) to keep the data fresh.
+pub async fn export_reviews_data(ctx: &Context, args: ExportArgs) -> Result<()> {
+    let util::Slug { owner, repo } = &args.repo;
+    tracing::info!("Get open pulls for {owner}/{repo} ...");
+    let pulls_api = ctx.octocrab.pulls(owner, repo);
+    let mut pulls = util::with_retry(|| async {
+        ctx.octocrab
+            .all_pages(
+                pulls_api
+                    .list()
+                    .state(octocrab::params::State::Open)
+                    .send()
+                    .await?,
+            )
+            .await
+    })
+    .await?;
+    tracing::info!("Open pulls: {}", pulls.len());
+    let since = { chrono::Utc::now() - chrono::Duration::days(args.closed_days) }.format("%F");
+    let query = format!("repo:{owner}/{repo} is:pr is:closed closed:>={since}");
+    let closed = util::with_retry(|| async {
+        ctx.octocrab
+            .all_pages(
+                ctx.octocrab
+                    .search()
+                    .issues_and_pull_requests(&query)
+                    .send()
+                    .await?,
+            )
+            .await
+    })
+    .await?;
+    tracing::info!("Pulls closed since {since}: {}", closed.len());
+    for issue in closed {
+        pulls.push(util::with_retry(|| pulls_api.get(issue.number)).await?);
+    }
+
+    let export = util::DataExport::new(args.export_dir);
+    let repository = Repository {
+        owner: owner.to_string(),
+        name: repo.to_string(),
+    };
+    for pull in &pulls {
+        let reviews = export_reviews(ctx, &repository, pull).await?;
+        export.write(owner, repo, pull.number, "reviews", &reviews);
+    }
+    if !ctx.dry_run {
+        export.publish("Update reviews data");
+    }
+    Ok(())
+}
@@ -1,4 +1,4 @@
-use super::{Feature, FeatureMeta};
+use super::{sync_label, Feature, FeatureMeta};
 use crate::errors::DrahtBotError;
 use crate::errors::Result;
 use crate::Context;
@@ -21,6 +21,9 @@ impl CiStatusFeature {
     }
 }
 
+/// The label of pull requests with a failed CI run.
+pub const CI_FAILED_LABEL: &str = "CI failed";
+
 #[async_trait]
 impl Feature for CiStatusFeature {
     fn meta(&self) -> &FeatureMeta {
@@ -33,7 +36,7 @@ impl Feature for CiStatusFeature {
         event: &GitHubEvent,
         payload: &serde_json::Value,
     ) -> Result<()> {
-        let ci_failed_label = "CI failed";
+        let ci_failed_label = CI_FAILED_LABEL;
         let action = payload["action"]
             .as_str()
             .ok_or(DrahtBotError::KeyNotFound)?;
@@ -46,7 +49,7 @@ impl Feature for CiStatusFeature {
             .as_str()
             .ok_or(DrahtBotError::KeyNotFound)?;
 
-        println!("Handling: {repo_user}/{repo_name} {event}::{action}");
+        tracing::info!("Handling: {repo_user}/{repo_name} {event}::{action}");
         match event {
             GitHubEvent::CheckSuite if action == "completed" => {
                 // https://docs.github.com/webhooks-and-events/webhooks/webhook-events-and-payloads#check_suite
@@ -58,105 +61,753 @@ impl Feature for CiStatusFeature {
                     return Ok(());
                 }
                 let success = "success" == conclusion;
+                ctx.state
+                    .update(|s| {
+                        s.record_ci_result(
+                            &format!("{repo_user}/{repo_name}"),
+                            chrono::Utc::now().date_naive(),
+                            success,
+                        )
+                    })
+                    .await?;
                 let suite_id = payload["check_suite"]["id"]
                     .as_u64()
                     .ok_or(DrahtBotError::KeyNotFound)?;
-                let checks_api = ctx.octocrab.checks(repo_user, repo_name);
-                let check_runs = checks_api
-                    .list_check_runs_in_a_check_suite(suite_id.into())
-                    .per_page(99)
-                    .send()
-                    .await?
-                    .check_runs;
-                let pull_number = {
-                    // Hacky way to get the pull number. See also https://github.com/bitcoin/bitcoin/issues/27178#issuecomment-1503475232
-                    let cirrus_task_id = check_runs
-                        .first()
-                        .ok_or(DrahtBotError::KeyNotFound)?
-                        .details_url
-                        .as_ref()
-                        .ok_or(DrahtBotError::KeyNotFound)?
-                        .split('/')
-                        .last()
-                        .ok_or(DrahtBotError::KeyNotFound)?
-                        .to_string();
-
-                    let query = format!(
-                        r#"{{ "query": "query GetTaskDetailsById($taskId: ID!) {{ task(id: $taskId) {{ id build {{ id repository {{ owner name }} pullRequest }} }} }}", "variables": {{ "taskId": "{}" }} }}"#,
-                        cirrus_task_id
-                    );
-
-                    let response = reqwest::Client::new()
-                        .post("https://api.cirrus-ci.com/graphql")
-                        .header("Content-Type", "application/json")
-                        .body(query)
-                        .send()
+                let head = payload["check_suite"]["head_sha"]
+                    .as_str()
+                    .ok_or(DrahtBotError::KeyNotFound)?;
+                let check_runs = list_check_runs(ctx, repo_user, repo_name, suite_id, head).await?;
+                let pull_number =
+                    find_pull_number(ctx, repo_user, repo_name, payload, &check_runs, head).await?;
+                let slug = format!("{repo_user}/{repo_name}");
+                // The head branch alone is ambiguous, as pull requests from forks may use a branch
+                // of the same name
+                let default_branch = payload["repository"]["default_branch"]
+                    .as_str()
+                    .ok_or(DrahtBotError::KeyNotFound)?;
+                let on_base = pull_number.is_none()
+                    && payload["check_suite"]["head_branch"] == default_branch
+                    && is_on_branch(ctx, repo_user, repo_name, default_branch, head).await?;
+                let today = chrono::Utc::now().date_naive();
+                if !success && (pull_number.is_some() || on_base) {
+                    let failures = ci_failures(&check_runs, today, pull_number);
+                    ctx.state
+                        .update(|s| s.record_ci_failures(&slug, today, failures))
                         .await?;
-
-                    response.json::<serde_json::Value>().await?["data"]["task"]["build"]
-                        ["pullRequest"]
-                        .as_u64()
-                };
-                if pull_number.is_none() {
-                    return Ok(());
                 }
-                let pull_number = pull_number.unwrap();
+                let Some(pull_number) = pull_number else {
+                    if on_base {
+                        handle_base_ci(ctx, repo_user, repo_name, &slug, success).await?;
+                    }
+                    return Ok(());
+                };
                 let issues_api = ctx.octocrab.issues(repo_user, repo_name);
-                let issue = issues_api.get(pull_number).await?;
+                let issue = util::with_retry(|| issues_api.get(pull_number)).await?;
                 if issue.state != octocrab::models::IssueState::Open {
                     return Ok(());
                 };
-                let labels = ctx
-                    .octocrab
-                    .all_pages(issues_api.list_labels_for_issue(pull_number).send().await?)
-                    .await?;
-                let found_label = labels.into_iter().any(|l| l.name == ci_failed_label);
+                let (pull_id, head) = (format!("{slug}/{pull_number}"), head.to_string());
+                ctx.update_pull_facts(move |f| {
+                    f.pull_mut(&pull_id).ci = Some(::state::CiResult {
+                        head,
+                        success,
+                        date: chrono::Utc::now(),
+                    })
+                })
+                .await?;
+                let labels = util::with_retry(|| async {
+                    ctx.octocrab
+                        .all_pages(issues_api.list_labels_for_issue(pull_number).send().await?)
+                        .await
+                })
+                .await?;
+                let found_label = labels.iter().any(|l| l.name == ci_failed_label);
+                let categories = if success {
+                    Vec::new()
+                } else {
+                    failure_categories(&check_runs)
+                };
+                let names = labels.iter().map(|l| l.name.as_str()).collect::<Vec<_>>();
+                let repo_slug = util::Slug {
+                    owner: repo_user.to_string(),
+                    repo: repo_name.to_string(),
+                };
+                for (label, add) in category_label_plan(&names, &categories) {
+                    sync_label(ctx, &repo_slug, pull_number, label, add).await?;
+                }
                 if found_label && success {
-                    println!("... {} remove label '{}')", pull_number, ci_failed_label);
-                    if !ctx.dry_run {
-                        issues_api
-                            .remove_label(pull_number, &ci_failed_label)
-                            .await?;
-                    }
+                    sync_label(ctx, &repo_slug, pull_number, ci_failed_label, false).await?;
                 } else if !found_label && !success {
-                    println!(
-                        "... {} add label '{}' due to {}",
-                        pull_number, ci_failed_label, conclusion
-                    );
-                    if !ctx.dry_run {
-                        issues_api
-                            .add_labels(pull_number, &[ci_failed_label.to_string()])
-                            .await?;
-                        // Check if *compile* failed and add comment
-                        // (functional tests are ignored due to intermittent issues)
-                        if let Some(first_fail) = check_runs.iter().find(|r| {
-                            let text = r.output.text.clone().unwrap_or_default();
-                            text.contains("make: *** [Makefile")
-                                || text.contains("clang-tidy-")
-                                || text.contains("ailure generated from")
-                        }) {
-                            let comment = format!(
-                                "{}\n{}\n<sub>Debug: {}</sub>",
-                                util::IdComment::CiFailed.str(),
-                                r#"
-🚧 At least one of the CI tasks failed. Make sure to run all tests locally, according to the
-documentation.
+                    tracing::info!("... {pull_number} failed due to {conclusion}");
+                    sync_label(ctx, &repo_slug, pull_number, ci_failed_label, true).await?;
+                }
+                // Also on success without the label, to hide a section left over from a failure
+                // whose label was removed manually
+                let mut cmt =
+                    util::get_metadata_sections(&ctx.octocrab, &issues_api, pull_number).await?;
+                if !success
+                    && ctx
+                        .state
+                        .update(|s| s.note_failure_on_broken_base(&slug, pull_number))
+                        .await?
+                {
+                    // The failure is likely unrelated, so only add a note
+                    tracing::info!("... {pull_number} base branch CI is failing");
+                    util::update_metadata_comment(
+                        &issues_api,
+                        &mut cmt,
+                        &BaseCiSection { failing: true },
+                        ctx.dry_run,
+                    )
+                    .await?;
+                    return Ok(());
+                }
+                let quiet = util::is_quiet(&issue.labels, issue.body.as_deref());
+                let section = ci_failed_section(ctx, &slug, &check_runs, success, quiet).await;
+                util::update_metadata_comment(&issues_api, &mut cmt, &section, ctx.dry_run).await?;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+/// The kind of a CI failure, so that contributors immediately know where to look.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum FailureCategory {
+    Build,
+    Tests,
+    Lint,
+}
+
+impl FailureCategory {
+    pub const ALL: [FailureCategory; 3] = [Self::Build, Self::Tests, Self::Lint];
+
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Build => "build",
+            Self::Tests => "tests",
+            Self::Lint => "lint",
+        }
+    }
+
+    /// The label of pull requests with a failed CI run of this kind, set along with
+    /// CI_FAILED_LABEL.
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Build => "CI failed: build",
+            Self::Tests => "CI failed: tests",
+            Self::Lint => "CI failed: lint",
+        }
+    }
+}
+
+fn is_failed(run: &octocrab::models::checks::CheckRun) -> bool {
+    matches!(run.conclusion.as_deref(), Some("failure" | "timed_out"))
+}
+
+/// Classify a failed check run by its name and log. Anything that is not a build or lint failure
+/// is assumed to be a test failure.
+fn classify_log(name: &str, log: &str) -> FailureCategory {
+    if name.to_lowercase().contains("lint") || log.contains("clang-tidy-") {
+        return FailureCategory::Lint;
+    }
+    if log.contains("make: *** [Makefile")
+        || log.contains("ninja: build stopped")
+        || log.contains("CMake Error")
+        || log.contains("ailure generated from")
+    {
+        return FailureCategory::Build;
+    }
+    FailureCategory::Tests
+}
+
+fn classify(run: &octocrab::models::checks::CheckRun) -> FailureCategory {
+    classify_log(&run.name, run.output.text.as_deref().unwrap_or_default())
+}
+
+/// Return the sorted categories of the failed check runs.
+fn failure_categories(check_runs: &[octocrab::models::checks::CheckRun]) -> Vec<FailureCategory> {
+    let mut categories = check_runs
+        .iter()
+        .filter(|r| is_failed(r))
+        .map(classify)
+        .collect::<Vec<_>>();
+    categories.sort();
+    categories.dedup();
+    categories
+}
+
+/// Return the category labels to add (true) or remove (false), so that the labels match the
+/// categories of the latest failure.
+fn category_label_plan(
+    labels: &[&str],
+    categories: &[FailureCategory],
+) -> Vec<(&'static str, bool)> {
+    FailureCategory::ALL
+        .iter()
+        .filter_map(|c| {
+            let want = categories.contains(c);
+            let found = labels.contains(&c.label());
+            (want != found).then_some((c.label(), want))
+        })
+        .collect()
+}
+
+/// Return the failed check run to show, preferring build and lint failures, which are rarely
+/// intermittent.
+fn latest_failure(
+    check_runs: &[octocrab::models::checks::CheckRun],
+) -> Option<(FailureCategory, &octocrab::models::checks::CheckRun)> {
+    let failed = || {
+        check_runs
+            .iter()
+            .filter(|r| is_failed(r))
+            .map(|r| (classify(r), r))
+    };
+    failed()
+        .find(|(c, _)| *c != FailureCategory::Tests)
+        .or_else(|| failed().next())
+}
+
+fn run_signature(run: &octocrab::models::checks::CheckRun) -> Option<String> {
+    crate::flakes::error_signature(run.output.text.as_deref().unwrap_or_default())
+}
+
+/// Return the failed check runs with an error signature, to be recorded.
+fn ci_failures(
+    check_runs: &[octocrab::models::checks::CheckRun],
+    date: chrono::NaiveDate,
+    pull: Option<u64>,
+) -> Vec<crate::flakes::CiFailure> {
+    check_runs
+        .iter()
+        .filter(|r| is_failed(r))
+        .filter_map(|r| {
+            Some(crate::flakes::CiFailure {
+                date,
+                task: r.name.clone(),
+                signature: run_signature(r)?,
+                pull,
+                run_id: r.id.into_inner(),
+            })
+        })
+        .collect()
+}
+
+/// Return the section for the check runs, showing the latest failure, if any. Hidden on quiet pull
+/// requests.
+async fn ci_failed_section<'a>(
+    ctx: &Context,
+    slug: &str,
+    check_runs: &'a [octocrab::models::checks::CheckRun],
+    success: bool,
+    quiet: bool,
+) -> CiFailedSection<'a> {
+    let failure = if success || quiet {
+        None
+    } else {
+        latest_failure(check_runs)
+    };
+    let flaky = match failure {
+        Some((_, run)) => flaky(ctx, slug, run).await,
+        None => None,
+    };
+    CiFailedSection { failure, flaky }
+}
+
+/// Return how often the failure of the check run was seen, if it looks intermittent.
+async fn flaky(
+    ctx: &Context,
+    slug: &str,
+    run: &octocrab::models::checks::CheckRun,
+) -> Option<Flaky> {
+    let signature = run_signature(run)?;
+    let count = ctx
+        .state
+        .read(|s| {
+            crate::flakes::flakes(s.ci_failures.get(slug).map_or(&[], |f| f))
+                .into_iter()
+                .find(|f| f.task == run.name && f.signature == signature)
+                .map(|f| f.count)
+        })
+        .await?;
+    Some(Flaky {
+        count,
+        dashboard: ctx
+            .config
+            .dashboard_url
+            .as_ref()
+            .map(|url| format!("{}/flakes/{slug}", url.trim_end_matches('/'))),
+    })
+}
+
+/// The number of lines at the end of the log to show.
+const LOG_EXCERPT_LINES: usize = 20;
+
+/// Return the end of the log, made safe to embed in a code block of the metadata comment.
+fn log_excerpt(log: &str) -> String {
+    let lines = log
+        .lines()
+        .filter(|l| !l.trim().is_empty())
+        .collect::<Vec<_>>();
+    lines[lines.len().saturating_sub(LOG_EXCERPT_LINES)..]
+        .iter()
+        .map(|l| {
+            // The metadata comment is split into sections at html comments
+            l.replace("<!--", "<!- -").replace("```", "` ` `")
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// The latest CI failure of the pull request, updated in place on each failure and hidden once
+/// the CI passes.
+struct CiFailedSection<'a> {
+    failure: Option<(FailureCategory, &'a octocrab::models::checks::CheckRun)>,
+    /// Set, if the same failure was also seen on the default branch or in other pull requests.
+    flaky: Option<Flaky>,
+}
+
+struct Flaky {
+    /// The number of times the failure was seen in the flake window.
+    count: usize,
+    /// The url of the flake dashboard of the repo.
+    dashboard: Option<String>,
+}
+
+impl util::Section for CiFailedSection<'_> {
+    fn id(&self) -> util::IdComment {
+        util::IdComment::SecCiFailed
+    }
+
+    fn render(&self) -> Option<String> {
+        let (category, run) = self.failure?;
+        let log = log_excerpt(run.output.text.as_deref().unwrap_or_default());
+        let mut text = format!(
+            "🚧 At least one of the CI tasks failed (category: {category}).\n\nTask `{task}`: {url}\n",
+            category = category.name(),
+            task = run.name,
+            url = run.html_url.as_deref().unwrap_or_default(),
+        );
+        if let Some(flaky) = &self.flaky {
+            text += &format!(
+                "\n⚠️ Likely intermittent: seen {count} times in the last {days} days, also in other pull requests or on the default branch.",
+                count = flaky.count,
+                days = crate::flakes::FLAKE_WINDOW_DAYS,
+            );
+            if let Some(url) = &flaky.dashboard {
+                text += &format!(" See the [dashboard]({url}).");
+            }
+            text += "\n";
+        }
+        if !log.is_empty() {
+            text += &format!(
+                "\n<details><summary>Log excerpt</summary>\n\n```\n{log}\n```\n\n</details>\n"
+            );
+        }
+        text += r#"
+Make sure to run all tests locally, according to the documentation.
 
 Possibly this is due to a silent merge conflict (the changes in this pull request being
 incompatible with the current code in the target branch). If so, make sure to rebase on the latest
 commit of the target branch.
 
 Leave a comment here, if you need help tracking down a confusing failure.
-"#,
-                                first_fail.html_url.clone().unwrap_or_default()
-                            );
-                            issues_api.create_comment(pull_number, comment).await?;
-                        }
-                    }
-                }
-            }
-            _ => {}
+"#;
+        Some(text)
+    }
+}
+
+/// Return the check runs of the suite. The suite may be gone by the time the event is handled
+/// (e.g. after a re-run), so fall back to the check runs of its head commit.
+async fn list_check_runs(
+    ctx: &Context,
+    repo_user: &str,
+    repo_name: &str,
+    suite_id: u64,
+    head: &str,
+) -> Result<Vec<octocrab::models::checks::CheckRun>> {
+    let checks_api = ctx.octocrab.checks(repo_user, repo_name);
+    match checks_api
+        .list_check_runs_in_a_check_suite(suite_id.into())
+        .per_page(99)
+        .send()
+        .await
+    {
+        Ok(runs) => Ok(runs.check_runs),
+        Err(err) if util::is_not_found(&err) => {
+            tracing::info!("... check suite {suite_id} not found, use the check runs of {head}");
+            Ok(util::with_retry(|| {
+                checks_api
+                    .list_check_runs_for_git_ref(head.to_string().into())
+                    .per_page(99)
+                    .send()
+            })
+            .await?
+            .check_runs)
         }
-        Ok(())
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Return the number of the pull request in the payload, which is only set if the head branch is
+/// in the same repo.
+fn payload_pull_number(payload: &serde_json::Value) -> Option<u64> {
+    payload["check_suite"]["pull_requests"]
+        .as_array()?
+        .iter()
+        .find(|p| p["base"]["repo"]["id"] == payload["repository"]["id"])?["number"]
+        .as_u64()
+}
+
+/// Hacky way to get the pull number from the Cirrus CI task of the first check run. See also
+/// https://github.com/bitcoin/bitcoin/issues/27178#issuecomment-1503475232
+async fn cirrus_pull_number(check_runs: &[octocrab::models::checks::CheckRun]) -> Option<u64> {
+    let cirrus_task_id = check_runs
+        .first()?
+        .details_url
+        .as_ref()?
+        .split('/')
+        .last()?
+        .to_string();
+
+    let query = format!(
+        r#"{{ "query": "query GetTaskDetailsById($taskId: ID!) {{ task(id: $taskId) {{ id build {{ id repository {{ owner name }} pullRequest }} }} }}", "variables": {{ "taskId": "{}" }} }}"#,
+        cirrus_task_id
+    );
+
+    let response = reqwest::Client::new()
+        .post("https://api.cirrus-ci.com/graphql")
+        .header("Content-Type", "application/json")
+        .body(query)
+        .send()
+        .await;
+    let json = match response {
+        Ok(response) => response.json::<serde_json::Value>().await,
+        Err(err) => Err(err),
+    };
+    match json {
+        Ok(json) => json["data"]["task"]["build"]["pullRequest"].as_u64(),
+        Err(err) => {
+            tracing::warn!("... Cirrus CI lookup of task {cirrus_task_id} failed: {err}");
+            None
+        }
+    }
+}
+
+/// Return the number of the open pull request with the head commit, if any. The pull requests of a
+/// commit also include the merged ones that contain it.
+fn pull_by_head(pulls: &[serde_json::Value], head: &str) -> Option<u64> {
+    pulls
+        .iter()
+        .find(|p| p["state"] == "open" && p["head"]["sha"] == head)?["number"]
+        .as_u64()
+}
+
+/// Return the number of the pull request the check suite ran on, or None if it ran on a branch.
+async fn find_pull_number(
+    ctx: &Context,
+    repo_user: &str,
+    repo_name: &str,
+    payload: &serde_json::Value,
+    check_runs: &[octocrab::models::checks::CheckRun],
+    head: &str,
+) -> Result<Option<u64>> {
+    if let Some(number) = payload_pull_number(payload) {
+        return Ok(Some(number));
+    }
+    if let Some(number) = cirrus_pull_number(check_runs).await {
+        return Ok(Some(number));
+    }
+    // Slow path, when the CI is not on Cirrus, or the task lacks the pull number
+    tracing::info!("... look up the pull request of {head}");
+    let route = format!("/repos/{repo_user}/{repo_name}/commits/{head}/pulls");
+    let pulls = ctx
+        .octocrab
+        .get::<Vec<serde_json::Value>, _, ()>(&route, None)
+        .await?;
+    Ok(pull_by_head(&pulls, head))
+}
+
+/// A note on the pull request, while the CI of the target branch is failing.
+struct BaseCiSection {
+    failing: bool,
+}
+
+impl util::Section for BaseCiSection {
+    fn id(&self) -> util::IdComment {
+        util::IdComment::SecBaseCi
+    }
+
+    fn render(&self) -> Option<String> {
+        self.failing.then(|| {
+            "The CI of the target branch is currently failing, so the CI failure of this pull request may be unrelated.\n".to_string()
+        })
+    }
+}
+
+/// Whether the comparison of a branch with a commit says that the commit is on the branch.
+fn is_on_branch_comparison(comparison: &serde_json::Value) -> bool {
+    matches!(comparison["status"].as_str(), Some("identical" | "behind"))
+}
+
+/// Return whether the commit is on the branch of the repo.
+async fn is_on_branch(
+    ctx: &Context,
+    repo_user: &str,
+    repo_name: &str,
+    branch: &str,
+    commit: &str,
+) -> Result<bool> {
+    let route = format!("/repos/{repo_user}/{repo_name}/compare/{branch}...{commit}");
+    match ctx
+        .octocrab
+        .get::<serde_json::Value, _, ()>(&route, None)
+        .await
+    {
+        Ok(comparison) => Ok(is_on_branch_comparison(&comparison)),
+        // The commit is only in a fork
+        Err(err) if util::is_not_found(&err) => Ok(false),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Remember whether the CI of the default branch is failing. Once it recovers, show the held back
+/// failures of the pull requests that failed in the meantime and remove the note.
+async fn handle_base_ci(
+    ctx: &Context,
+    repo_user: &str,
+    repo_name: &str,
+    slug: &str,
+    success: bool,
+) -> Result<()> {
+    tracing::info!("... base branch CI success: {success}");
+    let recovered = ctx
+        .state
+        .update(|s| s.record_base_ci_result(slug, success))
+        .await?;
+    for pull_number in recovered {
+        reprocess_pull(ctx, repo_user, repo_name, slug, pull_number).await?;
+    }
+    Ok(())
+}
+
+/// Update the CI failure of the pull request from the check runs of its head commit, and remove
+/// the note about the failing base branch CI.
+async fn reprocess_pull(
+    ctx: &Context,
+    repo_user: &str,
+    repo_name: &str,
+    slug: &str,
+    pull_number: u64,
+) -> Result<()> {
+    let issues_api = ctx.octocrab.issues(repo_user, repo_name);
+    let pulls_api = ctx.octocrab.pulls(repo_user, repo_name);
+    let pull = util::with_retry(|| pulls_api.get(pull_number)).await?;
+    if pull.state != Some(octocrab::models::IssueState::Open) {
+        return Ok(());
+    }
+    tracing::info!("... {pull_number} reprocess after the base branch CI recovered");
+    let checks_api = ctx.octocrab.checks(repo_user, repo_name);
+    let check_runs = util::with_retry(|| {
+        checks_api
+            .list_check_runs_for_git_ref(pull.head.sha.clone().into())
+            .per_page(99)
+            .send()
+    })
+    .await?
+    .check_runs;
+    let success = !check_runs.iter().any(is_failed);
+    let mut cmt = util::get_metadata_sections(&ctx.octocrab, &issues_api, pull_number).await?;
+    let quiet = util::is_quiet(
+        pull.labels.as_deref().unwrap_or_default(),
+        pull.body.as_deref(),
+    );
+    let section = ci_failed_section(ctx, slug, &check_runs, success, quiet).await;
+    util::update_metadata_comment(&issues_api, &mut cmt, &section, ctx.dry_run).await?;
+    util::update_metadata_comment(
+        &issues_api,
+        &mut cmt,
+        &BaseCiSection { failing: false },
+        ctx.dry_run,
+    )
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pull_number_lookup() {
+        let payload = serde_json::json!({
+            "repository": {"id": 1},
+            "check_suite": {"pull_requests": [
+                {"number": 7, "base": {"repo": {"id": 2}}},
+                {"number": 8, "base": {"repo": {"id": 1}}},
+            ]},
+        });
+        assert_eq!(payload_pull_number(&payload), Some(8));
+        let fork = serde_json::json!({
+            "repository": {"id": 1},
+            "check_suite": {"pull_requests": []},
+        });
+        assert_eq!(payload_pull_number(&fork), None);
+
+        let pull = |number, state, head: &str| serde_json::json!({"number": number, "state": state, "head": {"sha": head}});
+        let pulls = [
+            pull(1, "closed", "bb"),
+            pull(2, "open", "aa"),
+            pull(3, "open", "bb"),
+        ];
+        assert_eq!(pull_by_head(&pulls, "bb"), Some(3));
+        assert_eq!(pull_by_head(&pulls, "cc"), None);
+        // A commit of the default branch, which came in with a merged pull request
+        assert_eq!(pull_by_head(&[pull(4, "closed", "dd")], "dd"), None);
+    }
+
+    #[test]
+    fn test_base_ci() {
+        for (status, on_branch) in [
+            ("identical", true),
+            ("behind", true),
+            ("ahead", false),
+            ("diverged", false),
+        ] {
+            let comparison = serde_json::json!({"status": status});
+            assert_eq!(is_on_branch_comparison(&comparison), on_branch);
+        }
+
+        let mut state = crate::state::State::default();
+        assert!(!state.note_failure_on_broken_base("o/r", 1));
+        assert_eq!(state.record_base_ci_result("o/r", false), Vec::<u64>::new());
+        assert!(state.note_failure_on_broken_base("o/r", 1));
+        assert!(state.note_failure_on_broken_base("o/r", 2));
+        assert!(state.note_failure_on_broken_base("o/r", 1));
+        assert!(!state.note_failure_on_broken_base("o/other", 1));
+        // The pulls to reprocess on recovery, each once
+        assert_eq!(state.record_base_ci_result("o/r", true), [1, 2]);
+        assert_eq!(state.record_base_ci_result("o/r", true), Vec::<u64>::new());
+        assert!(!state.note_failure_on_broken_base("o/r", 1));
+    }
+
+    fn run(name: &str, conclusion: &str, text: &str) -> octocrab::models::checks::CheckRun {
+        serde_json::from_value(serde_json::json!({
+            "id": 1, "node_id": "", "details_url": null, "head_sha": "aa", "url": "",
+            "html_url": null, "conclusion": conclusion, "started_at": null,
+            "completed_at": null, "name": name,
+            "output": {"title": null, "summary": null, "text": text, "annotations_count": 0, "annotations_url": ""},
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_failure_categories() {
+        let runs = [
+            run("lint [bookworm]", "failure", ""),
+            run("tidy", "failure", "clang-tidy-18 found errors"),
+            run("ASan", "failure", "make: *** [Makefile:1] Error 1"),
+            run("TSan", "success", "make: *** [Makefile:1] Error 1"),
+        ];
+        assert_eq!(
+            failure_categories(&runs),
+            [FailureCategory::Build, FailureCategory::Lint]
+        );
+        assert_eq!(
+            classify_log("macOS native", "test_runner.py failed"),
+            FailureCategory::Tests
+        );
+        assert_eq!(
+            failure_categories(&[run("fuzzer", "timed_out", "")]),
+            [FailureCategory::Tests]
+        );
+
+        assert_eq!(
+            category_label_plan(
+                &["CI failed", "CI failed: tests"],
+                &[FailureCategory::Build]
+            ),
+            [("CI failed: build", true), ("CI failed: tests", false)]
+        );
+        assert_eq!(
+            category_label_plan(&["CI failed: lint"], &[]),
+            [("CI failed: lint", false)]
+        );
+        assert!(category_label_plan(&["CI failed: lint"], &[FailureCategory::Lint]).is_empty());
+
+        let section = CiFailedSection {
+            failure: latest_failure(&runs),
+            flaky: None,
+        };
+        let text = util::Section::render(&section).unwrap();
+        assert!(text.starts_with(
+            "🚧 At least one of the CI tasks failed (category: lint).\n\nTask `lint [bookworm]`: \n\n"
+        ));
+        let section = CiFailedSection {
+            flaky: Some(Flaky {
+                count: 3,
+                dashboard: Some("https://example.org/flakes/o/r".to_string()),
+            }),
+            ..section
+        };
+        let text = util::Section::render(&section).unwrap();
+        assert!(text.contains("\n⚠️ Likely intermittent: seen 3 times in the last 30 days, also in other pull requests or on the default branch. See the [dashboard](https://example.org/flakes/o/r).\n"));
+        assert_eq!(
+            latest_failure(&[run("TSan", "failure", ""), runs[2].clone()])
+                .map(|(c, r)| (c, &r.name[..])),
+            Some((FailureCategory::Build, "ASan"))
+        );
+        assert_eq!(
+            util::Section::render(&CiFailedSection {
+                failure: None,
+                flaky: None
+            }),
+            None
+        );
+
+        let log = format!(
+            "{}\n\n<!-- x -->\n```",
+            (0..30)
+                .map(|i| i.to_string())
+                .collect::<Vec<_>>()
+                .join("\n")
+        );
+        let excerpt = log_excerpt(&log);
+        assert_eq!(excerpt.lines().count(), LOG_EXCERPT_LINES);
+        assert!(excerpt.starts_with("12\n"));
+        assert!(excerpt.ends_with("<!- - x -->\n` ` `"));
+    }
+
+    #[test]
+    fn test_section_snapshots() {
+        let mut failed = run(
+            "ASan + LSan + UBSan + integer, no depends, USDT",
+            "failure",
+            "[ 42%] Building CXX object src/wallet/CMakeFiles/wallet.dir/wallet.cpp.o\n\
+             src/wallet/wallet.cpp:123:5: error: use of undeclared identifier 'foo'\n\
+             1 error generated.\n\
+             ninja: build stopped: subcommand failed.\n",
+        );
+        failed.html_url = Some("https://github.com/o/r/runs/1".to_string());
+        let runs = [run("lint", "success", ""), failed];
+        let section = CiFailedSection {
+            failure: latest_failure(&runs),
+            flaky: None,
+        };
+        insta::assert_snapshot!("ci_failed", util::SECTION_REGISTRY.render(&section));
+        let section = CiFailedSection {
+            flaky: Some(Flaky {
+                count: 4,
+                dashboard: Some("https://drahtbot.example.org/flakes/o/r".to_string()),
+            }),
+            ..section
+        };
+        insta::assert_snapshot!("ci_failed_flaky", util::SECTION_REGISTRY.render(&section));
+        insta::assert_snapshot!(
+            "base_ci_failing",
+            util::SECTION_REGISTRY.render(&BaseCiSection { failing: true })
+        );
     }
 }
@@ -0,0 +1,316 @@
+use super::{Feature, FeatureMeta};
+use crate::errors::DrahtBotError;
+use crate::errors::Result;
+use crate::Context;
+use crate::GitHubEvent;
+use std::collections::HashSet;
+use std::sync::{Mutex, OnceLock};
+
+use async_trait::async_trait;
+
+pub struct ConflictsFeature {
+    meta: FeatureMeta,
+}
+
+impl ConflictsFeature {
+    pub fn new() -> Self {
+        Self {
+            meta: FeatureMeta::new(
+                "Conflicts",
+                "Update the conflicts section of a pull request right after it was pushed to, instead of waiting for the next full conflicts run.",
+                vec![GitHubEvent::PullRequest],
+            ),
+        }
+    }
+}
+
+/// The pulls that wait for a conflicts run. A pull is only queued once, so that a burst of pushes
+/// results in a single run.
+fn queued() -> &'static Mutex<HashSet<String>> {
+    static QUEUED: OnceLock<Mutex<HashSet<String>>> = OnceLock::new();
+    QUEUED.get_or_init(|| Mutex::new(HashSet::new()))
+}
+
+/// Runs share the scratch dir of the conflicts tool, so only one may run at a time. The tool also
+/// locks the dir itself, to wait for the runs from cron.
+fn running() -> &'static tokio::sync::Mutex<()> {
+    static RUNNING: OnceLock<tokio::sync::Mutex<()>> = OnceLock::new();
+    RUNNING.get_or_init(|| tokio::sync::Mutex::new(()))
+}
+
+/// Return the arguments to run the conflicts tool for a single pull request.
+fn conflicts_args(command: &[String], pull_id: &str, dry_run: bool) -> Vec<String> {
+    let mut args = command[1..].to_vec();
+    args.push(format!("--pull-id={pull_id}"));
+    if dry_run {
+        args.push("--dry-run".to_string());
+    }
+    args
+}
+
+async fn run_conflicts(command: Vec<String>, token: String, pull_id: String, dry_run: bool) {
+    let _guard = running().lock().await;
+    // Pushes from now on need another run
+    queued().lock().unwrap().remove(&pull_id);
+    tracing::info!("... Run conflicts for {pull_id}");
+    let status = tokio::process::Command::new(&command[0])
+        .args(conflicts_args(&command, &pull_id, dry_run))
+        .env("GITHUB_ACCESS_TOKEN", token)
+        .status()
+        .await;
+    match status {
+        Ok(status) if status.success() => {}
+        Ok(status) => tracing::error!("Conflicts run for {pull_id} failed: {status}"),
+        Err(err) => tracing::error!("Conflicts run for {pull_id} failed to start: {err}"),
+    }
+}
+
+/// Whether the last conflicts run is older than the maximum age.
+fn is_outdated(
+    run: &::state::ConflictsRun,
+    now: chrono::DateTime<chrono::Utc>,
+    max_age_hours: u32,
+) -> bool {
+    now - run.date > chrono::Duration::hours(max_age_hours.into())
+}
+
+/// The pull requests of the repo that conflicted with others as of the last conflicts run.
+fn pulls_with_conflicts(facts: &::state::Facts, repo_slug: &str) -> Vec<u64> {
+    let prefix = format!("{repo_slug}/");
+    facts
+        .pulls
+        .iter()
+        .filter(|(_, f)| f.conflicts.as_ref().is_some_and(|c| !c.is_empty()))
+        .filter_map(|(pull_id, _)| pull_id.strip_prefix(&prefix)?.parse().ok())
+        .collect()
+}
+
+/// A note below the conflicts section, while the conflicts data of the repo is outdated.
+struct ConflictsOutdatedSection<'a> {
+    run: Option<&'a ::state::ConflictsRun>,
+}
+
+impl util::Section for ConflictsOutdatedSection<'_> {
+    fn id(&self) -> util::IdComment {
+        util::IdComment::SecConflictsOutdated
+    }
+
+    fn render(&self) -> Option<String> {
+        let run = self.run?;
+        Some(format!(
+            "⚠️ The conflicts were last computed on {date} against base commit {base}, so they may be outdated.\n",
+            date = run.date.format("%Y-%m-%d %H:%M UTC"),
+            base = &run.base[..run.base.len().min(12)],
+        ))
+    }
+}
+
+/// Add a note to the pull requests with conflicts, once the conflicts data of their repo is older
+/// than `conflicts_max_age_hours`, and remove it once a conflicts run updated it again.
+pub async fn check_conflicts_freshness(ctx: &Context) {
+    if ctx.pull_facts.is_none() {
+        return;
+    }
+    let repos = ctx
+        .config
+        .repositories
+        .iter()
+        .filter_map(|r| Some((&r.repo_slug, r.conflicts_max_age_hours?)))
+        .collect::<Vec<_>>();
+    if repos.is_empty() {
+        return;
+    }
+    let facts = match ctx.read_pull_facts().await {
+        Ok(facts) => facts.unwrap_or_default(),
+        Err(err) => {
+            tracing::error!("When reading the pull facts: {err:?}");
+            return;
+        }
+    };
+    let now = chrono::Utc::now();
+    for (slug, max_age_hours) in repos {
+        // Without a recorded run, the conflicts tool may not write the pull facts yet
+        let Some(run) = facts.conflicts_runs.get(slug) else {
+            continue;
+        };
+        let outdated = is_outdated(run, now, max_age_hours);
+        let changed = ctx
+            .state
+            .update(|s| {
+                s.record_conflicts_outdated(slug, outdated, pulls_with_conflicts(&facts, slug))
+            })
+            .await;
+        let pulls = match changed {
+            Ok(Some(pulls)) => pulls,
+            Ok(None) => continue,
+            Err(err) => {
+                tracing::error!("When writing the state: {err:?}");
+                continue;
+            }
+        };
+        if outdated {
+            tracing::error!(
+                "Conflicts data of {slug} is outdated, last run on {date} against {base}",
+                date = run.date,
+                base = run.base,
+            );
+        } else {
+            tracing::info!("Conflicts data of {slug} is up to date again");
+        }
+        let section = ConflictsOutdatedSection {
+            run: outdated.then_some(run),
+        };
+        for number in pulls {
+            if let Err(err) = update_outdated_note(ctx, slug, number, &section).await {
+                tracing::error!("When updating the conflicts note on {slug}/{number}: {err:?}");
+            }
+        }
+    }
+}
+
+async fn update_outdated_note(
+    ctx: &Context,
+    slug: &str,
+    number: u64,
+    section: &ConflictsOutdatedSection<'_>,
+) -> Result<()> {
+    tracing::info!("... {slug}/{number} update the conflicts note");
+    let util::Slug { owner, repo } = slug.parse().map_err(anyhow::Error::msg)?;
+    let issues_api = ctx.octocrab.issues(owner, repo);
+    let mut cmt = util::get_metadata_sections(&ctx.octocrab, &issues_api, number).await?;
+    util::update_metadata_comment(&issues_api, &mut cmt, section, ctx.dry_run).await?;
+    Ok(())
+}
+
+#[async_trait]
+impl Feature for ConflictsFeature {
+    fn meta(&self) -> &FeatureMeta {
+        &self.meta
+    }
+
+    async fn handle(
+        &self,
+        ctx: &Context,
+        event: &GitHubEvent,
+        payload: &serde_json::Value,
+    ) -> Result<()> {
+        let action = payload["action"]
+            .as_str()
+            .ok_or(DrahtBotError::KeyNotFound)?;
+
+        let repo_user = payload["repository"]["owner"]["login"]
+            .as_str()
+            .ok_or(DrahtBotError::KeyNotFound)?;
+
+        let repo_name = payload["repository"]["name"]
+            .as_str()
+            .ok_or(DrahtBotError::KeyNotFound)?;
+
+        tracing::info!("Handling: {repo_user}/{repo_name} {event}::{action}");
+        match event {
+            GitHubEvent::PullRequest if action == "synchronize" => {
+                // https://docs.github.com/en/developers/webhooks-and-events/webhooks/webhook-events-and-payloads#pull_request
+                let Some(config_repo) = ctx
+                    .config
+                    .repositories
+                    .iter()
+                    .find(|r| r.repo_slug == format!("{repo_user}/{repo_name}"))
+                else {
+                    return Ok(());
+                };
+                if config_repo.conflicts_command.is_empty() {
+                    return Ok(());
+                }
+                let pr_number = payload["number"]
+                    .as_u64()
+                    .ok_or(DrahtBotError::KeyNotFound)?;
+                let pull_id = format!("{repo_user}/{repo_name}/{pr_number}");
+                if !queued().lock().unwrap().insert(pull_id.clone()) {
+                    tracing::info!("... Conflicts run for {pull_id} already queued");
+                    return Ok(());
+                }
+                // A run merges the pull with all other open pulls, which takes longer than GitHub
+                // waits for the webhook response, so do not wait for it.
+                actix_web::rt::spawn(run_conflicts(
+                    config_repo.conflicts_command.clone(),
+                    ctx.token.clone(),
+                    pull_id,
+                    ctx.dry_run,
+                ));
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_conflicts_args() {
+        let command = ["conflicts", "--config-file=c.yml"].map(String::from);
+        assert_eq!(
+            conflicts_args(&command, "owner/repo/1", false),
+            ["--config-file=c.yml", "--pull-id=owner/repo/1"]
+        );
+        assert_eq!(
+            conflicts_args(&command, "owner/repo/1", true),
+            ["--config-file=c.yml", "--pull-id=owner/repo/1", "--dry-run"]
+        );
+    }
+
+    #[test]
+    fn test_conflicts_freshness() {
+        let run = ::state::ConflictsRun {
+            date: "2024-01-01T00:00:00Z".parse().unwrap(),
+            base: "0123456789abcdef".to_string(),
+        };
+        assert!(!is_outdated(
+            &run,
+            "2024-01-02T00:00:00Z".parse().unwrap(),
+            24
+        ));
+        assert!(is_outdated(
+            &run,
+            "2024-01-02T00:01:00Z".parse().unwrap(),
+            24
+        ));
+
+        let mut facts = ::state::Facts::default();
+        facts.pull_mut("owner/repo/1").conflicts = Some(vec!["owner/repo/2".to_string()]);
+        facts.pull_mut("owner/repo/2").conflicts = Some(vec!["owner/repo/1".to_string()]);
+        facts.pull_mut("owner/repo/3").conflicts = Some(vec![]);
+        facts.pull_mut("owner/repo/4").conflicts = None;
+        facts.pull_mut("owner/repo2/5").conflicts = Some(vec!["owner/repo2/6".to_string()]);
+        assert_eq!(pulls_with_conflicts(&facts, "owner/repo"), [1, 2]);
+
+        let mut state = crate::state::State::default();
+        assert_eq!(
+            state.record_conflicts_outdated("owner/repo", false, vec![1]),
+            None
+        );
+        assert_eq!(
+            state.record_conflicts_outdated("owner/repo", true, vec![1, 2]),
+            Some(vec![1, 2])
+        );
+        assert_eq!(
+            state.record_conflicts_outdated("owner/repo", true, vec![1]),
+            None
+        );
+        assert_eq!(
+            state.record_conflicts_outdated("owner/repo", false, vec![]),
+            Some(vec![1, 2])
+        );
+
+        insta::assert_snapshot!(
+            "conflicts_outdated",
+            util::SECTION_REGISTRY.render(&ConflictsOutdatedSection { run: Some(&run) })
+        );
+        assert_eq!(
+            util::SECTION_REGISTRY.render(&ConflictsOutdatedSection { run: None }),
+            ""
+        );
+    }
+}
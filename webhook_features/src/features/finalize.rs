@@ -0,0 +1,106 @@
+use super::{Feature, FeatureMeta};
+use crate::errors::DrahtBotError;
+use crate::errors::Result;
+use crate::Context;
+use crate::GitHubEvent;
+use async_trait::async_trait;
+
+pub struct FinalizeFeature {
+    meta: FeatureMeta,
+}
+
+impl FinalizeFeature {
+    pub fn new() -> Self {
+        Self {
+            meta: FeatureMeta::new(
+                "Finalize",
+                "Clean up bot labels and queued work on closed pull requests and summarize merged ones.",
+                vec![GitHubEvent::PullRequest],
+            ),
+        }
+    }
+}
+
+#[async_trait]
+impl Feature for FinalizeFeature {
+    fn meta(&self) -> &FeatureMeta {
+        &self.meta
+    }
+
+    async fn handle(
+        &self,
+        ctx: &Context,
+        event: &GitHubEvent,
+        payload: &serde_json::Value,
+    ) -> Result<()> {
+        let action = payload["action"]
+            .as_str()
+            .ok_or(DrahtBotError::KeyNotFound)?;
+
+        let repo_user = payload["repository"]["owner"]["login"]
+            .as_str()
+            .ok_or(DrahtBotError::KeyNotFound)?;
+
+        let repo_name = payload["repository"]["name"]
+            .as_str()
+            .ok_or(DrahtBotError::KeyNotFound)?;
+
+        tracing::info!("Handling: {repo_user}/{repo_name} {event}::{action}");
+        match event {
+            GitHubEvent::PullRequest if action == "closed" => {
+                // https://docs.github.com/en/developers/webhooks-and-events/webhooks/webhook-events-and-payloads#pull_request
+                let pr_number = payload["number"]
+                    .as_u64()
+                    .ok_or(DrahtBotError::KeyNotFound)?;
+                let pull_id = format!("{repo_user}/{repo_name}/{pr_number}");
+                ctx.state.update(|s| s.forget_pull(&pull_id)).await?;
+                ctx.update_pull_facts(move |f| f.forget(&pull_id)).await?;
+                let Some(config_repo) = ctx
+                    .config
+                    .repositories
+                    .iter()
+                    .find(|r| r.repo_slug == format!("{repo_user}/{repo_name}"))
+                else {
+                    return Ok(());
+                };
+                let merged = payload["pull_request"]["merged"]
+                    .as_bool()
+                    .ok_or(DrahtBotError::KeyNotFound)?;
+                let labels = payload["pull_request"]["labels"]
+                    .as_array()
+                    .ok_or(DrahtBotError::KeyNotFound)?
+                    .iter()
+                    .filter_map(|l| l["name"].as_str())
+                    .collect::<Vec<_>>();
+                // Removing the label of a work request (e.g. a guix build) also cancels the work,
+                // because the workers only pick up open pull requests with the label.
+                let mut plan = config_repo
+                    .bot_labels
+                    .iter()
+                    .filter(|l| labels.contains(&l.as_str()))
+                    .map(|label| util::Edit::RemoveLabel {
+                        number: pr_number,
+                        label: label.to_string(),
+                    })
+                    .collect::<Vec<_>>();
+                if merged && config_repo.merge_summary {
+                    let text = super::summary_comment::merge_summary_comment(
+                        ctx, repo_user, repo_name, pr_number,
+                    )
+                    .await?;
+                    plan.push(util::Edit::Comment {
+                        number: pr_number,
+                        text,
+                    });
+                }
+                let slug = util::Slug {
+                    owner: repo_user.to_string(),
+                    repo: repo_name.to_string(),
+                };
+                super::apply_plan(ctx, &slug, &plan, "finalize").await?;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
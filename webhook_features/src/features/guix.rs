@@ -0,0 +1,437 @@
+use super::{Feature, FeatureMeta};
+use crate::errors::DrahtBotError;
+use crate::errors::Result;
+use crate::Context;
+use crate::GitHubEvent;
+use std::collections::{HashMap, VecDeque};
+use std::path::PathBuf;
+use std::sync::{Mutex, OnceLock};
+
+use async_trait::async_trait;
+
+/// The label to request a guix build, removed by the guix script once the results are posted.
+pub const GUIX_LABEL: &str = "DrahtBot Guix build requested";
+
+pub struct GuixFeature {
+    meta: FeatureMeta,
+}
+
+impl GuixFeature {
+    pub fn new() -> Self {
+        Self {
+            meta: FeatureMeta::new(
+                "Guix",
+                "Start a guix build of a pull request once the guix label is added.",
+                vec![GitHubEvent::PullRequest],
+            ),
+        }
+    }
+}
+
+/// A pull request and the command to build it.
+struct Job {
+    number: u64,
+    command: Vec<String>,
+    /// Passed to the guix script as GITHUB_ACCESS_TOKEN.
+    token: String,
+    dry_run: bool,
+}
+
+/// The scratch folder of a worker. None for the folder given in the command, if no workers are
+/// configured.
+type Worker = Option<PathBuf>;
+
+/// The guix builds of a repo. Each worker runs one build at a time.
+struct Queue {
+    waiting: VecDeque<Job>,
+    running: Vec<u64>,
+    idle: Vec<Worker>,
+    /// The pulls with a queue position in their metadata comment.
+    noted: Vec<u64>,
+}
+
+impl Queue {
+    fn new(workers: &[PathBuf]) -> Self {
+        let idle = if workers.is_empty() {
+            vec![None]
+        } else {
+            // Reversed, so that the first worker is used first
+            workers.iter().rev().cloned().map(Some).collect()
+        };
+        Self {
+            waiting: VecDeque::new(),
+            running: Vec::new(),
+            idle,
+            noted: Vec::new(),
+        }
+    }
+
+    /// Queue the job, unless the pull is already waiting or being built. If a worker is idle,
+    /// return it, to be started.
+    fn push(&mut self, job: Job) -> Option<Worker> {
+        if self.running.contains(&job.number) || self.waiting.iter().any(|j| j.number == job.number)
+        {
+            return None;
+        }
+        self.waiting.push_back(job);
+        self.idle.pop()
+    }
+
+    /// Return the next job for the worker, or mark it idle.
+    fn next(&mut self, worker: &Worker) -> Option<Job> {
+        match self.waiting.pop_front() {
+            Some(job) => {
+                self.running.push(job.number);
+                Some(job)
+            }
+            None => {
+                self.idle.push(worker.clone());
+                None
+            }
+        }
+    }
+
+    fn finish(&mut self, number: u64) {
+        self.running.retain(|n| *n != number);
+    }
+
+    /// Return the queue position (starting at 1) to show on each pull, None to remove it, and
+    /// remember the pulls with a position.
+    fn take_notes(&mut self) -> Vec<(u64, Option<usize>)> {
+        let waiting = self.waiting.iter().map(|j| j.number).collect::<Vec<_>>();
+        let mut notes = std::mem::take(&mut self.noted)
+            .into_iter()
+            .filter(|n| !waiting.contains(n))
+            .map(|n| (n, None))
+            .collect::<Vec<_>>();
+        notes.extend(waiting.iter().enumerate().map(|(i, n)| (*n, Some(i + 1))));
+        self.noted = waiting;
+        notes
+    }
+}
+
+/// The guix builds of each repo (owner/repo).
+fn queues() -> &'static Mutex<HashMap<String, Queue>> {
+    static QUEUES: OnceLock<Mutex<HashMap<String, Queue>>> = OnceLock::new();
+    QUEUES.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
+/// The queue positions are updated by one task at a time, so that an outdated position does not
+/// overwrite a newer one.
+fn notes_lock() -> &'static tokio::sync::Mutex<()> {
+    static NOTES: OnceLock<tokio::sync::Mutex<()>> = OnceLock::new();
+    NOTES.get_or_init(|| tokio::sync::Mutex::new(()))
+}
+
+/// Return the arguments to run the guix script for a single pull request.
+fn guix_args(command: &[String], number: u64, worker: &Worker, dry_run: bool) -> Vec<String> {
+    let mut args = command[1..].to_vec();
+    args.push(format!("--pull_number={number}"));
+    if let Some(folder) = worker {
+        args.push(format!("--guix_folder={}", folder.display()));
+    }
+    if dry_run {
+        args.push("--dry_run".to_string());
+    }
+    args
+}
+
+/// Shows the position in the queue, while the pull waits for a free worker.
+struct GuixQueueSection {
+    position: Option<usize>,
+}
+
+impl util::Section for GuixQueueSection {
+    fn id(&self) -> util::IdComment {
+        util::IdComment::SecGuixQueue
+    }
+
+    fn render(&self) -> Option<String> {
+        let position = self.position?;
+        Some(format!(
+            "⏳ A guix build was requested. It is #{position} in the queue, and starts once a worker is free.\n"
+        ))
+    }
+}
+
+/// Bring the queue positions in the metadata comments of the repo up to date.
+async fn update_notes(api: &octocrab::Octocrab, slug: &util::Slug, dry_run: bool) {
+    let _guard = notes_lock().lock().await;
+    let notes = match queues().lock().unwrap().get_mut(&slug.str()) {
+        Some(queue) => queue.take_notes(),
+        None => return,
+    };
+    let issues_api = api.issues(&slug.owner, &slug.repo);
+    for (number, position) in notes {
+        tracing::info!("... {number} guix queue position: {position:?}");
+        let res = async {
+            let mut cmt = util::get_metadata_sections(api, &issues_api, number).await?;
+            util::update_metadata_comment(
+                &issues_api,
+                &mut cmt,
+                &GuixQueueSection { position },
+                dry_run,
+            )
+            .await
+        }
+        .await;
+        if let Err(err) = res {
+            tracing::error!("When updating the guix queue position on {number}: {err:?}");
+        }
+    }
+}
+
+/// Record in the pull facts, whether a guix build of the pull is queued or running, so that the
+/// other tools can hold back until it finished.
+async fn record_queued(facts: Option<::state::Store>, pull_id: String, queued: bool) {
+    let Some(facts) = facts else {
+        return;
+    };
+    let now = chrono::Utc::now();
+    let res = tokio::task::spawn_blocking(move || {
+        facts.update(|f| f.pull_mut(&pull_id).guix_queued = queued.then_some(now))
+    })
+    .await;
+    if let Err(err) = res.map_err(std::io::Error::other).and_then(|r| r) {
+        tracing::error!("When recording the guix build in the pull facts: {err}");
+    }
+}
+
+/// Run the queued builds of the repo on the worker, until the queue is empty.
+async fn run_worker(
+    api: octocrab::Octocrab,
+    facts: Option<::state::Store>,
+    slug: util::Slug,
+    worker: Worker,
+) {
+    loop {
+        let job = queues()
+            .lock()
+            .unwrap()
+            .get_mut(&slug.str())
+            .and_then(|q| q.next(&worker));
+        let Some(job) = job else {
+            return;
+        };
+        update_notes(&api, &slug, job.dry_run).await;
+        let pull_id = format!("{}/{}", slug.str(), job.number);
+        tracing::info!("... Run guix for {pull_id}");
+        let status = tokio::process::Command::new(&job.command[0])
+            .args(guix_args(&job.command, job.number, &worker, job.dry_run))
+            .env("GITHUB_ACCESS_TOKEN", &job.token)
+            .status()
+            .await;
+        match status {
+            Ok(status) if status.success() => {}
+            Ok(status) => tracing::error!("Guix run for {pull_id} failed: {status}"),
+            Err(err) => tracing::error!("Guix run for {pull_id} failed to start: {err}"),
+        }
+        if let Some(queue) = queues().lock().unwrap().get_mut(&slug.str()) {
+            queue.finish(job.number);
+        }
+        record_queued(facts.clone(), pull_id, false).await;
+    }
+}
+
+/// Queue a build of the pull request, and start it right away if a worker is idle.
+async fn queue_build(
+    ctx: &Context,
+    config_repo: &crate::config::Repo,
+    slug: util::Slug,
+    number: u64,
+) {
+    let job = Job {
+        number,
+        command: config_repo.guix_command.clone(),
+        token: ctx.token.clone(),
+        dry_run: ctx.dry_run,
+    };
+    record_queued(
+        ctx.pull_facts.clone(),
+        format!("{}/{number}", slug.str()),
+        true,
+    )
+    .await;
+    let worker = queues()
+        .lock()
+        .unwrap()
+        .entry(slug.str())
+        .or_insert_with(|| Queue::new(&config_repo.guix_workers))
+        .push(job);
+    match worker {
+        // A build takes far longer than GitHub waits for the webhook response, so do not wait for
+        // it.
+        Some(worker) => {
+            actix_web::rt::spawn(run_worker(
+                ctx.octocrab.clone(),
+                ctx.pull_facts.clone(),
+                slug,
+                worker,
+            ));
+        }
+        None => {
+            tracing::info!("... Guix run for {number} queued");
+            update_notes(&ctx.octocrab, &slug, ctx.dry_run).await;
+        }
+    }
+}
+
+/// Queue the builds of the pull requests that are still labeled, because the queue is only kept
+/// in memory and lost on a restart of the server. Called once on startup.
+pub async fn requeue_labeled(ctx: &Context) {
+    for config_repo in ctx
+        .config
+        .repositories
+        .iter()
+        .filter(|r| !r.guix_command.is_empty())
+    {
+        let Ok(slug) = config_repo.repo_slug.parse::<util::Slug>() else {
+            continue;
+        };
+        let issues_api = ctx.octocrab.issues(&slug.owner, &slug.repo);
+        let issues = util::with_retry(|| async {
+            ctx.octocrab
+                .all_pages(
+                    issues_api
+                        .list()
+                        .labels(&[GUIX_LABEL.to_string()])
+                        .state(octocrab::params::State::Open)
+                        .per_page(100)
+                        .send()
+                        .await?,
+                )
+                .await
+        })
+        .await;
+        let issues = match issues {
+            Ok(issues) => issues,
+            Err(err) => {
+                tracing::error!("When listing the guix requests of {}: {err:?}", slug.str());
+                continue;
+            }
+        };
+        for issue in issues.iter().filter(|i| i.pull_request.is_some()) {
+            tracing::info!("Requeue the guix build of {}/{}", slug.str(), issue.number);
+            queue_build(ctx, config_repo, slug.clone(), issue.number).await;
+        }
+    }
+}
+
+#[async_trait]
+impl Feature for GuixFeature {
+    fn meta(&self) -> &FeatureMeta {
+        &self.meta
+    }
+
+    async fn handle(
+        &self,
+        ctx: &Context,
+        event: &GitHubEvent,
+        payload: &serde_json::Value,
+    ) -> Result<()> {
+        let action = payload["action"]
+            .as_str()
+            .ok_or(DrahtBotError::KeyNotFound)?;
+
+        let repo_user = payload["repository"]["owner"]["login"]
+            .as_str()
+            .ok_or(DrahtBotError::KeyNotFound)?;
+
+        let repo_name = payload["repository"]["name"]
+            .as_str()
+            .ok_or(DrahtBotError::KeyNotFound)?;
+
+        tracing::info!("Handling: {repo_user}/{repo_name} {event}::{action}");
+        match event {
+            GitHubEvent::PullRequest if action == "labeled" => {
+                // https://docs.github.com/en/developers/webhooks-and-events/webhooks/webhook-events-and-payloads#pull_request
+                if payload["label"]["name"].as_str() != Some(GUIX_LABEL) {
+                    return Ok(());
+                }
+                let slug = util::Slug {
+                    owner: repo_user.to_string(),
+                    repo: repo_name.to_string(),
+                };
+                let Some(config_repo) = ctx
+                    .config
+                    .repositories
+                    .iter()
+                    .find(|r| r.repo_slug == slug.str())
+                else {
+                    return Ok(());
+                };
+                if config_repo.guix_command.is_empty() {
+                    return Ok(());
+                }
+                let pr_number = payload["number"]
+                    .as_u64()
+                    .ok_or(DrahtBotError::KeyNotFound)?;
+                queue_build(ctx, config_repo, slug, pr_number).await;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_guix_queue() {
+        let job = |number| Job {
+            number,
+            command: ["python3", "guix.py"].map(String::from).to_vec(),
+            token: "token".to_string(),
+            dry_run: false,
+        };
+        let workers = ["/g0", "/g1"].map(PathBuf::from);
+        let mut queue = Queue::new(&workers);
+        let w0 = queue.push(job(1)).unwrap();
+        assert_eq!(w0, Some(PathBuf::from("/g0")));
+        assert_eq!(queue.next(&w0).unwrap().number, 1);
+        let w1 = queue.push(job(2)).unwrap();
+        assert_eq!(queue.next(&w1).unwrap().number, 2);
+        // Both workers are busy
+        assert!(queue.push(job(3)).is_none());
+        assert!(queue.push(job(4)).is_none());
+        // Already queued or running
+        assert!(queue.push(job(3)).is_none());
+        assert!(queue.push(job(1)).is_none());
+        assert_eq!(queue.take_notes(), [(3, Some(1)), (4, Some(2))]);
+        assert_eq!(queue.take_notes(), [(3, Some(1)), (4, Some(2))]);
+
+        queue.finish(1);
+        assert_eq!(queue.next(&w0).unwrap().number, 3);
+        assert_eq!(queue.take_notes(), [(3, None), (4, Some(1))]);
+        queue.finish(2);
+        assert_eq!(queue.next(&w1).unwrap().number, 4);
+        queue.finish(3);
+        assert!(queue.next(&w0).is_none());
+        assert_eq!(queue.take_notes(), [(4, None)]);
+        assert_eq!(queue.take_notes(), []);
+        // An idle worker is started right away
+        assert_eq!(queue.push(job(1)), Some(w0.clone()));
+
+        assert_eq!(
+            guix_args(&job(1).command, 1, &w0, true),
+            [
+                "guix.py",
+                "--pull_number=1",
+                "--guix_folder=/g0",
+                "--dry_run"
+            ]
+        );
+        assert_eq!(
+            guix_args(&job(1).command, 1, &None, false),
+            ["guix.py", "--pull_number=1"]
+        );
+        assert_eq!(Queue::new(&[]).push(job(1)), Some(None));
+
+        insta::assert_snapshot!(
+            "guix_queue",
+            util::SECTION_REGISTRY.render(&GuixQueueSection { position: Some(3) })
+        );
+    }
+}
@@ -0,0 +1,156 @@
+use super::{Feature, FeatureMeta};
+use crate::errors::DrahtBotError;
+use crate::errors::Result;
+use crate::Context;
+use crate::GitHubEvent;
+use async_trait::async_trait;
+
+pub struct LabelMentionsFeature {
+    meta: FeatureMeta,
+}
+
+impl LabelMentionsFeature {
+    pub fn new() -> Self {
+        Self {
+            meta: FeatureMeta::new(
+                "Label Mentions",
+                "Mention the teams or users configured for a label once it is added to an issue or pull request.",
+                vec![GitHubEvent::Issues, GitHubEvent::PullRequest],
+            ),
+        }
+    }
+}
+
+/// Return the configured mentions of the label that were not made by an earlier comment of the
+/// bot, so that each team or user is mentioned at most once per issue.
+fn new_mentions(mentions: &[String], earlier_comments: &[&str]) -> Vec<String> {
+    let done = earlier_comments
+        .iter()
+        .flat_map(|c| c.split(|ch: char| ch.is_whitespace() || ch == ','))
+        .collect::<Vec<_>>();
+    mentions
+        .iter()
+        .filter(|m| !done.contains(&m.as_str()))
+        .cloned()
+        .collect()
+}
+
+fn mention_comment(label: &str, mentions: &[String]) -> String {
+    format!(
+        "{id}\nThe label `{label}` was added, cc {mentions}",
+        id = util::IdComment::LabelMention.str(),
+        mentions = mentions.join(", "),
+    )
+}
+
+#[async_trait]
+impl Feature for LabelMentionsFeature {
+    fn meta(&self) -> &FeatureMeta {
+        &self.meta
+    }
+
+    async fn handle(
+        &self,
+        ctx: &Context,
+        event: &GitHubEvent,
+        payload: &serde_json::Value,
+    ) -> Result<()> {
+        let action = payload["action"]
+            .as_str()
+            .ok_or(DrahtBotError::KeyNotFound)?;
+
+        let repo_user = payload["repository"]["owner"]["login"]
+            .as_str()
+            .ok_or(DrahtBotError::KeyNotFound)?;
+
+        let repo_name = payload["repository"]["name"]
+            .as_str()
+            .ok_or(DrahtBotError::KeyNotFound)?;
+
+        tracing::info!("Handling: {repo_user}/{repo_name} {event}::{action}");
+        match event {
+            GitHubEvent::Issues | GitHubEvent::PullRequest if action == "labeled" => {
+                // https://docs.github.com/en/developers/webhooks-and-events/webhooks/webhook-events-and-payloads#issues
+                // https://docs.github.com/en/developers/webhooks-and-events/webhooks/webhook-events-and-payloads#pull_request
+                let Some(config_repo) = ctx
+                    .config
+                    .repositories
+                    .iter()
+                    .find(|r| r.repo_slug == format!("{repo_user}/{repo_name}"))
+                else {
+                    return Ok(());
+                };
+                let label = payload["label"]["name"]
+                    .as_str()
+                    .ok_or(DrahtBotError::KeyNotFound)?;
+                let Some(mentions) = config_repo.label_mentions.get(label) else {
+                    return Ok(());
+                };
+                let number = payload["issue"]["number"]
+                    .as_u64()
+                    .or(payload["number"].as_u64())
+                    .ok_or(DrahtBotError::KeyNotFound)?;
+                let issues_api = ctx.octocrab.issues(repo_user, repo_name);
+                let issue = util::with_retry(|| issues_api.get(number)).await?;
+                if issue.state != octocrab::models::IssueState::Open
+                    || util::is_quiet(&issue.labels, issue.body.as_deref())
+                {
+                    return Ok(());
+                }
+                let comments = util::with_retry(|| async {
+                    ctx.octocrab
+                        .all_pages(issues_api.list_comments(number).send().await?)
+                        .await
+                })
+                .await?;
+                let earlier = comments
+                    .iter()
+                    .filter(|c| c.user.login == ctx.bot_username)
+                    .filter_map(|c| c.body.as_deref())
+                    .filter(|b| util::IdComment::LabelMention.is_start_of(b))
+                    .collect::<Vec<_>>();
+                let mentions = new_mentions(mentions, &earlier);
+                if mentions.is_empty() {
+                    return Ok(());
+                }
+                tracing::info!(" ... {number} mention {mentions:?} for label '{label}'");
+                let slug = util::Slug {
+                    owner: repo_user.to_string(),
+                    repo: repo_name.to_string(),
+                };
+                let plan = [util::Edit::Comment {
+                    number,
+                    text: mention_comment(label, &mentions),
+                }];
+                super::apply_plan(ctx, &slug, &plan, "label_mentions").await?;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_new_mentions() {
+        let mentions = ["@org/wallet-reviewers", "@alice"].map(String::from);
+        assert_eq!(new_mentions(&mentions, &[]), mentions);
+        let earlier = mention_comment("GUI", &["@alice".to_string()]);
+        assert_eq!(
+            new_mentions(&mentions, &[&earlier]),
+            ["@org/wallet-reviewers"]
+        );
+        assert_eq!(
+            mention_comment("Wallet", &mentions),
+            format!(
+                "{}\nThe label `Wallet` was added, cc @org/wallet-reviewers, @alice",
+                util::IdComment::LabelMention.str()
+            )
+        );
+        let earlier = mention_comment("Wallet", &mentions);
+        assert_eq!(new_mentions(&mentions, &[&earlier]), Vec::<String>::new());
+    }
+}
@@ -45,7 +45,7 @@ impl Feature for LabelsFeature {
             .as_str()
             .ok_or(DrahtBotError::KeyNotFound)?;
 
-        println!("Handling: {repo_user}/{repo_name} {event}::{action}");
+        tracing::info!("Handling: {repo_user}/{repo_name} {event}::{action}");
         match event {
             GitHubEvent::PullRequest
                 if action == "unlabeled" || action == "opened" || action == "edited" =>
@@ -63,18 +63,13 @@ impl Feature for LabelsFeature {
                     let base_name = payload["pull_request"]["base"]["repo"]["default_branch"]
                         .as_str()
                         .ok_or(DrahtBotError::KeyNotFound)?;
-                    let issues_api = ctx.octocrab.issues(repo_user, repo_name);
                     let pulls_api = ctx.octocrab.pulls(repo_user, repo_name);
-                    let pull = pulls_api.get(pr_number).await?;
-                    apply_labels_one(
-                        &ctx.octocrab,
-                        &issues_api,
-                        config_repo,
-                        base_name,
-                        &pull,
-                        ctx.dry_run,
-                    )
-                    .await?;
+                    let pull = util::with_retry(|| pulls_api.get(pr_number)).await?;
+                    let slug = util::Slug {
+                        owner: repo_user.to_string(),
+                        repo: repo_name.to_string(),
+                    };
+                    apply_labels_one(ctx, &slug, config_repo, base_name, &pull).await?;
                 }
             }
             _ => {}
@@ -84,12 +79,11 @@ impl Feature for LabelsFeature {
 }
 
 async fn apply_labels_one(
-    github: &octocrab::Octocrab,
-    issues_api: &octocrab::issues::IssueHandler<'_>,
+    ctx: &Context,
+    slug: &util::Slug,
     config_repo: &crate::config::Repo,
     base_name: &str,
     pull: &octocrab::models::pulls::PullRequest,
-    dry_run: bool,
 ) -> Result<()> {
     let regs = config_repo.repo_labels.iter().fold(
         std::collections::HashMap::<&String, Vec<regex::Regex>>::new(),
@@ -105,39 +99,50 @@ async fn apply_labels_one(
             acc
         },
     );
+    let mut plan = Vec::new();
     let pull_title = pull.title.as_ref().expect("remote api error");
     let pull_title_trimmed = pull_title.trim();
-    if pull_title_trimmed != pull_title && !dry_run {
-        issues_api
-            .update(pull.number)
-            .title(pull_title_trimmed)
-            .send()
-            .await?;
+    if pull_title_trimmed != pull_title {
+        plan.push(util::Edit::SetTitle {
+            number: pull.number,
+            title: pull_title_trimmed.to_string(),
+        });
     }
     let pull_title = pull_title_trimmed;
-    let labels = github
-        .all_pages(issues_api.list_labels_for_issue(pull.number).send().await?)
-        .await?;
-    if !labels.is_empty() {
-        return Ok(());
-    }
-    let mut new_labels = Vec::new();
-    if pull.base.ref_field != base_name {
-        new_labels.push(config_repo.backport_label.to_string());
-    } else {
-        for (label_name, title_regs) in regs {
-            if title_regs.iter().any(|r| r.is_match(pull_title)) {
-                new_labels.push(label_name.to_string());
-                break;
-            }
+    let issues_api = ctx.octocrab.issues(&slug.owner, &slug.repo);
+    let labels = util::with_retry(|| async {
+        ctx.octocrab
+            .all_pages(issues_api.list_labels_for_issue(pull.number).send().await?)
+            .await
+    })
+    .await?;
+    if labels.is_empty() {
+        if pull.base.ref_field != base_name {
+            plan.push(util::Edit::AddLabel {
+                number: pull.number,
+                label: config_repo.backport_label.to_string(),
+            });
+        } else if let Some(label_name) = regs
+            .into_iter()
+            .find(|(_, title_regs)| title_regs.iter().any(|r| r.is_match(pull_title)))
+            .map(|(label_name, _)| label_name)
+        {
+            plan.push(util::Edit::AddLabel {
+                number: pull.number,
+                label: label_name.to_string(),
+            });
         }
     }
-    if new_labels.is_empty() {
-        return Ok(());
-    }
-    println!(" ... add_to_labels({new_labels:?})");
-    if !dry_run {
-        issues_api.add_labels(pull.number, &new_labels).await?;
+    if let Some(approval) = &config_repo.title_approval {
+        plan = util::approve_plan(
+            &ctx.octocrab,
+            slug,
+            plan,
+            "the title has leading or trailing whitespace.",
+            &approval.policy(),
+            ctx.dry_run,
+        )
+        .await?;
     }
-    Ok(())
+    super::apply_plan(ctx, slug, &plan, "labels").await
 }
@@ -1,7 +1,15 @@
 pub mod ci_status;
+pub mod conflicts;
+pub mod finalize;
+pub mod guix;
+pub mod label_mentions;
 pub mod labels;
+pub mod reopen;
+pub mod review_pause;
+pub mod size_advisor;
 pub mod summary_comment;
 
+use crate::errors::DrahtBotError;
 use crate::errors::Result;
 use crate::Context;
 use crate::GitHubEvent;
@@ -45,3 +53,55 @@ pub trait Feature {
         payload: &serde_json::Value,
     ) -> Result<()>;
 }
+
+/// Apply the edits with util::apply_plan. If the quota deferred any of them, return an error, so
+/// that the event queue retries the feature later.
+pub async fn apply_plan(
+    ctx: &Context,
+    slug: &util::Slug,
+    plan: &[util::Edit],
+    feature: &str,
+) -> Result<()> {
+    let applied = util::apply_plan(&ctx.octocrab, slug, plan, feature, ctx.dry_run).await?;
+    if !applied.deferred.is_empty() {
+        return Err(DrahtBotError::QuotaDeferred.into());
+    }
+    Ok(())
+}
+
+/// Add or remove a label set by the bot, and record it in the pull facts.
+pub async fn sync_label(
+    ctx: &Context,
+    slug: &util::Slug,
+    pull_number: u64,
+    label: &str,
+    add: bool,
+) -> Result<()> {
+    let label = label.to_string();
+    let edit = if add {
+        util::Edit::AddLabel {
+            number: pull_number,
+            label: label.clone(),
+        }
+    } else {
+        util::Edit::RemoveLabel {
+            number: pull_number,
+            label: label.clone(),
+        }
+    };
+    apply_plan(ctx, slug, &[edit], "labels").await?;
+    if ctx.dry_run {
+        return Ok(());
+    }
+    let pull_id = format!("{}/{pull_number}", slug.str());
+    ctx.update_pull_facts(move |f| {
+        let labels = &mut f.pull_mut(&pull_id).labels;
+        if add {
+            labels.insert(label);
+        } else {
+            labels.remove(&label);
+        }
+    })
+    .await?;
+    Ok(())
+}
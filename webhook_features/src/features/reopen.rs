@@ -0,0 +1,216 @@
+use super::{Feature, FeatureMeta};
+use crate::errors::DrahtBotError;
+use crate::errors::Result;
+use crate::Context;
+use crate::GitHubEvent;
+
+use async_trait::async_trait;
+
+pub struct ReopenFeature {
+    meta: FeatureMeta,
+}
+
+impl ReopenFeature {
+    pub fn new() -> Self {
+        Self {
+            meta: FeatureMeta::new(
+                "Reopen On Comment",
+                "Reopen a pull request closed by the stale tool due to inactivity, or an issue closed while waiting for its author, once the author comments (only with --reopen-on-comment).",
+                vec![GitHubEvent::IssueComment],
+            ),
+        }
+    }
+}
+
+/// Whether the comment was made by the author on a closed issue or pull request.
+fn is_author_comment_on_closed(payload: &serde_json::Value) -> bool {
+    let issue = &payload["issue"];
+    issue["state"].as_str() == Some("closed")
+        && issue["user"]["login"].is_string()
+        && issue["user"]["login"] == payload["comment"]["user"]["login"]
+}
+
+/// Whether the issue of the payload has the label.
+fn has_label(issue: &serde_json::Value, label: &str) -> bool {
+    issue["labels"]
+        .as_array()
+        .is_some_and(|labels| labels.iter().any(|l| l["name"] == label))
+}
+
+/// Return the date until which the author may reopen the issue, if it was closed by the stale tool
+/// while waiting for the author. The close must directly follow the comment of the bot, to skip
+/// issues that were reopened and closed again by maintainers.
+fn waiting_reopen_until<'a>(
+    comments: impl Iterator<Item = (&'a str, chrono::DateTime<chrono::Utc>)>,
+    closed_at: chrono::DateTime<chrono::Utc>,
+) -> Option<chrono::NaiveDate> {
+    let (rest, posted) = comments
+        .filter_map(|(body, date)| Some((util::IdComment::WaitingAuthorClose.strip(body)?, date)))
+        .max_by_key(|(_, date)| *date)?;
+    if closed_at < posted || closed_at - posted > chrono::Duration::hours(1) {
+        return None;
+    }
+    util::parse_reopen_until(rest)
+}
+
+#[async_trait]
+impl Feature for ReopenFeature {
+    fn meta(&self) -> &FeatureMeta {
+        &self.meta
+    }
+
+    async fn handle(
+        &self,
+        ctx: &Context,
+        event: &GitHubEvent,
+        payload: &serde_json::Value,
+    ) -> Result<()> {
+        let action = payload["action"]
+            .as_str()
+            .ok_or(DrahtBotError::KeyNotFound)?;
+
+        let repo_user = payload["repository"]["owner"]["login"]
+            .as_str()
+            .ok_or(DrahtBotError::KeyNotFound)?;
+
+        let repo_name = payload["repository"]["name"]
+            .as_str()
+            .ok_or(DrahtBotError::KeyNotFound)?;
+
+        tracing::info!("Handling: {repo_user}/{repo_name} {event}::{action}");
+        match event {
+            GitHubEvent::IssueComment if action == "created" => {
+                // https://docs.github.com/en/developers/webhooks-and-events/webhooks/webhook-events-and-payloads#issue_comment
+                if !ctx.reopen_on_comment || !is_author_comment_on_closed(payload) {
+                    return Ok(());
+                }
+                let number = payload["issue"]["number"]
+                    .as_u64()
+                    .ok_or(DrahtBotError::KeyNotFound)?;
+                let issues_api = ctx.octocrab.issues(repo_user, repo_name);
+                // Only reopen issues and pull requests closed by the bot, not the ones closed by
+                // maintainers
+                let cmt = if payload["issue"]["pull_request"].is_object() {
+                    let cmt =
+                        util::get_metadata_sections(&ctx.octocrab, &issues_api, number).await?;
+                    if cmt
+                        .section(&util::IdComment::SecClosedInactive)
+                        .unwrap_or_default()
+                        .is_empty()
+                    {
+                        return Ok(());
+                    }
+                    Some(cmt)
+                } else {
+                    let closed_at = payload["issue"]["closed_at"]
+                        .as_str()
+                        .and_then(|d| d.parse().ok())
+                        .ok_or(DrahtBotError::KeyNotFound)?;
+                    let comments = util::with_retry(|| async {
+                        ctx.octocrab
+                            .all_pages(issues_api.list_comments(number).send().await?)
+                            .await
+                    })
+                    .await?;
+                    let until = waiting_reopen_until(
+                        comments
+                            .iter()
+                            .filter_map(|c| Some((c.body.as_deref()?, c.created_at))),
+                        closed_at,
+                    );
+                    if until.is_none_or(|u| chrono::Utc::now().date_naive() > u) {
+                        return Ok(());
+                    }
+                    None
+                };
+                tracing::info!(" ... Reopen {number}, after a comment by the author");
+                let slug = util::Slug {
+                    owner: repo_user.to_string(),
+                    repo: repo_name.to_string(),
+                };
+                super::apply_plan(ctx, &slug, &[util::Edit::Reopen { number }], "reopen").await?;
+                if let Some(mut cmt) = cmt {
+                    // Remove the labels added along with the close, e.g. "Up for grabs"
+                    let closed = cmt
+                        .section(&util::IdComment::SecClosedInactive)
+                        .unwrap_or_default();
+                    let labels = util::parse_closed_labels(closed)
+                        .into_iter()
+                        .filter(|l| has_label(&payload["issue"], l))
+                        .map(|l| l.to_string())
+                        .collect::<Vec<_>>();
+                    for label in labels {
+                        super::sync_label(ctx, &slug, number, &label, false).await?;
+                    }
+                    let section = util::ClosedInactiveSection {
+                        closed: None,
+                        labels: Vec::new(),
+                    };
+                    util::update_metadata_comment(&issues_api, &mut cmt, &section, ctx.dry_run)
+                        .await?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_author_comment_on_closed() {
+        let payload = |state: &str, author: &str| {
+            serde_json::json!({
+                "issue": {"number": 1, "state": state, "pull_request": {}, "user": {"login": "alice"}},
+                "comment": {"user": {"login": author}},
+            })
+        };
+        assert!(is_author_comment_on_closed(&payload("closed", "alice")));
+        assert!(!is_author_comment_on_closed(&payload("open", "alice")));
+        assert!(!is_author_comment_on_closed(&payload("closed", "bob")));
+        let mut issue = payload("closed", "alice");
+        issue["issue"]
+            .as_object_mut()
+            .unwrap()
+            .remove("pull_request");
+        assert!(is_author_comment_on_closed(&issue));
+    }
+
+    #[test]
+    fn test_has_label() {
+        let issue = serde_json::json!({"labels": [{"name": "Up for grabs"}]});
+        assert!(has_label(&issue, "Up for grabs"));
+        assert!(!has_label(&issue, "Stale"));
+        assert!(!has_label(&serde_json::json!({}), "Up for grabs"));
+    }
+
+    #[test]
+    fn test_waiting_reopen_until() {
+        let date = |s: &str| s.parse::<chrono::DateTime<chrono::Utc>>().unwrap();
+        let close = format!(
+            "{}{}\nClosing",
+            util::IdComment::WaitingAuthorClose.str(),
+            util::reopen_until_marker("2024-05-15".parse().unwrap())
+        );
+        let comments = [
+            ("Any update?", date("2024-04-01T00:00:00Z")),
+            (close.as_str(), date("2024-05-01T00:00:00Z")),
+        ];
+        assert_eq!(
+            waiting_reopen_until(comments.into_iter(), date("2024-05-01T00:00:02Z")),
+            Some("2024-05-15".parse().unwrap())
+        );
+        // Closed again later by a maintainer
+        assert_eq!(
+            waiting_reopen_until(comments.into_iter(), date("2024-05-03T00:00:00Z")),
+            None
+        );
+        assert_eq!(
+            waiting_reopen_until(comments[..1].iter().copied(), date("2024-05-01T00:00:02Z")),
+            None
+        );
+    }
+}
@@ -0,0 +1,152 @@
+use super::{Feature, FeatureMeta};
+use crate::errors::DrahtBotError;
+use crate::errors::Result;
+use crate::Context;
+use crate::GitHubEvent;
+
+use async_trait::async_trait;
+
+pub struct ReviewPauseFeature {
+    meta: FeatureMeta,
+}
+
+impl ReviewPauseFeature {
+    pub fn new() -> Self {
+        Self {
+            meta: FeatureMeta::new(
+                "Review Pause",
+                "Let reviewers pause review requests from the bot via `pause-reviews until YYYY-MM-DD` and `resume-reviews` commands.",
+                vec![GitHubEvent::IssueComment],
+            ),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum PauseCommand {
+    Pause(chrono::NaiveDate),
+    Resume,
+}
+
+fn parse_command(comment: &str, bot_username: &str) -> Option<PauseCommand> {
+    let mention = format!("@{bot_username} ");
+    for line in comment.lines().filter(|l| !l.starts_with('>')) {
+        let Some(pos) = line.find(&mention) else {
+            continue;
+        };
+        let mut words = line[pos + mention.len()..].split_whitespace();
+        match (words.next(), words.next(), words.next()) {
+            (Some("pause-reviews"), Some("until"), Some(date)) => {
+                if let Ok(date) = chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d") {
+                    return Some(PauseCommand::Pause(date));
+                }
+            }
+            (Some("resume-reviews"), _, _) => return Some(PauseCommand::Resume),
+            _ => {}
+        }
+    }
+    None
+}
+
+#[async_trait]
+impl Feature for ReviewPauseFeature {
+    fn meta(&self) -> &FeatureMeta {
+        &self.meta
+    }
+
+    async fn handle(
+        &self,
+        ctx: &Context,
+        event: &GitHubEvent,
+        payload: &serde_json::Value,
+    ) -> Result<()> {
+        let action = payload["action"]
+            .as_str()
+            .ok_or(DrahtBotError::KeyNotFound)?;
+
+        let repo_user = payload["repository"]["owner"]["login"]
+            .as_str()
+            .ok_or(DrahtBotError::KeyNotFound)?;
+
+        let repo_name = payload["repository"]["name"]
+            .as_str()
+            .ok_or(DrahtBotError::KeyNotFound)?;
+
+        tracing::info!("Handling: {repo_user}/{repo_name} {event}::{action}");
+        match event {
+            GitHubEvent::IssueComment if action == "created" => {
+                // https://docs.github.com/en/developers/webhooks-and-events/webhooks/webhook-events-and-payloads#issue_comment
+                let comment_author = payload["comment"]["user"]["login"]
+                    .as_str()
+                    .ok_or(DrahtBotError::KeyNotFound)?;
+                let comment_id = payload["comment"]["id"]
+                    .as_u64()
+                    .ok_or(DrahtBotError::KeyNotFound)?;
+                let body = payload["comment"]["body"].as_str().unwrap_or_default();
+                let Some(cmd) = parse_command(body, &ctx.bot_username) else {
+                    return Ok(());
+                };
+                tracing::info!(" ... {comment_author}: {cmd:?}");
+                let user = comment_author.to_string();
+                ctx.state
+                    .update(|s| match cmd {
+                        PauseCommand::Pause(until) => {
+                            s.review_pauses.insert(user, until);
+                        }
+                        PauseCommand::Resume => {
+                            s.review_pauses.remove(&user);
+                        }
+                    })
+                    .await?;
+                if !ctx.dry_run {
+                    ctx.octocrab
+                        .issues(repo_user, repo_name)
+                        .create_comment_reaction(
+                            comment_id,
+                            octocrab::models::reactions::ReactionContent::PlusOne,
+                        )
+                        .await?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_command() {
+        let date = chrono::NaiveDate::from_ymd_opt(2025, 9, 1).unwrap();
+        assert_eq!(
+            parse_command("@DrahtBot pause-reviews until 2025-09-01", "DrahtBot"),
+            Some(PauseCommand::Pause(date))
+        );
+        assert_eq!(
+            parse_command(
+                "Away.\n@DrahtBot pause-reviews until 2025-09-01 thx",
+                "DrahtBot"
+            ),
+            Some(PauseCommand::Pause(date))
+        );
+        assert_eq!(
+            parse_command("@DrahtBot resume-reviews", "DrahtBot"),
+            Some(PauseCommand::Resume)
+        );
+        assert_eq!(
+            parse_command("> @DrahtBot pause-reviews until 2025-09-01", "DrahtBot"),
+            None
+        );
+        assert_eq!(
+            parse_command("@DrahtBot pause-reviews until tomorrow", "DrahtBot"),
+            None
+        );
+        assert_eq!(
+            parse_command("@OtherBot pause-reviews until 2025-09-01", "DrahtBot"),
+            None
+        );
+    }
+}
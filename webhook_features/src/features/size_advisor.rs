@@ -0,0 +1,238 @@
+use super::{sync_label, Feature, FeatureMeta};
+use crate::config::SizeAdvisor;
+use crate::errors::DrahtBotError;
+use crate::errors::Result;
+use crate::Context;
+use crate::GitHubEvent;
+use async_trait::async_trait;
+
+pub struct SizeAdvisorFeature {
+    meta: FeatureMeta,
+}
+
+impl SizeAdvisorFeature {
+    pub fn new() -> Self {
+        Self {
+            meta: FeatureMeta::new(
+                "Size Advisor",
+                "Suggest splitting large pull requests in the metadata comment, and label them.",
+                vec![GitHubEvent::PullRequest],
+            ),
+        }
+    }
+}
+
+#[async_trait]
+impl Feature for SizeAdvisorFeature {
+    fn meta(&self) -> &FeatureMeta {
+        &self.meta
+    }
+
+    async fn handle(
+        &self,
+        ctx: &Context,
+        event: &GitHubEvent,
+        payload: &serde_json::Value,
+    ) -> Result<()> {
+        let action = payload["action"]
+            .as_str()
+            .ok_or(DrahtBotError::KeyNotFound)?;
+
+        let repo_user = payload["repository"]["owner"]["login"]
+            .as_str()
+            .ok_or(DrahtBotError::KeyNotFound)?;
+
+        let repo_name = payload["repository"]["name"]
+            .as_str()
+            .ok_or(DrahtBotError::KeyNotFound)?;
+
+        tracing::info!("Handling: {repo_user}/{repo_name} {event}::{action}");
+        match event {
+            GitHubEvent::PullRequest if action == "opened" || action == "synchronize" => {
+                // https://docs.github.com/en/developers/webhooks-and-events/webhooks/webhook-events-and-payloads#pull_request
+                let slug = format!("{repo_user}/{repo_name}");
+                let Some(config) = ctx
+                    .config
+                    .repositories
+                    .iter()
+                    .find(|r| r.repo_slug == slug)
+                    .and_then(|r| r.size_advisor.as_ref())
+                else {
+                    return Ok(());
+                };
+                let pull = &payload["pull_request"];
+                let number = payload["number"]
+                    .as_u64()
+                    .ok_or(DrahtBotError::KeyNotFound)?;
+                let pulls_api = ctx.octocrab.pulls(repo_user, repo_name);
+                let files = util::with_retry(|| async {
+                    ctx.octocrab
+                        .all_pages(pulls_api.list_files(number).await?)
+                        .await
+                })
+                .await?;
+                let stats = DiffStats {
+                    files: pull["changed_files"]
+                        .as_u64()
+                        .ok_or(DrahtBotError::KeyNotFound)?,
+                    additions: pull["additions"]
+                        .as_u64()
+                        .ok_or(DrahtBotError::KeyNotFound)?,
+                    commits: pull["commits"].as_u64().ok_or(DrahtBotError::KeyNotFound)?,
+                    subsystems: subsystems(files.iter().map(|f| f.filename.as_str())),
+                };
+                let large = is_large(config, &stats);
+                let found_label = pull["labels"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .any(|l| l["name"].as_str() == Some(&config.label));
+                tracing::info!("... {number} large: {large}");
+                let issues_api = ctx.octocrab.issues(repo_user, repo_name);
+                if large != found_label {
+                    let repo_slug = util::Slug {
+                        owner: repo_user.to_string(),
+                        repo: repo_name.to_string(),
+                    };
+                    sync_label(ctx, &repo_slug, number, &config.label, large).await?;
+                }
+                let mut cmt =
+                    util::get_metadata_sections(&ctx.octocrab, &issues_api, number).await?;
+                let section = SizeSection {
+                    config,
+                    stats: &stats,
+                };
+                util::update_metadata_comment(&issues_api, &mut cmt, &section, ctx.dry_run).await?;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct DiffStats {
+    files: u64,
+    additions: u64,
+    commits: u64,
+    subsystems: Vec<String>,
+}
+
+/// Return the sorted subsystems touched by the files, i.e. their directories up to a depth of two.
+fn subsystems<'a>(files: impl Iterator<Item = &'a str>) -> Vec<String> {
+    let mut subsystems = files
+        .map(|f| match f.rsplit_once('/') {
+            Some((dir, _)) => dir.split('/').take(2).collect::<Vec<_>>().join("/"),
+            None => "/".to_string(),
+        })
+        .collect::<Vec<_>>();
+    subsystems.sort();
+    subsystems.dedup();
+    subsystems
+}
+
+fn is_large(config: &SizeAdvisor, stats: &DiffStats) -> bool {
+    stats.files > config.max_files
+        || stats.additions > config.max_additions
+        || stats.commits > config.max_commits
+        || stats.subsystems.len() > config.max_subsystems
+}
+
+/// The number of touched subsystems to list.
+const MAX_SUBSYSTEMS_SHOWN: usize = 10;
+
+/// A note on large pull requests, hidden once the pull request is below all thresholds.
+struct SizeSection<'a> {
+    config: &'a SizeAdvisor,
+    stats: &'a DiffStats,
+}
+
+impl util::Section for SizeSection<'_> {
+    fn id(&self) -> util::IdComment {
+        util::IdComment::SecSize
+    }
+
+    fn render(&self) -> Option<String> {
+        if !is_large(self.config, self.stats) {
+            return None;
+        }
+        let s = self.stats;
+        let mut shown = s
+            .subsystems
+            .iter()
+            .take(MAX_SUBSYSTEMS_SHOWN)
+            .map(|d| format!("`{d}`"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        if s.subsystems.len() > MAX_SUBSYSTEMS_SHOWN {
+            shown += &format!(" and {} more", s.subsystems.len() - MAX_SUBSYSTEMS_SHOWN);
+        }
+        Some(format!(
+            r#"📏 This pull request is on the larger side: {files} changed files, {additions} added lines and {commits} commits, touching {count} subsystems ({shown}).
+
+Large pull requests take longer to review and are more likely to stall. If parts of the change can be reviewed and merged on their own (e.g. refactors, preparations or tests), consider splitting them into separate pull requests. See [the guideline]({url}).
+"#,
+            files = s.files,
+            additions = s.additions,
+            commits = s.commits,
+            count = s.subsystems.len(),
+            url = self.config.guideline_url,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_size_section() {
+        let config = SizeAdvisor {
+            max_files: 30,
+            max_additions: 1000,
+            max_commits: 20,
+            max_subsystems: 5,
+            label: "Large".to_string(),
+            guideline_url: "https://example.org/contributing".to_string(),
+        };
+        let files = [
+            "README.md",
+            "src/wallet/wallet.cpp",
+            "src/wallet/test/wallet_tests.cpp",
+            "src/init.cpp",
+            "test/functional/wallet_basic.py",
+        ];
+        let mut stats = DiffStats {
+            files: 5,
+            additions: 1000,
+            commits: 3,
+            subsystems: subsystems(files.into_iter()),
+        };
+        assert_eq!(
+            stats.subsystems,
+            ["/", "src", "src/wallet", "test/functional"]
+        );
+        assert!(!is_large(&config, &stats));
+        let section = SizeSection {
+            config: &config,
+            stats: &stats,
+        };
+        assert_eq!(util::Section::render(&section), None);
+
+        stats.additions = 1001;
+        let section = SizeSection {
+            config: &config,
+            stats: &stats,
+        };
+        insta::assert_snapshot!("size", util::SECTION_REGISTRY.render(&section));
+        stats.additions = 10;
+        stats.subsystems = (0..12).map(|i| format!("src/s{i:02}")).collect();
+        let section = SizeSection {
+            config: &config,
+            stats: &stats,
+        };
+        assert!(util::Section::render(&section)
+            .unwrap()
+            .contains("`src/s09` and 2 more)."));
+    }
+}
@@ -13,9 +13,9 @@ pub struct SummaryCommentFeature {
     meta: FeatureMeta,
 }
 
-struct Repository {
-    owner: String,
-    name: String,
+pub struct Repository {
+    pub owner: String,
+    pub name: String,
 }
 
 impl SummaryCommentFeature {
@@ -28,6 +28,7 @@ impl SummaryCommentFeature {
                     GitHubEvent::IssueComment,
                     GitHubEvent::PullRequest,
                     GitHubEvent::PullRequestReview,
+                    GitHubEvent::PullRequestReviewThread,
                 ],
             ),
         }
@@ -63,14 +64,23 @@ impl Feature for SummaryCommentFeature {
             name: repo_name.to_string(),
         };
 
-        println!("Handling: {repo_user}/{repo_name} {event}::{action}");
+        tracing::info!("Handling: {repo_user}/{repo_name} {event}::{action}");
         match event {
             GitHubEvent::PullRequest if action == "synchronize" || action == "opened" => {
                 // https://docs.github.com/en/developers/webhooks-and-events/webhooks/webhook-events-and-payloads#pull_request
                 let pr_number = payload["number"]
                     .as_u64()
                     .ok_or(DrahtBotError::KeyNotFound)?;
-                refresh_summary_comment(ctx, repo, pr_number).await?
+                if action == "synchronize" {
+                    let pull_id = format!("{repo_user}/{repo_name}/{pr_number}");
+                    let now = chrono::Utc::now();
+                    ctx.state
+                        .update(|s| s.last_push.insert(pull_id.clone(), now))
+                        .await?;
+                    ctx.update_pull_facts(move |f| f.pull_mut(&pull_id).last_push = Some(now))
+                        .await?;
+                }
+                refresh_summary_comment(ctx, repo, pr_number, true).await?
             }
             GitHubEvent::IssueComment if payload["issue"].get("pull_request").is_some() => {
                 // https://docs.github.com/en/developers/webhooks-and-events/webhooks/webhook-events-and-payloads#issue_comment
@@ -80,13 +90,34 @@ impl Feature for SummaryCommentFeature {
                 let pr_number = payload["issue"]["number"]
                     .as_u64()
                     .ok_or(DrahtBotError::KeyNotFound)?;
+                let body = payload["comment"]["body"].as_str().unwrap_or_default();
+                // New guix results of the bot may replace outdated ones
+                let own_results = comment_author == ctx.bot_username
+                    && action == "created"
+                    && util::IdComment::GuixResults.is_start_of(body);
+                let patterns = ack_patterns(ctx, &repo);
+                // Edits and deletions only matter if they change a review. They never re-request
+                // reviews, because they are not a new reply of the reviewer.
+                let relevant = match action {
+                    "edited" => payload["changes"]["body"]["from"]
+                        .as_str()
+                        .is_some_and(|old| review_changed(old, body, &patterns)),
+                    "deleted" => parse_review(body, &patterns).is_some(),
+                    _ => true,
+                };
                 if payload["issue"]["state"]
                     .as_str()
                     .ok_or(DrahtBotError::KeyNotFound)?
                     == "open"
-                    && comment_author != ctx.bot_username
+                    && (comment_author != ctx.bot_username && relevant || own_results)
                 {
-                    refresh_summary_comment(ctx, repo, pr_number).await?
+                    refresh_summary_comment(
+                        ctx,
+                        repo,
+                        pr_number,
+                        action == "created" && !own_results,
+                    )
+                    .await?
                 }
             }
             GitHubEvent::PullRequestReview => {
@@ -99,7 +130,22 @@ impl Feature for SummaryCommentFeature {
                     .ok_or(DrahtBotError::KeyNotFound)?
                     == "open"
                 {
-                    refresh_summary_comment(ctx, repo, pr_number).await?
+                    refresh_summary_comment(ctx, repo, pr_number, true).await?
+                }
+            }
+            GitHubEvent::PullRequestReviewThread => {
+                // https://docs.github.com/en/webhooks/webhook-events-and-payloads#pull_request_review_thread
+                // A new thread comes with a review, so only resolving and unresolving are left to
+                // handle here. They never re-request reviews.
+                let pr_number = payload["pull_request"]["number"]
+                    .as_u64()
+                    .ok_or(DrahtBotError::KeyNotFound)?;
+                if payload["pull_request"]["state"]
+                    .as_str()
+                    .ok_or(DrahtBotError::KeyNotFound)?
+                    == "open"
+                {
+                    refresh_summary_comment(ctx, repo, pr_number, false).await?
                 }
             }
             _ => {}
@@ -108,24 +154,63 @@ impl Feature for SummaryCommentFeature {
     }
 }
 
-fn summary_comment_template(reviews: Vec<Review>) -> String {
-    let mut comment = r#"
-### Reviews
-See [the guideline](https://github.com/bitcoin/bitcoin/blob/master/CONTRIBUTING.md#code-review) for information on the review process.
-"#
-    .to_string();
+struct ReviewsSection<'a> {
+    reviews: &'a [Review],
+    summary: &'a crate::config::Summary,
+    /// The number of unresolved review threads, if enabled.
+    unresolved_threads: Option<u64>,
+}
+
+impl util::Section for ReviewsSection<'_> {
+    fn id(&self) -> util::IdComment {
+        util::IdComment::SecReviews
+    }
+
+    fn render(&self) -> Option<String> {
+        Some(summary_comment_template(
+            self.reviews,
+            self.summary,
+            self.unresolved_threads,
+        ))
+    }
+}
+
+fn summary_comment_template(
+    reviews: &[Review],
+    summary: &crate::config::Summary,
+    unresolved_threads: Option<u64>,
+) -> String {
+    let threads = match unresolved_threads {
+        None | Some(0) => String::new(),
+        Some(1) => "\n💬 1 unresolved review thread\n".to_string(),
+        Some(n) => format!("\n💬 {n} unresolved review threads\n"),
+    };
+    let mut comment = format!(
+        "{}\n",
+        summary
+            .guideline_text
+            .replace("{guideline_url}", &summary.guideline_url)
+    );
 
     if reviews.is_empty() {
-        comment += "A summary of reviews will appear here.\n";
+        comment += &summary.empty_text;
+        comment += "\n";
+        comment += &threads;
     } else {
+        let history = if summary.ack_history {
+            ack_history(reviews)
+        } else {
+            String::new()
+        };
         comment += "| Type | Reviewers |\n";
         comment += "| ---- | --------- |\n";
 
-        let mut ack_map = reviews.into_iter().fold(HashMap::new(), |mut acc, review| {
+        let mut ack_map = reviews.iter().fold(HashMap::new(), |mut acc, review| {
             acc.entry(review.ack_type).or_insert(Vec::<_>::new()).push((
-                review.user,
-                review.url,
+                &review.user,
+                &review.url,
                 review.date,
+                &review.excerpt,
             ));
             acc
         });
@@ -133,6 +218,9 @@ See [the guideline](https://github.com/bitcoin/bitcoin/blob/master/CONTRIBUTING.
         // Display ACKs in the following order
         for ack_type in &[
             AckType::Ack,
+            AckType::TestedAck,
+            AckType::UtAck,
+            AckType::LightAck,
             AckType::ConceptNack,
             AckType::ConceptAck,
             AckType::ApproachAck,
@@ -148,21 +236,60 @@ See [the guideline](https://github.com/bitcoin/bitcoin/blob/master/CONTRIBUTING.
                     ack_type.as_str(),
                     users
                         .iter()
-                        .map(|(user, url, _)| format!("[{user}]({url})"))
+                        .map(|(user, url, _, excerpt)| match excerpt {
+                            Some(excerpt) => format!("[{user}]({url}) (_\"{excerpt}\"_)"),
+                            None => format!("[{user}]({url})"),
+                        })
                         .collect::<Vec<_>>()
                         .join(", ")
                 );
             }
         }
 
+        comment += &threads;
+        comment += &history;
         comment += "\n";
-        comment +="If your review is incorrectly listed, please react with 👎 to this comment and the bot will ignore it on the next update.";
+        comment += &summary.footer_text;
         comment += "\n";
     }
 
     comment
 }
 
+/// Return a collapsed list of the reviews of each reviewer over time (e.g. "Concept ACK → Stale
+/// ACK → ACK"), or an empty string if nobody reviewed more than once.
+fn ack_history(reviews: &[Review]) -> String {
+    let mut reviewers = reviews
+        .iter()
+        .filter(|r| !r.history.is_empty())
+        .collect::<Vec<_>>();
+    if reviewers.is_empty() {
+        return String::new();
+    }
+    reviewers.sort_by_key(|r| r.date);
+    let mut section = "\n<details><summary>Review history</summary>\n\n".to_string();
+    for latest in reviewers {
+        section += &format!(
+            "* {user}: {steps}\n",
+            user = latest.user,
+            steps = latest
+                .history
+                .iter()
+                .chain(std::iter::once(latest))
+                .map(|r| format!(
+                    "[{typ}]({url}) ({date})",
+                    typ = r.ack_type.as_str(),
+                    url = r.url,
+                    date = r.date.format("%Y-%m-%d"),
+                ))
+                .collect::<Vec<_>>()
+                .join(" → ")
+        );
+    }
+    section += "\n</details>\n";
+    section
+}
+
 struct GitHubReviewComment {
     user: String,
     url: String,
@@ -170,49 +297,476 @@ struct GitHubReviewComment {
     date: chrono::DateTime<chrono::Utc>,
 }
 
-async fn refresh_summary_comment(ctx: &Context, repo: Repository, pr_number: u64) -> Result<()> {
-    println!("Refresh summary comment for {pr_number}");
+/// Create or update the summary comment of the pull request. With `request_reviews`, also
+/// re-request reviews from reviewers whose review went stale.
+pub async fn refresh_summary_comment(
+    ctx: &Context,
+    repo: Repository,
+    pr_number: u64,
+    request_reviews: bool,
+) -> Result<()> {
+    tracing::info!("Refresh summary comment for {pr_number}");
     let issues_api = ctx.octocrab.issues(&repo.owner, &repo.name);
     let pulls_api = ctx.octocrab.pulls(&repo.owner, &repo.name);
-    let pr = pulls_api.get(pr_number).await?;
+    let pr = util::with_retry(|| pulls_api.get(pr_number)).await?;
 
-    let all_comments = ctx
-        .octocrab
-        .all_pages(issues_api.list_comments(pr_number).send().await?)
-        .await?;
+    let all_comments = util::with_retry(|| async {
+        ctx.octocrab
+            .all_pages(issues_api.list_comments(pr_number).send().await?)
+            .await
+    })
+    .await?;
 
     let mut cmt = util::get_metadata_sections_from_comments(&all_comments, pr_number);
+    let guix_results = all_comments
+        .iter()
+        .filter(|c| {
+            c.body
+                .as_deref()
+                .is_some_and(|b| util::IdComment::GuixResults.is_start_of(b))
+        })
+        .max_by_key(|c| c.created_at)
+        .map(|c| (c.body.clone().unwrap_or_default(), c.html_url.to_string()));
 
-    if let Some(config_repo) = ctx
+    let config_repo = ctx
         .config
         .repositories
         .iter()
-        .find(|r| r.repo_slug == format!("{}/{}", repo.owner, repo.name))
-    {
+        .find(|r| r.repo_slug == format!("{}/{}", repo.owner, repo.name));
+    let default_summary = crate::config::Summary::default();
+    let summary = config_repo.map_or(&default_summary, |r| &r.summary);
+
+    if let Some(config_repo) = config_repo {
         if config_repo.corecheck {
-            let coverage = r#"
-### Code Coverage
-For detailed information about the code coverage, see the [test coverage report](https://corecheck.dev/{owner}/{repo}/pulls/{pull_num}).
-"#;
             util::update_metadata_comment(
                 &issues_api,
                 &mut cmt,
-                &coverage
-                    .replace("{owner}", &repo.owner)
-                    .replace("{repo}", &repo.name)
-                    .replace("{pull_num}", &pr_number.to_string()),
-                util::IdComment::SecCodeCoverage,
+                &CodeCoverageSection {
+                    repo: &repo,
+                    pr_number,
+                },
                 ctx.dry_run,
             )
             .await?;
         }
     }
 
-    let ignored_users = if let Some(cmt_id) = cmt.id {
-        let reactions = ctx
-            .octocrab
-            .all_pages(issues_api.list_comment_reactions(cmt_id).send().await?)
+    let user_reviews = get_user_reviews(ctx, &repo, &pr, all_comments, &cmt).await?;
+
+    let max_ack_date = user_reviews
+        .iter()
+        .filter(|r| r.ack_type.is_ack())
+        .max_by_key(|r| r.date)
+        .map(|r| r.date);
+
+    // Re-request reviewers.
+    // Only do this some time (7 days) after the last push to avoid requesting reviewers on a pull
+    // that did not finish CI yet and to avoid too agressive spam. The API does not give a last
+    // push date, so it is stored from the synchronize event. The held back requests are sent by
+    // request_reviews_after_push once the time passed.
+    // Also, only if there was 1 ACK. This also helps to avoid notification email spam, because the
+    // review request is most likely sent out along with the previous ACK comment notification
+    // email.
+    let pull_id = format!("{}/{}/{pr_number}", repo.owner, repo.name);
+    let delay = chrono::Duration::days(summary.review_request_delay_days.into());
+    let pushed_recently = ctx
+        .state
+        .read(|s| {
+            s.last_push
+                .get(&pull_id)
+                .is_some_and(|pushed| chrono::Utc::now() - *pushed < delay)
+        })
+        .await;
+    let ci_failed = pr
+        .labels
+        .iter()
+        .flatten()
+        .any(|l| l.name == super::ci_status::CI_FAILED_LABEL);
+    let paused_reviewers = ctx
+        .state
+        .update(|s| s.paused_reviewers(chrono::Utc::now().date_naive()))
+        .await?;
+    let quiet = util::is_quiet(pr.labels.as_deref().unwrap_or_default(), pr.body.as_deref());
+    let stale_reviewers = if quiet || !request_reviews || pushed_recently || ci_failed {
+        Vec::new()
+    } else if let Some(max_ack_date) = max_ack_date {
+        user_reviews
+            .iter()
+            .filter(|r| match r.ack_type {
+                // Only mark "weak" reviews as stale when they were done before max_ack_date. This
+                // avoids requesting a review when the users just now left a review comment yet to
+                // be addressed.
+                AckType::ApproachAck => r.date < max_ack_date,
+                AckType::ApproachNack => r.date < max_ack_date, // ApproachNack implies ConceptAck
+                AckType::ConceptAck => r.date < max_ack_date,
+                AckType::StaleAck => true,
+
+                AckType::Ack => false,
+                AckType::TestedAck => false,
+                AckType::UtAck => false,
+                AckType::LightAck => false,
+                AckType::ConceptNack => false,
+                AckType::Ignored => false,
+            })
+            .filter(|r| !paused_reviewers.contains(&r.user))
+            .map(|r| r.user.clone())
+            .collect::<Vec<_>>()
+    } else {
+        Vec::new()
+    };
+    let timeline = get_timeline(ctx, &repo, pr_number).await?;
+    let logins = |users: &Option<Vec<octocrab::models::Author>>| {
+        users
+            .iter()
+            .flatten()
+            .map(|u| u.login.clone())
+            .collect::<Vec<_>>()
+    };
+    let mut requests = review_requests(&logins(&pr.requested_reviewers), &timeline);
+    let answered_requests = answered_bot_requests(&requests, &user_reviews, &ctx.bot_username)
+        .into_iter()
+        .filter(|r| !stale_reviewers.contains(r))
+        .collect::<Vec<_>>();
+    requests.retain(|r| !answered_requests.contains(&r.reviewer));
+
+    if summary.reviews {
+        let unresolved_threads = if summary.unresolved_threads {
+            let slug = util::Slug {
+                owner: repo.owner.clone(),
+                repo: repo.name.clone(),
+            };
+            // Leave out the count rather than the whole summary, if the query failed
+            match util::unresolved_review_threads(&ctx.octocrab, &slug, pr_number).await {
+                Ok(count) => Some(count),
+                Err(err) => {
+                    tracing::warn!("... Failed to count the unresolved review threads: {err:?}");
+                    None
+                }
+            }
+        } else {
+            None
+        };
+        util::update_metadata_comment(
+            &issues_api,
+            &mut cmt,
+            &ReviewsSection {
+                reviews: &user_reviews,
+                summary,
+                unresolved_threads,
+            },
+            ctx.dry_run,
+        )
+        .await?;
+    }
+    if summary.review_requests {
+        util::update_metadata_comment(
+            &issues_api,
+            &mut cmt,
+            &ReviewRequestsSection {
+                requests: &requests,
+                assignees: &logins(&pr.assignees),
+                now: chrono::Utc::now(),
+            },
+            ctx.dry_run,
+        )
+        .await?;
+    }
+    if summary.outdated_results {
+        let mut results = Vec::new();
+        if let Some((body, url)) = &guix_results {
+            results.push((
+                format!("[Guix build]({url})"),
+                body.as_str(),
+                format!(
+                    "Remove and re-add the label \"{}\" to rebuild.",
+                    super::guix::GUIX_LABEL
+                ),
+            ));
+        }
+        for id in [
+            util::IdComment::SecCoverage,
+            util::IdComment::SecFuzzCoverage,
+        ] {
+            if let Some(text) = cmt.section(&id) {
+                let title = util::SECTION_REGISTRY.get(id).unwrap().title;
+                results.push((
+                    title.to_string(),
+                    text,
+                    // Pull request coverage is only computed on demand, with coverage --pull-number
+                    "Not refreshed automatically. Ask a maintainer to re-run the coverage for this pull request.".to_string(),
+                ));
+            }
+        }
+        let results = outdated_results(&pr.head.sha, results);
+        util::update_metadata_comment(
+            &issues_api,
+            &mut cmt,
+            &OutdatedResultsSection(results),
+            ctx.dry_run,
+        )
+        .await?;
+    }
+    if summary.bot_activity {
+        util::update_metadata_comment(
+            &issues_api,
+            &mut cmt,
+            &BotActivitySection(bot_activity(&timeline, &ctx.bot_username)),
+            ctx.dry_run,
+        )
+        .await?;
+    }
+    // Done one-by-one, so that a failure for one reviewer does not affect the others. Failures are
+    // remembered and retried by retry_failed_unrequests.
+    ctx.state
+        .update(|s| s.retain_unrequests(&pull_id, &answered_requests))
+        .await?;
+    let slug = util::Slug {
+        owner: repo.owner.to_string(),
+        repo: repo.name.to_string(),
+    };
+    for reviewer in answered_requests {
+        let edit = util::Edit::RemoveReviewRequest {
+            number: pr_number,
+            reviewer: reviewer.clone(),
+        };
+        let res = super::apply_plan(ctx, &slug, &[edit], "summary_comment").await;
+        if ctx.dry_run {
+            continue;
+        }
+        if let Err(err) = &res {
+            tracing::error!("... when removing review request {:?}", err);
+        }
+        let retry = ctx
+            .state
+            .update(|s| s.record_unrequest(&pull_id, &reviewer, res.is_ok()))
             .await?;
+        if !retry {
+            tracing::error!("... giving up to remove review request of {reviewer} on {pull_id}");
+        }
+    }
+    // Done last to work around https://github.com/maflcko/DrahtBot/issues/29
+    // Done one-by-one to also work around the same issue.
+    for stale_reviewer in &stale_reviewers {
+        let edit = util::Edit::RequestReview {
+            number: pr_number,
+            reviewer: stale_reviewer.to_string(),
+        };
+        if let Err(err) = super::apply_plan(ctx, &slug, &[edit], "summary_comment").await {
+            tracing::error!("... when requesting review {:?}", err);
+        }
+    }
+    Ok(())
+}
+
+/// Parse a pull id of the form owner/repo/number.
+fn parse_pull_id(pull_id: &str) -> Option<(Repository, u64)> {
+    let (slug, pr_number) = pull_id.rsplit_once('/')?;
+    let slug = slug.parse::<util::Slug>().ok()?;
+    Some((
+        Repository {
+            owner: slug.owner,
+            name: slug.repo,
+        },
+        pr_number.parse().ok()?,
+    ))
+}
+
+/// Refresh the pull requests where removing a review request failed, to try again.
+pub async fn retry_failed_unrequests(ctx: &Context) {
+    let failed = ctx.state.read(|s| s.failed_unrequests.clone()).await;
+    for (pull_id, reviewers) in failed {
+        let Some((repo, pr_number)) = parse_pull_id(&pull_id) else {
+            continue;
+        };
+        if let Err(err) = refresh_summary_comment(ctx, repo, pr_number, false).await {
+            tracing::error!("When retrying to remove review requests on {pull_id}: {err:?}");
+            // Count the failed refresh as a failed attempt, to not retry a closed pull forever
+            let res = ctx
+                .state
+                .update(|s| {
+                    for reviewer in reviewers.keys() {
+                        s.record_unrequest(&pull_id, reviewer, false);
+                    }
+                })
+                .await;
+            if let Err(err) = res {
+                tracing::error!("When writing the state: {err:?}");
+            }
+        }
+    }
+}
+
+/// Refresh the pull requests whose last push is older than the review request delay, to request
+/// the reviews that were held back after the push.
+pub async fn request_reviews_after_push(ctx: &Context) {
+    let now = chrono::Utc::now();
+    let default_summary = crate::config::Summary::default();
+    let settled = ctx
+        .state
+        .update(|s| {
+            s.take_settled_pushes(|pull_id, pushed| {
+                let summary = ctx
+                    .config
+                    .repositories
+                    .iter()
+                    .find(|r| pull_id.starts_with(&format!("{}/", r.repo_slug)))
+                    .map_or(&default_summary, |r| &r.summary);
+                now - pushed >= chrono::Duration::days(summary.review_request_delay_days.into())
+            })
+        })
+        .await;
+    let settled = match settled {
+        Ok(settled) => settled,
+        Err(err) => {
+            tracing::error!("When writing the state: {err:?}");
+            return;
+        }
+    };
+    for pull_id in settled {
+        let Some((repo, pr_number)) = parse_pull_id(&pull_id) else {
+            continue;
+        };
+        if let Err(err) = refresh_summary_comment(ctx, repo, pr_number, true).await {
+            tracing::error!("When requesting reviews after the last push on {pull_id}: {err:?}");
+        }
+    }
+}
+
+/// Return the latest review of each reviewer, every review, and the other comments of reviewers
+/// as json, for the public data export.
+pub async fn export_reviews(
+    ctx: &Context,
+    repo: &Repository,
+    pr: &octocrab::models::pulls::PullRequest,
+) -> Result<serde_json::Value> {
+    let issues_api = ctx.octocrab.issues(&repo.owner, &repo.name);
+    let all_comments = util::with_retry(|| async {
+        ctx.octocrab
+            .all_pages(issues_api.list_comments(pr.number).send().await?)
+            .await
+    })
+    .await?;
+    let cmt = util::get_metadata_sections_from_comments(&all_comments, pr.number);
+    let (all_reviews, comments) = get_all_reviews(ctx, repo, pr, all_comments, &cmt).await?;
+    let review_json = |r: &Review| {
+        serde_json::json!({
+            "user": r.user,
+            "type": r.ack_type.as_str(),
+            "url": r.url,
+            "date": r.date,
+        })
+    };
+    let history = all_reviews.iter().map(review_json).collect::<Vec<_>>();
+    let mut reviews = latest_reviews(all_reviews);
+    reviews.sort_by(|a, b| a.user.cmp(&b.user));
+    Ok(serde_json::json!({
+        "head": pr.head.sha,
+        "reviews": reviews.iter().map(review_json).collect::<Vec<_>>(),
+        "history": history,
+        "comments": comments
+            .iter()
+            .map(|c| serde_json::json!({
+                "user": c.user,
+                "url": c.url,
+                "date": c.date,
+            }))
+            .collect::<Vec<_>>(),
+    }))
+}
+
+/// Return a comment listing the final ACKs of a merged pull request, for the archives.
+pub async fn merge_summary_comment(
+    ctx: &Context,
+    owner: &str,
+    name: &str,
+    pr_number: u64,
+) -> Result<String> {
+    let repo = Repository {
+        owner: owner.to_string(),
+        name: name.to_string(),
+    };
+    let issues_api = ctx.octocrab.issues(&repo.owner, &repo.name);
+    let pulls_api = ctx.octocrab.pulls(&repo.owner, &repo.name);
+    let pr = util::with_retry(|| pulls_api.get(pr_number)).await?;
+
+    let all_comments = util::with_retry(|| async {
+        ctx.octocrab
+            .all_pages(issues_api.list_comments(pr_number).send().await?)
+            .await
+    })
+    .await?;
+    let cmt = util::get_metadata_sections_from_comments(&all_comments, pr_number);
+
+    let mut acks = get_user_reviews(ctx, &repo, &pr, all_comments, &cmt)
+        .await?
+        .into_iter()
+        .filter(|r| r.ack_type.is_ack())
+        .collect::<Vec<_>>();
+    acks.sort_by_key(|r| r.date);
+
+    let mut comment = format!(
+        "{id}\nMerged as {sha} with the following ACKs on the final commit:\n",
+        id = util::IdComment::MergeSummary.str(),
+        sha = pr.merge_commit_sha.unwrap_or_default(),
+    );
+    if acks.is_empty() {
+        comment += "\n(none)\n";
+    }
+    for ack in acks {
+        comment += &format!("\n* [{user}]({url})", user = ack.user, url = ack.url);
+    }
+    Ok(comment)
+}
+
+/// Parse all comments and reviews on the pull request and return the latest review of each user.
+async fn get_user_reviews(
+    ctx: &Context,
+    repo: &Repository,
+    pr: &octocrab::models::pulls::PullRequest,
+    all_comments: Vec<octocrab::models::issues::Comment>,
+    cmt: &util::MetaComment,
+) -> Result<Vec<Review>> {
+    let (reviews, _) = get_all_reviews(ctx, repo, pr, all_comments, cmt).await?;
+    Ok(latest_reviews(reviews))
+}
+
+/// Return the latest review of each user, with the earlier ones as its history.
+fn latest_reviews(reviews: Vec<Review>) -> Vec<Review> {
+    let mut user_reviews: HashMap<String, Vec<Review>> = HashMap::new();
+    for r in reviews {
+        user_reviews.entry(r.user.clone()).or_default().push(r);
+    }
+    user_reviews
+        .into_values()
+        .map(|mut v| {
+            v.sort_by_key(|r| r.date);
+            let mut latest = v.pop().unwrap();
+            latest.history = v;
+            latest
+        })
+        .collect::<Vec<_>>()
+}
+
+/// Parse all comments and reviews on the pull request and return every review, and the other
+/// comments that are not by the author.
+async fn get_all_reviews(
+    ctx: &Context,
+    repo: &Repository,
+    pr: &octocrab::models::pulls::PullRequest,
+    all_comments: Vec<octocrab::models::issues::Comment>,
+    cmt: &util::MetaComment,
+) -> Result<(Vec<Review>, Vec<GitHubReviewComment>)> {
+    let issues_api = ctx.octocrab.issues(&repo.owner, &repo.name);
+    let pulls_api = ctx.octocrab.pulls(&repo.owner, &repo.name);
+    let pr_number = pr.number;
+
+    let ignored_users = if let Some(cmt_id) = cmt.id {
+        let reactions = util::with_retry(|| async {
+            ctx.octocrab
+                .all_pages(issues_api.list_comment_reactions(cmt_id).send().await?)
+                .await
+        })
+        .await?;
 
         reactions
             .into_iter()
@@ -223,9 +777,10 @@ For detailed information about the code coverage, see the [test coverage report]
         vec![]
     };
 
+    let meta_ids = cmt.comment_ids();
     let mut all_comments = all_comments
         .into_iter()
-        .filter(|c| cmt.id != Some(c.id))
+        .filter(|c| !meta_ids.contains(&c.id))
         .map(|c| GitHubReviewComment {
             user: c.user.login,
             url: c.html_url.to_string(),
@@ -233,150 +788,97 @@ For detailed information about the code coverage, see the [test coverage report]
             date: c.updated_at.unwrap_or(c.created_at),
         })
         .collect::<Vec<_>>();
-    let mut all_review_comments = ctx
-        .octocrab
-        .all_pages(pulls_api.list_reviews(pr_number).send().await?)
-        .await?
-        .into_iter()
-        .filter(|c| c.user.is_some())
-        .map(|c| GitHubReviewComment {
-            user: c.user.unwrap().login,
-            url: c.html_url.to_string(),
-            body: c.body.unwrap_or_default(),
-            date: c.submitted_at.unwrap(),
-        })
-        .collect::<Vec<_>>();
+    let mut all_review_comments = util::with_retry(|| async {
+        ctx.octocrab
+            .all_pages(pulls_api.list_reviews(pr_number).send().await?)
+            .await
+    })
+    .await?
+    .into_iter()
+    .filter(|c| c.user.is_some())
+    .map(|c| GitHubReviewComment {
+        user: c.user.unwrap().login,
+        url: c.html_url.to_string(),
+        body: c.body.unwrap_or_default(),
+        date: c.submitted_at.unwrap(),
+    })
+    .collect::<Vec<_>>();
 
     all_comments.append(&mut all_review_comments);
 
-    let head_commit = pr.head.sha;
+    let head_commit = &pr.head.sha;
 
-    let mut user_reviews: HashMap<String, Vec<Review>> = HashMap::new(); // Need to store all acks per user to avoid duplicates
+    let mut reviews = Vec::new();
+    let mut other_comments = Vec::new();
 
-    println!(
+    tracing::info!(
         " ... Refresh of {num} comments from {url}.",
         num = all_comments.len(),
-        url = pr.html_url.unwrap(),
+        url = pr.html_url.as_ref().unwrap(),
     );
 
-    let pr_author = pr.user.unwrap().login;
+    let patterns = ack_patterns(ctx, repo);
+    let pr_author = &pr.user.as_ref().unwrap().login;
     for comment in all_comments.into_iter() {
-        if comment.user == pr_author {
+        if &comment.user == pr_author {
             continue;
         }
-        if let Some(ac) = parse_review(&comment.body) {
-            let v = user_reviews.entry(comment.user.clone()).or_default();
-            let has_current_head = ac.commit.map_or(false, |c| head_commit.starts_with(&c));
-            v.push(Review {
+        if let Some(ac) = parse_review(&comment.body, &patterns) {
+            // An ACK without a commit (only possible with configured patterns) never goes stale
+            let has_current_head = ac.commit.map_or(true, |c| head_commit.starts_with(&c));
+            reviews.push(Review {
                 user: comment.user.clone(),
                 ack_type: if ignored_users.contains(&comment.user) {
                     AckType::Ignored
-                } else if ac.ack_type == AckType::Ack && !has_current_head {
+                } else if ac.ack_type.is_ack() && !has_current_head {
                     AckType::StaleAck
                 } else {
                     ac.ack_type
                 },
+                excerpt: match ac.ack_type {
+                    AckType::ConceptNack | AckType::ApproachNack => {
+                        nack_excerpt(&comment.body, &patterns)
+                    }
+                    _ => None,
+                },
                 url: comment.url,
                 date: comment.date,
+                history: Vec::new(),
             });
+        } else {
+            other_comments.push(comment);
         }
     }
-
-    let user_reviews = user_reviews
-        .into_iter()
-        .map(|e| e.1.into_iter().max_by_key(|r| r.date).unwrap())
-        .collect::<Vec<_>>();
-
-    let max_ack_date = user_reviews
-        .iter()
-        .filter(|r| r.ack_type == AckType::Ack)
-        .max_by_key(|r| r.date)
-        .map(|r| r.date);
-
-    // Re-request reviewers.
-    // Ideally, do this after some time (7 days) after the last push to avoid requesting reviewers
-    // on a pull that did not finish CI yet and to avoid too agressive spam.
-    // However, the API does not give a last push date, so it would need to be fetched and stored
-    // somehow (via a synchronize event, or opened event, or the commit date of the head commit).
-    // For now, if there was 1 ACK, assume it happened after sufficient time.
-    // This also helps to avoid notification email spam, because the review request is most likely
-    // sent out along with the previous ACK comment notification email.
-    let stale_reviewers = if let Some(max_ack_date) = max_ack_date {
-        user_reviews
-            .iter()
-            .filter(|r| match r.ack_type {
-                // Only mark "weak" reviews as stale when they were done before max_ack_date. This
-                // avoids requesting a review when the users just now left a review comment yet to
-                // be addressed.
-                AckType::ApproachAck => r.date < max_ack_date,
-                AckType::ApproachNack => r.date < max_ack_date, // ApproachNack implies ConceptAck
-                AckType::ConceptAck => r.date < max_ack_date,
-                AckType::StaleAck => true,
-
-                AckType::Ack => false,
-                AckType::ConceptNack => false,
-                AckType::Ignored => false,
-            })
-            .map(|r| r.user.clone())
-            .collect::<Vec<_>>()
-    } else {
-        Vec::new()
-    };
-    let maybe_leftover_review_requests = user_reviews
-        .iter()
-        .filter(|r| r.ack_type == AckType::Ack)
-        .map(|r| r.user.clone())
-        .collect::<Vec<_>>();
-
-    let comment = summary_comment_template(user_reviews);
-    util::update_metadata_comment(
-        &issues_api,
-        &mut cmt,
-        &comment,
-        util::IdComment::SecReviews,
-        ctx.dry_run,
-    )
-    .await?;
-    if !maybe_leftover_review_requests.is_empty() {
-        println!(
-            " ... Unrequest review from {:?}",
-            maybe_leftover_review_requests
-        );
-        // Temporarily disabled due to https://support.github.com/ticket/personal/0/2621973
-        //pulls_api
-        //    .remove_requested_reviewers(pr_number, maybe_leftover_review_requests, [])
-        //    .await?;
-    }
-    // Done last to work around https://github.com/maflcko/DrahtBot/issues/29
-    // Done one-by-one to also work around the same issue.
-    for stale_reviewer in &stale_reviewers {
-        println!(" ... Request review from {}", stale_reviewer);
-        if let Err(err) = pulls_api
-            .request_reviews(pr_number, [stale_reviewer.to_string()], [])
-            .await
-        {
-            println!(" ... ERROR when requesting review {:?}", err);
-        }
-    }
-    Ok(())
+    reviews.sort_by_key(|r| r.date);
+    other_comments.sort_by_key(|c| c.date);
+    Ok((reviews, other_comments))
 }
 
-#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
-enum AckType {
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AckType {
     Ack,
+    TestedAck, // ACK after testing the changes
+    UtAck,     // ACK after only reviewing the code (untested)
+    LightAck,  // ACK after a light review
     ConceptAck,
     ConceptNack,
     ApproachAck,
     ApproachNack,
 
+    #[serde(skip_deserializing)]
     StaleAck, // ACK, but the commit is not the head of the PR anymore
-    Ignored,  // The user has a -1 reaction on the summary comment
+    #[serde(skip_deserializing)]
+    Ignored, // The user has a -1 reaction on the summary comment
 }
 
 impl AckType {
     fn as_str(&self) -> &str {
         match self {
             AckType::Ack => "ACK",
+            AckType::TestedAck => "Tested ACK",
+            AckType::UtAck => "utACK",
+            AckType::LightAck => "Light ACK",
             AckType::ConceptAck => "Concept ACK",
             AckType::ConceptNack => "Concept NACK",
             AckType::ApproachAck => "Approach ACK",
@@ -385,14 +887,34 @@ impl AckType {
             AckType::Ignored => "Ignored review",
         }
     }
+
+    /// Whether this is an ACK of the current head commit, in any of its forms.
+    fn is_ack(&self) -> bool {
+        matches!(
+            self,
+            AckType::Ack | AckType::TestedAck | AckType::UtAck | AckType::LightAck
+        )
+    }
 }
 
 lazy_static! {
-    static ref ACK_PATTERNS: Vec<(Regex, AckType)> = vec![
+    static ref DEFAULT_ACK_PATTERNS: Vec<(Regex, AckType)> = vec![
         (r"\b(Approach ACK)\b", AckType::ApproachAck),
         (r"\b(Approach NACK)\b", AckType::ApproachNack),
         (r"\b(NACK)\b", AckType::ConceptNack),
         (r"\b(Concept ACK)\b", AckType::ConceptAck),
+        (
+            r"\b((?i:light(?:ly)?(?: tested| reviewed)?) ACK)(?:.*?)([0-9a-f]{6,40})\b",
+            AckType::LightAck
+        ),
+        (
+            r"\b(tACK|(?i:tested) ACK)(?:.*?)([0-9a-f]{6,40})\b",
+            AckType::TestedAck
+        ),
+        (
+            r"\b(utACK|crACK|(?:CR|(?i:code[ -]review|untested)) ACK)(?:.*?)([0-9a-f]{6,40})\b",
+            AckType::UtAck
+        ),
         (r"(ACK)(?:.*?)([0-9a-f]{6,40})\b", AckType::Ack),
         (r"(ACK)\b", AckType::ConceptAck)
     ]
@@ -406,6 +928,333 @@ struct Review {
     ack_type: AckType,
     url: String,
     date: chrono::DateTime<chrono::Utc>,
+    excerpt: Option<String>, // Short rationale of a NACK
+    history: Vec<Review>,    // The earlier reviews of the user, oldest first
+}
+
+/// Return the first sentence after the NACK keyword of the comment, truncated, for display in a
+/// table cell.
+fn nack_excerpt(comment: &str, patterns: &[(Regex, AckType)]) -> Option<String> {
+    const MAX_CHARS: usize = 80;
+    let lines = comment
+        .split('\n')
+        .filter(|s| !s.starts_with('>'))
+        .collect::<Vec<_>>();
+    // Find the keyword the same way as parse_review. The reason follows on the same line or the
+    // next ones.
+    let (line, end) = patterns
+        .iter()
+        .filter(|(_, typ)| matches!(typ, AckType::ConceptNack | AckType::ApproachNack))
+        .find_map(|(re, _)| {
+            lines
+                .iter()
+                .enumerate()
+                .find_map(|(i, l)| re.find(l).map(|m| (i, m.end())))
+        })?;
+    let text = std::iter::once(&lines[line][end..])
+        .chain(lines[line + 1..].iter().copied())
+        .map(|s| s.trim())
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ");
+    let text = text.trim_start_matches(|c: char| c.is_whitespace() || ",.:;-!".contains(c));
+    let sentence = match text.find(['.', '!', '?']) {
+        Some(end) => &text[..=end],
+        None => text,
+    };
+    let sentence = sentence.replace('|', "\\|").replace('`', "");
+    if sentence.is_empty() {
+        return None;
+    }
+    if sentence.chars().count() > MAX_CHARS {
+        return Some(format!(
+            "{}…",
+            sentence
+                .chars()
+                .take(MAX_CHARS)
+                .collect::<String>()
+                .trim_end()
+        ));
+    }
+    Some(sentence)
+}
+
+/// Return the raw timeline events of the pull request. Raw json is used, because the timeline may
+/// contain event types unknown to octocrab.
+async fn get_timeline(
+    ctx: &Context,
+    repo: &Repository,
+    pr_number: u64,
+) -> Result<Vec<serde_json::Value>> {
+    let mut events = Vec::new();
+    for page in 1.. {
+        let route = format!(
+            "/repos/{}/{}/issues/{pr_number}/timeline",
+            repo.owner, repo.name
+        );
+        let query = [("per_page", "100".to_string()), ("page", page.to_string())];
+        let batch: Vec<serde_json::Value> =
+            util::with_retry(|| ctx.octocrab.get(&route, Some(&query))).await?;
+        let done = batch.len() < 100;
+        events.extend(batch);
+        if done {
+            break;
+        }
+    }
+    Ok(events)
+}
+
+/// Return a dated line for each action of the bot on the pull request, oldest first.
+fn bot_activity(timeline: &[serde_json::Value], bot_username: &str) -> Vec<String> {
+    let mut activity = Vec::new();
+    for event in timeline {
+        let actor = event["actor"]["login"]
+            .as_str()
+            .or(event["user"]["login"].as_str());
+        if actor != Some(bot_username) {
+            continue;
+        }
+        let date = event["created_at"].as_str().unwrap_or_default();
+        let date = date.split('T').next().unwrap_or_default();
+        let label = event["label"]["name"].as_str().unwrap_or_default();
+        let what = match event["event"].as_str().unwrap_or_default() {
+            "labeled" => format!("Added label `{label}`"),
+            "unlabeled" => format!("Removed label `{label}`"),
+            "review_requested" => format!(
+                "Requested review from {}",
+                event["requested_reviewer"]["login"]
+                    .as_str()
+                    .unwrap_or_default()
+            ),
+            "commented" => {
+                let body = event["body"].as_str().unwrap_or_default();
+                let kind = if util::IdComment::Metadata.is_start_of(body)
+                    || util::IdComment::MetadataContinued.is_start_of(body)
+                {
+                    continue;
+                } else if util::IdComment::SecCiFailed.is_start_of(body) {
+                    // Posted before the CI failure became a section
+                    "CI failure"
+                } else if util::IdComment::NeedsRebase.is_start_of(body) {
+                    "Rebase request"
+                } else if util::IdComment::InactiveRebase.is_start_of(body)
+                    || util::IdComment::InactiveCi.is_start_of(body)
+                    || util::IdComment::InactiveStale.is_start_of(body)
+                {
+                    "Inactivity notice"
+                } else if util::IdComment::GuixResults.is_start_of(body) {
+                    "Guix build results"
+                } else if util::IdComment::LabelMention.is_start_of(body) {
+                    "Label mention"
+                } else {
+                    "Comment"
+                };
+                format!(
+                    "[{kind}]({url})",
+                    url = event["html_url"].as_str().unwrap_or_default()
+                )
+            }
+            _ => continue,
+        };
+        activity.push(format!("{date}: {what}"));
+    }
+    activity
+}
+
+/// The actions of the bot on the pull request, as returned by bot_activity.
+struct BotActivitySection(Vec<String>);
+
+impl util::Section for BotActivitySection {
+    fn id(&self) -> util::IdComment {
+        util::IdComment::SecBotActivity
+    }
+
+    fn render(&self) -> Option<String> {
+        const MAX_ENTRIES: usize = 30;
+        let activity = &self.0;
+        if activity.is_empty() {
+            return None;
+        }
+        let mut section = format!(
+            "<details><summary>{} actions of the bot on this pull request</summary>\n\n",
+            activity.len()
+        );
+        if activity.len() > MAX_ENTRIES {
+            section += "* (older actions omitted)\n";
+        }
+        for line in &activity[activity.len().saturating_sub(MAX_ENTRIES)..] {
+            section += &format!("* {line}\n");
+        }
+        section += "\n</details>\n";
+        Some(section)
+    }
+}
+
+/// A result that was computed for an earlier head commit of the pull request.
+#[derive(Debug, PartialEq)]
+struct OutdatedResult {
+    /// The kind of the result, linked to it if possible.
+    what: String,
+    head: String,
+    /// How to refresh the result.
+    refresh: String,
+}
+
+/// Return the results, given as (what, text, refresh), whose head-commit marker names another
+/// commit than the current head. Results without the marker are skipped, because it is unknown
+/// which commit they are for.
+fn outdated_results(head: &str, results: Vec<(String, &str, String)>) -> Vec<OutdatedResult> {
+    results
+        .into_iter()
+        .filter_map(|(what, text, refresh)| {
+            let result_head = util::parse_head_commit(text)?;
+            (result_head != head).then(|| OutdatedResult {
+                what,
+                head: result_head.to_string(),
+                refresh,
+            })
+        })
+        .collect()
+}
+
+struct OutdatedResultsSection(Vec<OutdatedResult>);
+
+impl util::Section for OutdatedResultsSection {
+    fn id(&self) -> util::IdComment {
+        util::IdComment::SecOutdatedResults
+    }
+
+    fn render(&self) -> Option<String> {
+        if self.0.is_empty() {
+            return None;
+        }
+        let mut section = "These results were computed before the latest push to this pull request, and may no longer apply:\n\n".to_string();
+        for r in &self.0 {
+            section += &format!(
+                "* {what} for {head}. {refresh}\n",
+                what = r.what,
+                head = &r.head[..r.head.len().min(12)],
+                refresh = r.refresh,
+            );
+        }
+        Some(section)
+    }
+}
+
+struct CodeCoverageSection<'a> {
+    repo: &'a Repository,
+    pr_number: u64,
+}
+
+impl util::Section for CodeCoverageSection<'_> {
+    fn id(&self) -> util::IdComment {
+        util::IdComment::SecCodeCoverage
+    }
+
+    fn render(&self) -> Option<String> {
+        Some(format!(
+            "For detailed information about the code coverage, see the [test coverage report](https://corecheck.dev/{owner}/{repo}/pulls/{pull_num}).\n",
+            owner = self.repo.owner,
+            repo = self.repo.name,
+            pull_num = self.pr_number,
+        ))
+    }
+}
+
+/// A review request that is still open on the pull request.
+#[derive(Debug, PartialEq)]
+struct ReviewRequest {
+    reviewer: String,
+    requested_by: Option<String>, // None if the event is missing in the timeline
+    since: Option<chrono::DateTime<chrono::Utc>>, // The date of the latest request
+}
+
+/// Return the open review requests, along with who requested them and when, from the timeline.
+fn review_requests(reviewers: &[String], timeline: &[serde_json::Value]) -> Vec<ReviewRequest> {
+    reviewers
+        .iter()
+        .map(|reviewer| {
+            let event = timeline.iter().rev().find(|e| {
+                e["event"] == "review_requested"
+                    && e["requested_reviewer"]["login"].as_str() == Some(reviewer)
+            });
+            ReviewRequest {
+                reviewer: reviewer.clone(),
+                requested_by: event
+                    .and_then(|e| e["actor"]["login"].as_str())
+                    .map(|a| a.to_string()),
+                since: event
+                    .and_then(|e| e["created_at"].as_str())
+                    .and_then(|d| d.parse().ok()),
+            }
+        })
+        .collect()
+}
+
+/// Return the reviewers whose review was requested by the bot and who left a review since then, or
+/// whose latest review is an ACK of the current head. GitHub only clears a request on a review via
+/// the GitHub review feature, not on a review comment.
+fn answered_bot_requests(
+    requests: &[ReviewRequest],
+    user_reviews: &[Review],
+    bot_username: &str,
+) -> Vec<String> {
+    requests
+        .iter()
+        .filter(|r| r.requested_by.as_deref() == Some(bot_username))
+        .filter(|r| {
+            user_reviews
+                .iter()
+                .filter(|rev| rev.user == r.reviewer)
+                .any(|rev| rev.ack_type.is_ack() || r.since.is_some_and(|since| rev.date > since))
+        })
+        .map(|r| r.reviewer.clone())
+        .collect()
+}
+
+struct ReviewRequestsSection<'a> {
+    requests: &'a [ReviewRequest],
+    assignees: &'a [String],
+    now: chrono::DateTime<chrono::Utc>,
+}
+
+impl util::Section for ReviewRequestsSection<'_> {
+    fn id(&self) -> util::IdComment {
+        util::IdComment::SecReviewRequests
+    }
+
+    fn render(&self) -> Option<String> {
+        let Self {
+            requests,
+            assignees,
+            now,
+        } = self;
+        if requests.is_empty() && assignees.is_empty() {
+            return None;
+        }
+        let mut section = String::new();
+        if !requests.is_empty() {
+            section += "| Reviewer | Requested by | Pending for |\n";
+            section += "| -------- | ------------ | ----------- |\n";
+            for r in requests.iter() {
+                let pending = match r.since.map(|since| (*now - since).num_days()) {
+                    None => "?".to_string(),
+                    Some(0) => "less than a day".to_string(),
+                    Some(1) => "1 day".to_string(),
+                    Some(days) => format!("{days} days"),
+                };
+                section += &format!(
+                    "| {} | {} | {pending} |\n",
+                    r.reviewer,
+                    r.requested_by.as_deref().unwrap_or("?")
+                );
+            }
+        }
+        if !assignees.is_empty() {
+            section += &format!("\nAssigned to: {}\n", assignees.join(", "));
+        }
+        Some(section)
+    }
 }
 
 #[derive(Debug, PartialEq)]
@@ -414,10 +1263,32 @@ struct AckCommit {
     commit: Option<String>,
 }
 
-fn parse_review(comment: &str) -> Option<AckCommit> {
+/// Return the review patterns configured for the repo, or the default ones.
+fn ack_patterns(ctx: &Context, repo: &Repository) -> Vec<(Regex, AckType)> {
+    let configured = ctx
+        .config
+        .repositories
+        .iter()
+        .find(|r| r.repo_slug == format!("{}/{}", repo.owner, repo.name))
+        .map(|r| &r.ack_patterns[..])
+        .unwrap_or_default();
+    if configured.is_empty() {
+        return DEFAULT_ACK_PATTERNS.clone();
+    }
+    configured_ack_patterns(configured)
+}
+
+fn configured_ack_patterns(configured: &[crate::config::AckPattern]) -> Vec<(Regex, AckType)> {
+    configured
+        .iter()
+        .map(|p| (p.regex.clone(), p.ack_type))
+        .collect()
+}
+
+fn parse_review(comment: &str, patterns: &[(Regex, AckType)]) -> Option<AckCommit> {
     let lines = comment.split('\n').filter(|s| !s.starts_with('>'));
 
-    for (re, ack_type) in ACK_PATTERNS.iter() {
+    for (re, ack_type) in patterns {
         for line in lines.clone() {
             if let Some(caps) = re.captures(line) {
                 let commit = caps.get(2).map(|m| m.as_str().to_string());
@@ -431,6 +1302,21 @@ fn parse_review(comment: &str) -> Option<AckCommit> {
     None
 }
 
+/// Return whether the edit of a comment changed the review in it, or the excerpt shown for a NACK.
+fn review_changed(old: &str, new: &str, patterns: &[(Regex, AckType)]) -> bool {
+    let (old_review, new_review) = (parse_review(old, patterns), parse_review(new, patterns));
+    if old_review != new_review {
+        return true;
+    }
+    matches!(
+        new_review,
+        Some(AckCommit {
+            ack_type: AckType::ConceptNack | AckType::ApproachNack,
+            ..
+        })
+    ) && nack_excerpt(old, patterns) != nack_excerpt(new, patterns)
+}
+
 // Test that parse_review works
 #[cfg(test)]
 mod tests {
@@ -514,24 +1400,80 @@ mod tests {
             TestCase {
                 comment: "tACK 1234567890123456789012345678901234567890",
                 expected: Some(AckCommit {
-                    ack_type: AckType::Ack,
+                    ack_type: AckType::TestedAck,
                     commit: Some("1234567890123456789012345678901234567890".to_string()),
                 }),
             },
             TestCase {
                 comment: "Code Review ACK 123456",
                 expected: Some(AckCommit {
-                    ack_type: AckType::Ack,
+                    ack_type: AckType::UtAck,
                     commit: Some("123456".to_string()),
                 }),
             },
             TestCase {
                 comment: "Code Review ACK 1234567890123456789012345678901234567890",
                 expected: Some(AckCommit {
-                    ack_type: AckType::Ack,
+                    ack_type: AckType::UtAck,
                     commit: Some("1234567890123456789012345678901234567890".to_string()),
                 }),
             },
+            TestCase {
+                comment: "tested ACK 12345678",
+                expected: Some(AckCommit {
+                    ack_type: AckType::TestedAck,
+                    commit: Some("12345678".to_string()),
+                }),
+            },
+            TestCase {
+                comment: "utACK 12345678",
+                expected: Some(AckCommit {
+                    ack_type: AckType::UtAck,
+                    commit: Some("12345678".to_string()),
+                }),
+            },
+            TestCase {
+                comment: "utACK",
+                expected: Some(AckCommit {
+                    ack_type: AckType::ConceptAck,
+                    commit: None,
+                }),
+            },
+            TestCase {
+                comment: "code-review ACK 12345678",
+                expected: Some(AckCommit {
+                    ack_type: AckType::UtAck,
+                    commit: Some("12345678".to_string()),
+                }),
+            },
+            TestCase {
+                comment: "light ACK 12345678",
+                expected: Some(AckCommit {
+                    ack_type: AckType::LightAck,
+                    commit: Some("12345678".to_string()),
+                }),
+            },
+            TestCase {
+                comment: "Lightly tested ACK 12345678",
+                expected: Some(AckCommit {
+                    ack_type: AckType::LightAck,
+                    commit: Some("12345678".to_string()),
+                }),
+            },
+            TestCase {
+                comment: "untested ACK 12345678",
+                expected: Some(AckCommit {
+                    ack_type: AckType::UtAck,
+                    commit: Some("12345678".to_string()),
+                }),
+            },
+            TestCase {
+                comment: "Untested ACK 12345678",
+                expected: Some(AckCommit {
+                    ack_type: AckType::UtAck,
+                    commit: Some("12345678".to_string()),
+                }),
+            },
             TestCase {
                 comment: "Approach ACK",
                 expected: Some(AckCommit {
@@ -595,7 +1537,7 @@ mod tests {
                 comment: "Code review ACK  bba667e ",
                 expected: Some(
                     AckCommit {
-                        ack_type: AckType::Ack,
+                        ack_type: AckType::UtAck,
                         commit: Some("bba667e".to_string()),
                     },
                 ),
@@ -698,7 +1640,7 @@ mod tests {
                 comment: "CR ACK 12345678",
                 expected: Some(
                     AckCommit {
-                        ack_type: AckType::Ack,
+                        ack_type: AckType::UtAck,
                         commit: Some("12345678".to_string()),
                     },
                 ),
@@ -707,7 +1649,7 @@ mod tests {
                 comment: "crACK 12345678",
                 expected: Some(
                     AckCommit {
-                        ack_type: AckType::Ack,
+                        ack_type: AckType::UtAck,
                         commit: Some("12345678".to_string()),
                     },
                 ),
@@ -751,9 +1693,380 @@ mod tests {
         ];
 
         for test_case in test_cases {
-            let actual = parse_review(test_case.comment);
+            let actual = parse_review(test_case.comment, &DEFAULT_ACK_PATTERNS);
             println!("Test case: {}", test_case.comment);
             assert_eq!(actual, test_case.expected);
         }
     }
+
+    #[test]
+    fn test_parse_review_configured() {
+        let configured: Vec<crate::config::AckPattern> = serde_yaml::from_str(
+            r#"
+- regex: '\b(LGTM)\b(?:.*?\b([0-9a-f]{6,40})\b)?'
+  type: ack
+- regex: '^\+1\b'
+  type: concept_ack
+"#,
+        )
+        .unwrap();
+        let patterns = configured_ack_patterns(&configured);
+        assert_eq!(
+            parse_review("LGTM", &patterns),
+            Some(AckCommit {
+                ack_type: AckType::Ack,
+                commit: None,
+            })
+        );
+        assert_eq!(
+            parse_review("LGTM at 12345678", &patterns),
+            Some(AckCommit {
+                ack_type: AckType::Ack,
+                commit: Some("12345678".to_string()),
+            })
+        );
+        assert_eq!(
+            parse_review("+1 on the idea", &patterns),
+            Some(AckCommit {
+                ack_type: AckType::ConceptAck,
+                commit: None,
+            })
+        );
+        assert_eq!(parse_review("ACK 12345678", &patterns), None);
+        // Only types a comment can be parsed as are allowed
+        assert!(serde_yaml::from_str::<Vec<crate::config::AckPattern>>(
+            "- regex: 'ACK'\n  type: stale_ack"
+        )
+        .is_err());
+        // A bad pattern fails when the config is loaded
+        assert!(serde_yaml::from_str::<Vec<crate::config::AckPattern>>(
+            "- regex: 'ACK('\n  type: ack"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_review_changed() {
+        assert!(review_changed(
+            "Concept ACK",
+            "ACK 1234567",
+            &DEFAULT_ACK_PATTERNS
+        ));
+        assert!(review_changed(
+            "ACK 1234567",
+            "Thanks, will re-review",
+            &DEFAULT_ACK_PATTERNS
+        ));
+        assert!(review_changed(
+            "NACK, too risky.",
+            "NACK, not needed.",
+            &DEFAULT_ACK_PATTERNS
+        ));
+        assert!(!review_changed(
+            "Concept ACK, nice",
+            "Concept ACK, nice!",
+            &DEFAULT_ACK_PATTERNS
+        ));
+        assert!(!review_changed("lgtm", "LGTM", &DEFAULT_ACK_PATTERNS));
+    }
+
+    #[test]
+    fn test_ack_history() {
+        let review = |ack_type, day, history| Review {
+            user: "alice".to_string(),
+            ack_type,
+            url: format!("https://github.com/o/r/pull/1#{day}"),
+            date: chrono::NaiveDate::from_ymd_opt(2024, 1, day)
+                .unwrap()
+                .and_hms_opt(0, 0, 0)
+                .unwrap()
+                .and_utc(),
+            excerpt: None,
+            history,
+        };
+        assert_eq!(ack_history(&[review(AckType::Ack, 3, Vec::new())]), "");
+        let history = vec![
+            review(AckType::ConceptAck, 1, Vec::new()),
+            review(AckType::StaleAck, 2, Vec::new()),
+        ];
+        assert_eq!(
+            ack_history(&[review(AckType::Ack, 3, history)]),
+            "\n<details><summary>Review history</summary>\n\n\
+             * alice: [Concept ACK](https://github.com/o/r/pull/1#1) (2024-01-01) → \
+             [Stale ACK](https://github.com/o/r/pull/1#2) (2024-01-02) → \
+             [ACK](https://github.com/o/r/pull/1#3) (2024-01-03)\n\
+             \n</details>\n"
+        );
+        let latest = latest_reviews(vec![
+            review(AckType::ConceptAck, 1, Vec::new()),
+            review(AckType::Ack, 3, Vec::new()),
+        ]);
+        assert_eq!(latest.len(), 1);
+        assert_eq!(latest[0].ack_type, AckType::Ack);
+        assert_eq!(latest[0].history.len(), 1);
+    }
+
+    #[test]
+    fn test_reviews_snapshots() {
+        let review = |user: &str, ack_type, day, excerpt: Option<&str>, history| Review {
+            user: user.to_string(),
+            ack_type,
+            url: format!("https://github.com/o/r/pull/1#{user}-{day}"),
+            date: chrono::NaiveDate::from_ymd_opt(2024, 1, day)
+                .unwrap()
+                .and_hms_opt(0, 0, 0)
+                .unwrap()
+                .and_utc(),
+            excerpt: excerpt.map(|e| e.to_string()),
+            history,
+        };
+        let render = |reviews: &[Review], summary: &crate::config::Summary| {
+            util::SECTION_REGISTRY.render(&ReviewsSection {
+                reviews,
+                summary,
+                unresolved_threads: None,
+            })
+        };
+        let summary = crate::config::Summary::default();
+        insta::assert_snapshot!("no_reviews", render(&[], &summary));
+        insta::assert_snapshot!(
+            "acks",
+            render(
+                &[
+                    review("carol", AckType::Ack, 3, None, Vec::new()),
+                    review("alice", AckType::Ack, 1, None, Vec::new()),
+                    review("bob", AckType::TestedAck, 2, None, Vec::new()),
+                    review("dave", AckType::ConceptAck, 4, None, Vec::new()),
+                ],
+                &summary
+            )
+        );
+        insta::assert_snapshot!(
+            "nacks",
+            render(
+                &[
+                    review("alice", AckType::StaleAck, 1, None, Vec::new()),
+                    review(
+                        "bob",
+                        AckType::ConceptNack,
+                        2,
+                        Some("This adds a new dependency for little gain."),
+                        Vec::new()
+                    ),
+                    review("carol", AckType::ApproachAck, 3, None, Vec::new()),
+                    review("dave", AckType::Ignored, 4, None, Vec::new()),
+                ],
+                &summary
+            )
+        );
+        let history = vec![
+            review("alice", AckType::ConceptAck, 1, None, Vec::new()),
+            review("alice", AckType::StaleAck, 2, None, Vec::new()),
+        ];
+        insta::assert_snapshot!(
+            "ack_history",
+            render(
+                &[
+                    review("alice", AckType::Ack, 3, None, history),
+                    review("bob", AckType::UtAck, 4, None, Vec::new()),
+                ],
+                &crate::config::Summary {
+                    ack_history: true,
+                    ..Default::default()
+                }
+            )
+        );
+        insta::assert_snapshot!(
+            "unresolved_threads",
+            util::SECTION_REGISTRY.render(&ReviewsSection {
+                reviews: &[review("alice", AckType::Ack, 1, None, Vec::new())],
+                summary: &summary,
+                unresolved_threads: Some(3),
+            })
+        );
+        assert!(!util::SECTION_REGISTRY
+            .render(&ReviewsSection {
+                reviews: &[],
+                summary: &summary,
+                unresolved_threads: Some(0),
+            })
+            .contains("unresolved"));
+    }
+
+    #[test]
+    fn test_nack_excerpt() {
+        let excerpt = |comment: &str| nack_excerpt(comment, &DEFAULT_ACK_PATTERNS);
+        assert_eq!(excerpt(""), None);
+        assert_eq!(excerpt("NACK"), None);
+        assert_eq!(
+            excerpt("> quote\nNACK. This breaks the wallet.\nMore text"),
+            Some("This breaks the wallet.".to_string())
+        );
+        assert_eq!(
+            excerpt("NACK, this | breaks\nthe wallet. More"),
+            Some("this \\| breaks the wallet.".to_string())
+        );
+        assert_eq!(
+            excerpt("Thanks for the work.\n\nConcept NACK:\nThe `-par` option is needed."),
+            Some("The -par option is needed.".to_string())
+        );
+        assert_eq!(
+            excerpt("Approach NACK - use a map instead"),
+            Some("use a map instead".to_string())
+        );
+        assert_eq!(
+            excerpt(&format!("NACK {}", "a".repeat(100))),
+            Some(format!("{}…", "a".repeat(80)))
+        );
+    }
+
+    #[test]
+    fn test_bot_activity() {
+        let timeline = serde_json::json!([
+            {"event": "labeled", "actor": {"login": "DrahtBot"}, "created_at": "2024-01-02T10:00:00Z", "label": {"name": "CI failed"}},
+            {"event": "labeled", "actor": {"login": "maintainer"}, "created_at": "2024-01-02T11:00:00Z", "label": {"name": "Wallet"}},
+            {"event": "commented", "actor": {"login": "DrahtBot"}, "created_at": "2024-01-02T10:00:01Z", "body": format!("{}\nfailed", util::IdComment::SecCiFailed.str()), "html_url": "https://x/1"},
+            {"event": "commented", "actor": {"login": "DrahtBot"}, "created_at": "2024-01-01T10:00:01Z", "body": format!("{}\nmeta", util::IdComment::Metadata.str()), "html_url": "https://x/0"},
+            {"event": "unlabeled", "actor": {"login": "DrahtBot"}, "created_at": "2024-01-03T10:00:00Z", "label": {"name": "CI failed"}},
+            {"event": "review_requested", "actor": {"login": "DrahtBot"}, "created_at": "2024-01-04T10:00:00Z", "requested_reviewer": {"login": "alice"}},
+            {"event": "head_ref_force_pushed", "actor": {"login": "DrahtBot"}, "created_at": "2024-01-05T10:00:00Z"},
+        ]);
+        assert_eq!(
+            bot_activity(timeline.as_array().unwrap(), "DrahtBot"),
+            vec![
+                "2024-01-02: Added label `CI failed`",
+                "2024-01-02: [CI failure](https://x/1)",
+                "2024-01-03: Removed label `CI failed`",
+                "2024-01-04: Requested review from alice",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_outdated_results() {
+        let head = "ab".repeat(20);
+        let guix = format!(
+            "{}\n{}\n### Guix builds",
+            util::IdComment::GuixResults.str(),
+            util::head_commit_marker(&"cd".repeat(20))
+        );
+        let coverage = format!(
+            "\n### Coverage\n{}\nAll covered",
+            util::head_commit_marker(&head)
+        );
+        let results = outdated_results(
+            &head,
+            vec![
+                (
+                    "[Guix build](https://x/1)".to_string(),
+                    &guix,
+                    "Rebuild.".to_string(),
+                ),
+                ("Coverage".to_string(), &coverage, "Rerun.".to_string()),
+                (
+                    "Fuzz coverage".to_string(),
+                    "\n### Fuzz coverage\n",
+                    "Rerun.".to_string(),
+                ),
+            ],
+        );
+        assert_eq!(
+            results,
+            [OutdatedResult {
+                what: "[Guix build](https://x/1)".to_string(),
+                head: "cd".repeat(20),
+                refresh: "Rebuild.".to_string(),
+            }]
+        );
+        insta::assert_snapshot!(
+            "outdated_results",
+            util::SECTION_REGISTRY.render(&OutdatedResultsSection(results))
+        );
+        assert_eq!(
+            util::Section::render(&OutdatedResultsSection(Vec::new())),
+            None
+        );
+    }
+
+    #[test]
+    fn test_review_requests() {
+        let timeline = serde_json::json!([
+            {"event": "review_requested", "actor": {"login": "maintainer"}, "created_at": "2024-01-01T10:00:00Z", "requested_reviewer": {"login": "alice"}},
+            {"event": "review_requested", "actor": {"login": "DrahtBot"}, "created_at": "2024-01-02T10:00:00Z", "requested_reviewer": {"login": "alice"}},
+            {"event": "review_requested", "actor": {"login": "DrahtBot"}, "created_at": "2024-01-03T10:00:00Z", "requested_reviewer": {"login": "bob"}},
+            {"event": "review_requested", "actor": {"login": "maintainer"}, "created_at": "2024-01-03T10:00:00Z", "requested_reviewer": {"login": "carol"}},
+        ]);
+        let date = |s: &str| s.parse::<chrono::DateTime<chrono::Utc>>().unwrap();
+        let requests = review_requests(
+            &["alice", "bob", "carol", "dave"].map(String::from),
+            timeline.as_array().unwrap(),
+        );
+        assert_eq!(
+            requests[0],
+            ReviewRequest {
+                reviewer: "alice".to_string(),
+                requested_by: Some("DrahtBot".to_string()),
+                since: Some(date("2024-01-02T10:00:00Z")),
+            }
+        );
+        assert_eq!(requests[3].requested_by, None);
+
+        // alice ACKed the current head before the request and bob replied to the request. carol
+        // was requested by someone else.
+        let review = |user: &str, ack_type, date| Review {
+            user: user.to_string(),
+            ack_type,
+            url: "https://github.com/o/r/pull/1#1".to_string(),
+            date,
+            excerpt: None,
+            history: Vec::new(),
+        };
+        let reviews = [
+            review("alice", AckType::Ack, date("2024-01-01T12:00:00Z")),
+            review("bob", AckType::ConceptAck, date("2024-01-04T12:00:00Z")),
+            review("carol", AckType::Ack, date("2024-01-04T12:00:00Z")),
+        ];
+        assert_eq!(
+            answered_bot_requests(&requests, &reviews, "DrahtBot"),
+            ["alice", "bob"]
+        );
+        assert_eq!(
+            answered_bot_requests(&requests, &reviews[1..], "DrahtBot"),
+            ["bob"]
+        );
+
+        let section = ReviewRequestsSection {
+            requests: &requests,
+            assignees: &["maintainer".to_string()],
+            now: date("2024-01-04T09:00:00Z"),
+        };
+        assert_eq!(
+            util::SECTION_REGISTRY.render(&section),
+            "\n### Review requests\n\
+             | Reviewer | Requested by | Pending for |\n\
+             | -------- | ------------ | ----------- |\n\
+             | alice | DrahtBot | 1 day |\n\
+             | bob | DrahtBot | less than a day |\n\
+             | carol | maintainer | less than a day |\n\
+             | dave | ? | ? |\n\
+             \nAssigned to: maintainer\n"
+        );
+        let section = ReviewRequestsSection {
+            requests: &[],
+            assignees: &[],
+            now: date("2024-01-04T09:00:00Z"),
+        };
+        assert_eq!(util::Section::render(&section), None);
+    }
+
+    #[test]
+    fn test_parse_pull_id() {
+        let (repo, pr_number) = parse_pull_id("bitcoin-core/gui/123").unwrap();
+        assert_eq!(
+            (repo.owner.as_str(), repo.name.as_str(), pr_number),
+            ("bitcoin-core", "gui", 123)
+        );
+        assert!(parse_pull_id("bitcoin/123").is_none());
+        assert!(parse_pull_id("bitcoin/bitcoin/x").is_none());
+    }
 }
@@ -0,0 +1,273 @@
+use actix_web::{get, web, HttpResponse};
+
+use crate::Context;
+
+/// Failures are only remembered for this many days.
+pub const FLAKE_WINDOW_DAYS: u64 = 30;
+
+/// A failed check run, as recorded by the CI status feature.
+#[derive(Clone, serde::Serialize, serde::Deserialize)]
+pub struct CiFailure {
+    pub date: chrono::NaiveDate,
+    pub task: String,
+    /// The normalized error line of the log, see error_signature.
+    pub signature: String,
+    /// None for failures on the default branch.
+    pub pull: Option<u64>,
+    /// The id of the check run, to ignore repeated deliveries of the same result. Zero for
+    /// failures recorded before it was tracked.
+    #[serde(default)]
+    pub run_id: u64,
+}
+
+/// Whether the (lowercase) line is configure output, such as a compiler flag probe, which may
+/// mention a failure without being one.
+fn is_configure_output(l: &str) -> bool {
+    l.starts_with("-- ")
+        || l.starts_with("checking ")
+        || l.contains("performing test")
+        || l.contains("-werror")
+}
+
+/// Whether the (lowercase) line only reports that an earlier step failed, such as the exit code of
+/// make or of the job.
+fn is_generic_failure(l: &str) -> bool {
+    l.starts_with("make: ***")
+        || l.starts_with("make[")
+        || l.starts_with("ninja: build stopped")
+        || l.contains("exit code")
+        || l.contains("exit status")
+}
+
+/// Return the most specific error line of the log, with the numbers and hashes replaced, so that
+/// the same error in different runs has the same signature. None, if no error line was found.
+///
+/// Assertions and "error:" lines are preferred over other lines mentioning a failure, and the last
+/// one is taken, because test runners print their own summary after the actual error.
+pub fn error_signature(log: &str) -> Option<String> {
+    let candidates = log
+        .lines()
+        .map(|l| l.trim())
+        .filter(|l| {
+            let l = l.to_lowercase();
+            (l.contains("error") || l.contains("fail") || l.contains("assert"))
+                && !is_configure_output(&l)
+        })
+        .collect::<Vec<_>>();
+    let specific = |l: &&str| {
+        let l = l.to_lowercase();
+        (l.contains("assert") || l.contains("error:")) && !is_generic_failure(&l)
+    };
+    let line = candidates
+        .iter()
+        .rev()
+        .find(|l| specific(l))
+        .or_else(|| {
+            candidates
+                .iter()
+                .rev()
+                .find(|l| !is_generic_failure(&l.to_lowercase()))
+        })
+        .or(candidates.last())?;
+    let mut signature = String::new();
+    let mut word = String::new();
+    for c in line.chars().chain([' ']) {
+        if c.is_ascii_alphanumeric() {
+            word.push(c);
+            continue;
+        }
+        if word.chars().any(|c| c.is_ascii_digit()) {
+            // Line numbers, durations, ports, hashes, ...
+            signature.push('N');
+        } else {
+            signature += &word;
+        }
+        word.clear();
+        if !(c.is_whitespace() && signature.ends_with(' ')) {
+            signature.push(if c.is_whitespace() { ' ' } else { c });
+        }
+    }
+    Some(signature.trim().chars().take(120).collect())
+}
+
+/// A failure that was seen several times, and is likely intermittent.
+#[derive(Debug, PartialEq)]
+pub struct Flake {
+    pub task: String,
+    pub signature: String,
+    pub count: usize,
+    pub on_base: bool,
+    pub pulls: Vec<u64>,
+    pub last_seen: chrono::NaiveDate,
+}
+
+/// Group the failures by task and signature, and return the ones that also happened on the
+/// default branch or in more than one pull request, most frequent first.
+pub fn flakes(failures: &[CiFailure]) -> Vec<Flake> {
+    let mut flakes = Vec::<Flake>::new();
+    for f in failures {
+        let flake = match flakes
+            .iter_mut()
+            .find(|g| g.task == f.task && g.signature == f.signature)
+        {
+            Some(g) => g,
+            None => {
+                flakes.push(Flake {
+                    task: f.task.clone(),
+                    signature: f.signature.clone(),
+                    count: 0,
+                    on_base: false,
+                    pulls: Vec::new(),
+                    last_seen: f.date,
+                });
+                flakes.last_mut().unwrap()
+            }
+        };
+        flake.count += 1;
+        flake.last_seen = flake.last_seen.max(f.date);
+        match f.pull {
+            None => flake.on_base = true,
+            Some(p) if !flake.pulls.contains(&p) => flake.pulls.push(p),
+            Some(_) => {}
+        }
+    }
+    flakes.retain(|f| f.on_base || f.pulls.len() > 1);
+    flakes.sort_by(|a, b| b.count.cmp(&a.count).then(b.last_seen.cmp(&a.last_seen)));
+    flakes
+}
+
+#[get("/flakes/{owner}/{repo}")]
+async fn flake_dashboard(
+    ctx: web::Data<Context>,
+    path: web::Path<(String, String)>,
+) -> HttpResponse {
+    let (owner, repo) = path.into_inner();
+    let slug = format!("{owner}/{repo}");
+    if !ctx.config.repositories.iter().any(|r| r.repo_slug == slug) {
+        return HttpResponse::NotFound().body("Unknown repo");
+    }
+    let flakes = ctx
+        .state
+        .read(|s| flakes(s.ci_failures.get(&slug).map_or(&[][..], |f| &f[..])))
+        .await;
+    let escape = |s: &str| {
+        s.replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+    };
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>Intermittent CI failures of {slug}</title></head><body>\n<h1>Intermittent CI failures of {slug}</h1>\n<p>Failures seen on the default branch or in more than one pull request in the last {FLAKE_WINDOW_DAYS} days.</p>\n<table>\n<tr><th>Task</th><th>Error</th><th>Seen</th><th>On default branch</th><th>Pull requests</th><th>Last seen</th></tr>\n"
+    );
+    for f in &flakes {
+        let pulls = f
+            .pulls
+            .iter()
+            .map(|p| format!("<a href=\"https://github.com/{slug}/pull/{p}\">#{p}</a>"))
+            .collect::<Vec<_>>()
+            .join(" ");
+        html += &format!(
+            "<tr><td>{}</td><td><code>{}</code></td><td>{}</td><td>{}</td><td>{pulls}</td><td>{}</td></tr>\n",
+            escape(&f.task),
+            escape(&f.signature),
+            f.count,
+            if f.on_base { "yes" } else { "no" },
+            f.last_seen,
+        );
+    }
+    html += "</table>\n</body></html>\n";
+    HttpResponse::Ok().content_type("text/html").body(html)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_signature() {
+        assert_eq!(
+            error_signature("ok\n  AssertionError: 3 != 4 (wallet_basic.py:123) \n"),
+            Some("AssertionError: N != N (wallet_basic.py:N)".to_string())
+        );
+        assert_eq!(
+            error_signature("node0 2024-01-01T00:00:00Z Error: port 18444 in use"),
+            error_signature("node0 2024-02-01T10:11:12Z Error: port 28444 in use")
+        );
+        assert_eq!(error_signature("All tests passed"), None);
+        let log = "-- Performing Test HAVE_X - Failed
+checking whether the C++ compiler accepts -Werror... no
+test_framework: Assertion failed
+AssertionError: 3 != 4
+wallet_basic.py failed, Duration: 12 s
+Error: Process completed with exit code 1.
+";
+        assert_eq!(
+            error_signature(log),
+            Some("AssertionError: N != N".to_string())
+        );
+        assert_eq!(
+            error_signature("-- Performing Test HAVE_X - Failed\nmake: *** [all] Error 2\n"),
+            Some("make: *** [all] Error N".to_string())
+        );
+        assert_eq!(
+            error_signature("feature_x.py failed\nError: Process completed with exit code 1.\n"),
+            Some("feature_x.py failed".to_string())
+        );
+        assert_eq!(error_signature("-- Performing Test HAVE_X - Failed"), None);
+    }
+
+    #[test]
+    fn test_record_redelivery() {
+        let failure = |run_id| CiFailure {
+            date: "2024-01-01".parse().unwrap(),
+            task: "ASan".to_string(),
+            signature: "timeout".to_string(),
+            pull: Some(1),
+            run_id,
+        };
+        let mut state = crate::state::State::default();
+        let today = "2024-01-02".parse().unwrap();
+        state.record_ci_failures("o/r", today, vec![failure(7)]);
+        state.record_ci_failures("o/r", today, vec![failure(7), failure(8)]);
+        assert_eq!(state.ci_failures["o/r"].len(), 2);
+    }
+
+    #[test]
+    fn test_flakes() {
+        let failure = |date: &str, task: &str, signature: &str, pull| CiFailure {
+            date: date.parse().unwrap(),
+            task: task.to_string(),
+            signature: signature.to_string(),
+            pull,
+            run_id: 0,
+        };
+        let failures = [
+            failure("2024-01-01", "ASan", "timeout", Some(1)),
+            failure("2024-01-02", "ASan", "timeout", Some(1)),
+            failure("2024-01-03", "ASan", "timeout", Some(2)),
+            failure("2024-01-03", "TSan", "data race", Some(1)),
+            failure("2024-01-04", "TSan", "data race", Some(1)),
+            failure("2024-01-05", "lint", "typo", None),
+        ];
+        assert_eq!(
+            flakes(&failures),
+            [
+                Flake {
+                    task: "ASan".to_string(),
+                    signature: "timeout".to_string(),
+                    count: 3,
+                    on_base: false,
+                    pulls: vec![1, 2],
+                    last_seen: "2024-01-03".parse().unwrap(),
+                },
+                Flake {
+                    task: "lint".to_string(),
+                    signature: "typo".to_string(),
+                    count: 1,
+                    on_base: true,
+                    pulls: vec![],
+                    last_seen: "2024-01-05".parse().unwrap(),
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,39 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// A set of async locks, one per key, created on demand. Unused locks are dropped.
+#[derive(Default)]
+pub struct KeyedLock {
+    locks: std::sync::Mutex<HashMap<String, Arc<tokio::sync::Mutex<()>>>>,
+}
+
+impl KeyedLock {
+    /// Wait for the lock of the key and return a guard, which releases it when dropped.
+    pub async fn lock(&self, key: &str) -> tokio::sync::OwnedMutexGuard<()> {
+        let lock = {
+            let mut locks = self.locks.lock().unwrap();
+            // Drop locks that nobody holds or waits for
+            locks.retain(|_, l| Arc::strong_count(l) > 1);
+            locks.entry(key.to_string()).or_default().clone()
+        };
+        lock.lock_owned().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[actix_web::test]
+    async fn test_keyed_lock() {
+        let locks = KeyedLock::default();
+        let a = locks.lock("o/r#1").await;
+        // A different key does not block
+        let b = locks.lock("o/r#2").await;
+        assert!(locks.locks.lock().unwrap()["o/r#1"].try_lock().is_err());
+        drop(a);
+        drop(b);
+        let _c = locks.lock("o/r#3").await;
+        assert_eq!(locks.locks.lock().unwrap().len(), 1);
+    }
+}
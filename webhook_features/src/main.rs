@@ -1,16 +1,32 @@
+mod activation;
+mod backfill;
+mod chaos;
+mod check_config;
 mod config;
 mod errors;
+mod event_queue;
+mod export;
 mod features;
+mod flakes;
+mod keyed_lock;
+mod metrics;
+#[cfg(test)]
+mod mock_github;
+mod onboard;
+mod overview;
+mod reviewer_report;
+mod state;
 
 use std::str::FromStr;
 
 use crate::features::summary_comment::SummaryCommentFeature;
-use actix_web::{get, post, web, App, HttpRequest, HttpServer, Responder};
+use actix_web::{get, post, web, App, HttpRequest, HttpResponse, HttpServer, Responder};
 use clap::Parser;
 use features::Feature;
 use lazy_static::lazy_static;
 use octocrab::Octocrab;
 use strum::{Display, EnumString};
+use tracing::Instrument;
 
 use crate::config::Config;
 use crate::errors::{DrahtBotError, Result};
@@ -18,18 +34,79 @@ use crate::errors::{DrahtBotError, Result};
 #[derive(Parser)]
 #[command(about="Run features on webhooks", long_about = None)]
 struct Args {
-    #[arg(short, long, help = "GitHub token")]
-    token: String,
-    #[arg(long, help = "Host to listen on", default_value = "localhost")]
+    #[arg(
+        short,
+        long,
+        help = "GitHub token. Required, unless only checking the config."
+    )]
+    token: Option<String>,
+    #[arg(
+        long,
+        help = "Host to listen on, unless a socket is passed by systemd",
+        default_value = "localhost"
+    )]
     host: String,
-    #[arg(long, help = "Port to listen on", default_value = "1337")]
+    #[arg(
+        long,
+        help = "Port to listen on, unless a socket is passed by systemd",
+        default_value = "1337"
+    )]
     port: u16,
+    /// On shutdown, wait this many seconds for events in progress to finish. Unfinished events are
+    /// retried from the queue file after a restart.
+    #[arg(long, default_value_t = 30)]
+    shutdown_timeout: u64,
     /// The path to the yaml config file.
     #[arg(long)]
     config_file: std::path::PathBuf,
     /// Print changes/edits instead of calling the GitHub/CI API.
     #[arg(long, default_value_t = false)]
     dry_run: bool,
+    /// Also write the dry-run preview (API calls and comment diffs) to this file.
+    #[arg(long, requires = "dry_run")]
+    dry_run_output: Option<std::path::PathBuf>,
+    /// The path to the json file to persist the bot state in.
+    #[arg(long)]
+    state_file: Option<std::path::PathBuf>,
+    /// The sqlite database with the facts about pull requests shared with the other tools of the
+    /// bot.
+    #[arg(long)]
+    pull_facts_file: Option<std::path::PathBuf>,
+    /// Development only: Route GitHub API calls through a proxy that replaces responses with
+    /// errors at this rate (between 0 and 1).
+    #[arg(long)]
+    chaos: Option<f64>,
+    /// The path to the json file to queue incoming events in, until all features handled them.
+    #[arg(long)]
+    queue_file: Option<std::path::PathBuf>,
+    /// The format of the log output: pretty or json.
+    #[arg(long, default_value = "pretty")]
+    log_format: util::LogFormat,
+    /// Reopen pull requests closed by the stale tool due to inactivity, once their author comments.
+    #[arg(long, default_value_t = false)]
+    reopen_on_comment: bool,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(clap::Subcommand)]
+enum Command {
+    /// Create or update the summary comment on all open pull requests of a repo and exit, instead
+    /// of running the server.
+    BackfillSummaries(crate::backfill::BackfillArgs),
+    /// Publish the parsed reviews of all open pull requests of a repo to the reports repo and
+    /// exit, instead of running the server.
+    ExportReviews(crate::export::ExportArgs),
+    /// Write a monthly report recognizing the reviewers of a repo to the reports repo, from the
+    /// data published by export-reviews, and exit, instead of running the server.
+    ReviewerReport(crate::reviewer_report::ReviewerReportArgs),
+    /// Prepare a repo for the bot: Check the token and the webhook, create the missing labels,
+    /// write a starter config stanza and run each feature once with it in dry-run mode. Then exit,
+    /// instead of running the server.
+    Onboard(crate::onboard::OnboardArgs),
+    /// Check the config file for problems (unknown keys, invalid regexes, typos in templates,
+    /// inconsistent settings), and exit, instead of running the server. Does not need a token.
+    CheckConfig,
 }
 
 #[derive(Display, EnumString, PartialEq, Eq)]
@@ -37,8 +114,10 @@ struct Args {
 pub enum GitHubEvent {
     CheckSuite,
     IssueComment,
+    Issues,
     PullRequest,
     PullRequestReview,
+    PullRequestReviewThread,
 
     Unknown,
 }
@@ -50,9 +129,44 @@ async fn index() -> &'static str {
 
 pub struct Context {
     octocrab: Octocrab,
+    /// The GitHub token, passed on to the tools the features run (as GITHUB_ACCESS_TOKEN).
+    token: String,
     bot_username: String,
     pub config: Config,
     dry_run: bool,
+    reopen_on_comment: bool,
+    state: crate::state::StateStore,
+    pull_facts: Option<::state::Store>,
+    queue: crate::event_queue::EventQueue,
+    metrics: crate::metrics::Metrics,
+}
+
+impl Context {
+    /// Modify the facts about pull requests shared with the other tools, if a file was given. The
+    /// other tools may hold the write lock of the database for a while, so wait on a blocking
+    /// thread.
+    async fn update_pull_facts(
+        &self,
+        f: impl FnOnce(&mut ::state::Facts) + Send + 'static,
+    ) -> Result<()> {
+        if let Some(facts) = self.pull_facts.clone() {
+            tokio::task::spawn_blocking(move || facts.update(f))
+                .await?
+                .map_err(DrahtBotError::IOError)?;
+        }
+        Ok(())
+    }
+
+    /// Read the facts about pull requests shared with the other tools, if a file was given.
+    async fn read_pull_facts(&self) -> Result<Option<::state::Facts>> {
+        let Some(facts) = self.pull_facts.clone() else {
+            return Ok(None);
+        };
+        let facts = tokio::task::spawn_blocking(move || facts.read())
+            .await?
+            .map_err(DrahtBotError::IOError)?;
+        Ok(Some(facts))
+    }
 }
 
 #[post("/drahtbot")]
@@ -67,11 +181,66 @@ async fn postreceive_handler(
         .unwrap()
         .to_str()
         .unwrap();
+    ctx.metrics.event_received(event_str);
     let event = GitHubEvent::from_str(event_str).unwrap_or(GitHubEvent::Unknown);
+    let delivery = req
+        .headers()
+        .get("X-GitHub-Delivery")
+        .and_then(|d| d.to_str().ok())
+        .map(|d| d.to_string());
 
-    emit_event(&ctx, event, data).await.unwrap();
+    let names = features()
+        .iter()
+        .filter(|f| f.meta().events().contains(&event))
+        .map(|f| f.meta().name().to_string())
+        .collect::<Vec<_>>();
+    if !names.is_empty() {
+        let res = match ctx
+            .queue
+            .push(
+                event.to_string(),
+                delivery.clone(),
+                data.into_inner(),
+                names,
+            )
+            .await
+        {
+            Ok(queued) => handle_queued(&ctx, queued).await,
+            Err(err) => Err(err),
+        };
+        if let Err(err) = res {
+            // Failed features are retried from the queue, but a queue error may lose the event, so
+            // let GitHub deliver it again
+            tracing::error!(
+                delivery = delivery.as_deref().unwrap_or_default(),
+                "Failed to queue {event_str} event: {err:?}"
+            );
+            return HttpResponse::InternalServerError().body("Failed to queue the event");
+        }
+    }
 
-    "OK"
+    HttpResponse::Ok().body("OK")
+}
+
+/// Let the remaining features handle the queued event, and record the result in the queue.
+async fn handle_queued(ctx: &Context, queued: crate::event_queue::QueuedEvent) -> Result<()> {
+    let event = GitHubEvent::from_str(&queued.event).unwrap_or(GitHubEvent::Unknown);
+    let payload = &queued.payload;
+    let pr = event_number(payload);
+    let span = tracing::info_span!(
+        "event",
+        delivery = queued.delivery.as_deref().unwrap_or_default(),
+        event = queued.event,
+        repo = payload["repository"]["full_name"]
+            .as_str()
+            .unwrap_or_default(),
+        pr,
+        attempt = queued.attempts + 1,
+    );
+    let failed = emit_event(ctx, &event, payload, &queued.features)
+        .instrument(span)
+        .await;
+    ctx.queue.finish_attempt(queued.id, failed).await
 }
 
 fn features() -> Vec<Box<dyn Feature>> {
@@ -79,50 +248,100 @@ fn features() -> Vec<Box<dyn Feature>> {
         Box::new(SummaryCommentFeature::new()),
         Box::new(crate::features::ci_status::CiStatusFeature::new()),
         Box::new(crate::features::labels::LabelsFeature::new()),
+        Box::new(crate::features::finalize::FinalizeFeature::new()),
+        Box::new(crate::features::review_pause::ReviewPauseFeature::new()),
+        Box::new(crate::features::conflicts::ConflictsFeature::new()),
+        Box::new(crate::features::guix::GuixFeature::new()),
+        Box::new(crate::features::label_mentions::LabelMentionsFeature::new()),
+        Box::new(crate::features::reopen::ReopenFeature::new()),
+        Box::new(crate::features::size_advisor::SizeAdvisorFeature::new()),
     ]
 }
 
 lazy_static! {
-    static ref MUTEX: tokio::sync::Mutex<()> = tokio::sync::Mutex::new(());
+    static ref LOCKS: crate::keyed_lock::KeyedLock = crate::keyed_lock::KeyedLock::default();
+}
+
+/// Return the number of the pull request or issue the event is about, if any.
+fn event_number(payload: &serde_json::Value) -> Option<u64> {
+    payload["number"]
+        .as_u64()
+        .or(payload["issue"]["number"].as_u64())
+        .or(payload["pull_request"]["number"].as_u64())
+        .or(payload["check_suite"]["pull_requests"][0]["number"].as_u64())
 }
 
+/// Let the features with the given names handle the event, and return the names of the ones that
+/// failed.
 async fn emit_event(
     ctx: &Context,
-    event: GitHubEvent,
-    data: web::Json<serde_json::Value>,
-) -> Result<()> {
-    let _guard = MUTEX.lock().await;
+    event: &GitHubEvent,
+    data: &serde_json::Value,
+    names: &[String],
+) -> Vec<String> {
+    // Events on different pull requests are handled concurrently. Events on the same pull request
+    // are serialized, to avoid races when editing the same labels or comments.
+    let _guard = LOCKS
+        .lock(&format!(
+            "{}#{}",
+            data["repository"]["full_name"].as_str().unwrap_or_default(),
+            event_number(data).unwrap_or_default()
+        ))
+        .await;
 
+    let mut failed = Vec::new();
     for feature in features() {
-        if feature.meta().events().contains(&event) {
-            feature.handle(ctx, &event, &data).await?;
+        let name = feature.meta().name();
+        if feature.meta().events().contains(event) && names.iter().any(|n| n == name) {
+            // Keep going, so that one failing feature does not abort the others
+            let start = std::time::Instant::now();
+            let res = feature
+                .handle(ctx, event, data)
+                .instrument(tracing::info_span!("feature", name))
+                .await;
+            ctx.metrics.feature_handled(name, start.elapsed(), &res);
+            if let Err(err) = res {
+                tracing::error!(feature = name, "{err:?}");
+                failed.push(name.to_string());
+            }
         }
     }
-
-    Ok(())
+    failed
 }
 
 #[actix_web::main]
 async fn main() -> Result<()> {
     let args = Args::parse();
+    util::init_logging(args.log_format);
+    if let Some(path) = &args.dry_run_output {
+        util::set_dry_run_output(path).expect("dry-run output file error");
+    }
 
-    let config: Config = serde_yaml::from_reader(
-        std::fs::File::open(args.config_file).expect("config file path error"),
-    )
-    .expect("yaml error");
+    if let Some(Command::CheckConfig) = args.command {
+        return crate::check_config::check_config_file(&args.config_file);
+    }
 
-    let octocrab = octocrab::Octocrab::builder()
-        .personal_token(args.token)
-        .build()
-        .map_err(DrahtBotError::GitHubError)?;
+    // Refuse to start on a config that would only fail once an event hits it
+    let config = crate::check_config::load_config(&args.config_file)?;
+    // The server runs for long, so its quota is only kept in memory
+    util::set_quota_limits(config.quota.clone());
 
-    println!("DrahtBot will will run the following features:");
-    for feature in features() {
-        println!(" - {}", feature.meta().name());
-        println!("   {}", feature.meta().description());
+    let token = args.token.expect("--token missing");
+    let mut octocrab = octocrab::Octocrab::builder().personal_token(token.clone());
+    if let Some(rate) = args.chaos {
+        let proxy = chaos::start("https://api.github.com", rate)?;
+        tracing::info!("CHAOS: Injecting GitHub API errors at rate {rate} via {proxy}");
+        octocrab = octocrab
+            .base_uri(proxy)
+            .map_err(DrahtBotError::GitHubError)?;
     }
+    let octocrab = octocrab.build().map_err(DrahtBotError::GitHubError)?;
 
-    println!();
+    tracing::info!("DrahtBot will will run the following features:");
+    for feature in features() {
+        tracing::info!(" - {}", feature.meta().name());
+        tracing::info!("   {}", feature.meta().description());
+    }
 
     // Get the bot's username
     let bot_username = octocrab
@@ -132,23 +351,277 @@ async fn main() -> Result<()> {
         .map_err(DrahtBotError::GitHubError)?
         .login;
 
-    println!("Running as {bot_username}...");
+    tracing::info!("Running as {bot_username}...");
 
     let context = web::Data::new(Context {
         octocrab,
+        token,
         bot_username,
         config,
         dry_run: args.dry_run,
+        reopen_on_comment: args.reopen_on_comment,
+        state: crate::state::StateStore::load(args.state_file)?,
+        pull_facts: args.pull_facts_file.map(::state::Store::new),
+        queue: crate::event_queue::EventQueue::load(args.queue_file)?,
+        metrics: crate::metrics::Metrics::default(),
+    });
+
+    match args.command {
+        Some(Command::BackfillSummaries(backfill_args)) => {
+            return crate::backfill::backfill_summaries(&context, backfill_args).await;
+        }
+        Some(Command::ExportReviews(export_args)) => {
+            return crate::export::export_reviews_data(&context, export_args).await;
+        }
+        Some(Command::ReviewerReport(report_args)) => {
+            return crate::reviewer_report::reviewer_report(&context, report_args).await;
+        }
+        Some(Command::Onboard(onboard_args)) => {
+            return crate::onboard::onboard(&context, onboard_args).await;
+        }
+        Some(Command::CheckConfig) | None => {}
+    }
+
+    crate::features::guix::requeue_labeled(&context).await;
+    let retry_context = context.clone();
+    actix_web::rt::spawn(async move {
+        loop {
+            actix_web::rt::time::sleep(std::time::Duration::from_secs(30)).await;
+            for queued in retry_context.queue.due(chrono::Utc::now()).await {
+                if let Err(err) = handle_queued(&retry_context, queued).await {
+                    tracing::error!("When retrying event: {err:?}");
+                }
+            }
+            crate::features::summary_comment::retry_failed_unrequests(&retry_context).await;
+            crate::features::summary_comment::request_reviews_after_push(&retry_context).await;
+            crate::features::conflicts::check_conflicts_freshness(&retry_context).await;
+        }
     });
 
-    HttpServer::new(move || {
+    let server = HttpServer::new(move || {
         App::new()
             .app_data(context.clone())
             .service(index)
             .service(postreceive_handler)
+            .service(crate::overview::overview)
+            .service(crate::flakes::flake_dashboard)
+            .service(crate::metrics::metrics)
     })
-    .bind(format!("{}:{}", args.host, args.port))?
-    .run()
-    .await?;
+    .shutdown_timeout(args.shutdown_timeout);
+    let server = match crate::activation::activated_listener() {
+        Some(listener) => {
+            tracing::info!("Listening on the socket passed by systemd");
+            server.listen(listener)?
+        }
+        None => server.bind(format!("{}:{}", args.host, args.port))?,
+    };
+    server.run().await?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    pub(crate) fn test_context(base_uri: String, dry_run: bool) -> Context {
+        util::retry_without_delay();
+        Context {
+            octocrab: octocrab::Octocrab::builder()
+                .base_uri(base_uri)
+                .unwrap()
+                .build()
+                .unwrap(),
+            token: "token".to_string(),
+            bot_username: "DrahtBot".to_string(),
+            config: serde_yaml::from_str(
+                r#"
+repositories:
+  - repo_slug: "owner/repo"
+    backport_label: "Backport"
+    repo_labels:
+      Wallet:
+        - '^wallet:'
+    corecheck: true
+    bot_labels:
+      - CI failed
+    merge_summary: true
+"#,
+            )
+            .unwrap(),
+            dry_run,
+            reopen_on_comment: true,
+            state: crate::state::StateStore::load(None).unwrap(),
+            pull_facts: None,
+            queue: crate::event_queue::EventQueue::load(None).unwrap(),
+            metrics: crate::metrics::Metrics::default(),
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_queue_error() {
+        let mut ctx = test_context("http://127.0.0.1:1".to_string(), true);
+        ctx.queue = crate::event_queue::EventQueue::load(Some(
+            std::env::temp_dir().join("drahtbot_missing_dir/queue.json"),
+        ))
+        .unwrap();
+        let app = actix_web::test::init_service(
+            App::new()
+                .app_data(web::Data::new(ctx))
+                .service(postreceive_handler),
+        )
+        .await;
+        let req = actix_web::test::TestRequest::post()
+            .uri("/drahtbot")
+            .insert_header(("X-GitHub-Event", "pull_request"))
+            .set_json(serde_json::json!({"action": "opened"}))
+            .to_request();
+        let resp = actix_web::test::call_service(&app, req).await;
+        assert_eq!(
+            resp.status(),
+            actix_web::http::StatusCode::INTERNAL_SERVER_ERROR
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_features_survive_api_errors() {
+        // Without fixtures, every call fails with a 404
+        let mock = crate::mock_github::MockGitHub::start(serde_json::json!({})).unwrap();
+        let repository = serde_json::json!({"owner": {"login": "owner"}, "name": "repo"});
+        let pull_request = serde_json::json!({
+            "number": 1,
+            "state": "open",
+            "merged": true,
+            "labels": [],
+            "base": {"repo": {"default_branch": "master"}},
+        });
+        // Each case with the features that fail and the calls they made before giving up (a 404 is
+        // retried once). No feature goes on to edit anything after a failed call.
+        let cases = [
+            (
+                GitHubEvent::PullRequest,
+                serde_json::json!({"action": "opened", "number": 1, "repository": repository, "pull_request": pull_request}),
+                &["Summary Comment", "Labels"][..],
+                &["GET /repos/owner/repo/pulls/1"; 4][..],
+            ),
+            (
+                GitHubEvent::PullRequest,
+                serde_json::json!({"action": "closed", "number": 1, "repository": repository, "pull_request": pull_request}),
+                &["Finalize"][..],
+                &["GET /repos/owner/repo/pulls/1"; 2][..],
+            ),
+            (
+                GitHubEvent::IssueComment,
+                serde_json::json!({"action": "created", "repository": repository, "issue": {"number": 1, "state": "open", "pull_request": {}}, "comment": {"id": 1, "user": {"login": "alice"}, "body": "ACK"}}),
+                &["Summary Comment"][..],
+                &["GET /repos/owner/repo/pulls/1"; 2][..],
+            ),
+            (
+                GitHubEvent::PullRequestReview,
+                serde_json::json!({"action": "submitted", "repository": repository, "pull_request": pull_request}),
+                &["Summary Comment"][..],
+                &["GET /repos/owner/repo/pulls/1"; 2][..],
+            ),
+            (
+                GitHubEvent::CheckSuite,
+                serde_json::json!({"action": "completed", "repository": repository, "check_suite": {"id": 1, "conclusion": "failure", "head_sha": "aa"}}),
+                &["CI Status"][..],
+                &[
+                    "GET /repos/owner/repo/check-suites/1/check-runs",
+                    "GET /repos/owner/repo/commits/aa/check-runs",
+                    "GET /repos/owner/repo/commits/aa/check-runs",
+                ][..],
+            ),
+        ];
+        let names = features()
+            .iter()
+            .map(|f| f.meta().name().to_string())
+            .collect::<Vec<_>>();
+        let ctx = test_context(mock.url.clone(), false);
+        for (event, payload, expected_failed, expected_requests) in &cases {
+            let before = mock.requests().len();
+            let failed = emit_event(&ctx, event, payload, &names).await;
+            assert_eq!(&failed, expected_failed, "{event}");
+            assert_eq!(&mock.requests()[before..], *expected_requests, "{event}");
+        }
+        assert_eq!(mock.mutations(), Vec::<String>::new());
+
+        // The same with the other kinds of errors, e.g. rate limits, which the chaos proxy injects
+        // before the calls reach the mock
+        let proxy = chaos::start(&mock.url, 1.0).unwrap();
+        let ctx = test_context(proxy, false);
+        let before = mock.requests().len();
+        for (event, payload, expected_failed, _) in &cases {
+            let failed = emit_event(&ctx, event, payload, &names).await;
+            assert_eq!(&failed, expected_failed, "{event}");
+        }
+        assert_eq!(mock.requests().len(), before);
+    }
+
+    #[actix_web::test]
+    async fn test_dry_run_has_no_mutations() {
+        let repository = serde_json::json!({"owner": {"login": "owner"}, "name": "repo"});
+        let pull_request = serde_json::json!({
+            "number": 1,
+            "state": "open",
+            "merged": true,
+            "labels": [{"name": "CI failed"}],
+            "base": {"repo": {"default_branch": "master"}},
+        });
+        // The CI status feature is missing, because it asks the Cirrus API for the pull number,
+        // which is not mocked.
+        let cases = [
+            (
+                GitHubEvent::PullRequest,
+                serde_json::json!({"action": "opened", "number": 1, "repository": repository, "pull_request": pull_request}),
+            ),
+            (
+                GitHubEvent::PullRequest,
+                serde_json::json!({"action": "closed", "number": 1, "repository": repository, "pull_request": pull_request}),
+            ),
+            (
+                GitHubEvent::PullRequestReview,
+                serde_json::json!({"action": "submitted", "repository": repository, "pull_request": pull_request}),
+            ),
+            (
+                GitHubEvent::PullRequestReviewThread,
+                serde_json::json!({"action": "resolved", "repository": repository, "pull_request": pull_request}),
+            ),
+            (
+                GitHubEvent::IssueComment,
+                serde_json::json!({"action": "created", "repository": repository, "issue": {"number": 1, "state": "open", "pull_request": {}}, "comment": {"id": 103, "user": {"login": "alice"}, "body": "@DrahtBot pause-reviews until 2099-01-01"}}),
+            ),
+        ];
+        let names = features()
+            .iter()
+            .map(|f| f.meta().name().to_string())
+            .collect::<Vec<_>>();
+        let mut mutations = Vec::new();
+        for dry_run in [false, true] {
+            let fixtures =
+                serde_json::from_str(include_str!("../testdata/github_api.json")).unwrap();
+            let mock = crate::mock_github::MockGitHub::start(fixtures).unwrap();
+            let ctx = test_context(mock.url.clone(), dry_run);
+            for (event, payload) in &cases {
+                emit_event(&ctx, event, payload, &names).await;
+            }
+            mutations.push(mock.mutations());
+        }
+        // Without --dry-run, the recorded responses lead to edits of each kind, so that a new
+        // code path that forgets to check for --dry-run is caught below.
+        for call in [
+            "POST /repos/owner/repo/issues/1/comments",
+            "POST /repos/owner/repo/issues/1/labels",
+            "DELETE /repos/owner/repo/issues/1/labels/CI%20failed",
+            "POST /repos/owner/repo/pulls/1/requested_reviewers",
+            "DELETE /repos/owner/repo/pulls/1/requested_reviewers",
+            "POST /repos/owner/repo/issues/comments/103/reactions",
+        ] {
+            assert!(
+                mutations[0].iter().any(|m| m == call),
+                "{call} missing in {:?}",
+                mutations[0]
+            );
+        }
+        assert_eq!(mutations[1], Vec::<String>::new());
+    }
+}
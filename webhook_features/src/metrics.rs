@@ -0,0 +1,195 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use actix_web::{get, web, HttpResponse};
+
+use crate::Context;
+
+/// The upper bounds (in seconds) of the feature latency histogram buckets.
+const LATENCY_BUCKETS: [f64; 8] = [0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0];
+
+#[derive(Default)]
+struct Histogram {
+    /// The number of observations per bucket in LATENCY_BUCKETS (not cumulative).
+    buckets: [u64; LATENCY_BUCKETS.len()],
+    count: u64,
+    sum: f64,
+}
+
+#[derive(Default)]
+struct Counters {
+    /// By event type.
+    events: BTreeMap<String, u64>,
+    /// By feature name.
+    feature_latency: BTreeMap<String, Histogram>,
+    /// By feature name.
+    feature_errors: BTreeMap<String, u64>,
+    /// By HTTP status.
+    github_errors: BTreeMap<u16, u64>,
+}
+
+/// Counters of the server, exported in the Prometheus text format on /metrics. They are kept in
+/// memory and reset on restart.
+#[derive(Default)]
+pub struct Metrics {
+    counters: std::sync::Mutex<Counters>,
+}
+
+impl Metrics {
+    pub fn event_received(&self, event: &str) {
+        let mut c = self.counters.lock().unwrap();
+        *c.events.entry(event.to_string()).or_default() += 1;
+    }
+
+    /// Record that a feature handled an event, and whether it failed.
+    pub fn feature_handled(
+        &self,
+        feature: &str,
+        duration: std::time::Duration,
+        result: &crate::errors::Result<()>,
+    ) {
+        let mut c = self.counters.lock().unwrap();
+        let secs = duration.as_secs_f64();
+        let h = c.feature_latency.entry(feature.to_string()).or_default();
+        if let Some(i) = LATENCY_BUCKETS.iter().position(|b| secs <= *b) {
+            h.buckets[i] += 1;
+        }
+        h.count += 1;
+        h.sum += secs;
+        let Err(err) = result else {
+            return;
+        };
+        *c.feature_errors.entry(feature.to_string()).or_default() += 1;
+        let github_err = err.downcast_ref::<octocrab::Error>().or_else(|| {
+            match err.downcast_ref::<crate::errors::DrahtBotError>() {
+                Some(crate::errors::DrahtBotError::GitHubError(e)) => Some(e),
+                _ => None,
+            }
+        });
+        if let Some(octocrab::Error::GitHub { source, .. }) = github_err {
+            *c.github_errors
+                .entry(source.status_code.as_u16())
+                .or_default() += 1;
+        }
+    }
+
+    fn render(&self) -> String {
+        let c = self.counters.lock().unwrap();
+        let mut out = String::new();
+
+        out += "# HELP drahtbot_events_received_total Webhook events received, by type.\n";
+        out += "# TYPE drahtbot_events_received_total counter\n";
+        for (event, n) in &c.events {
+            writeln!(
+                out,
+                "drahtbot_events_received_total{{event=\"{event}\"}} {n}"
+            )
+            .unwrap();
+        }
+
+        out += "# HELP drahtbot_feature_duration_seconds Time a feature took to handle an event.\n";
+        out += "# TYPE drahtbot_feature_duration_seconds histogram\n";
+        for (feature, h) in &c.feature_latency {
+            let mut cumulative = 0;
+            for (bound, n) in LATENCY_BUCKETS.iter().zip(h.buckets) {
+                cumulative += n;
+                writeln!(
+                    out,
+                    "drahtbot_feature_duration_seconds_bucket{{feature=\"{feature}\",le=\"{bound}\"}} {cumulative}"
+                )
+                .unwrap();
+            }
+            writeln!(
+                out,
+                "drahtbot_feature_duration_seconds_bucket{{feature=\"{feature}\",le=\"+Inf\"}} {}",
+                h.count
+            )
+            .unwrap();
+            writeln!(
+                out,
+                "drahtbot_feature_duration_seconds_sum{{feature=\"{feature}\"}} {}",
+                h.sum
+            )
+            .unwrap();
+            writeln!(
+                out,
+                "drahtbot_feature_duration_seconds_count{{feature=\"{feature}\"}} {}",
+                h.count
+            )
+            .unwrap();
+        }
+
+        out += "# HELP drahtbot_feature_errors_total Events a feature failed to handle.\n";
+        out += "# TYPE drahtbot_feature_errors_total counter\n";
+        for (feature, n) in &c.feature_errors {
+            writeln!(
+                out,
+                "drahtbot_feature_errors_total{{feature=\"{feature}\"}} {n}"
+            )
+            .unwrap();
+        }
+
+        out += "# HELP drahtbot_github_api_errors_total GitHub API errors that failed a feature, by HTTP status.\n";
+        out += "# TYPE drahtbot_github_api_errors_total counter\n";
+        for (status, n) in &c.github_errors {
+            writeln!(
+                out,
+                "drahtbot_github_api_errors_total{{status=\"{status}\"}} {n}"
+            )
+            .unwrap();
+        }
+
+        out += "# HELP drahtbot_quota_denials_total Actions deferred, because the hourly quota was reached.\n";
+        out += "# TYPE drahtbot_quota_denials_total counter\n";
+        for (repo, action, n) in util::quota_denials() {
+            writeln!(
+                out,
+                "drahtbot_quota_denials_total{{repo=\"{repo}\",action=\"{action:?}\"}} {n}"
+            )
+            .unwrap();
+        }
+        out
+    }
+}
+
+#[get("/metrics")]
+async fn metrics(ctx: web::Data<Context>) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(ctx.metrics.render())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let m = Metrics::default();
+        m.event_received("pull_request");
+        m.event_received("pull_request");
+        m.feature_handled("Labels", std::time::Duration::from_millis(300), &Ok(()));
+        m.feature_handled(
+            "Labels",
+            std::time::Duration::from_secs(60),
+            &Err(crate::errors::DrahtBotError::KeyNotFound.into()),
+        );
+        let out = m.render();
+        assert!(out.contains("drahtbot_events_received_total{event=\"pull_request\"} 2\n"));
+        assert!(out.contains(
+            "drahtbot_feature_duration_seconds_bucket{feature=\"Labels\",le=\"0.25\"} 0\n"
+        ));
+        assert!(out.contains(
+            "drahtbot_feature_duration_seconds_bucket{feature=\"Labels\",le=\"0.5\"} 1\n"
+        ));
+        assert!(out.contains(
+            "drahtbot_feature_duration_seconds_bucket{feature=\"Labels\",le=\"30\"} 1\n"
+        ));
+        assert!(out.contains(
+            "drahtbot_feature_duration_seconds_bucket{feature=\"Labels\",le=\"+Inf\"} 2\n"
+        ));
+        assert!(out.contains("drahtbot_feature_duration_seconds_count{feature=\"Labels\"} 2\n"));
+        assert!(out.contains("drahtbot_feature_errors_total{feature=\"Labels\"} 1\n"));
+        assert!(out.contains("# TYPE drahtbot_quota_denials_total counter\n"));
+    }
+}
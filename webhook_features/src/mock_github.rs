@@ -0,0 +1,86 @@
+use std::sync::{Arc, Mutex};
+
+use actix_web::{web, App, HttpRequest, HttpResponse, HttpServer};
+
+/// A local stand-in for the GitHub API in tests. Requests are answered from recorded responses,
+/// and are recorded themselves, to check which calls the features make.
+pub struct MockGitHub {
+    pub url: String,
+    requests: Arc<Mutex<Vec<String>>>,
+}
+
+struct State {
+    /// The recorded response body, by method and path (without the query), e.g.
+    /// "GET /repos/owner/repo/pulls/1".
+    fixtures: serde_json::Map<String, serde_json::Value>,
+    requests: Arc<Mutex<Vec<String>>>,
+}
+
+async fn handle(state: web::Data<State>, req: HttpRequest, body: web::Bytes) -> HttpResponse {
+    let call = format!("{} {}", req.method(), req.path());
+    let fixture = state.fixtures.get(&call);
+    // GraphQL queries and mutations share the same endpoint, so record which one it was
+    let operation = (req.path() == "/graphql")
+        .then(|| serde_json::from_slice::<serde_json::Value>(&body).ok())
+        .flatten()
+        .and_then(|b| b["query"].as_str().map(|q| q.trim_start().to_string()))
+        .map(|q| {
+            if q.starts_with("mutation") {
+                " mutation"
+            } else {
+                " query"
+            }
+        });
+    state
+        .requests
+        .lock()
+        .unwrap()
+        .push(format!("{call}{}", operation.unwrap_or_default()));
+    match fixture {
+        Some(body) => HttpResponse::Ok().json(body),
+        None => HttpResponse::NotFound().json(serde_json::json!({
+            "message": "Not Found",
+            "documentation_url": "https://docs.github.com/rest",
+        })),
+    }
+}
+
+impl MockGitHub {
+    /// Start the mock in the background, answering from the given map of request to response body.
+    pub fn start(fixtures: serde_json::Value) -> std::io::Result<Self> {
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let state = web::Data::new(State {
+            fixtures: fixtures
+                .as_object()
+                .expect("fixtures must be a map")
+                .clone(),
+            requests: requests.clone(),
+        });
+        let server = HttpServer::new(move || {
+            App::new()
+                .app_data(state.clone())
+                .default_service(web::to(handle))
+        })
+        .workers(1)
+        .bind(("127.0.0.1", 0))?;
+        let addr = server.addrs()[0];
+        actix_web::rt::spawn(server.run());
+        Ok(Self {
+            url: format!("http://{addr}"),
+            requests,
+        })
+    }
+
+    /// Return all requests so far, e.g. "GET /repos/owner/repo/pulls/1" or "POST /graphql query".
+    pub fn requests(&self) -> Vec<String> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    /// Return the requests that could change something on GitHub.
+    pub fn mutations(&self) -> Vec<String> {
+        self.requests()
+            .into_iter()
+            .filter(|r| !r.starts_with("GET ") && r != "POST /graphql query")
+            .collect()
+    }
+}
@@ -0,0 +1,541 @@
+use crate::errors::Result;
+use crate::features::ci_status::{FailureCategory, CI_FAILED_LABEL};
+use crate::features::guix::GUIX_LABEL;
+use crate::Context;
+use crate::GitHubEvent;
+
+#[derive(clap::Args)]
+pub struct OnboardArgs {
+    /// The repo slug of the remote on GitHub. Format: owner/repo
+    #[arg(long)]
+    repo: util::Slug,
+    /// The public url of the webhook endpoint of this server, e.g.
+    /// https://bot.example.com/drahtbot. Default: any url ending in /drahtbot.
+    #[arg(long)]
+    webhook_url: Option<String>,
+    /// Write the starter config stanza of the repo to this file, instead of printing it.
+    #[arg(long)]
+    stanza_file: Option<std::path::PathBuf>,
+}
+
+/// The color of the labels created by the bot (the default gray of GitHub).
+const LABEL_COLOR: &str = "ededed";
+
+/// Return the labels that the features set or react to, along with a description for the ones
+/// that have to be created.
+fn required_labels() -> Vec<(&'static str, &'static str)> {
+    [(CI_FAILED_LABEL, "The CI of the pull request failed")]
+        .into_iter()
+        .chain(
+            FailureCategory::ALL
+                .iter()
+                .map(|c| (c.label(), "The CI of the pull request failed in this step")),
+        )
+        .chain([
+            (GUIX_LABEL, "Request a guix build of the pull request"),
+            (util::QUIET_LABEL, "Keep the bot from commenting"),
+        ])
+        .collect()
+}
+
+/// Return the required labels that are missing in the repo.
+fn missing_labels<'a>(
+    existing: &[String],
+    required: &[(&'a str, &'a str)],
+) -> Vec<(&'a str, &'a str)> {
+    required
+        .iter()
+        .filter(|(name, _)| !existing.iter().any(|e| e.eq_ignore_ascii_case(name)))
+        .copied()
+        .collect()
+}
+
+/// Return the names of the webhook events that any of the features handles, sorted.
+fn webhook_events() -> Vec<String> {
+    let mut events = crate::features()
+        .iter()
+        .flat_map(|f| f.meta().events().iter().map(|e| e.to_string()))
+        .collect::<Vec<_>>();
+    events.sort();
+    events.dedup();
+    events
+}
+
+/// Return the problems of the token scopes, as returned in the X-OAuth-Scopes header. Only
+/// classic tokens have scopes, so nothing is checked for other tokens.
+fn scope_problems(scopes: Option<&str>) -> Vec<String> {
+    let Some(scopes) = scopes else {
+        return Vec::new();
+    };
+    let scopes = scopes.split(',').map(|s| s.trim()).collect::<Vec<_>>();
+    let mut problems = Vec::new();
+    if !scopes.iter().any(|s| ["repo", "public_repo"].contains(s)) {
+        problems.push(format!(
+            "token: The scope 'repo' or 'public_repo' is missing (scopes: {scopes:?})"
+        ));
+    }
+    if !scopes
+        .iter()
+        .any(|s| ["admin:repo_hook", "write:repo_hook", "read:repo_hook"].contains(s))
+    {
+        problems.push(format!(
+            "token: The scope 'read:repo_hook' is missing, so the webhook can not be checked (scopes: {scopes:?})"
+        ));
+    }
+    problems
+}
+
+/// Return the problems of the permissions of the token in the repo. Setting labels and
+/// requesting reviews needs at least triage access.
+fn permission_problems(slug: &str, permissions: &serde_json::Value) -> Vec<String> {
+    let has = |p: &str| permissions[p].as_bool().unwrap_or_default();
+    if ["triage", "push", "maintain", "admin"].into_iter().any(has) {
+        return Vec::new();
+    }
+    vec![format!(
+        "{slug}: The token has no triage access, so it can not set labels or request reviews"
+    )]
+}
+
+/// Return the problems of the webhooks of the repo, given the url of the endpoint (if known) and
+/// the events the features need.
+fn hook_problems(
+    slug: &str,
+    hooks: &[serde_json::Value],
+    url: Option<&str>,
+    events: &[String],
+) -> Vec<String> {
+    let matches = |u: &str| match url {
+        Some(url) => u == url,
+        None => u.ends_with("/drahtbot"),
+    };
+    let Some(hook) = hooks
+        .iter()
+        .find(|h| matches(h["config"]["url"].as_str().unwrap_or_default()))
+    else {
+        return vec![format!(
+            "{slug}: No webhook with the url {} found",
+            url.unwrap_or("*/drahtbot")
+        )];
+    };
+    let mut problems = Vec::new();
+    if hook["active"].as_bool() != Some(true) {
+        problems.push(format!("{slug}: The webhook is not active"));
+    }
+    if hook["config"]["content_type"].as_str() != Some("json") {
+        problems.push(format!(
+            "{slug}: The content type of the webhook must be application/json"
+        ));
+    }
+    let hook_events = hook["events"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|e| e.as_str())
+        .collect::<Vec<_>>();
+    if !hook_events.contains(&"*") {
+        let missing = events
+            .iter()
+            .filter(|e| !hook_events.contains(&e.as_str()))
+            .collect::<Vec<_>>();
+        if !missing.is_empty() {
+            problems.push(format!(
+                "{slug}: The webhook does not send the events {missing:?}"
+            ));
+        }
+    }
+    problems
+}
+
+/// Return a starter config stanza for the repo, to be added to the list of repositories. The bot
+/// labels are the ones set by the CI status and guix features.
+fn starter_stanza(slug: &util::Slug) -> String {
+    let bot_labels = required_labels()
+        .into_iter()
+        .map(|(name, _)| name)
+        .filter(|name| *name != util::QUIET_LABEL)
+        .map(|name| format!("      - \"{name}\"\n"))
+        .collect::<String>();
+    format!(
+        r#"  - repo_slug: "{slug}"
+    backport_label: "Backport"
+    repo_labels: {{}}
+    corecheck: false
+    bot_labels:
+{bot_labels}"#,
+        slug = slug.str()
+    )
+}
+
+/// Return a payload of the event on the pull request, as GitHub would send it, or None if the
+/// event is not about pull requests.
+fn sample_payload(
+    event: &GitHubEvent,
+    repository: &serde_json::Value,
+    pull: &serde_json::Value,
+    check_suite: Option<&serde_json::Value>,
+    bot_username: &str,
+) -> Option<serde_json::Value> {
+    let number = &pull["number"];
+    let issue = serde_json::json!({
+        "number": number,
+        "state": pull["state"],
+        "user": pull["user"],
+        "labels": pull["labels"],
+        "pull_request": {},
+    });
+    let payload = match event {
+        GitHubEvent::PullRequest => serde_json::json!({
+            "action": "opened",
+            "number": number,
+            "pull_request": pull,
+        }),
+        GitHubEvent::PullRequestReview => serde_json::json!({
+            "action": "submitted",
+            "pull_request": pull,
+        }),
+        GitHubEvent::PullRequestReviewThread => serde_json::json!({
+            "action": "resolved",
+            "pull_request": pull,
+        }),
+        GitHubEvent::IssueComment => serde_json::json!({
+            "action": "created",
+            "issue": issue,
+            "comment": {"id": 0, "user": {"login": bot_username}, "body": ""},
+        }),
+        GitHubEvent::Issues => serde_json::json!({
+            "action": "labeled",
+            "issue": issue,
+            "label": {"name": ""},
+        }),
+        GitHubEvent::CheckSuite => {
+            let mut check_suite = check_suite?.clone();
+            check_suite["pull_requests"] = serde_json::json!([{"number": number}]);
+            serde_json::json!({
+                "action": "completed",
+                "check_suite": check_suite,
+            })
+        }
+        GitHubEvent::Unknown => return None,
+    };
+    let mut payload = payload;
+    payload["repository"] = repository.clone();
+    Some(payload)
+}
+
+/// Return all items of the paginated list at the route.
+async fn get_all(ctx: &Context, route: &str) -> Result<Vec<serde_json::Value>> {
+    let mut items = Vec::new();
+    for page in 1.. {
+        let batch: Vec<serde_json::Value> = ctx
+            .octocrab
+            .get(
+                route,
+                Some(&[("per_page", "100"), ("page", &page.to_string())]),
+            )
+            .await?;
+        let done = batch.len() < 100;
+        items.extend(batch);
+        if done {
+            break;
+        }
+    }
+    Ok(items)
+}
+
+/// Run each feature once in dry-run mode with the config stanza, on an event of the newest open
+/// pull request, and return the problems.
+async fn dry_run_features(
+    ctx: &Context,
+    slug: &util::Slug,
+    repository: &serde_json::Value,
+    stanza: &str,
+) -> Result<Vec<String>> {
+    let util::Slug { owner, repo } = slug;
+    let pulls: Vec<serde_json::Value> = ctx
+        .octocrab
+        .get(
+            format!("/repos/{owner}/{repo}/pulls"),
+            Some(&[("state", "open"), ("per_page", "1")]),
+        )
+        .await?;
+    let Some(pull) = pulls.first() else {
+        tracing::warn!("No open pull request to run the features on");
+        return Ok(Vec::new());
+    };
+    let check_suites: serde_json::Value = ctx
+        .octocrab
+        .get(
+            format!(
+                "/repos/{owner}/{repo}/commits/{}/check-suites",
+                pull["head"]["sha"].as_str().unwrap_or_default()
+            ),
+            None::<&()>,
+        )
+        .await?;
+    let check_suite = check_suites["check_suites"].get(0);
+
+    let dry_ctx = Context {
+        octocrab: ctx.octocrab.clone(),
+        token: ctx.token.clone(),
+        bot_username: ctx.bot_username.clone(),
+        config: serde_yaml::from_str(&format!("repositories:\n{stanza}"))?,
+        dry_run: true,
+        reopen_on_comment: false,
+        state: crate::state::StateStore::load(None)?,
+        pull_facts: None,
+        queue: crate::event_queue::EventQueue::load(None)?,
+        metrics: crate::metrics::Metrics::default(),
+    };
+    let mut problems = Vec::new();
+    for feature in crate::features() {
+        let name = feature.meta().name();
+        let Some((event, payload)) = feature.meta().events().iter().find_map(|e| {
+            sample_payload(e, repository, pull, check_suite, &ctx.bot_username).map(|p| (e, p))
+        }) else {
+            tracing::warn!("{name}: No event to run the feature on");
+            continue;
+        };
+        tracing::info!("Dry run {name} on {event} of pull {}", pull["number"]);
+        if let Err(err) = feature.handle(&dry_ctx, event, &payload).await {
+            problems.push(format!(
+                "{}: {name}: The dry run failed: {err:?}",
+                slug.str()
+            ));
+        }
+    }
+    Ok(problems)
+}
+
+/// Prepare a repo for the bot and check that it is set up correctly: The token has the needed
+/// scopes and permissions, the labels of the features exist (missing ones are created), and the
+/// webhook sends the events of the features. Then, write a starter config stanza and run each
+/// feature once with it in dry-run mode.
+pub async fn onboard(ctx: &Context, args: OnboardArgs) -> Result<()> {
+    let slug_str = args.repo.str();
+    let util::Slug { owner, repo } = &args.repo;
+    let mut problems = Vec::new();
+
+    tracing::info!("Check the token ...");
+    let root = ctx.octocrab._get("/").await?;
+    let scopes = root
+        .headers()
+        .get("x-oauth-scopes")
+        .and_then(|s| s.to_str().ok());
+    problems.extend(scope_problems(scopes));
+    let repository: serde_json::Value = ctx
+        .octocrab
+        .get(format!("/repos/{owner}/{repo}"), None::<&()>)
+        .await?;
+    problems.extend(permission_problems(&slug_str, &repository["permissions"]));
+
+    tracing::info!("Check the labels ...");
+    let existing = get_all(ctx, &format!("/repos/{owner}/{repo}/labels"))
+        .await?
+        .iter()
+        .filter_map(|l| l["name"].as_str().map(|n| n.to_string()))
+        .collect::<Vec<_>>();
+    let issues_api = ctx.octocrab.issues(owner, repo);
+    for (name, description) in missing_labels(&existing, &required_labels()) {
+        tracing::info!(" ... create label '{name}'");
+        if ctx.dry_run {
+            util::dry_run_preview(&format!("POST /repos/{slug_str}/labels {name}"), None);
+        } else {
+            issues_api
+                .create_label(name, LABEL_COLOR, description)
+                .await?;
+        }
+    }
+
+    tracing::info!("Check the webhook ...");
+    if repository["permissions"]["admin"].as_bool() == Some(true) {
+        let hooks = get_all(ctx, &format!("/repos/{owner}/{repo}/hooks")).await?;
+        problems.extend(hook_problems(
+            &slug_str,
+            &hooks,
+            args.webhook_url.as_deref(),
+            &webhook_events(),
+        ));
+    } else {
+        tracing::warn!(
+            "The webhooks can only be read with admin access. Check in the repo settings that a webhook sends the events {:?} as json.",
+            webhook_events()
+        );
+    }
+
+    tracing::info!("Write the config stanza ...");
+    let stanza = starter_stanza(&args.repo);
+    if ctx
+        .config
+        .repositories
+        .iter()
+        .any(|r| r.repo_slug == slug_str)
+    {
+        tracing::warn!("{slug_str} is already in the config file");
+    }
+    match &args.stanza_file {
+        Some(path) => std::fs::write(path, &stanza)?,
+        None => println!("{stanza}"),
+    }
+
+    tracing::info!("Run each feature once in dry-run mode ...");
+    problems.extend(dry_run_features(ctx, &args.repo, &repository, &stanza).await?);
+
+    for p in &problems {
+        tracing::error!("{p}");
+    }
+    if !problems.is_empty() {
+        anyhow::bail!("{} problems found for {slug_str}", problems.len());
+    }
+    tracing::info!("{slug_str} is ready for the bot");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_labels() {
+        let existing = ["ci failed".to_string(), GUIX_LABEL.to_string()];
+        let missing = missing_labels(&existing, &required_labels())
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            missing,
+            [
+                "CI failed: build",
+                "CI failed: tests",
+                "CI failed: lint",
+                util::QUIET_LABEL
+            ]
+        );
+    }
+
+    #[test]
+    fn test_token() {
+        assert!(scope_problems(None).is_empty());
+        assert!(scope_problems(Some("repo, read:repo_hook")).is_empty());
+        let problems = scope_problems(Some("gist, read:org"));
+        assert_eq!(problems.len(), 2, "{problems:?}");
+        assert!(problems[1].starts_with("token: The scope 'read:repo_hook' is missing"));
+
+        let permissions = serde_json::json!({"admin": false, "push": false, "pull": true});
+        assert_eq!(permission_problems("o/r", &permissions).len(), 1);
+        let permissions = serde_json::json!({"admin": false, "push": true, "pull": true});
+        assert!(permission_problems("o/r", &permissions).is_empty());
+    }
+
+    #[test]
+    fn test_hooks() {
+        let events = webhook_events();
+        assert_eq!(
+            events,
+            [
+                "check_suite",
+                "issue_comment",
+                "issues",
+                "pull_request",
+                "pull_request_review",
+                "pull_request_review_thread"
+            ]
+        );
+        let hook = |url: &str, content_type: &str, events: &[&str]| {
+            serde_json::json!({
+                "active": true,
+                "events": events,
+                "config": {"url": url, "content_type": content_type},
+            })
+        };
+        let ok = hook("https://bot.example.com/drahtbot", "json", &["*"]);
+        assert!(hook_problems("o/r", std::slice::from_ref(&ok), None, &events).is_empty());
+        assert_eq!(
+            hook_problems(
+                "o/r",
+                &[ok],
+                Some("https://other.example.com/drahtbot"),
+                &events
+            ),
+            ["o/r: No webhook with the url https://other.example.com/drahtbot found"]
+        );
+        let bad = hook(
+            "https://bot.example.com/drahtbot",
+            "form",
+            &["pull_request", "issue_comment", "issues"],
+        );
+        assert_eq!(
+            hook_problems("o/r", &[bad], None, &events),
+            [
+                "o/r: The content type of the webhook must be application/json",
+                r#"o/r: The webhook does not send the events ["check_suite", "pull_request_review", "pull_request_review_thread"]"#
+            ]
+        );
+    }
+
+    #[test]
+    fn test_starter_stanza() {
+        let stanza = starter_stanza(&"owner/repo".parse().unwrap());
+        let config = serde_yaml::from_str(&format!("repositories:\n{stanza}")).unwrap();
+        assert_eq!(
+            crate::check_config::check_config(&config),
+            Vec::<String>::new()
+        );
+        assert!(stanza.contains(GUIX_LABEL));
+        assert!(!stanza.contains(util::QUIET_LABEL));
+    }
+
+    #[actix_web::test]
+    async fn test_onboard_dry_run() {
+        let pull = serde_json::json!({
+            "number": 1,
+            "state": "open",
+            "merged": false,
+            "labels": [],
+            "user": {"login": "alice"},
+            "head": {"sha": "aaaa"},
+            "base": {"repo": {"default_branch": "master"}},
+        });
+        let fixtures = serde_json::json!({
+            "GET /": {},
+            "GET /repos/owner/repo": {
+                "name": "repo",
+                "owner": {"login": "owner"},
+                "permissions": {"admin": true, "push": true, "pull": true},
+            },
+            "GET /repos/owner/repo/labels": [{"name": CI_FAILED_LABEL}],
+            "GET /repos/owner/repo/hooks": [{
+                "active": true,
+                "events": ["*"],
+                "config": {"url": "https://bot.example.com/drahtbot", "content_type": "json"},
+            }],
+            "GET /repos/owner/repo/pulls": [pull],
+            "GET /repos/owner/repo/commits/aaaa/check-suites": {"check_suites": []},
+        });
+        let mock = crate::mock_github::MockGitHub::start(fixtures).unwrap();
+        let ctx = crate::tests::test_context(mock.url.clone(), true);
+        let stanza_file = std::env::temp_dir().join("drahtbot_test_onboard_stanza.yml");
+        let args = OnboardArgs {
+            repo: "owner/repo".parse().unwrap(),
+            webhook_url: None,
+            stanza_file: Some(stanza_file.clone()),
+        };
+        // The features fail on the calls that are not mocked, so only check that nothing was
+        // changed.
+        let _ = onboard(&ctx, args).await;
+        assert_eq!(mock.mutations(), Vec::<String>::new());
+        let requests = mock.requests();
+        for call in [
+            "GET /repos/owner/repo/labels",
+            "GET /repos/owner/repo/hooks",
+            "GET /repos/owner/repo/commits/aaaa/check-suites",
+        ] {
+            assert!(requests.iter().any(|r| r == call), "{call} in {requests:?}");
+        }
+        assert_eq!(
+            std::fs::read_to_string(&stanza_file).unwrap(),
+            starter_stanza(&"owner/repo".parse().unwrap())
+        );
+        std::fs::remove_file(stanza_file).unwrap();
+    }
+}
@@ -0,0 +1,81 @@
+use std::collections::BTreeMap;
+
+use actix_web::{get, web, HttpResponse};
+
+use crate::Context;
+
+/// Aggregated state of a repo, for external dashboards.
+#[derive(serde::Serialize)]
+struct Overview {
+    open_pulls: usize,
+    /// The number of open pull requests per label.
+    pulls_by_label: BTreeMap<String, usize>,
+    /// The number of open pull requests per label set by the bot (waiting on the author or on
+    /// the bot).
+    bot_label_queues: BTreeMap<String, usize>,
+    /// The share of failed CI results in the last week, if any were seen.
+    ci_failure_rate_7d: Option<f64>,
+    /// The last run of the conflicts tool, if recorded in the pull facts.
+    conflicts_last_run: Option<::state::ConflictsRun>,
+    /// Whether the conflicts data is older than `conflicts_max_age_hours`.
+    conflicts_outdated: bool,
+}
+
+#[get("/api/repo/{owner}/{repo}/overview")]
+async fn overview(ctx: web::Data<Context>, path: web::Path<(String, String)>) -> HttpResponse {
+    let (owner, repo) = path.into_inner();
+    let slug = format!("{owner}/{repo}");
+    let Some(config_repo) = ctx.config.repositories.iter().find(|r| r.repo_slug == slug) else {
+        return HttpResponse::NotFound().body("Unknown repo");
+    };
+
+    let pulls = match ctx
+        .octocrab
+        .pulls(&owner, &repo)
+        .list()
+        .state(octocrab::params::State::Open)
+        .per_page(100)
+        .send()
+        .await
+    {
+        Ok(page) => ctx.octocrab.all_pages(page).await,
+        Err(e) => Err(e),
+    };
+    let pulls = match pulls {
+        Ok(pulls) => pulls,
+        Err(e) => return HttpResponse::BadGateway().body(format!("GitHub Error {e}")),
+    };
+
+    let mut pulls_by_label = BTreeMap::new();
+    for label in pulls.iter().flat_map(|p| p.labels.iter().flatten()) {
+        *pulls_by_label.entry(label.name.clone()).or_insert(0) += 1;
+    }
+    let bot_label_queues = config_repo
+        .bot_labels
+        .iter()
+        .map(|l| (l.clone(), pulls_by_label.get(l).copied().unwrap_or(0)))
+        .collect();
+    let ci_failure_rate_7d = ctx
+        .state
+        .read(|s| s.ci_failure_rate(&slug, chrono::Utc::now().date_naive()))
+        .await;
+    let conflicts_last_run = ctx
+        .read_pull_facts()
+        .await
+        .ok()
+        .flatten()
+        .and_then(|f| f.conflicts_runs.get(&slug).cloned());
+    let conflicts_outdated = ctx
+        .state
+        .read(|s| s.conflicts_outdated.contains_key(&slug))
+        .await;
+
+    HttpResponse::Ok().json(Overview {
+        open_pulls: pulls.len(),
+        pulls_by_label,
+        bot_label_queues,
+        ci_failure_rate_7d,
+        conflicts_last_run,
+        conflicts_outdated,
+    })
+}
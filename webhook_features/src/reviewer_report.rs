@@ -0,0 +1,252 @@
+use crate::errors::{DrahtBotError, Result};
+use crate::Context;
+
+#[derive(clap::Args)]
+pub struct ReviewerReportArgs {
+    /// The repo slug of the remote on GitHub. Format: owner/repo
+    #[arg(long)]
+    repo: util::Slug,
+    /// A clone of the reports repo, with the data published by export-reviews.
+    #[arg(long)]
+    export_dir: std::path::PathBuf,
+    /// The month to report on. Format: YYYY-MM. Default: the previous month.
+    #[arg(long)]
+    month: Option<String>,
+    /// Also post the report as a comment to the GitHub discussion with this number.
+    #[arg(long)]
+    discussion: Option<u64>,
+}
+
+/// The review activity of a reviewer in a month.
+#[derive(Debug, PartialEq)]
+struct ReviewerStats {
+    user: String,
+    acks: usize,
+    reviews: usize,
+    pulls: usize,
+}
+
+/// The review types that count as an ACK. A stale ACK was an ACK of an earlier commit.
+const ACK_TYPES: [&str; 5] = ["ACK", "Tested ACK", "utACK", "Light ACK", "Stale ACK"];
+
+/// Aggregate the exported reviews and comments of the pull requests with a date in the given range,
+/// most ACKs first. Every review counts, not only the latest of each reviewer.
+fn reviewer_stats(
+    pulls: &[(u64, serde_json::Value)],
+    from: chrono::DateTime<chrono::Utc>,
+    to: chrono::DateTime<chrono::Utc>,
+) -> Vec<ReviewerStats> {
+    let mut stats = Vec::<ReviewerStats>::new();
+    for (_, data) in pulls {
+        let mut reviewers = Vec::new();
+        // Exports written before the history was added only have the latest reviews
+        let history = data.get("history").unwrap_or(&data["reviews"]);
+        let comments = data["comments"].as_array().into_iter().flatten();
+        for review in history.as_array().into_iter().flatten().chain(comments) {
+            let Some(date) = review["date"]
+                .as_str()
+                .and_then(|d| d.parse::<chrono::DateTime<chrono::Utc>>().ok())
+            else {
+                continue;
+            };
+            if date < from || date >= to {
+                continue;
+            }
+            let user = review["user"].as_str().unwrap_or_default();
+            let s = match stats.iter_mut().find(|s| s.user == user) {
+                Some(s) => s,
+                None => {
+                    stats.push(ReviewerStats {
+                        user: user.to_string(),
+                        acks: 0,
+                        reviews: 0,
+                        pulls: 0,
+                    });
+                    stats.last_mut().unwrap()
+                }
+            };
+            s.reviews += 1;
+            if ACK_TYPES.contains(&review["type"].as_str().unwrap_or_default()) {
+                s.acks += 1;
+            }
+            if !reviewers.contains(&user) {
+                reviewers.push(user);
+                s.pulls += 1;
+            }
+        }
+    }
+    stats.sort_by(|a, b| (b.acks, b.reviews, &a.user).cmp(&(a.acks, a.reviews, &b.user)));
+    stats
+}
+
+fn report_text(slug: &util::Slug, month: &str, stats: &[ReviewerStats]) -> String {
+    let mut text = format!("## Reviewers of {} in {month}\n\n", slug.str());
+    if stats.is_empty() {
+        text += "No reviews were recorded this month.\n";
+        return text;
+    }
+    text += "Thank you to everyone who reviewed pull requests this month! Reviews are the bottleneck of the project, and every one of them helps.\n\n";
+    text += "| Reviewer | ACKs | Reviews | Pull requests |\n";
+    text += "| -------- | ---- | ------- | ------------- |\n";
+    for s in stats {
+        text += &format!(
+            "| [{user}](https://github.com/{user}) | {} | {} | {} |\n",
+            s.acks,
+            s.reviews,
+            s.pulls,
+            user = s.user
+        );
+    }
+    text
+}
+
+/// Return the start of the month and the start of the following month.
+fn month_range(
+    month: &str,
+) -> Option<(chrono::DateTime<chrono::Utc>, chrono::DateTime<chrono::Utc>)> {
+    let from = chrono::NaiveDate::parse_from_str(&format!("{month}-01"), "%Y-%m-%d").ok()?;
+    let to = from.checked_add_months(chrono::Months::new(1))?;
+    Some((
+        from.and_hms_opt(0, 0, 0)?.and_utc(),
+        to.and_hms_opt(0, 0, 0)?.and_utc(),
+    ))
+}
+
+/// Write a monthly report recognizing the reviewers of a repo to the reports repo, computed from
+/// the reviews published by export-reviews. Run this monthly (e.g. from cron), after
+/// export-reviews, which also exports the recently closed pull requests.
+pub async fn reviewer_report(ctx: &Context, args: ReviewerReportArgs) -> Result<()> {
+    let util::Slug { owner, repo } = &args.repo;
+    let month = args.month.unwrap_or_else(|| {
+        use chrono::Datelike;
+        let first = chrono::Utc::now().date_naive().with_day0(0).unwrap();
+        (first - chrono::Days::new(1)).format("%Y-%m").to_string()
+    });
+    let (from, to) = month_range(&month).expect("Wrong month format, see --help.");
+    tracing::info!("Aggregate the reviews of {owner}/{repo} in {month} ...");
+
+    let export = util::DataExport::new(args.export_dir);
+    let stats = reviewer_stats(&export.read_all(owner, repo, "reviews"), from, to);
+    let text = report_text(&args.repo, &month, &stats);
+    export.write_report(owner, repo, &format!("reviewers-{month}.md"), &text);
+    if ctx.dry_run {
+        util::dry_run_preview(
+            &format!("Write reports/reviewers-{month}.md"),
+            Some(("", &text)),
+        );
+    } else {
+        export.publish(&format!("Add reviewer report for {month}"));
+    }
+
+    if let Some(number) = args.discussion {
+        post_to_discussion(ctx, &args.repo, number, &text).await?;
+    }
+    Ok(())
+}
+
+async fn post_to_discussion(
+    ctx: &Context,
+    slug: &util::Slug,
+    number: u64,
+    text: &str,
+) -> Result<()> {
+    tracing::info!("... Post the report to discussion {number}");
+    if ctx.dry_run {
+        util::dry_run_preview(
+            &format!("POST /graphql addDiscussionComment {}#{number}", slug.str()),
+            Some(("", text)),
+        );
+        return Ok(());
+    }
+    // Discussions are only available via GraphQL
+    let res: serde_json::Value = ctx
+        .octocrab
+        .graphql(&serde_json::json!({
+            "query": "query ($owner: String!, $repo: String!, $number: Int!) { repository(owner: $owner, name: $repo) { discussion(number: $number) { id } } }",
+            "variables": {"owner": slug.owner, "repo": slug.repo, "number": number},
+        }))
+        .await?;
+    let id = res["data"]["repository"]["discussion"]["id"]
+        .as_str()
+        .ok_or(DrahtBotError::KeyNotFound)?;
+    let res: serde_json::Value = ctx
+        .octocrab
+        .graphql(&serde_json::json!({
+            "query": "mutation ($id: ID!, $body: String!) { addDiscussionComment(input: {discussionId: $id, body: $body}) { comment { url } } }",
+            "variables": {"id": id, "body": text},
+        }))
+        .await?;
+    if res["errors"].is_array() {
+        anyhow::bail!("Failed to post to discussion {number}: {}", res["errors"]);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reviewer_stats() {
+        let review = |user: &str, kind: &str, date: &str| serde_json::json!({"user": user, "type": kind, "url": "", "date": date});
+        let comment =
+            |user: &str, date: &str| serde_json::json!({"user": user, "url": "", "date": date});
+        let pulls = [
+            (
+                1,
+                serde_json::json!({"head": "aa", "reviews": [
+                    review("alice", "ACK", "2024-01-02T00:00:00Z"),
+                    review("bob", "Concept ACK", "2024-01-03T00:00:00Z"),
+                    review("carol", "ACK", "2023-12-31T23:59:59Z"),
+                ], "history": [
+                    review("alice", "Concept ACK", "2024-01-01T00:00:00Z"),
+                    review("alice", "ACK", "2024-01-02T00:00:00Z"),
+                    review("bob", "Concept ACK", "2024-01-03T00:00:00Z"),
+                    review("carol", "ACK", "2023-12-31T23:59:59Z"),
+                ], "comments": [
+                    comment("dave", "2024-01-04T00:00:00Z"),
+                ]}),
+            ),
+            (
+                2,
+                serde_json::json!({"head": "bb", "reviews": [
+                    review("bob", "Stale ACK", "2024-01-31T23:59:59Z"),
+                    review("alice", "Concept ACK", "2024-02-01T00:00:00Z"),
+                ]}),
+            ),
+        ];
+        let (from, to) = month_range("2024-01").unwrap();
+        let stats = reviewer_stats(&pulls, from, to);
+        assert_eq!(
+            stats,
+            [
+                ReviewerStats {
+                    user: "alice".to_string(),
+                    acks: 1,
+                    reviews: 2,
+                    pulls: 1,
+                },
+                ReviewerStats {
+                    user: "bob".to_string(),
+                    acks: 1,
+                    reviews: 2,
+                    pulls: 2,
+                },
+                ReviewerStats {
+                    user: "dave".to_string(),
+                    acks: 0,
+                    reviews: 1,
+                    pulls: 1,
+                },
+            ]
+        );
+        let slug = "owner/repo".parse().unwrap();
+        assert!(report_text(&slug, "2024-01", &stats).ends_with(
+            "| [alice](https://github.com/alice) | 1 | 2 | 1 |\n\
+             | [bob](https://github.com/bob) | 1 | 2 | 2 |\n\
+             | [dave](https://github.com/dave) | 0 | 1 | 1 |\n"
+        ));
+        assert!(report_text(&slug, "2024-01", &[]).contains("No reviews"));
+        assert_eq!(month_range("2024-13"), None);
+    }
+}
@@ -0,0 +1,223 @@
+use std::collections::HashMap;
+
+use crate::errors::Result;
+
+/// Persistent state of the bot, which can not be derived from the GitHub API.
+#[derive(Default, serde::Serialize, serde::Deserialize)]
+pub struct State {
+    /// Reviewers that asked to not be requested for review before the given date.
+    #[serde(default)]
+    pub review_pauses: HashMap<String, chrono::NaiveDate>,
+    /// The dates of the recent CI results (true for success) per repo slug.
+    #[serde(default)]
+    pub ci_results: HashMap<String, Vec<(chrono::NaiveDate, bool)>>,
+    /// The repo slugs whose default branch CI is failing, each with the pull requests whose CI
+    /// failed in the meantime.
+    #[serde(default)]
+    pub base_ci_failing: HashMap<String, Vec<u64>>,
+    /// The review requests of the bot that could not be removed, by pull id (owner/repo/number),
+    /// with the number of failed attempts per reviewer.
+    #[serde(default)]
+    pub failed_unrequests: HashMap<String, HashMap<String, u32>>,
+    /// The date of the last push to open pull requests (owner/repo/number), kept until reviews may
+    /// be requested again.
+    #[serde(default)]
+    pub last_push: HashMap<String, chrono::DateTime<chrono::Utc>>,
+    /// The failed check runs of the last days per repo slug, to detect intermittent failures.
+    #[serde(default)]
+    pub ci_failures: HashMap<String, Vec<crate::flakes::CiFailure>>,
+    /// The repo slugs whose conflicts data is outdated, each with the pull requests that got a
+    /// note about it.
+    #[serde(default)]
+    pub conflicts_outdated: HashMap<String, Vec<u64>>,
+}
+
+/// Give up removing a review request after this many failed attempts.
+const MAX_UNREQUEST_ATTEMPTS: u32 = 3;
+
+impl State {
+    /// Return the reviewers with an active pause, and forget the expired ones.
+    pub fn paused_reviewers(&mut self, today: chrono::NaiveDate) -> Vec<String> {
+        self.review_pauses.retain(|_, until| *until > today);
+        self.review_pauses.keys().cloned().collect()
+    }
+
+    /// Forget everything about a closed pull request.
+    pub fn forget_pull(&mut self, pull_id: &str) {
+        self.failed_unrequests.remove(pull_id);
+        self.last_push.remove(pull_id);
+    }
+
+    /// Forget and return the pushes for which the given predicate is true.
+    pub fn take_settled_pushes(
+        &mut self,
+        settled: impl Fn(&str, chrono::DateTime<chrono::Utc>) -> bool,
+    ) -> Vec<String> {
+        let pull_ids = self
+            .last_push
+            .iter()
+            .filter(|(pull_id, date)| settled(pull_id, **date))
+            .map(|(pull_id, _)| pull_id.clone())
+            .collect::<Vec<_>>();
+        for pull_id in &pull_ids {
+            self.last_push.remove(pull_id);
+        }
+        pull_ids
+    }
+
+    /// Forget the failed removals of review requests on the pull, except for the given reviewers,
+    /// e.g. because the request was removed by someone else in the meantime.
+    pub fn retain_unrequests(&mut self, pull_id: &str, reviewers: &[String]) {
+        if let Some(failed) = self.failed_unrequests.get_mut(pull_id) {
+            failed.retain(|r, _| reviewers.contains(r));
+            if failed.is_empty() {
+                self.failed_unrequests.remove(pull_id);
+            }
+        }
+    }
+
+    /// Remember the outcome of removing a review request. Return false if the removal failed too
+    /// often and is not retried anymore.
+    pub fn record_unrequest(&mut self, pull_id: &str, reviewer: &str, success: bool) -> bool {
+        let failed = self
+            .failed_unrequests
+            .entry(pull_id.to_string())
+            .or_default();
+        let retry = if success {
+            failed.remove(reviewer);
+            true
+        } else {
+            let attempts = failed.entry(reviewer.to_string()).or_default();
+            *attempts += 1;
+            if *attempts >= MAX_UNREQUEST_ATTEMPTS {
+                failed.remove(reviewer);
+                false
+            } else {
+                true
+            }
+        };
+        if failed.is_empty() {
+            self.failed_unrequests.remove(pull_id);
+        }
+        retry
+    }
+
+    /// Remember a CI result, and forget the ones older than a week.
+    pub fn record_ci_result(&mut self, repo_slug: &str, today: chrono::NaiveDate, success: bool) {
+        let results = self.ci_results.entry(repo_slug.to_string()).or_default();
+        results.retain(|(date, _)| *date > today - chrono::Days::new(7));
+        results.push((today, success));
+    }
+
+    /// Remember the failed check runs of a CI result, and forget the ones older than the flake
+    /// window.
+    pub fn record_ci_failures(
+        &mut self,
+        repo_slug: &str,
+        today: chrono::NaiveDate,
+        failures: Vec<crate::flakes::CiFailure>,
+    ) {
+        let recorded = self.ci_failures.entry(repo_slug.to_string()).or_default();
+        recorded.retain(|f| f.date > today - chrono::Days::new(crate::flakes::FLAKE_WINDOW_DAYS));
+        let new = failures
+            .into_iter()
+            .filter(|f| f.run_id == 0 || !recorded.iter().any(|r| r.run_id == f.run_id))
+            .collect::<Vec<_>>();
+        recorded.extend(new);
+    }
+
+    /// Remember the CI result of the default branch. On recovery, return the pull requests whose
+    /// CI failed while it was failing.
+    pub fn record_base_ci_result(&mut self, repo_slug: &str, success: bool) -> Vec<u64> {
+        if success {
+            self.base_ci_failing.remove(repo_slug).unwrap_or_default()
+        } else {
+            self.base_ci_failing
+                .entry(repo_slug.to_string())
+                .or_default();
+            Vec::new()
+        }
+    }
+
+    /// If the CI of the default branch is failing, remember the pull request to reprocess it on
+    /// recovery, and return true.
+    pub fn note_failure_on_broken_base(&mut self, repo_slug: &str, pull_number: u64) -> bool {
+        let Some(pulls) = self.base_ci_failing.get_mut(repo_slug) else {
+            return false;
+        };
+        if !pulls.contains(&pull_number) {
+            pulls.push(pull_number);
+        }
+        true
+    }
+
+    /// Remember whether the conflicts data of the repo is outdated. If that changed, return the
+    /// pull requests to add the note to (the given ones) or to remove it from.
+    pub fn record_conflicts_outdated(
+        &mut self,
+        repo_slug: &str,
+        outdated: bool,
+        pulls: Vec<u64>,
+    ) -> Option<Vec<u64>> {
+        match (outdated, self.conflicts_outdated.contains_key(repo_slug)) {
+            (true, false) => {
+                self.conflicts_outdated
+                    .insert(repo_slug.to_string(), pulls.clone());
+                Some(pulls)
+            }
+            (false, true) => self.conflicts_outdated.remove(repo_slug),
+            _ => None,
+        }
+    }
+
+    /// The share of failed CI results in the last week, if there were any.
+    pub fn ci_failure_rate(&self, repo_slug: &str, today: chrono::NaiveDate) -> Option<f64> {
+        let recent = self
+            .ci_results
+            .get(repo_slug)?
+            .iter()
+            .filter(|(date, _)| *date > today - chrono::Days::new(7))
+            .collect::<Vec<_>>();
+        if recent.is_empty() {
+            return None;
+        }
+        let failed = recent.iter().filter(|(_, success)| !success).count();
+        Some(failed as f64 / recent.len() as f64)
+    }
+}
+
+/// The state, stored in a json file. Without a file, the state is only kept in memory.
+pub struct StateStore {
+    path: Option<std::path::PathBuf>,
+    state: tokio::sync::Mutex<State>,
+}
+
+impl StateStore {
+    pub fn load(path: Option<std::path::PathBuf>) -> Result<Self> {
+        let state = match &path {
+            Some(p) if p.exists() => serde_json::from_reader(std::fs::File::open(p)?)?,
+            _ => State::default(),
+        };
+        Ok(Self {
+            path,
+            state: tokio::sync::Mutex::new(state),
+        })
+    }
+
+    /// Read the state.
+    pub async fn read<R>(&self, f: impl FnOnce(&State) -> R) -> R {
+        f(&*self.state.lock().await)
+    }
+
+    /// Modify the state and write it back to the file.
+    pub async fn update<R>(&self, f: impl FnOnce(&mut State) -> R) -> Result<R> {
+        let mut state = self.state.lock().await;
+        let ret = f(&mut state);
+        if let Some(path) = &self.path {
+            let tmp = path.with_extension("tmp");
+            std::fs::write(&tmp, serde_json::to_vec_pretty(&*state)?)?;
+            std::fs::rename(tmp, path)?;
+        }
+        Ok(ret)
+    }
+}